@@ -1,27 +1,102 @@
 use url::Url;
 use crate::render::{FontCache};
-use crate::layout::{Dimensions, RenderBox};
-use crate::dom::{Document, strip_empty_nodes, expand_entities};
-use crate::net::{BrowserError, load_doc_from_net, relative_filepath_to_url, load_stylesheets_new};
-use crate::style::{dom_tree_to_stylednodes};
+use crate::layout::{Dimensions, RenderBox, Rect, Brush, ScrollArea};
+use crate::dom::{Document, NodeType, ElementState, Mutation, strip_empty_nodes, expand_entities, load_doc_from_bytestring, find_viewport_meta, DEFAULT_VIEWPORT_WIDTH};
+use crate::net::{BrowserError, StylesheetSet, load_doc_from_net, relative_filepath_to_url, load_stylesheets_with_user, calculate_url_from_doc, allow_invalid_certificates, escape_html, check_navigation, PageLoad, LoadProgress, with_cached_images_only, forget_decoded_images};
+use crate::style::{dom_tree_to_stylednodes, query_selector, restyle_subtree, strip_collapsible_whitespace, media_query_results, set_color_scheme, ColorScheme, MediaEnvironment, StyledTree};
+use crate::style::invalidation::restyle_after_mutation;
+use crate::css::{parse_selector_list, parse_stylesheet};
 use crate::layout;
+use crate::watch::FileWatcher;
 use std::env;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
 use glium_glyph::glyph_brush::rusttype::Font;
+use glium_glyph::glyph_brush::GlyphBrushBuilder;
 
 pub fn navigate_to_doc(url:&Url, font_cache:&mut FontCache, containing_block:Dimensions) -> Result<(Document, RenderBox),BrowserError> {
-    let mut doc = load_doc_from_net(&url)?;
+    let doc = load_document(url)?;
+    let render_root = layout_doc(&doc, font_cache, containing_block)?;
+    Ok((doc,render_root))
+}
+
+// Loads a page the user asked for. Certificate errors are errors here, since only a Browser
+// can show the page that lets the user proceed anyway.
+pub fn load_document(url:&Url) -> Result<Document, BrowserError> {
+    check_navigation(None, url)?;
+    let mut doc = load_doc_from_net(url)?;
     strip_empty_nodes(&mut doc);
     expand_entities(&mut doc);
     // println!("doc is now {:#?}",doc);
-    let mut stylesheets = load_stylesheets_new(&doc, font_cache)?;
+    Ok(doc)
+}
+
+// The proceed link of the certificate error page being shown. Only that link, with its
+// token, turns off certificate checks, and only once. A page can link to about:tls-proceed
+// too, but it can't know the token.
+struct TlsProceed {
+    token: String,
+    url: Url,
+}
+
+// a token no page can guess. each RandomState is seeded apart from the others.
+fn proceed_token() -> String {
+    format!("{:016x}", RandomState::new().build_hasher().finish())
+}
+
+fn proceed_anyway_url(url:&Url, token:&str) -> Url {
+    Url::parse_with_params("about:tls-proceed", &[("url", url.as_str()), ("token", token)]).unwrap()
+}
+
+// the page a proceed link is for, and its token
+fn proceed_anyway_target(url:&Url) -> Option<(Url, String)> {
+    if url.scheme() != "about" || url.path() != "tls-proceed" {
+        return None;
+    }
+    let param = |name:&str| url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned());
+    let target = param("url").and_then(|value| Url::parse(&value).ok())?;
+    Some((target, param("token").unwrap_or_default()))
+}
+
+fn tls_error_page(url:&Url, details:&str, token:&str) -> Document {
+    let html = format!(r#"<html><body>
+<h1>Your connection is not private</h1>
+<p>The certificate for {host} could not be verified, so this may not really be {host}.</p>
+<p>{details}</p>
+<p><a href="{proceed}">Proceed to {url} anyway</a></p>
+</body></html>"#,
+        host = escape_html(url.host_str().unwrap_or("")),
+        details = escape_html(details),
+        // attributes don't have their entities expanded, so the &s are left as they are. the
+        // query is form encoded, so there are no quotes or brackets in it to escape.
+        proceed = proceed_anyway_url(url, token),
+        url = escape_html(url.as_str()));
+    let mut doc = load_doc_from_bytestring(html.as_bytes());
+    doc.base_url = url.clone();
+    doc
+}
+
+pub fn layout_doc(doc:&Document, font_cache:&mut FontCache, containing_block:Dimensions) -> Result<RenderBox,BrowserError> {
+    let (_stylesheets, stree) = style_doc(doc, font_cache, containing_block, None)?;
+    Ok(layout_styled_doc(&stree, doc, font_cache, containing_block))
+}
+
+/// Loads the page's stylesheets, and the user's stylesheet if there is one, and styles the
+/// document with them.
+pub fn style_doc(doc:&Document, font_cache:&mut FontCache, containing_block:Dimensions, user_css:Option<&str>) -> Result<(StylesheetSet, StyledTree),BrowserError> {
+    let mut stylesheets = load_stylesheets_with_user(doc, font_cache, user_css)?;
+    stylesheets.media = MediaEnvironment::screen(containing_block.content.width, containing_block.content.height);
     let stree = dom_tree_to_stylednodes(&doc.root_node, &stylesheets);
     // println!("styled tree is {:#?}", stree);
+    Ok((stylesheets, stree))
+}
+
+pub fn layout_styled_doc(stree:&StyledTree, doc:&Document, font_cache:&mut FontCache, containing_block:Dimensions) -> RenderBox {
     // println!("font_cache looks like {:#?}",font_cache.families);
-    let mut bbox = layout::build_layout_tree(&stree.root.borrow(), &doc);
+    let mut bbox = layout::build_layout_tree(&stree.root.borrow(), doc);
     // println!("doing layout with bounds {:#?}", containing_block);
-    let render_root = bbox.layout(&mut containing_block.clone(), font_cache, &doc);
-    // println!("render root is {:#?}",render_root);
-    Ok((doc,render_root))
+    bbox.layout(&containing_block, font_cache, doc)
 }
 
 pub fn install_standard_fonts(font_cache:&mut FontCache) -> Result<(),BrowserError> {
@@ -50,7 +125,8 @@ pub fn install_standard_fonts(font_cache:&mut FontCache) -> Result<(),BrowserErr
 }
 
 pub fn parse_args() -> Result<Url, BrowserError> {
-    let args: Vec<String> = env::args().collect();
+    // options like --device come before or after the page
+    let args: Vec<String> = env::args().filter(|arg| !arg.starts_with("--")).collect();
     println!("args = {:?}", args);
     let mut start_page = relative_filepath_to_url("tests/page1.html")?;
     if args.len() > 1 {
@@ -63,3 +139,810 @@ pub fn parse_args() -> Result<Url, BrowserError> {
     }
     Ok(start_page)
 }
+
+/// The device to emulate from `--device=375x667@2`, with `--user-agent=...` to override
+/// the mobile user agent.
+pub fn parse_device_arg() -> Option<DeviceEmulation> {
+    let args: Vec<String> = env::args().collect();
+    let mut device = args.iter()
+        .find_map(|arg| arg.strip_prefix("--device="))
+        .and_then(DeviceEmulation::parse)?;
+    if let Some(ua) = args.iter().find_map(|arg| arg.strip_prefix("--user-agent=")) {
+        device.user_agent = String::from(ua);
+    }
+    Some(device)
+}
+
+/// The color scheme from `--color-scheme=dark` or `--color-scheme=light`, if given.
+pub fn parse_color_scheme_arg() -> Option<ColorScheme> {
+    match env::args().find_map(|arg| arg.strip_prefix("--color-scheme=").map(String::from))?.as_str() {
+        "dark" => Some(ColorScheme::dark()),
+        "light" => Some(ColorScheme::light()),
+        _ => None,
+    }
+}
+
+pub const MOBILE_USER_AGENT:&str = "Mozilla/5.0 (Linux; Android 10; Mobile) rust-minibrowser/0.1";
+
+/// A phone or tablet to pretend to be, for testing responsive pages. Pages are laid out
+/// for its screen, by their viewport meta tag, and scaled to fit the window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceEmulation {
+    // the screen size in css pixels
+    pub width: f32,
+    pub height: f32,
+    pub device_pixel_ratio: f32,
+    pub user_agent: String,
+}
+
+impl DeviceEmulation {
+    /// Parses "WIDTHxHEIGHT" or "WIDTHxHEIGHT@RATIO". The ratio defaults to 1.
+    pub fn parse(spec:&str) -> Option<DeviceEmulation> {
+        let mut parts = spec.splitn(2, '@');
+        let size = parts.next()?;
+        let device_pixel_ratio = match parts.next() {
+            Some(ratio) => ratio.parse().ok().filter(|r:&f32| *r > 0.0)?,
+            None => 1.0,
+        };
+        let mut dims = size.splitn(2, 'x');
+        let width:f32 = dims.next()?.parse().ok()?;
+        let height:f32 = dims.next()?.parse().ok()?;
+        Some(DeviceEmulation {
+            width,
+            height,
+            device_pixel_ratio,
+            user_agent: String::from(MOBILE_USER_AGENT),
+        })
+    }
+}
+
+/// A loaded page plus everything needed to lay it out again. Each window has its own,
+/// and it can be driven headlessly (WebDriver-lite) for testing pages and the engine.
+pub struct Browser {
+    pub url: Url,
+    pub doc: Document,
+    pub render_root: RenderBox,
+    pub font_cache: FontCache,
+    // where the page is laid out. the window's width, unless emulating a device.
+    pub viewport: Dimensions,
+    // how far down the page is scrolled, in the viewport
+    pub page_scroll: ScrollArea,
+    pub window_width: f32,
+    pub emulation: Option<DeviceEmulation>,
+    // show pages while they download, instead of waiting for all of them
+    pub progressive: bool,
+    loading: Option<PageLoad>,
+    // when the loading page was last laid out, and how much of it there was then
+    last_partial_layout: Option<(Instant, usize)>,
+    // reload local pages when their files change
+    auto_reload: bool,
+    watcher: Option<FileWatcher>,
+    // the stylesheets and styled tree of the last full layout, so a hover or focus only
+    // restyles the elements it changed
+    styles: Option<(StylesheetSet, StyledTree)>,
+    // css the user wants on every page, between the browser's stylesheet and the page's
+    user_stylesheet: Option<String>,
+    tls_proceed: Option<TlsProceed>,
+}
+
+/// How often a page that's still downloading is laid out again.
+pub const PARTIAL_LAYOUT_INTERVAL:Duration = Duration::from_millis(100);
+
+/// What following a link asks of the windows.
+#[derive(Debug, PartialEq)]
+pub enum LinkAction {
+    Navigated,
+    OpenWindow(Url),
+}
+
+impl Browser {
+    pub fn open(url:&Url, font_cache:FontCache, viewport:Dimensions) -> Result<Browser, BrowserError> {
+        let mut browser = Browser::blank(url, font_cache, viewport, false)?;
+        browser.navigate(url)?;
+        Ok(browser)
+    }
+    /// Starts with an empty page and shows the url as it downloads.
+    pub fn open_progressively(url:&Url, font_cache:FontCache, viewport:Dimensions) -> Result<Browser, BrowserError> {
+        let mut browser = Browser::blank(url, font_cache, viewport, true)?;
+        browser.navigate(url)?;
+        Ok(browser)
+    }
+    // an empty page, to navigate from
+    fn blank(url:&Url, mut font_cache:FontCache, viewport:Dimensions, progressive:bool) -> Result<Browser, BrowserError> {
+        let mut doc = load_doc_from_bytestring(b"<html><body></body></html>");
+        doc.base_url = url.clone();
+        let render_root = layout_doc(&doc, &mut font_cache, viewport)?;
+        Ok(Browser {
+            url: url.clone(),
+            doc,
+            page_scroll: ScrollArea::for_page(&render_root, viewport.content, 0.0),
+            render_root,
+            font_cache,
+            viewport,
+            window_width: viewport.content.width,
+            emulation: None,
+            progressive,
+            loading: None,
+            last_partial_layout: None,
+            auto_reload: false,
+            watcher: None,
+            styles: None,
+            user_stylesheet: None,
+            tls_proceed: None,
+        })
+    }
+    // The page to really load, after the proceed link and the security policy have had their
+    // say. `from` is the page that started the navigation, or None for the user.
+    fn resolve_navigation(&mut self, from:Option<&Url>, url:&Url) -> Result<Url, BrowserError> {
+        if let Some((target, token)) = proceed_anyway_target(url) {
+            match self.tls_proceed.take() {
+                Some(proceed) if proceed.token == token && proceed.url == target => {}
+                _ => {
+                    println!("blocked navigation to {}: not the certificate error page's own link", url);
+                    return Err(BrowserError::Blocked(url.clone()));
+                }
+            }
+            check_navigation(from, &target)?;
+            if let Some(host) = target.host_str() {
+                allow_invalid_certificates(host);
+            }
+            return Ok(target);
+        }
+        check_navigation(from, url)?;
+        Ok(url.clone())
+    }
+    // A certificate error becomes an interstitial page explaining it, whose proceed link turns
+    // off certificate checks for that host and loads the page.
+    fn show_tls_errors(&mut self, result:Result<Document, BrowserError>) -> Result<Document, BrowserError> {
+        match result {
+            Err(BrowserError::Tls(url, details)) => {
+                let token = proceed_token();
+                let doc = tls_error_page(&url, &details, &token);
+                self.tls_proceed = Some(TlsProceed { token, url });
+                Ok(doc)
+            },
+            other => other,
+        }
+    }
+    // loads a page to navigate to, and says where it really came from
+    fn load_document(&mut self, from:Option<&Url>, url:&Url) -> Result<(Url, Document), BrowserError> {
+        let url = self.resolve_navigation(from, url)?;
+        let mut doc = self.show_tls_errors(load_doc_from_net(&url))?;
+        expand_entities(&mut doc);
+        Ok((url, doc))
+    }
+    /// Lays the page out as the device would, or for the window again when None.
+    pub fn emulate(&mut self, emulation:Option<DeviceEmulation>) -> Result<(), BrowserError> {
+        self.emulation = emulation;
+        self.relayout_viewport()
+    }
+    fn layout_width(&self) -> f32 {
+        match &self.emulation {
+            None => self.window_width,
+            Some(device) => find_viewport_meta(&self.doc.root_node)
+                .map(|meta| meta.layout_width(device.width))
+                .unwrap_or(DEFAULT_VIEWPORT_WIDTH),
+        }
+    }
+    /// How much to scale the laid out page by to fill the window's width.
+    pub fn zoom(&self) -> f32 {
+        self.window_width / self.viewport.content.width
+    }
+    pub fn open_headless(url:&Url, width:f32) -> Result<Browser, BrowserError> {
+        let mut font_cache = FontCache {
+            brush: Brush::Style2(GlyphBrushBuilder::without_fonts().build()),
+            families: Default::default(),
+            fonts: Default::default()
+        };
+        install_standard_fonts(&mut font_cache)?;
+        let viewport = Dimensions {
+            content: Rect { x: 0.0, y: 0.0, width, height: 0.0 },
+            padding: Default::default(),
+            border: Default::default(),
+            margin: Default::default()
+        };
+        Browser::open(url, font_cache, viewport)
+    }
+    /// Goes to a url the user asked for.
+    pub fn navigate(&mut self, url:&Url) -> Result<(), BrowserError> {
+        self.navigate_from(None, url)
+    }
+    fn navigate_from(&mut self, from:Option<&Url>, url:&Url) -> Result<(), BrowserError> {
+        if self.progressive {
+            let url = self.resolve_navigation(from, url)?;
+            self.loading = Some(PageLoad::start(&url));
+            self.last_partial_layout = None;
+            self.url = url;
+            self.scroll_to_top();
+            return Ok(());
+        }
+        let (url, doc) = self.load_document(from, url)?;
+        self.doc = doc;
+        self.url = url;
+        self.scroll_to_top();
+        self.watch_page()?;
+        self.relayout()
+    }
+    /// Live preview: reload the page, keeping its scroll position, whenever its file or
+    /// its stylesheets' files change. Only local pages are watched.
+    pub fn set_auto_reload(&mut self, on:bool) -> Result<(), BrowserError> {
+        self.auto_reload = on;
+        self.watch_page()
+    }
+    fn watch_page(&mut self) -> Result<(), BrowserError> {
+        self.watcher = None;
+        if self.auto_reload {
+            self.watcher = FileWatcher::for_page(&self.url, &self.doc)?;
+        }
+        Ok(())
+    }
+    /// Reloads the page if its files changed. Returns whether it did.
+    pub fn check_for_changes(&mut self) -> Result<bool, BrowserError> {
+        match &self.watcher {
+            Some(watcher) if watcher.changed() => {
+                self.reload()?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+    pub fn reload(&mut self) -> Result<(), BrowserError> {
+        let url = self.url.clone();
+        self.doc = self.load_document(None, &url)?.1;
+        // the stylesheets and images it links to might have changed too
+        forget_decoded_images();
+        self.watch_page()?;
+        self.relayout()
+    }
+    pub fn is_watching(&self) -> bool {
+        self.watcher.is_some()
+    }
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+    /// Takes in what has downloaded of the loading page. Lays out the partial page if it's
+    /// been long enough since the last time, or the whole page once it is done. Returns
+    /// whether the page changed.
+    pub fn pump_load(&mut self, now:Instant) -> Result<bool, BrowserError> {
+        let load = match &mut self.loading {
+            Some(load) => load,
+            None => return Ok(false),
+        };
+        match load.poll() {
+            LoadProgress::Loading => {
+                let received = load.received();
+                let due = self.last_partial_layout.is_none_or(|(at, shown)| {
+                    received > shown && now >= at + PARTIAL_LAYOUT_INTERVAL
+                });
+                if !due {
+                    return Ok(false);
+                }
+                let mut doc = match load.partial_document() {
+                    Some(doc) => doc,
+                    None => return Ok(false),
+                };
+                expand_entities(&mut doc);
+                self.doc = doc;
+                self.last_partial_layout = Some((now, received));
+                with_cached_images_only(|| self.relayout())?;
+                Ok(true)
+            }
+            LoadProgress::Done(result) => {
+                self.loading = None;
+                let mut doc = self.show_tls_errors(result)?;
+                expand_entities(&mut doc);
+                self.doc = doc;
+                self.watch_page()?;
+                self.relayout()?;
+                Ok(true)
+            }
+        }
+    }
+    /// Navigates this window to the link, unless it should open in a new window.
+    pub fn follow_link(&mut self, href:&str, new_window:bool) -> Result<LinkAction, BrowserError> {
+        let url = calculate_url_from_doc(&self.doc, href)?;
+        check_navigation(Some(&self.doc.base_url), &url)?;
+        if new_window {
+            return Ok(LinkAction::OpenWindow(url));
+        }
+        let from = self.doc.base_url.clone();
+        self.navigate_from(Some(&from), &url)?;
+        Ok(LinkAction::Navigated)
+    }
+    pub fn scroll_y(&self) -> f32 {
+        self.page_scroll.scroll_y
+    }
+    /// Scrolls the page.
+    pub fn scroll_by(&mut self, dy:f32) {
+        self.page_scroll.scroll_by(dy);
+    }
+    /// Scrolls the scrolling box under the point, in page coordinates, then the boxes around
+    /// it and the page by whatever it can't.
+    pub fn scroll_at(&mut self, x:f32, y:f32, dy:f32) {
+        let left = self.render_root.scroll_at(x, y, dy);
+        self.page_scroll.scroll_by(left);
+    }
+    fn scroll_to_top(&mut self) {
+        self.page_scroll.scroll_y = 0.0;
+        self.render_root.set_scroll_offsets(&[]);
+    }
+    // takes in the page laid out again. the page and the boxes in it stay scrolled as far
+    // as they still go.
+    fn show(&mut self, mut render_root:RenderBox) {
+        render_root.set_scroll_offsets(&self.render_root.scroll_offsets());
+        self.render_root = render_root;
+        self.page_scroll = ScrollArea::for_page(&self.render_root, self.viewport.content, self.page_scroll.scroll_y);
+    }
+    /// Lays the page out again for the window's new size. The height matters too, for media
+    /// queries and for how far the page scrolls.
+    pub fn resize(&mut self, width:f32, height:f32) -> Result<(), BrowserError> {
+        if self.window_width != width || self.viewport.content.height != height {
+            self.window_width = width;
+            self.viewport.content.height = height;
+            self.relayout_viewport()?;
+        }
+        Ok(())
+    }
+    // lays the page out again for a new viewport, keeping its stylesheets. it is only
+    // restyled when one of their media queries comes out differently.
+    fn relayout_viewport(&mut self) -> Result<(), BrowserError> {
+        self.viewport.content.width = self.layout_width();
+        let (stylesheets, stree) = match &mut self.styles {
+            Some(styles) => styles,
+            None => return self.relayout(),
+        };
+        let before = media_query_results(stylesheets);
+        stylesheets.media = MediaEnvironment::screen(self.viewport.content.width, self.viewport.content.height);
+        if media_query_results(stylesheets) != before {
+            *stree = dom_tree_to_stylednodes(&self.doc.root_node, stylesheets);
+        }
+        let render_root = layout_styled_doc(stree, &self.doc, &mut self.font_cache, self.viewport);
+        self.show(render_root);
+        Ok(())
+    }
+    /// Styles this and every later page with css as the user's stylesheet. It beats the
+    /// browser's stylesheet, and the page's own rules beat it.
+    pub fn set_user_stylesheet(&mut self, css:&str) -> Result<(), BrowserError> {
+        parse_stylesheet(css)?;
+        self.user_stylesheet = Some(String::from(css));
+        self.relayout()
+    }
+    /// Switches between light and dark. System colors and `prefers-color-scheme` queries
+    /// both follow it, so the page is styled again with its kept stylesheets.
+    pub fn set_color_scheme(&mut self, scheme:ColorScheme) -> Result<(), BrowserError> {
+        set_color_scheme(scheme);
+        let (stylesheets, stree) = match &mut self.styles {
+            Some(styles) => styles,
+            None => return self.relayout(),
+        };
+        stylesheets.media = MediaEnvironment::screen(self.viewport.content.width, self.viewport.content.height);
+        *stree = dom_tree_to_stylednodes(&self.doc.root_node, stylesheets);
+        let render_root = layout_styled_doc(stree, &self.doc, &mut self.font_cache, self.viewport);
+        self.show(render_root);
+        Ok(())
+    }
+    pub fn relayout(&mut self) -> Result<(), BrowserError> {
+        self.viewport.content.width = self.layout_width();
+        let (stylesheets, mut stree) = style_doc(&self.doc, &mut self.font_cache, self.viewport, self.user_stylesheet.as_deref())?;
+        // whitespace is kept by the parser, and goes once the styles say where it collapses
+        if strip_collapsible_whitespace(&mut self.doc.root_node, &stree.root.borrow()) {
+            stree = dom_tree_to_stylednodes(&self.doc.root_node, &stylesheets);
+        }
+        let render_root = layout_styled_doc(&stree, &self.doc, &mut self.font_cache, self.viewport);
+        self.show(render_root);
+        self.styles = Some((stylesheets, stree));
+        Ok(())
+    }
+    // after the state of the elements under `changed` did, restyles just them and lays the
+    // page out from the kept styled tree. false when no rule cares about the state.
+    fn restyle_for_state(&mut self, changed:&[usize], state:ElementState) -> Result<bool, BrowserError> {
+        let (stylesheets, stree) = match &self.styles {
+            Some(styles) => styles,
+            None => {
+                self.relayout()?;
+                return Ok(true);
+            }
+        };
+        if !restyle_subtree(stree, &self.doc.root_node, changed, state, stylesheets) {
+            return Ok(false);
+        }
+        let render_root = layout_styled_doc(stree, &self.doc, &mut self.font_cache, self.viewport);
+        self.show(render_root);
+        Ok(true)
+    }
+
+    /// Hovers the element under the point, in page coordinates. True if the page was
+    /// restyled because of it.
+    pub fn hover_at(&mut self, x:f32, y:f32) -> Result<bool, BrowserError> {
+        let path = self.render_root.find_element_path(x, y, self.scroll_y());
+        self.set_element_state(path.as_deref(), ElementState::HOVER)
+    }
+    /// Presses the mouse on the element under the point, which makes it active and focused.
+    pub fn press_at(&mut self, x:f32, y:f32) -> Result<bool, BrowserError> {
+        let path = self.render_root.find_element_path(x, y, self.scroll_y());
+        let active = self.set_element_state(path.as_deref(), ElementState::ACTIVE)?;
+        let focus = self.set_element_state(path.as_deref(), ElementState::FOCUS)?;
+        Ok(active || focus)
+    }
+    /// Lets go of the mouse. The pressed element stays focused.
+    pub fn release(&mut self) -> Result<bool, BrowserError> {
+        self.set_element_state(None, ElementState::ACTIVE)
+    }
+    fn set_element_state(&mut self, path:Option<&[usize]>, state:ElementState) -> Result<bool, BrowserError> {
+        match self.doc.root_node.update_element_state(path, state) {
+            Some(changed) => self.restyle_for_state(&changed, state),
+            None => Ok(false),
+        }
+    }
+
+    fn find(&self, selector:&str) -> Result<Vec<usize>, BrowserError> {
+        let selectors = parse_selector_list(selector)?;
+        query_selector(&self.doc.root_node, &selectors)
+            .ok_or_else(|| BrowserError::NoSuchElement(String::from(selector)))
+    }
+
+    /// Follows the link on the matched element or the nearest enclosing `a`.
+    pub fn click(&mut self, selector:&str) -> Result<(), BrowserError> {
+        let path = self.find(selector)?;
+        let mut href:Option<String> = None;
+        for depth in 0..=path.len() {
+            if let NodeType::Element(ed) = &self.doc.root_node.node_at_path(&path[..depth]).node_type {
+                if ed.tag_name == "a" && ed.attributes.contains_key("href") {
+                    href = ed.attributes.get("href").cloned();
+                }
+            }
+        }
+        if let Some(href) = href {
+            self.follow_link(&href, false)?;
+        }
+        Ok(())
+    }
+
+    /// Appends text to the value of the matched form control.
+    pub fn type_text(&mut self, selector:&str, text:&str) -> Result<(), BrowserError> {
+        let path = self.find(selector)?;
+        let value = match &self.doc.root_node.node_at_path(&path).node_type {
+            NodeType::Element(ed) => ed.attributes.get("value").cloned().unwrap_or_default() + text,
+            _ => return Ok(()),
+        };
+        match self.doc.root_node.set_attribute(&path, "value", Some(&value)) {
+            Some(mutation) => self.document_changed(&mutation),
+            None => Ok(()),
+        }
+    }
+
+    /// Brings the page up to date after the document was changed. Only the styles the
+    /// change makes stale are worked out again, then the page is laid out.
+    pub fn document_changed(&mut self, mutation:&Mutation) -> Result<(), BrowserError> {
+        match &self.styles {
+            Some((stylesheets, stree)) if restyle_after_mutation(stree, &self.doc.root_node, mutation, stylesheets).is_some() => {
+                let render_root = layout_styled_doc(stree, &self.doc, &mut self.font_cache, self.viewport);
+                self.show(render_root);
+                Ok(())
+            },
+            _ => self.relayout(),
+        }
+    }
+
+    /// Loading is synchronous, so the element is either there already or never will be.
+    pub fn wait_for(&self, selector:&str) -> Result<(), BrowserError> {
+        self.find(selector).map(|_| ())
+    }
+
+    /// The text content of the matched element, or the value of a form control.
+    pub fn text_of(&self, selector:&str) -> Result<String, BrowserError> {
+        let node = self.doc.root_node.node_at_path(&self.find(selector)?);
+        if let NodeType::Element(ed) = &node.node_type {
+            if let Some(value) = ed.attributes.get("value") {
+                return Ok(value.clone());
+            }
+        }
+        Ok(node.text_content())
+    }
+}
+
+#[test]
+fn test_headless_driver() {
+    let url = relative_filepath_to_url("tests/page1.html").unwrap();
+    let mut browser = Browser::open_headless(&url, 500.0).unwrap();
+    assert!(browser.wait_for("a").is_ok());
+    assert!(browser.wait_for("table").is_err());
+    assert_eq!(browser.text_of("a").unwrap(), "link to the next page");
+    browser.click("a").unwrap();
+    assert!(browser.url.as_str().ends_with("tests/page2.html"));
+}
+
+#[test]
+fn test_hover_restyles() {
+    fn first_text(bx:&RenderBox) -> Option<&crate::layout::RenderTextBox> {
+        match bx {
+            RenderBox::Block(block) => block.children.iter().find_map(first_text),
+            RenderBox::Anonymous(anon) => anon.children.iter()
+                .flat_map(|line| line.children.iter())
+                .find_map(|ch| match ch {
+                    crate::layout::RenderInlineBoxType::Text(text) => Some(text),
+                    _ => None,
+                }),
+            _ => None,
+        }
+    }
+    let url = relative_filepath_to_url("tests/hover.html").unwrap();
+    let mut browser = Browser::open_headless(&url, 500.0).unwrap();
+    let text = first_text(&browser.render_root).unwrap();
+    let (x, y) = (text.rect.x + 1.0, text.rect.y + 1.0);
+    let black = crate::style::find_color_lazy_static("black");
+    assert_eq!(text.color, black);
+
+    assert!(browser.hover_at(x, y).unwrap());
+    assert_eq!(first_text(&browser.render_root).unwrap().color, crate::style::find_color_lazy_static("red"));
+    // the div is hovered too, since the p is inside it
+    let div = query_selector(&browser.doc.root_node, &parse_selector_list("div").unwrap()).unwrap();
+    match &browser.doc.root_node.node_at_path(&div).node_type {
+        NodeType::Element(ed) => assert!(ed.state.contains(ElementState::HOVER)),
+        _ => panic!(),
+    }
+    // moving within the same element doesn't restyle again
+    assert!(!browser.hover_at(x + 2.0, y).unwrap());
+
+    assert!(browser.press_at(x, y).unwrap());
+    assert_eq!(first_text(&browser.render_root).unwrap().color, crate::style::find_color_lazy_static("blue"));
+    assert!(browser.release().unwrap());
+    assert_eq!(first_text(&browser.render_root).unwrap().color, crate::style::find_color_lazy_static("red"));
+
+    assert!(browser.hover_at(-100.0, -100.0).unwrap());
+    assert_eq!(first_text(&browser.render_root).unwrap().color, black);
+}
+
+#[test]
+fn test_media_queries_on_resize() {
+    fn first_color(bx:&RenderBox) -> Option<crate::css::Color> {
+        match bx {
+            RenderBox::Block(block) => block.children.iter().find_map(first_color),
+            RenderBox::Anonymous(anon) => anon.children.iter()
+                .flat_map(|line| line.children.iter())
+                .find_map(|ch| match ch {
+                    crate::layout::RenderInlineBoxType::Text(text) => text.color.clone(),
+                    _ => None,
+                }),
+            _ => None,
+        }
+    }
+    let url = relative_filepath_to_url("tests/media.html").unwrap();
+    let mut browser = Browser::open_headless(&url, 500.0).unwrap();
+    assert_eq!(first_color(&browser.render_root), crate::style::find_color_lazy_static("black"));
+    let styled_root = |browser:&Browser| std::rc::Rc::clone(&browser.styles.as_ref().unwrap().1.root.borrow());
+    let before = styled_root(&browser);
+    browser.resize(300.0, 0.0).unwrap();
+    assert_eq!(first_color(&browser.render_root), crate::style::find_color_lazy_static("red"));
+    assert!(!std::rc::Rc::ptr_eq(&before, &styled_root(&browser)));
+    browser.resize(600.0, 0.0).unwrap();
+    assert_eq!(first_color(&browser.render_root), crate::style::find_color_lazy_static("black"));
+    // no query changes between these widths, so the page is only laid out again
+    let before = styled_root(&browser);
+    browser.resize(700.0, 0.0).unwrap();
+    assert!(std::rc::Rc::ptr_eq(&before, &styled_root(&browser)));
+    assert_eq!(browser.viewport.content.width, 700.0);
+    // a new height alone is laid out for too
+    browser.resize(700.0, 300.0).unwrap();
+    assert_eq!(browser.styles.as_ref().unwrap().0.media.height, 300.0);
+    assert_eq!(browser.page_scroll.rect.height, 300.0);
+}
+
+#[test]
+fn test_user_stylesheet() {
+    let url = relative_filepath_to_url("tests/media.html").unwrap();
+    let mut browser = Browser::open_headless(&url, 500.0).unwrap();
+    assert!(browser.set_user_stylesheet("p { color: green !important; font-family: monospace; } h1 { font-family: monospace; }").is_ok());
+    let styled_value = |browser:&Browser, path:&[usize], name:&str| {
+        let (_, stree) = browser.styles.as_ref().unwrap();
+        let mut node = std::rc::Rc::clone(&stree.root.borrow());
+        for depth in 0..path.len() {
+            let child = node.children.borrow().iter().find(|ch| ch.path == path[..=depth]).cloned().unwrap();
+            node = child;
+        }
+        node.value(name)
+    };
+    let p = query_selector(&browser.doc.root_node, &crate::css::parse_selector_list("p").unwrap()).unwrap();
+    // the user's !important rule beats the page's, and the user's normal one beats the browser's
+    assert_eq!(styled_value(&browser, &p, "color"), Some(crate::css::Value::Keyword(String::from("green"))));
+    assert_eq!(styled_value(&browser, &p, "font-family"), Some(crate::css::Value::Keyword(String::from("monospace"))));
+    let (styles, _) = browser.styles.as_ref().unwrap();
+    let origins:Vec<crate::style::Origin> = crate::style::matching_rules(&browser.doc.root_node, &p, styles)
+        .iter().map(|rule| rule.origin).collect();
+    assert!(origins.contains(&crate::style::Origin::User));
+    assert!(origins.contains(&crate::style::Origin::Author));
+    assert_eq!(origins.last(), Some(&crate::style::Origin::UserImportant));
+    // and it stays for the next page
+    browser.navigate(&relative_filepath_to_url("tests/simple.html").unwrap()).unwrap();
+    let h1 = query_selector(&browser.doc.root_node, &crate::css::parse_selector_list("h1").unwrap()).unwrap();
+    assert_eq!(styled_value(&browser, &h1, "font-family"), Some(crate::css::Value::Keyword(String::from("monospace"))));
+}
+
+#[test]
+fn test_tls_interstitial() {
+    let url = Url::parse("https://expired.example.com/page.html?a=1&b=2").unwrap();
+    let proceed = proceed_anyway_url(&url, "abc");
+    assert_eq!(proceed.scheme(), "about");
+    assert_eq!(proceed_anyway_target(&proceed), Some((url.clone(), String::from("abc"))));
+    assert_eq!(proceed_anyway_target(&url), None);
+
+    let mut browser = Browser::open_headless(&relative_filepath_to_url("tests/simple.html").unwrap(), 500.0).unwrap();
+    // a page can't make up its own proceed link
+    let forged = proceed_anyway_url(&url, "abc");
+    assert!(matches!(browser.navigate(&forged), Err(BrowserError::Blocked(_))));
+    assert!(!crate::net::allows_invalid_certificates("expired.example.com"));
+
+    let doc = browser.show_tls_errors(Err(BrowserError::Tls(url.clone(), String::from("certificate verify failed <self signed>")))).unwrap();
+    let text = doc.root_node.text_content();
+    assert!(text.contains("expired.example.com"));
+    assert!(text.contains("certificate verify failed"));
+    let links = crate::dom::getElementsByTagName(&doc.root_node, "a");
+    assert_eq!(links.len(), 1);
+    let href = match &links[0].node_type {
+        NodeType::Element(ed) => Url::parse(ed.attributes.get("href").unwrap()).unwrap(),
+        _ => panic!("the proceed link should be an element"),
+    };
+    assert_eq!(proceed_anyway_target(&href).map(|(target, _)| target), Some(url.clone()));
+    // the error page's own link works, once
+    assert_eq!(browser.resolve_navigation(Some(&url), &href).unwrap(), url);
+    assert!(crate::net::allows_invalid_certificates("expired.example.com"));
+    assert!(matches!(browser.resolve_navigation(Some(&url), &href), Err(BrowserError::Blocked(_))));
+    // where it proceeds to is checked against the page the link was on
+    let file = relative_filepath_to_url("tests/simple.html").unwrap();
+    browser.show_tls_errors(Err(BrowserError::Tls(file.clone(), String::new()))).unwrap();
+    let proceed = proceed_anyway_url(&file, &browser.tls_proceed.as_ref().unwrap().token);
+    assert!(matches!(browser.resolve_navigation(Some(&url), &proceed), Err(BrowserError::Blocked(_))));
+}
+
+#[test]
+fn test_independent_windows() {
+    let url = relative_filepath_to_url("tests/page1.html").unwrap();
+    let mut first = Browser::open_headless(&url, 500.0).unwrap();
+    first.scroll_by(120.0);
+    first.scroll_by(-20.0);
+    assert_eq!(first.scroll_y(), 100.0);
+    let action = first.follow_link("page2.html", true).unwrap();
+    let page2 = relative_filepath_to_url("tests/page2.html").unwrap();
+    assert_eq!(action, LinkAction::OpenWindow(page2.clone()));
+    // the new window loads on its own. the first one keeps its page and scroll position
+    let mut second = Browser::open_headless(&page2, 300.0).unwrap();
+    assert_eq!(first.url, url);
+    assert_eq!(first.scroll_y(), 100.0);
+    assert_eq!(second.scroll_y(), 0.0);
+    second.scroll_by(-50.0);
+    assert_eq!(second.scroll_y(), 0.0);
+    assert_eq!(first.follow_link("page2.html", false).unwrap(), LinkAction::Navigated);
+    assert_eq!(first.url, page2);
+    assert_eq!(first.scroll_y(), 0.0);
+}
+
+#[test]
+fn test_scrolling_boxes() {
+    let url = relative_filepath_to_url("tests/scroll.html").unwrap();
+    let mut browser = Browser::open_headless(&url, 500.0).unwrap();
+    let box_scroll = |browser:&Browser| browser.render_root.scroll_offsets().iter().map(|(_, y)| *y).sum::<f32>();
+    // the box under the mouse scrolls to its end, and the page takes the rest
+    browser.scroll_at(10.0, 10.0, 100.0);
+    assert_eq!(box_scroll(&browser), 70.0);
+    assert_eq!(browser.scroll_y(), 30.0);
+    browser.scroll_at(10.0, 60.0, -10.0);
+    assert_eq!(box_scroll(&browser), 70.0);
+    assert_eq!(browser.scroll_y(), 20.0);
+    // hovering hits the scrolled content, and the box stays scrolled when it is restyled
+    let three = browser.render_root.find_element_path(10.0, 20.0, browser.scroll_y()).unwrap();
+    assert_eq!(browser.doc.root_node.node_at_path(&three).text_content(), "three");
+    assert!(browser.hover_at(10.0, 20.0).unwrap());
+    assert_eq!(box_scroll(&browser), 70.0);
+    match &browser.doc.root_node.node_at_path(&three).node_type {
+        NodeType::Element(ed) => assert!(ed.state.contains(ElementState::HOVER)),
+        _ => panic!(),
+    }
+    // a new page starts at the top
+    browser.navigate(&url).unwrap();
+    assert_eq!(box_scroll(&browser), 0.0);
+    assert_eq!(browser.scroll_y(), 0.0);
+}
+
+#[test]
+fn test_device_emulation() {
+    let phone = DeviceEmulation::parse("375x667@2").unwrap();
+    assert_eq!((phone.width, phone.height, phone.device_pixel_ratio), (375.0, 667.0, 2.0));
+    assert_eq!(DeviceEmulation::parse("768x1024").unwrap().device_pixel_ratio, 1.0);
+    assert_eq!(DeviceEmulation::parse("375"), None);
+    assert_eq!(DeviceEmulation::parse("375x667@0"), None);
+
+    let url = relative_filepath_to_url("tests/viewport.html").unwrap();
+    let mut browser = Browser::open_headless(&url, 750.0).unwrap();
+    assert_eq!(browser.viewport.content.width, 750.0);
+    assert_eq!(browser.zoom(), 1.0);
+    browser.emulate(Some(phone.clone())).unwrap();
+    // width=device-width, so the page is laid out 375 wide and drawn twice as big
+    assert_eq!(browser.viewport.content.width, 375.0);
+    if let RenderBox::Block(html) = &browser.render_root {
+        assert_eq!(html.rect.width, 375.0);
+    }
+    assert_eq!(browser.zoom(), 2.0);
+    // pages without a viewport tag get the desktop-ish default and are shrunk to fit
+    browser.navigate(&relative_filepath_to_url("tests/page1.html").unwrap()).unwrap();
+    assert_eq!(browser.viewport.content.width, DEFAULT_VIEWPORT_WIDTH);
+    browser.emulate(None).unwrap();
+    assert_eq!(browser.viewport.content.width, 750.0);
+}
+
+#[test]
+fn test_progressive_load() {
+    use std::io::{Read, Write};
+    use std::sync::mpsc::channel;
+    // the server sends the top of the page, then waits to be told to send the rest
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("http://{}/slow.html", listener.local_addr().unwrap())).unwrap();
+    let (send_rest, rest) = channel::<()>();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0u8; 1024];
+        assert!(stream.read(&mut request).unwrap() > 0);
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n").unwrap();
+        write!(stream, "<html><body><p>above the fold</p><p>still com").unwrap();
+        stream.flush().unwrap();
+        rest.recv().unwrap();
+        write!(stream, "ing</p><img src=\"cat.png\"></body></html>").unwrap();
+    });
+
+    let mut browser = Browser::open_headless(&relative_filepath_to_url("tests/page1.html").unwrap(), 500.0).unwrap();
+    browser.progressive = true;
+    browser.navigate(&url).unwrap();
+    assert!(browser.is_loading());
+    let start = Instant::now();
+    while !browser.doc.root_node.text_content().contains("above the fold") {
+        browser.pump_load(Instant::now()).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    // only the complete part of the page is shown
+    assert!(browser.is_loading());
+    assert!(!browser.doc.root_node.text_content().contains("still"));
+    send_rest.send(()).unwrap();
+    server.join().unwrap();
+    while browser.is_loading() {
+        browser.pump_load(Instant::now()).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(browser.doc.root_node.text_content(), "above the foldstill coming");
+    assert_eq!(browser.url, url);
+}
+
+#[test]
+fn test_auto_reload() {
+    let dir = std::env::temp_dir().join("minibrowser-reload-test");
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("page.html"), r#"<html><head><link rel="stylesheet" href="page.css"></head><body><p>before</p></body></html>"#).unwrap();
+    std::fs::write(dir.join("page.css"), "p { color: red; }").unwrap();
+    let url = Url::from_file_path(dir.join("page.html")).unwrap();
+    let mut browser = Browser::open_headless(&url, 500.0).unwrap();
+    assert!(!browser.is_watching());
+    browser.set_auto_reload(true).unwrap();
+    assert!(browser.is_watching());
+    browser.scroll_by(40.0);
+    let wait_for_reload = |browser:&mut Browser| {
+        let start = Instant::now();
+        while !browser.check_for_changes().unwrap() {
+            assert!(start.elapsed() < Duration::from_secs(5), "the change was never noticed");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    };
+
+    std::fs::write(dir.join("page.html"), r#"<html><head><link rel="stylesheet" href="page.css"></head><body><p>after</p></body></html>"#).unwrap();
+    wait_for_reload(&mut browser);
+    assert_eq!(browser.doc.root_node.text_content(), "after");
+    assert_eq!(browser.scroll_y(), 40.0);
+
+    // saving by renaming over the stylesheet still counts
+    std::fs::write(dir.join("page.css.tmp"), "p { color: blue; }").unwrap();
+    std::fs::rename(dir.join("page.css.tmp"), dir.join("page.css")).unwrap();
+    wait_for_reload(&mut browser);
+
+    // network pages aren't watched
+    assert!(FileWatcher::for_page(&Url::parse("https://www.example.com/").unwrap(), &browser.doc).unwrap().is_none());
+}
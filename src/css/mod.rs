@@ -11,6 +11,8 @@ use self::pom::parser::{list, call, take};
 use url::Url;
 use crate::css::RuleType::Comment;
 
+mod serialize;
+
 
 #[derive(Debug, PartialEq)]
 pub struct Stylesheet {
@@ -21,6 +23,10 @@ pub struct Stylesheet {
 pub enum RuleType {
     Rule(Rule),
     AtRule(AtRule),
+    Supports(SupportsRule),
+    Page(PageRule),
+    Media(MediaRule),
+    Keyframes(KeyframesRule),
     Comment(String),
 }
 #[derive(Debug, PartialEq)]
@@ -36,11 +42,69 @@ pub struct AtRule {
     pub rules: Vec<RuleType>,
 }
 
+#[derive(Debug, PartialEq)]
+pub struct SupportsRule {
+    pub condition: SupportsCondition,
+    pub rules: Vec<RuleType>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PageRule {
+    /// `:first`, `:left` or `:right`, if the rule only applies to some pages
+    pub pseudo_class: Option<String>,
+    pub declarations: Vec<Declaration>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MediaRule {
+    /// the rules apply when any of the queries match
+    pub queries: Vec<MediaQuery>,
+    pub rules: Vec<RuleType>,
+}
+
+/// `@keyframes spin { from { ... } 50% { ... } to { ... } }`
+#[derive(Debug, PartialEq)]
+pub struct KeyframesRule {
+    pub name: String,
+    pub keyframes: Vec<Keyframe>,
+}
+
+/// One block of a @keyframes rule. `from` is the offset 0 and `to` is 1, and a
+/// block can be at several offsets, like `0%, 100% { ... }`.
+#[derive(Debug, PartialEq)]
+pub struct Keyframe {
+    pub offsets: Vec<f32>,
+    pub declarations: Vec<Declaration>,
+}
+
+/// `not screen and (min-width: 600px)`. No media type means all media.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MediaQuery {
+    pub negated: bool,
+    pub media_type: Option<String>,
+    pub features: Vec<MediaFeature>,
+}
+
+/// `(max-width: 40em)`, or just `(color)` without a value
+#[derive(Debug, PartialEq, Clone)]
+pub struct MediaFeature {
+    pub name: String,
+    pub value: Option<Value>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SupportsCondition {
+    Declaration(Declaration),
+    Not(Box<SupportsCondition>),
+    And(Box<SupportsCondition>, Box<SupportsCondition>),
+    Or(Box<SupportsCondition>, Box<SupportsCondition>),
+}
 
 #[derive(Debug, PartialEq)]
 pub enum Selector {
     Simple(SimpleSelector),
     Ancestor(AncestorSelector),
+    Sibling(SiblingSelector),
 }
 #[derive(Debug, PartialEq)]
 pub struct SimpleSelector {
@@ -48,6 +112,7 @@ pub struct SimpleSelector {
     pub id: Option<String>,
     pub class: Vec<String>,
     pub pseudo_class: Vec<String>,
+    pub pseudo_element: Option<String>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -56,10 +121,19 @@ pub struct AncestorSelector {
     pub child: Box<Selector>,
     pub immediate: bool,
 }
+/// `h1 + p` when `adjacent`, otherwise `h1 ~ p`
+#[derive(Debug, PartialEq)]
+pub struct SiblingSelector {
+    pub previous: Box<Selector>,
+    pub next: Box<Selector>,
+    pub adjacent: bool,
+}
 #[derive(Debug, PartialEq, Clone)]
 pub struct Declaration {
     pub(crate) name: String,
     pub(crate) value: Value,
+    /// marked !important, which puts it in the cascade above the normal declarations
+    pub(crate) important: bool,
 }
 #[derive(Debug, PartialEq, Clone)]
 pub enum Value {
@@ -73,6 +147,49 @@ pub enum Value {
     UnicodeCodepoint(i32),
     UnicodeRange(i32,i32),
     Number(f32),
+    /// raw text of a value the parser doesn't understand, kept so it isn't lost
+    Unparsed(String),
+    /// `calc(100% - 40px)`
+    Calc(CalcExpr),
+    /// a duration like `200ms` or `1.5s`, in seconds
+    Time(f32),
+    /// an angle like `45deg`, `0.25turn` or `1rad`, in degrees
+    Angle(f32),
+}
+
+/// The expression inside a `calc()`. Percentages and ems can only be turned into pixels
+/// once layout knows what they are relative to.
+#[derive(Debug, PartialEq, Clone)]
+pub enum CalcExpr {
+    Length(f32, Unit),
+    Number(f32),
+    Sum(Box<CalcExpr>, Box<CalcExpr>),
+    Difference(Box<CalcExpr>, Box<CalcExpr>),
+    Product(Box<CalcExpr>, Box<CalcExpr>),
+    Quotient(Box<CalcExpr>, Box<CalcExpr>),
+}
+
+impl CalcExpr {
+    /// Evaluate to pixels, with `percent_of` as the length that 100% means.
+    pub fn to_px(&self, font_size:f32, percent_of:f32) -> f32 {
+        match self {
+            CalcExpr::Length(v, Unit::Px) => *v,
+            // the computed values have already made rems px, so one is only left outside a style tree
+            CalcExpr::Length(v, Unit::Em) | CalcExpr::Length(v, Unit::Rem) => v * font_size,
+            CalcExpr::Length(v, Unit::Per) => percent_of * v / 100.0,
+            // without the font, both are taken as half an em
+            CalcExpr::Length(v, Unit::Ch) | CalcExpr::Length(v, Unit::Ex) => v * font_size / 2.0,
+            CalcExpr::Length(v, unit) => v * unit.px_per_unit().unwrap_or(0.0),
+            CalcExpr::Number(v) => *v,
+            CalcExpr::Sum(a, b) => a.to_px(font_size, percent_of) + b.to_px(font_size, percent_of),
+            CalcExpr::Difference(a, b) => a.to_px(font_size, percent_of) - b.to_px(font_size, percent_of),
+            CalcExpr::Product(a, b) => a.to_px(font_size, percent_of) * b.to_px(font_size, percent_of),
+            CalcExpr::Quotient(a, b) => {
+                let divisor = b.to_px(font_size, percent_of);
+                if divisor == 0.0 { 0.0 } else { a.to_px(font_size, percent_of) / divisor }
+            }
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -81,6 +198,32 @@ pub enum Unit {
     Em,
     Per,
     Rem,
+    Pt,
+    Pc,
+    In,
+    Cm,
+    Mm,
+    /// the width of the font's `0`
+    Ch,
+    /// the height of the font's `x`
+    Ex,
+    /// a share of the space left over in a grid container
+    Fr,
+}
+
+impl Unit {
+    /// Pixels per unit for the absolute units, at 96 pixels to the inch.
+    pub fn px_per_unit(&self) -> Option<f32> {
+        match self {
+            Unit::Px => Some(1.0),
+            Unit::Pt => Some(96.0 / 72.0),
+            Unit::Pc => Some(96.0 / 6.0),
+            Unit::In => Some(96.0),
+            Unit::Cm => Some(96.0 / 2.54),
+            Unit::Mm => Some(96.0 / 25.4),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -91,21 +234,62 @@ pub struct Color {
     pub a:u8,
 }
 impl Color {
+    /// accepts `#RGB`, `#RGBA`, `#RRGGBB` and `#RRGGBBAA`
     pub fn from_hex(str:&str) -> Self {
-        let n = i32::from_str_radix(&str[1..], 16).unwrap();
-        let r = (n >> 16) & 0xFF;
-        let g = (n >> 8) & 0xFF;
-        let b = (n/*>>0*/) & 0xFF;
+        let digits = &str[1..];
+        let channel = |i:usize, len:usize| {
+            let v = u8::from_str_radix(&digits[i*len..(i+1)*len], 16).unwrap();
+            if len == 1 { v * 17 } else { v }
+        };
+        let len = if digits.len() > 4 { 2 } else { 1 };
+        Self {
+            r: channel(0, len),
+            g: channel(1, len),
+            b: channel(2, len),
+            a: if digits.len() == 4 || digits.len() == 8 { channel(3, len) } else { 255 },
+        }
+    }
+    /// hue in degrees, whiteness, blackness and alpha from 0 to 1
+    pub fn from_hwb(hue:f32, white:f32, black:f32, alpha:f32) -> Self {
+        let (white, black) = if white + black >= 1.0 {
+            (white / (white + black), black / (white + black))
+        } else {
+            (white, black)
+        };
+        let channel = |n:f32| {
+            let k = (n + hue.rem_euclid(360.0) / 30.0) % 12.0;
+            let pure = 0.5 - 0.5 * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0);
+            ((pure * (1.0 - white - black) + white) * 255.0).round() as u8
+        };
+        Self {
+            r: channel(0.0),
+            g: channel(8.0),
+            b: channel(4.0),
+            a: (alpha * 255.0).round() as u8,
+        }
+    }
+    /// hue in degrees, saturation, lightness and alpha from 0 to 1
+    pub fn from_hsl(hue:f32, sat:f32, light:f32, alpha:f32) -> Self {
+        let (sat, light) = (sat.clamp(0.0, 1.0), light.clamp(0.0, 1.0));
+        let a = sat * light.min(1.0 - light);
+        let channel = |n:f32| {
+            let k = (n + hue.rem_euclid(360.0) / 30.0) % 12.0;
+            ((light - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)) * 255.0).round() as u8
+        };
         Self {
-            r: r as u8,
-            g: g as u8,
-            b: b as u8,
-            a: 255
+            r: channel(0.0),
+            g: channel(8.0),
+            b: channel(4.0),
+            a: (alpha.clamp(0.0, 1.0) * 255.0).round() as u8,
         }
     }
     pub fn to_array(&self) -> [f32;4]{
         [(self.r as f32)/255.0, (self.g as f32)/255.0, (self.b as f32)/255.0, (self.a as f32)/255.0]
     }
+    /// the same color with its alpha scaled by an opacity from 0 to 1
+    pub fn with_opacity(&self, opacity:f32) -> Color {
+        Color { a: (self.a as f32 * opacity.clamp(0.0, 1.0)).round() as u8, ..self.clone() }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -120,12 +304,19 @@ impl Selector {
     pub fn specificity(&self) -> Specificity {
         if let Selector::Simple(ref simple) = *self {
             let a = simple.id.iter().count();
-            let b = simple.class.len();
-            let c = simple.tag_name.iter().count();
+            let b = simple.class.len() + simple.pseudo_class.len();
+            let c = simple.tag_name.iter().count() + simple.pseudo_element.iter().count();
             return (a, b, c)
         }
         if let Selector::Ancestor(ref anc) = *self {
-            return anc.ancestor.specificity();
+            let (a1, b1, c1) = anc.ancestor.specificity();
+            let (a2, b2, c2) = anc.child.specificity();
+            return (a1 + a2, b1 + b2, c1 + c2);
+        }
+        if let Selector::Sibling(ref sib) = *self {
+            let (a1, b1, c1) = sib.previous.specificity();
+            let (a2, b2, c2) = sib.next.specificity();
+            return (a1 + a2, b1 + b2, c1 + c2);
         }
         panic!("unknown selector type");
     }
@@ -156,10 +347,14 @@ fn string<'a>() -> Parser<'a, u8, String> {
     string.convert(String::from_utf8)
 }
 fn single_quote_string<'a>() -> Parser<'a, u8, String> {
-    (space() * sym(b'\'') * none_of(b"'").repeat(0..) - sym(b'\'')).map(|v|v2s(&v))
+    (space() * sym(b'\'') * (string_escape() | none_of(b"'")).repeat(0..) - sym(b'\'')).map(|v|v2s(&v))
 }
 fn double_quote_string<'a>() -> Parser<'a, u8, String> {
-    (space() * sym(b'\"') * none_of(b"\"").repeat(0..) - sym(b'\"')).map(|v|v2s(&v))
+    (space() * sym(b'\"') * (string_escape() | none_of(b"\"")).repeat(0..) - sym(b'\"')).map(|v|v2s(&v))
+}
+// a quote or backslash escaped inside a string. other escapes are kept as written.
+fn string_escape<'a>() -> Parser<'a, u8, u8> {
+    sym(b'\\') * one_of(b"\"'\\")
 }
 
 fn v2s(v:&[u8]) -> String {
@@ -193,9 +388,9 @@ fn id_string<'a>() -> Parser<'a,u8,String> {
     (sym(b'#') + is_a(alphanum).repeat(1..)).map(|(_,str)| v2s(&str))
 }
 fn pseudo_class_call<'a>() -> Parser<'a,u8, String> {
-    //:string(stuff)
+    //:string(stuff), where stuff can be a selector list like :not(.a, .b)
     (sym(b'(')
-        * call(simple_selector)
+        * list(call(selector), sym(b','))
     - sym(b')')).map(|a|String::from("some-call"))
 }
 fn pseudo_class_string<'a>() -> Parser<'a,u8,String> {
@@ -204,8 +399,12 @@ fn pseudo_class_string<'a>() -> Parser<'a,u8,String> {
         + pseudo_class_call().opt()
     ).map(|(str,call)| v2s(&str))
 }
+fn pseudo_element_string<'a>() -> Parser<'a,u8,String> {
+    (seq(b"::") * is_a(alphanumdash).repeat(1..)).map(|str| v2s(&str))
+}
 fn child_combinator<'a>() -> Parser<'a, u8, AncestorSelector> {
-    let r = simple_selector() - space1() - sym(b'>') - space1() + call(selector);
+    // ul > li, with or without the spaces
+    let r = simple_selector() - space() - sym(b'>') - space() + call(selector);
     r.map(|(a,b)| AncestorSelector{
         ancestor: Box::new(a),
         child: Box::new(b),
@@ -220,8 +419,19 @@ fn descendant_combinator<'a>() -> Parser<'a, u8, AncestorSelector> {
         immediate:false,
     })
 }
+fn sibling_combinator<'a>(symbol:u8, adjacent:bool) -> Parser<'a, u8, SiblingSelector> {
+    let r = simple_selector() - space() - sym(symbol) - space() + call(selector);
+    r.map(move |(a,b)| SiblingSelector{
+        previous: Box::new(a),
+        next: Box::new(b),
+        adjacent,
+    })
+}
 fn ancestor<'a>() -> Parser<'a,u8,Selector> {
-    (child_combinator() | descendant_combinator()).map(|c|Selector::Ancestor(c))
+    child_combinator().map(Selector::Ancestor)
+        | sibling_combinator(b'+', true).map(Selector::Sibling)
+        | sibling_combinator(b'~', false).map(Selector::Sibling)
+        | descendant_combinator().map(Selector::Ancestor)
 }
 
 
@@ -233,37 +443,52 @@ fn string_literal<'a>() -> Parser<'a, u8, Value> {
 fn test_string_literal() {
     assert_eq!(string_literal().parse(br#""foo""#),
                Ok(Value::StringLiteral(String::from("foo"))));
+    assert_eq!(string_literal().parse(br#""say \"hi\" \\ \201C""#),
+               Ok(Value::StringLiteral(String::from(r#"say "hi" \ \201C"#))));
+    assert_eq!(string_literal().parse(br#"'it\'s'"#),
+               Ok(Value::StringLiteral(String::from("it's"))));
 }
 
 fn simple_selector<'a>() -> Parser<'a, u8, Selector> {
+    // ids and classes can come in any order and number, like div.card.featured#main
+    let id_or_class = id_string().map(|id| (b'#', id)) | class_string().map(|class| (b'.', class));
     let p = (
         element_name_string().opt()
-        + id_string().opt()
-        + class_string().opt()
-        + pseudo_class_string().opt()
+        + id_or_class.repeat(0..)
+        + pseudo_class_string().repeat(0..)
+        + pseudo_element_string().opt()
         );
-    p.convert(|(((a,i),c),b)| {
+    p.convert(|(((a,parts),b),pe)| {
         // println!("simple selectors {:#?} {:#?} {:#?} {:#?}",a,i,c,b);
-        if a.is_none() && i.is_none() && c.is_none() && b.is_none() {
+        if a.is_none() && parts.is_empty() && b.is_empty() && pe.is_none() {
             return Result::Err("warning, nothing matched")
         }
         let mut sel = SimpleSelector{
             tag_name: None,
             id: None,
             class: vec![],
-            pseudo_class: vec![]
+            pseudo_class: vec![],
+            pseudo_element: pe,
         };
         if let Some(element_name) = a {
             sel.tag_name = Some(element_name);
         }
-        if let Some(id_name) = i {
-            sel.id = Some(id_name);
-        }
-        if let Some(class_string) = c {
-            sel.class.push(class_string)
+        for (kind, name) in parts {
+            if kind == b'.' {
+                sel.class.push(name);
+            } else if sel.id.as_ref().is_some_and(|id| *id != name) {
+                // an element has only one id, so #a#b can never match
+                return Result::Err("two different ids");
+            } else {
+                sel.id = Some(name);
+            }
         }
-        if let Some(pseudo_class_string) = b {
-            sel.pseudo_class.push(pseudo_class_string);
+        sel.pseudo_class = b;
+        // the old single colon :before and :after are pseudo-elements too
+        if let Some(pos) = sel.pseudo_class.iter().position(|pc| pc == "before" || pc == "after") {
+            if sel.pseudo_element.is_none() {
+                sel.pseudo_element = Some(sel.pseudo_class.remove(pos));
+            }
         }
         Result::Ok(Selector::Simple(sel))
     }
@@ -285,28 +510,32 @@ fn test_selectors() {
                    tag_name: Some("div".to_string()),
                    id: None,
                    class: vec![],
-                   pseudo_class: vec![]
+                   pseudo_class: vec![],
+                   pseudo_element: None
                })));
     assert_eq!(selector().parse(b"h3"),
                Ok(Selector::Simple(SimpleSelector {
                    tag_name: Some("h3".to_string()),
                    id: None,
                    class: vec![],
-                   pseudo_class: vec![]
+                   pseudo_class: vec![],
+                   pseudo_element: None
                })));
     assert_eq!(selector().parse(b".cool"),
                Ok(Selector::Simple(SimpleSelector {
                    tag_name: None,
                    id: None,
                    class: vec![String::from("cool")],
-                   pseudo_class: vec![]
+                   pseudo_class: vec![],
+                   pseudo_element: None
                })));
     assert_eq!(selector().parse(b"div.cool"),
                Ok(Selector::Simple(SimpleSelector {
                    tag_name: Some(String::from("div")),
                    id: None,
                    class: vec![String::from("cool")],
-                   pseudo_class: vec![]
+                   pseudo_class: vec![],
+                   pseudo_element: None
                })));
 }
 #[test]
@@ -317,13 +546,15 @@ fn test_ancestor_selector() {
                        tag_name:Some(String::from("a")),
                        id: None,
                        class: vec![],
-                       pseudo_class: vec![]
+                       pseudo_class: vec![],
+                       pseudo_element: None
                    })),
                    child:Box::new(Selector::Simple(SimpleSelector{
                        tag_name:Some(String::from("b")),
                        id: None,
                        class: vec![],
-                       pseudo_class: vec![]
+                       pseudo_class: vec![],
+                       pseudo_element: None
                    })),
                    immediate:false,
                })));
@@ -333,13 +564,15 @@ fn test_ancestor_selector() {
                        tag_name:Some(String::from("a")),
                        id: None,
                        class: vec![],
-                       pseudo_class: vec![]
+                       pseudo_class: vec![],
+                       pseudo_element: None
                    })),
                    child:Box::new(Selector::Simple(SimpleSelector{
                        tag_name:Some(String::from("b")),
                        id: None,
                        class: vec![],
-                       pseudo_class: vec![]
+                       pseudo_class: vec![],
+                       pseudo_element: None
                    })),
                    immediate:true,
                })));
@@ -349,36 +582,60 @@ fn test_ancestor_selector() {
                        tag_name:Some(String::from("div")),
                        id: None,
                        class: vec![String::from("epigraph")],
-                       pseudo_class: vec![]
+                       pseudo_class: vec![],
+                       pseudo_element: None
                    })),
                    child:Box::new(Selector::Simple(SimpleSelector{
                        tag_name:Some(String::from("blockquote")),
                        id: None,
                        class: vec![],
-                       pseudo_class: vec![]
+                       pseudo_class: vec![],
+                       pseudo_element: None
                    })),
                    immediate:true,
                })));
+    assert_eq!(selector().parse(b"a>b"), selector().parse(b"a > b"));
+    let simple = |name:&str| Box::new(Selector::Simple(SimpleSelector{
+        tag_name: Some(String::from(name)),
+        id: None,
+        class: vec![],
+        pseudo_class: vec![],
+        pseudo_element: None
+    }));
+    assert_eq!(selector().parse(b"h1 + p"),
+               Ok(Selector::Sibling(SiblingSelector{ previous: simple("h1"), next: simple("p"), adjacent: true })));
+    assert_eq!(selector().parse(b"h1~p"),
+               Ok(Selector::Sibling(SiblingSelector{ previous: simple("h1"), next: simple("p"), adjacent: false })));
+    assert_eq!(selector().parse(b"div h1 + p"),
+               Ok(Selector::Ancestor(AncestorSelector{
+                   ancestor: simple("div"),
+                   child: Box::new(Selector::Sibling(SiblingSelector{ previous: simple("h1"), next: simple("p"), adjacent: true })),
+                   immediate: false,
+               })));
+    assert_eq!(selector().parse(b"a>b c"), selector().parse(b"a > b c"));
     assert_eq!(selector().parse(b"a > b > c"),
                Ok(Selector::Ancestor(AncestorSelector{
                    ancestor:Box::new(Selector::Simple(SimpleSelector{
                        tag_name:Some(String::from("a")),
                        id: None,
                        class: vec![],
-                       pseudo_class: vec![]
+                       pseudo_class: vec![],
+                       pseudo_element: None
                    })),
                    child:Box::new(Selector::Ancestor(AncestorSelector{
                        ancestor: Box::new(Selector::Simple(SimpleSelector{
                            tag_name:Some(String::from("b")),
                            id: None,
                            class: vec![],
-                           pseudo_class: vec![]
+                           pseudo_class: vec![],
+                           pseudo_element: None
                        })),
                        child: Box::new(Selector::Simple(SimpleSelector{
                            tag_name:Some(String::from("c")),
                            id: None,
                            class: vec![],
-                           pseudo_class: vec![]
+                           pseudo_class: vec![],
+                           pseudo_element: None
                        })),
                        immediate: true
                    })),
@@ -390,20 +647,23 @@ fn test_ancestor_selector() {
                        tag_name:Some(String::from("a")),
                        id: None,
                        class: vec![],
-                       pseudo_class: vec![]
+                       pseudo_class: vec![],
+                       pseudo_element: None
                    })),
                    child:Box::new(Selector::Ancestor(AncestorSelector{
                        ancestor: Box::new(Selector::Simple(SimpleSelector{
                            tag_name:Some(String::from("b")),
                            id: None,
                            class: vec![],
-                           pseudo_class: vec![]
+                           pseudo_class: vec![],
+                           pseudo_element: None
                        })),
                        child: Box::new(Selector::Simple(SimpleSelector{
                            tag_name:Some(String::from("c")),
                            id: None,
                            class: vec![],
-                           pseudo_class: vec![]
+                           pseudo_class: vec![],
+                           pseudo_element: None
                        })),
                        immediate: false
                    })),
@@ -420,17 +680,37 @@ fn test_all_selector() {
         tag_name:Some("*".to_string()),
         id: None,
         class: vec![],
-        pseudo_class: vec![]
+        pseudo_class: vec![],
+        pseudo_element: None
     }), result.unwrap())
 }
 
+#[test]
+fn test_pseudo_element_selector() {
+    assert_eq!(selector().parse(b"::selection"), Ok(Selector::Simple(SimpleSelector {
+        tag_name: None,
+        id: None,
+        class: vec![],
+        pseudo_class: vec![],
+        pseudo_element: Some(String::from("selection"))
+    })));
+    assert_eq!(selector().parse(b"p.note::selection"), Ok(Selector::Simple(SimpleSelector {
+        tag_name: Some(String::from("p")),
+        id: None,
+        class: vec![String::from("note")],
+        pseudo_class: vec![],
+        pseudo_element: Some(String::from("selection"))
+    })));
+}
+
 #[test]
 fn test_pseudo_selector() {
     assert_eq!(selector().parse(b":link"), Ok(Selector::Simple(SimpleSelector {
         tag_name: None,
         id: None,
         class: vec![],
-        pseudo_class: vec![String::from("link")]
+        pseudo_class: vec![String::from("link")],
+        pseudo_element: None
     })));
 
     assert_eq!(rule().parse(b"a:link, a:visited { }"),
@@ -440,13 +720,15 @@ fn test_pseudo_selector() {
                            tag_name: Some(String::from("a")),
                            id: None,
                            class: vec![],
-                           pseudo_class: vec![String::from("link")]
+                           pseudo_class: vec![String::from("link")],
+                           pseudo_element: None
                        }),
                        Selector::Simple(SimpleSelector{
                            tag_name: Some(String::from("a")),
                            id: None,
                            class: vec![],
-                           pseudo_class: vec![String::from("visited")]
+                           pseudo_class: vec![String::from("visited")],
+                           pseudo_element: None
                        })
                    ],
                    declarations: vec![]
@@ -457,7 +739,8 @@ fn test_pseudo_selector() {
         tag_name: None,
         id: None,
         class: vec![String::from("no-tufte-underline")],
-        pseudo_class: vec![String::from("link")]
+        pseudo_class: vec![String::from("link")],
+        pseudo_element: None
     })));
 
     assert_eq!(rule().parse(b"li:not(:first-child), b { }"),Ok(RuleType::Rule(Rule{
@@ -466,13 +749,15 @@ fn test_pseudo_selector() {
                 tag_name: Some(String::from("li")),
                 id: None,
                 class: vec![],
-                pseudo_class: vec![String::from("not")]
+                pseudo_class: vec![String::from("not")],
+                pseudo_element: None
             }),
             Selector::Simple(SimpleSelector {
                 tag_name: Some(String::from("b")),
                 id: None,
                 class: vec![],
-                pseudo_class: vec![]
+                pseudo_class: vec![],
+                pseudo_element: None
             }),
         ],
         declarations: vec![]
@@ -513,13 +798,28 @@ fn unit_rem<'a>() -> Parser<'a, u8, Unit> {
 fn unit_em<'a>() -> Parser<'a, u8, Unit> {
     seq(b"em").map(|_| Unit::Em)
 }
+fn unit_other<'a>() -> Parser<'a, u8, Unit> {
+    seq(b"pt").map(|_| Unit::Pt)
+        | seq(b"pc").map(|_| Unit::Pc)
+        | seq(b"in").map(|_| Unit::In)
+        | seq(b"cm").map(|_| Unit::Cm)
+        | seq(b"mm").map(|_| Unit::Mm)
+        | seq(b"ch").map(|_| Unit::Ch)
+        | seq(b"ex").map(|_| Unit::Ex)
+        | seq(b"fr").map(|_| Unit::Fr)
+}
 fn unit<'a>() -> Parser<'a, u8, Unit> {
-    unit_per() | unit_px() | unit_rem() | unit_em()
+    unit_per() | unit_px() | unit_rem() | unit_em() | unit_other()
 }
 #[test]
 fn test_unit() {
     assert_eq!(unit().parse(b"px"),Ok(Unit::Px));
     assert_eq!(unit().parse(b"em"),Ok(Unit::Em));
+    assert_eq!(unit().parse(b"ex"),Ok(Unit::Ex));
+    assert_eq!(length_unit().parse(b"12pt"),Ok(Length(12.0,Unit::Pt)));
+    assert_eq!(length_unit().parse(b"2.54cm"),Ok(Length(2.54,Unit::Cm)));
+    assert_eq!(Unit::Pt.px_per_unit().map(|px| 12.0 * px), Some(16.0));
+    assert_eq!(Unit::Ch.px_per_unit(), None);
 }
 
 fn length_unit<'a>() -> Parser<'a, u8, Value> {
@@ -538,7 +838,40 @@ fn test_length_units() {
 }
 
 fn funarg<'a>() -> Parser<'a, u8, Value> {
-    string_literal() | hexcolor() | length_unit() | keyword()
+    string_literal() | hexcolor() | angle_value() | length_unit() | keyword() | simple_number()
+}
+
+fn time_value<'a>() -> Parser<'a, u8, Value> {
+    let unit = (seq(b"ms").map(|_| 0.001) | seq(b"s").map(|_| 1.0)) - !is_a(alphanum);
+    (number() + unit).map(|(v, scale)| Value::Time((v * scale) as f32))
+}
+
+fn angle_value<'a>() -> Parser<'a, u8, Value> {
+    let unit = (seq(b"deg").map(|_| 1.0) | seq(b"grad").map(|_| 0.9)
+        | seq(b"rad").map(|_| 180.0 / std::f64::consts::PI) | seq(b"turn").map(|_| 360.0)) - !is_a(alphanum);
+    (number() + unit).map(|(v, scale)| Value::Angle((v * scale) as f32))
+}
+
+#[test]
+fn test_angle_value() {
+    assert_eq!(one_value().parse(b"45deg"), Ok(Value::Angle(45.0)));
+    assert_eq!(one_value().parse(b"-0.5turn"), Ok(Value::Angle(-180.0)));
+    assert_eq!(one_value().parse(b"100grad"), Ok(Value::Angle(90.0)));
+    assert_eq!(funcall().parse(b"rotate(3.14159265rad)"), Ok(Value::FunCall(FunCallValue {
+        name: String::from("rotate"),
+        arguments: vec![Value::Angle(180.0)],
+    })));
+}
+
+#[test]
+fn test_time_value() {
+    assert_eq!(one_value().parse(b"1.5s"), Ok(Value::Time(1.5)));
+    assert_eq!(one_value().parse(b"200ms"), Ok(Value::Time(0.2)));
+    assert_eq!(one_value().parse(b"2mm"), Ok(Length(2.0, Unit::Mm)));
+    assert_eq!(one_value().parse(b"cubic-bezier(0.1, 0.7, 1, 0.1)"), Ok(Value::FunCall(FunCallValue {
+        name: String::from("cubic-bezier"),
+        arguments: vec![Value::Number(0.1), Value::Number(0.7), Value::Number(1.0), Value::Number(0.1)],
+    })));
 }
 
 fn normal_funcall<'a>() -> Parser<'a, u8, Value> {
@@ -578,15 +911,70 @@ fn url_funcall<'a>() -> Parser<'a, u8, Value> {
         - space()
         + format_follower().opt()
         ;
-    p.map(|((_a,url),_format)| Value::FunCall(FunCallValue{
+    // a format() hint is kept as a second argument
+    p.map(|((_a,url),format)| Value::FunCall(FunCallValue{
         name: "url".to_string(),
-        arguments: vec![url]
+        arguments: vec![Some(url), format].into_iter().flatten().collect()
+    }))
+
+}
+
+// a number, a length, or a nested calc() or parenthesized expression
+fn calc_term<'a>() -> Parser<'a, u8, CalcExpr> {
+    let nested = seq(b"calc").opt() * sym(b'(') * space() * call(calc_sum) - space() - sym(b')');
+    (number() + unit()).map(|(v, unit)| CalcExpr::Length(v as f32, unit))
+        | number().map(|v| CalcExpr::Number(v as f32))
+        | nested
+}
+
+fn calc_product<'a>() -> Parser<'a, u8, CalcExpr> {
+    let p = calc_term() + (space() * one_of(b"*/") - space() + calc_term()).repeat(0..);
+    p.map(|(first, rest)| rest.into_iter().fold(first, |a, (op, b)| match op {
+        b'*' => CalcExpr::Product(Box::new(a), Box::new(b)),
+        _ => CalcExpr::Quotient(Box::new(a), Box::new(b)),
     }))
+}
 
+// + and - must have whitespace around them, so `100% -40px` is not a subtraction
+fn calc_sum<'a>() -> Parser<'a, u8, CalcExpr> {
+    let p = calc_product() + (space1() * one_of(b"+-") - space1() + calc_product()).repeat(0..);
+    p.map(|(first, rest)| rest.into_iter().fold(first, |a, (op, b)| match op {
+        b'+' => CalcExpr::Sum(Box::new(a), Box::new(b)),
+        _ => CalcExpr::Difference(Box::new(a), Box::new(b)),
+    }))
+}
+
+fn calc_funcall<'a>() -> Parser<'a, u8, Value> {
+    let p = space() * seq(b"calc") * space() * sym(b'(') * space() * calc_sum() - space() - sym(b')');
+    p.map(Value::Calc)
+}
+
+#[test]
+fn test_calc_value() {
+    use CalcExpr::*;
+    let px = |v| Box::new(CalcExpr::Length(v, Unit::Px));
+    assert_eq!(value().parse(b"calc(100% - 40px)"), Ok(Value::Calc(
+        Difference(Box::new(CalcExpr::Length(100.0, Unit::Per)), px(40.0)))));
+    // * and / bind tighter than + and -
+    assert_eq!(value().parse(b"calc(10px + 2px * 3)"), Ok(Value::Calc(
+        Sum(px(10.0), Box::new(Product(px(2.0), Box::new(Number(3.0))))))));
+    assert_eq!(value().parse(b"calc((1em + 10px) / 2)"), Ok(Value::Calc(
+        Quotient(Box::new(Sum(Box::new(CalcExpr::Length(1.0, Unit::Em)), px(10.0))), Box::new(Number(2.0))))));
+    assert_eq!(declaration().parse(b"width: calc(50% - calc(2 * 1em));").map(|d| d.value.clone()),
+               Ok(Value::Calc(Difference(Box::new(CalcExpr::Length(50.0, Unit::Per)),
+                   Box::new(Product(Box::new(Number(2.0)), Box::new(CalcExpr::Length(1.0, Unit::Em))))))));
+    // without spaces the minus belongs to the number
+    assert!(calc_funcall().parse(b"calc(100% -40px)").is_err());
+
+    let expr = match value().parse(b"calc(50% - 2em + 4px / 2)") {
+        Ok(Value::Calc(expr)) => expr,
+        other => panic!("not a calc value {:#?}", other),
+    };
+    assert_eq!(expr.to_px(10.0, 400.0), 200.0 - 20.0 + 2.0);
 }
 
 fn funcall<'a>() -> Parser<'a, u8, Value> {
-    url_funcall() | normal_funcall()
+    url_funcall() | calc_funcall() | rgb_funcall() | hsl_funcall() | hwb_funcall() | normal_funcall()
 }
 
 #[test]
@@ -634,7 +1022,8 @@ fn test_funcall_value() {
                            Value::HexColor(String::from("#fffff8")),
                            Value::HexColor(String::from("#fffff8")),
                        ],
-                   })
+                   }),
+                   important: false,
                }
                ));
     //check url with double quotes
@@ -646,7 +1035,8 @@ fn test_funcall_value() {
                        arguments: vec![
                            Value::StringLiteral(String::from("https://www.google.com/")),
                        ],
-                   })
+                   }),
+                   important: false,
                }
                ));
     //check url with single quotes
@@ -658,7 +1048,8 @@ fn test_funcall_value() {
                        arguments: vec![
                            Value::StringLiteral(String::from("https://www.google.com/")),
                        ],
-                   })
+                   }),
+                   important: false,
                }
                ));
     //check url with no quotes
@@ -670,7 +1061,8 @@ fn test_funcall_value() {
                        arguments: vec![
                            Value::StringLiteral(String::from("https://www.google.com/")),
                        ],
-                   })
+                   }),
+                   important: false,
                }
                ));
 }
@@ -681,7 +1073,9 @@ fn simple_number<'a>() -> Parser<'a, u8, Value> {
 }
 fn hexcolor<'a>() -> Parser<'a, u8, Value> {
     let p = sym(b'#')
-        + (  one_of(b"0123456789ABCDEFabcdef").repeat(6..7)
+        + (  one_of(b"0123456789ABCDEFabcdef").repeat(8..9)
+            | one_of(b"0123456789ABCDEFabcdef").repeat(6..7)
+            | one_of(b"0123456789ABCDEFabcdef").repeat(4..5)
             | one_of(b"0123456789ABCDEFabcdef").repeat(3..4));
     p.map(|(a,mut c)| {
         c.insert(0,b'#');
@@ -696,17 +1090,124 @@ fn test_hexcolor() {
     println!("{:?}", result);
     assert_eq!( Value::HexColor("#4455FF".to_lowercase()), result.unwrap());
     assert_eq!( Ok(Value::HexColor("#333".to_lowercase())), hexcolor().parse(br"#333"));
+    assert_eq!( Ok(Value::HexColor(String::from("#11223344"))), hexcolor().parse(br"#11223344"));
+    assert_eq!( Ok(Value::HexColor(String::from("#1234"))), hexcolor().parse(br"#1234"));
+}
+
+#[test]
+fn test_hex_alpha() {
+    assert_eq!(Color::from_hex("#4455ff"), Color { r: 0x44, g: 0x55, b: 0xff, a: 255 });
+    assert_eq!(Color::from_hex("#333"), Color { r: 0x33, g: 0x33, b: 0x33, a: 255 });
+    assert_eq!(Color::from_hex("#4455ff80"), Color { r: 0x44, g: 0x55, b: 0xff, a: 0x80 });
+    assert_eq!(Color::from_hex("#f008"), Color { r: 0xff, g: 0, b: 0, a: 0x88 });
+}
+
+// hwb(120 30% 20%), hwb(120deg 30% 20% / 0.5). commas are tolerated between the parts.
+// 0.5 or 50%, as 0 to 1
+fn color_alpha<'a>() -> Parser<'a, u8, f64> {
+    (number() + sym(b'%').opt()).map(|(v, per)| if per.is_some() { v / 100.0 } else { v })
+}
+
+fn hwb_funcall<'a>() -> Parser<'a, u8, Value> {
+    let sep = || space() - sym(b',').opt() - space();
+    let percent = || number() - sym(b'%');
+    let p
+        = space()
+        * seq(b"hwb")
+        * sym(b'(')
+        * space()
+        * number() - seq(b"deg").opt()
+        - sep() + percent()
+        - sep() + percent()
+        + (space() * sym(b'/') * space() * color_alpha()).opt()
+        - space()
+        - sym(b')');
+    p.map(|(((hue, white), black), alpha)| {
+        Value::ColorValue(Color::from_hwb(hue as f32, white as f32 / 100.0,
+                                          black as f32 / 100.0, alpha.unwrap_or(1.0) as f32))
+    })
+}
+
+#[test]
+fn test_hwb_color() {
+    assert_eq!(hwb_funcall().parse(b"hwb(0 0% 0%)"),
+               Ok(Value::ColorValue(Color { r: 255, g: 0, b: 0, a: 255 })));
+    assert_eq!(hwb_funcall().parse(b"hwb(120deg 20% 20% / 0.5)"),
+               Ok(Value::ColorValue(Color { r: 51, g: 204, b: 51, a: 128 })));
+    assert_eq!(hwb_funcall().parse(b"hwb(240, 60%, 60%)"),
+               Ok(Value::ColorValue(Color { r: 128, g: 128, b: 128, a: 255 })));
+}
+
+// rgb(255, 0, 0), rgba(255, 0, 0, 0.5) or rgb(100% 0% 0% / 50%). rgba is just another name for rgb.
+fn rgb_funcall<'a>() -> Parser<'a, u8, Value> {
+    let sep = || space() - sym(b',').opt() - space();
+    let channel = || (number() + sym(b'%').opt())
+        .map(|(v, per)| if per.is_some() { v * 2.55 } else { v });
+    let p
+        = space()
+        * (seq(b"rgba") | seq(b"rgb"))
+        * sym(b'(')
+        * space()
+        * channel()
+        - sep() + channel()
+        - sep() + channel()
+        + (space() * one_of(b",/") * space() * color_alpha()).opt()
+        - space()
+        - sym(b')');
+    p.map(|(((r, g), b), alpha)| {
+        let channel = |v:f64| v.round().clamp(0.0, 255.0) as u8;
+        Value::ColorValue(Color { r: channel(r), g: channel(g), b: channel(b),
+            a: (alpha.unwrap_or(1.0).clamp(0.0, 1.0) * 255.0).round() as u8 })
+    })
+}
+
+// hsl(120, 100%, 50%), hsla(120, 100%, 50%, 0.5) or hsl(120deg 100% 50% / 50%)
+fn hsl_funcall<'a>() -> Parser<'a, u8, Value> {
+    let sep = || space() - sym(b',').opt() - space();
+    let percent = || number() - sym(b'%');
+    let p
+        = space()
+        * (seq(b"hsla") | seq(b"hsl"))
+        * sym(b'(')
+        * space()
+        * number() - seq(b"deg").opt()
+        - sep() + percent()
+        - sep() + percent()
+        + (space() * one_of(b",/") * space() * color_alpha()).opt()
+        - space()
+        - sym(b')');
+    p.map(|(((hue, sat), light), alpha)| {
+        Value::ColorValue(Color::from_hsl(hue as f32, sat as f32 / 100.0,
+                                          light as f32 / 100.0, alpha.unwrap_or(1.0) as f32))
+    })
+}
+
+#[test]
+fn test_rgb_hsl_colors() {
+    let color = |r, g, b, a| Ok(Value::ColorValue(Color { r, g, b, a }));
+    assert_eq!(value().parse(b"rgb(255, 128, 0)"), color(255, 128, 0, 255));
+    assert_eq!(value().parse(b"rgba(0,0,255,0.5)"), color(0, 0, 255, 128));
+    assert_eq!(value().parse(b"rgb(100% 0% 0% / 25%)"), color(255, 0, 0, 64));
+    assert_eq!(value().parse(b"rgba(300, -5, 0, 2)"), color(255, 0, 0, 255));
+    assert_eq!(value().parse(b"hsl(120, 100%, 50%)"), color(0, 255, 0, 255));
+    assert_eq!(value().parse(b"hsla(240deg, 100%, 25%, 0.5)"), color(0, 0, 128, 128));
+    assert_eq!(value().parse(b"hsl(0 0% 50% / 0)"), color(128, 128, 128, 0));
+    assert_eq!(declaration().parse(b"background-color: rgba(0, 0, 0, 0.25);").map(|d| d.value.clone()),
+               color(0, 0, 0, 64));
 }
 
 
+// a keyword can start with dashes, but not a digit, so `-2` is left to be a number
 fn keyword<'a>() -> Parser<'a, u8, Value> {
     let r
         = space()
-        + (is_a(|term:u8| {
-            (term >= 0x41 && term < 0x5A) || (term >= 0x61 && term <= 0x7A) || (term == '-' as u8)
-            })).repeat(1..)
+        + sym(b'-').repeat(0..)
+        + is_a(|term:u8| term.is_ascii_alphabetic())
+        + is_a(alphanumdash).repeat(0..)
         ;
-    r.map(|(_,c)| {
+    r.map(|(((_,mut c),first),rest)| {
+        c.push(first);
+        c.extend(rest);
         Value::Keyword(String::from_utf8(c).unwrap())
     })
 }
@@ -717,6 +1218,11 @@ fn test_keyword() {
     println!("{:#?}",keyword().parse(input))
 }
 #[test]
+fn test_keyword_digits() {
+    assert_eq!(keyword().parse(b"A4"), Ok(Value::Keyword(String::from("A4"))));
+    assert_eq!(keyword().parse(b"h2"), Ok(Value::Keyword(String::from("h2"))));
+}
+#[test]
 fn test_keyword_dash() {
     let input = b"inline-block";
     let result = keyword().parse(input);
@@ -755,7 +1261,7 @@ fn test_unicode_range() {
 
 
 fn one_value<'a>() -> Parser<'a, u8, Value> {
-    unicode_range() | unicode_codepoint() | funcall() | hexcolor() | length_unit() | keyword() | string_literal() | simple_number()
+    unicode_range() | unicode_codepoint() | funcall() | hexcolor() | time_value() | angle_value() | length_unit() | keyword() | string_literal() | simple_number()
 }
 
 fn list_array_value<'a>() -> Parser<'a, u8, Value> {
@@ -844,7 +1350,69 @@ fn declaration<'a>() -> Parser<'a, u8, Declaration> {
         + value()
         - (space() - sym(b';') - space())
     ;
-    r.map(|(((), name), value)| Declaration { name, value })
+    r.map(|(((), name), value)| Declaration { name, value, important: false })
+}
+
+// any other `name: stuff;`, such as vendor extensions or properties we don't support yet.
+// the value is kept as raw text unless it turns out to be a regular value missing its semicolon.
+fn unparsed_declaration<'a>() -> Parser<'a, u8, Declaration> {
+    let r = space()
+        * identifier()
+        - (space() - sym(b':') - space())
+        + none_of(b";}").repeat(1..)
+        - sym(b';').opt()
+        - space()
+        ;
+    r.map(|(name, raw)| {
+        let raw = v2s(&raw).trim().to_string();
+        let parsed = (value() - space() - pom::parser::end()).parse(raw.as_bytes()).ok();
+        let value = parsed.unwrap_or(Value::Unparsed(raw));
+        Declaration { name, value, important: false }
+    })
+}
+
+#[test]
+fn test_unparsed_declaration() {
+    let input = br#"div {
+        -webkit-transition: opacity 0.3s ease-in-out, transform 0.2s;
+        filter: blur(2px) !important;
+        color: red
+    }"#;
+    assert_eq!(rule().parse(input), Ok(RuleType::Rule(Rule {
+        selectors: vec![Selector::Simple(SimpleSelector {
+            tag_name: Some(String::from("div")),
+            id: None,
+            class: vec![],
+            pseudo_class: vec![],
+            pseudo_element: None
+        })],
+        declarations: vec![
+            Declaration {
+                name: String::from("-webkit-transition"),
+                value: Value::Unparsed(String::from("opacity 0.3s ease-in-out, transform 0.2s")),
+                important: false,
+            },
+            Declaration {
+                name: String::from("filter"),
+                value: Value::FunCall(FunCallValue { name: String::from("blur"), arguments: vec![Length(2.0, Unit::Px)] }),
+                important: true,
+            },
+            Declaration {
+                name: String::from("color"),
+                value: Value::Keyword(String::from("red")),
+                important: false,
+            },
+        ]
+    })));
+}
+
+#[test]
+fn test_important_declarations() {
+    let declarations = parse_declarations("margin: 1px 2px ! IMPORTANT; font: bold 12px serif !important; color: red; width: important;").unwrap();
+    assert_eq!(declarations.iter().map(|dec| (dec.name.as_str(), dec.important)).collect::<Vec<_>>(),
+        vec![("margin", true), ("font", true), ("color", false), ("width", false)]);
+    assert_eq!(declarations[0].value, Value::ArrayValue(vec![Length(1.0, Unit::Px), Length(2.0, Unit::Px)]));
+    assert_eq!(declarations[1].to_string(), "font: bold 12px serif !important");
 }
 
 #[test]
@@ -869,7 +1437,8 @@ fn test_prop_def4() {
     println!("{:?}", result);
     assert_eq!(Declaration {
         name: "border-color".to_string(),
-        value: Value::HexColor("#ff00aa".to_lowercase())
+        value: Value::HexColor("#ff00aa".to_lowercase()),
+        important: false,
     },result.unwrap());
     println!("{:?}", declaration().parse(input))
 }
@@ -878,12 +1447,510 @@ fn ws_sym<'a>(ch:u8) -> Parser<'a, u8,u8> {
     space() * sym(ch) - space()
 }
 
+/// The longhands of the `font` shorthand, in the order its value array holds them.
+pub const FONT_LONGHANDS:[&str;6] = ["font-style", "font-variant", "font-weight", "font-size", "line-height", "font-family"];
+
+fn keyword_in<'a>(allowed:&'static [&'static str]) -> Parser<'a, u8, Value> {
+    keyword().convert(move |value| match &value {
+        Keyword(kw) if allowed.contains(&kw.as_str()) => Ok(value),
+        _ => Err(()),
+    })
+}
+
+// an unquoted family name can be several words
+fn font_family_name<'a>() -> Parser<'a, u8, Value> {
+    let words = list(keyword(), space1()).convert(|words| match words.len() {
+        0 => Err(()),
+        1 => Ok(words[0].clone()),
+        _ => Ok(StringLiteral(words.iter().filter_map(|word| match word {
+            Keyword(kw) => Some(kw.as_str()),
+            _ => None,
+        }).collect::<Vec<&str>>().join(" "))),
+    });
+    string_literal() | words
+}
+
+// `font: italic bold 16px/1.5 "Open Sans", sans-serif`. the value is an array with one value per
+// FONT_LONGHANDS entry, and the ones left out are `normal`, as the shorthand resets them.
+fn font_declaration<'a>() -> Parser<'a, u8, Declaration> {
+    let style = keyword_in(&["italic", "oblique"]);
+    let variant = keyword_in(&["small-caps"]);
+    let weight = keyword_in(&["bold", "bolder", "lighter"]) | (simple_number() - !unit());
+    let prefix = (keyword_in(&["normal"]).map(|_| (None, None, None))
+        | style.map(|v| (Some(v), None, None))
+        | variant.map(|v| (None, Some(v), None))
+        | weight.map(|v| (None, None, Some(v)))) - space1();
+    let size = length_unit()
+        | keyword_in(&["xx-small", "x-small", "small", "medium", "large", "x-large", "xx-large", "smaller", "larger"]);
+    let line_height = space() * sym(b'/') * space() * (length_unit() | simple_number() | keyword_in(&["normal"]));
+    let p
+        = space()
+        * seq(b"font")
+        * (space() - sym(b':') - space())
+        * prefix.repeat(0..4)
+        + size
+        + line_height.opt()
+        - space1()
+        + list(font_family_name(), ws_sym(b','))
+        - (space() - sym(b';') - space());
+    p.map(|(((prefixes, size), line_height), families)| {
+        let normal = || Keyword(String::from("normal"));
+        let pick = |i:usize| prefixes.iter().find_map(|p| [&p.0, &p.1, &p.2][i].clone()).unwrap_or_else(normal);
+        let family = if families.len() == 1 { families[0].clone() } else { Value::ArrayValue(families) };
+        Declaration {
+            name: String::from("font"),
+            value: Value::ArrayValue(vec![pick(0), pick(1), pick(2), size, line_height.unwrap_or_else(normal), family]),
+            important: false,
+        }
+    })
+}
+
+/// The longhands of the `background` shorthand, in the order its value array holds them.
+pub const BACKGROUND_LONGHANDS:[&str;5] = ["background-color", "background-image", "background-repeat",
+    "background-position", "background-size"];
+
+// sorts the parts of a background shorthand into its longhands, in any order. None is the `/`
+// between the position and the size. `background: none` and `background: red` work too.
+fn background_longhands(items:Vec<Option<Value>>) -> Option<Value> {
+    let (mut color, mut image) = (None, None);
+    let (mut repeat, mut position, mut size) = (vec![], vec![], vec![]);
+    let mut after_slash = false;
+    for item in items {
+        let value = match item {
+            Some(value) => value,
+            None if !position.is_empty() && !after_slash => { after_slash = true; continue; }
+            None => return None,
+        };
+        let kw = match &value {
+            Keyword(kw) => kw.to_lowercase(),
+            _ => String::new(),
+        };
+        let is_size = matches!(value, Length(_, _) | Value::Number(_)) || ["auto", "cover", "contain"].contains(&kw.as_str());
+        if after_slash && is_size {
+            size.push(value);
+            continue;
+        }
+        if after_slash && size.is_empty() {
+            return None;
+        }
+        match value {
+            _ if ["repeat", "repeat-x", "repeat-y", "no-repeat", "space", "round"].contains(&kw.as_str()) => repeat.push(value),
+            _ if ["left", "right", "top", "bottom", "center"].contains(&kw.as_str()) => position.push(value),
+            Length(_, _) | Value::Number(_) => position.push(value),
+            // attachment, origin and clip aren't supported
+            _ if ["scroll", "fixed", "local", "border-box", "padding-box", "content-box", "text"].contains(&kw.as_str()) => {}
+            Value::FunCall(ref call) if call.name == "url" || call.name.ends_with("gradient") => image = Some(value),
+            _ if kw == "none" => image = Some(value),
+            // css-wide keywords apply to every longhand, so they aren't a color
+            _ if ["inherit", "initial", "unset"].contains(&kw.as_str()) => return None,
+            Keyword(_) | Value::HexColor(_) | Value::ColorValue(_) if color.is_none() => color = Some(value),
+            _ => return None,
+        }
+    }
+    let one_or_array = |mut values:Vec<Value>, default:Value| match values.len() {
+        0 => default,
+        1 => values.remove(0),
+        _ => Value::ArrayValue(values),
+    };
+    Some(Value::ArrayValue(vec![
+        color.unwrap_or_else(|| Keyword(String::from("transparent"))),
+        image.unwrap_or_else(|| Keyword(String::from("none"))),
+        one_or_array(repeat, Keyword(String::from("repeat"))),
+        one_or_array(position, Value::ArrayValue(vec![Length(0.0, Unit::Per), Length(0.0, Unit::Per)])),
+        one_or_array(size, Keyword(String::from("auto"))),
+    ]))
+}
+
+// `background: #fff url(bg.png) no-repeat center / cover`, with one value per
+// BACKGROUND_LONGHANDS entry. layers separated by commas are left to the plain declaration.
+fn background_declaration<'a>() -> Parser<'a, u8, Declaration> {
+    let item = sym(b'/').map(|_| None) | one_value().map(Some);
+    let p
+        = space()
+        * seq(b"background")
+        * (space() - sym(b':') - space())
+        * list(item, space())
+        - (space() - sym(b';') - space());
+    p.convert(|items| match background_longhands(items) {
+        Some(value) => Ok(Declaration { name: String::from("background"), value, important: false }),
+        None => Err(()),
+    })
+}
+
+/// The longhands of the `border-radius` shorthand, clockwise from the top left.
+pub const BORDER_RADIUS_LONGHANDS:[&str;4] = ["border-top-left-radius", "border-top-right-radius",
+    "border-bottom-right-radius", "border-bottom-left-radius"];
+
+// one to four radii spread over the corners, the way margin values are spread over the sides
+fn corner_radii(radii:Vec<Value>) -> Result<Vec<Value>, ()> {
+    let pick = |i:usize| radii[i].clone();
+    match radii.len() {
+        1 => Ok(vec![pick(0), pick(0), pick(0), pick(0)]),
+        2 => Ok(vec![pick(0), pick(1), pick(0), pick(1)]),
+        3 => Ok(vec![pick(0), pick(1), pick(2), pick(1)]),
+        4 => Ok(radii),
+        _ => Err(()),
+    }
+}
+
+// `border-radius: 10px 5% / 20px`, with one value per BORDER_RADIUS_LONGHANDS entry. a corner
+// whose vertical radius differs is an array of both, like the longhand `10px 20px` parses to.
+fn border_radius_declaration<'a>() -> Parser<'a, u8, Declaration> {
+    let radii = || list(length_unit() | simple_number(), space1()).convert(corner_radii);
+    let p
+        = space()
+        * seq(b"border-radius")
+        * (space() - sym(b':') - space())
+        * radii()
+        + (space() * sym(b'/') * space() * radii()).opt()
+        - (space() - sym(b';') - space());
+    p.map(|(horizontal, vertical)| {
+        let corners = horizontal.into_iter().enumerate().map(|(i, h)| match &vertical {
+            Some(vertical) if vertical[i] != h => Value::ArrayValue(vec![h, vertical[i].clone()]),
+            _ => h,
+        }).collect();
+        Declaration { name: String::from("border-radius"), value: Value::ArrayValue(corners), important: false }
+    })
+}
+
+/// The longhands of the `animation` shorthand, in the order its value array holds them.
+pub const ANIMATION_LONGHANDS:[&str;7] = ["animation-name", "animation-duration", "animation-timing-function",
+    "animation-delay", "animation-iteration-count", "animation-direction", "animation-fill-mode"];
+
+const TIMING_KEYWORDS:[&str;7] = ["ease", "linear", "ease-in", "ease-out", "ease-in-out", "step-start", "step-end"];
+
+// one comma separated part of an animation shorthand, like `spin 2s ease-in 1s infinite alternate`.
+// the first time is the duration and the second the delay.
+fn animation_layer(items:Vec<Value>) -> Option<Vec<Value>> {
+    let mut layer:[Option<Value>;7] = Default::default();
+    for value in items {
+        let kw = match &value {
+            Keyword(kw) => kw.to_lowercase(),
+            _ => String::new(),
+        };
+        let slot = match value {
+            Value::Time(_) if layer[1].is_none() => 1,
+            Value::Time(_) => 3,
+            _ if TIMING_KEYWORDS.contains(&kw.as_str()) => 2,
+            Value::FunCall(ref call) if call.name == "cubic-bezier" || call.name == "steps" => 2,
+            Value::Number(_) => 4,
+            _ if kw == "infinite" => 4,
+            _ if ["normal", "reverse", "alternate", "alternate-reverse"].contains(&kw.as_str()) => 5,
+            _ if ["forwards", "backwards", "both"].contains(&kw.as_str()) => 6,
+            _ if kw == "none" && layer[0].is_some() => 6,
+            // the play state isn't part of the model
+            _ if kw == "running" || kw == "paused" => continue,
+            Keyword(_) | StringLiteral(_) => 0,
+            _ => return None,
+        };
+        if layer[slot].is_some() {
+            return None;
+        }
+        layer[slot] = Some(value);
+    }
+    let defaults = [Keyword(String::from("none")), Value::Time(0.0), Keyword(String::from("ease")),
+        Value::Time(0.0), Value::Number(1.0), Keyword(String::from("normal")), Keyword(String::from("none"))];
+    Some(layer.iter().zip(defaults.iter()).map(|(value, default)| value.clone().unwrap_or_else(|| default.clone())).collect())
+}
+
+// `animation: spin 2s linear infinite, fade 1s`. the value has one entry per ANIMATION_LONGHANDS
+// name, which is a list when there is more than one animation.
+fn animation_declaration<'a>() -> Parser<'a, u8, Declaration> {
+    let layer = list(one_value(), space1()).convert(|items| animation_layer(items).ok_or(()));
+    let p
+        = space()
+        * seq(b"animation")
+        * (space() - sym(b':') - space())
+        * list(layer, ws_sym(b','))
+        - (space() - sym(b';') - space());
+    p.convert(|layers| {
+        if layers.is_empty() {
+            return Err(());
+        }
+        let longhands = (0..ANIMATION_LONGHANDS.len()).map(|i| match layers.len() {
+            1 => layers[0][i].clone(),
+            _ => Value::ArrayValue(layers.iter().map(|layer| layer[i].clone()).collect()),
+        }).collect();
+        Ok(Declaration { name: String::from("animation"), value: Value::ArrayValue(longhands), important: false })
+    })
+}
+
+#[test]
+fn test_animation_shorthand() {
+    let keyword = |kw:&str| Keyword(String::from(kw));
+    assert_eq!(animation_declaration().parse(b"animation: spin 2s linear 0.5s infinite alternate both;").map(|d| d.value),
+               Ok(Value::ArrayValue(vec![keyword("spin"), Value::Time(2.0), keyword("linear"), Value::Time(0.5),
+                                         keyword("infinite"), keyword("alternate"), keyword("both")])));
+    assert_eq!(animation_declaration().parse(b"animation: fade 300ms, spin 1s steps(4, end) 3;").map(|d| d.value),
+               Ok(Value::ArrayValue(vec![
+                   Value::ArrayValue(vec![keyword("fade"), keyword("spin")]),
+                   Value::ArrayValue(vec![Value::Time(0.3), Value::Time(1.0)]),
+                   Value::ArrayValue(vec![keyword("ease"), Value::FunCall(FunCallValue {
+                       name: String::from("steps"), arguments: vec![Value::Number(4.0), keyword("end")] })]),
+                   Value::ArrayValue(vec![Value::Time(0.0), Value::Time(0.0)]),
+                   Value::ArrayValue(vec![Value::Number(1.0), Value::Number(3.0)]),
+                   Value::ArrayValue(vec![keyword("normal"), keyword("normal")]),
+                   Value::ArrayValue(vec![keyword("none"), keyword("none")]),
+               ])));
+    assert!(animation_declaration().parse(b"animation: 1s 2s 3s;").is_err());
+}
+
+/// The longhands of the `grid-column` shorthand, a start line and an end line.
+pub const GRID_COLUMN_LONGHANDS:[&str;2] = ["grid-column-start", "grid-column-end"];
+/// The longhands of the `grid-row` shorthand.
+pub const GRID_ROW_LONGHANDS:[&str;2] = ["grid-row-start", "grid-row-end"];
+
+// a track size, or `repeat(3, 1fr 100px)`, which is a FunCall of the count and then the sizes
+fn grid_track<'a>() -> Parser<'a, u8, Value> {
+    let size = || length_unit() | keyword() | simple_number();
+    let repeat
+        = seq(b"repeat")
+        * ws_sym(b'(')
+        * simple_number()
+        - ws_sym(b',')
+        + list(size(), space1())
+        - space()
+        - sym(b')');
+    repeat.map(|(count, mut sizes)| {
+        sizes.insert(0, count);
+        Value::FunCall(FunCallValue { name: String::from("repeat"), arguments: sizes })
+    }) | size()
+}
+
+// `grid-template-columns: 200px repeat(2, 1fr) auto`, which can be longer than value() takes
+fn grid_template_declaration<'a>() -> Parser<'a, u8, Declaration> {
+    let name = seq(b"grid-template-columns") | seq(b"grid-template-rows");
+    let p
+        = space()
+        * name.collect()
+        - (space() - sym(b':') - space())
+        + list(grid_track(), space1())
+        - (space() - sym(b';') - space());
+    p.map(|(name, mut tracks)| {
+        let value = if tracks.len() == 1 { tracks.remove(0) } else { Value::ArrayValue(tracks) };
+        Declaration { name: v2s(name), value, important: false }
+    })
+}
+
+// `grid-column: 1 / 3` or `grid-row: 2 / span 2`, with the start and end lines as an array of
+// two. an end that's left out is auto, or the same keyword as the start.
+fn grid_line_declaration<'a>() -> Parser<'a, u8, Declaration> {
+    let name = seq(b"grid-column") | seq(b"grid-row");
+    let line = || (seq(b"span") * space1() * simple_number())
+        .map(|n| Value::ArrayValue(vec![Keyword(String::from("span")), n]))
+        | simple_number()
+        | keyword();
+    let p
+        = space()
+        * name.collect()
+        - (space() - sym(b':') - space())
+        + line()
+        + (ws_sym(b'/') * line()).opt()
+        - (space() - sym(b';') - space());
+    p.map(|((name, start), end)| {
+        let end = end.unwrap_or_else(|| match &start {
+            Keyword(_) => start.clone(),
+            _ => Keyword(String::from("auto")),
+        });
+        Declaration { name: v2s(name), value: Value::ArrayValue(vec![start, end]), important: false }
+    })
+}
+
+#[test]
+fn test_grid_declarations() {
+    let px = |v:f32| Length(v, Unit::Px);
+    let fr = |v:f32| Length(v, Unit::Fr);
+    assert_eq!(grid_template_declaration().parse(b"grid-template-columns: 100px 1fr 1fr 1fr 2fr;").map(|d| d.value),
+               Ok(Value::ArrayValue(vec![px(100.0), fr(1.0), fr(1.0), fr(1.0), fr(2.0)])));
+    assert_eq!(grid_template_declaration().parse(b"grid-template-rows: repeat(2, 50px auto) 1fr;").map(|d| d.value),
+               Ok(Value::ArrayValue(vec![
+                   Value::FunCall(FunCallValue { name: String::from("repeat"),
+                       arguments: vec![Value::Number(2.0), px(50.0), Keyword(String::from("auto"))] }),
+                   fr(1.0)])));
+    assert_eq!(grid_line_declaration().parse(b"grid-column: 1 / span 2;").map(|d| d.value),
+               Ok(Value::ArrayValue(vec![Value::Number(1.0),
+                   Value::ArrayValue(vec![Keyword(String::from("span")), Value::Number(2.0)])])));
+    assert_eq!(grid_line_declaration().parse(b"grid-row: 3;").map(|d| d.value),
+               Ok(Value::ArrayValue(vec![Value::Number(3.0), Keyword(String::from("auto"))])));
+    assert!(grid_line_declaration().parse(b"grid-row-start: 3;").is_err());
+}
+
+// `from`, `to` or a percentage
+fn keyframe_offset<'a>() -> Parser<'a, u8, f32> {
+    space() * (seq(b"from").map(|_| 0.0)
+        | seq(b"to").map(|_| 1.0)
+        | (number() - sym(b'%')).map(|v| (v / 100.0) as f32)) - space()
+}
+
+fn keyframe<'a>() -> Parser<'a, u8, Keyframe> {
+    let p
+        = list(keyframe_offset(), sym(b','))
+        - ws_sym(b'{')
+        - comment().opt()
+        + declaration_list()
+        - comment().opt()
+        - ws_sym(b'}');
+    p.map(|(offsets, declarations)| Keyframe { offsets, declarations })
+}
+
+fn keyframes_rule<'a>() -> Parser<'a, u8, RuleType> {
+    let name = identifier() | string_literal().map(|value| match value {
+        StringLiteral(name) => name,
+        _ => String::new(),
+    });
+    let p
+        = space()
+        * (seq(b"@keyframes") | seq(b"@-webkit-keyframes") | seq(b"@-moz-keyframes"))
+        * space1()
+        * name
+        - ws_sym(b'{')
+        + (comment().map(|_| None) | keyframe().map(Some)).repeat(0..)
+        - ws_sym(b'}');
+    p.map(|(name, keyframes)| RuleType::Keyframes(KeyframesRule {
+        name,
+        keyframes: keyframes.into_iter().flatten().collect(),
+    }))
+}
+
+#[test]
+fn test_keyframes_rule() {
+    let rule = keyframes_rule().parse(br#"@keyframes pulse {
+        from { opacity: 0; }
+        /* halfway */
+        50%, 75% { opacity: 1; color: red; }
+        to { opacity: 0.5; }
+    }"#);
+    assert_eq!(rule, Ok(RuleType::Keyframes(KeyframesRule {
+        name: String::from("pulse"),
+        keyframes: vec![
+            Keyframe { offsets: vec![0.0], declarations: vec![
+                Declaration { name: String::from("opacity"), value: Value::Number(0.0), important: false }] },
+            Keyframe { offsets: vec![0.5, 0.75], declarations: vec![
+                Declaration { name: String::from("opacity"), value: Value::Number(1.0), important: false },
+                Declaration { name: String::from("color"), value: Keyword(String::from("red")), important: false }] },
+            Keyframe { offsets: vec![1.0], declarations: vec![
+                Declaration { name: String::from("opacity"), value: Value::Number(0.5), important: false }] },
+        ],
+    })));
+    let sheet = parse_stylesheet("@media screen { @-webkit-keyframes spin { to { width: 10px; } } } div { color: red; }").unwrap();
+    assert_eq!(sheet.rules.len(), 2);
+    assert!(matches!(&sheet.rules[0], RuleType::Media(block) if matches!(&block.rules[0], RuleType::Keyframes(_))));
+}
+
+#[test]
+fn test_background_shorthand() {
+    let keyword = |kw:&str| Keyword(String::from(kw));
+    let url = Value::FunCall(FunCallValue { name: String::from("url"), arguments: vec![StringLiteral(String::from("bg.png"))] });
+    assert_eq!(background_declaration().parse(b"background: #fff url(bg.png) no-repeat center / cover;").map(|d| d.value),
+               Ok(Value::ArrayValue(vec![Value::HexColor(String::from("#fff")), url.clone(), keyword("no-repeat"),
+                                         keyword("center"), keyword("cover")])));
+    assert_eq!(background_declaration().parse(b"background: url(bg.png) left 10px top/50% auto repeat-x red fixed;").map(|d| d.value),
+               Ok(Value::ArrayValue(vec![keyword("red"), url,  keyword("repeat-x"),
+                                         Value::ArrayValue(vec![keyword("left"), Length(10.0, Unit::Px), keyword("top")]),
+                                         Value::ArrayValue(vec![Length(50.0, Unit::Per), keyword("auto")])])));
+    assert_eq!(background_declaration().parse(b"background: blue;").map(|d| d.value),
+               Ok(Value::ArrayValue(vec![keyword("blue"), keyword("none"), keyword("repeat"),
+                                         Value::ArrayValue(vec![Length(0.0, Unit::Per), Length(0.0, Unit::Per)]), keyword("auto")])));
+    // a size needs a position before it, and there is only one color
+    assert!(background_declaration().parse(b"background: red / cover;").is_err());
+    assert!(background_declaration().parse(b"background: red blue;").is_err());
+    assert!(background_declaration().parse(b"background: inherit;").is_err());
+    assert!(background_declaration().parse(b"background: linear-gradient(#fff, #000), red;").is_err());
+}
+
+#[test]
+fn test_border_radius_shorthand() {
+    let px = |v:f32| Length(v, Unit::Px);
+    assert_eq!(border_radius_declaration().parse(b"border-radius: 8px;").map(|d| d.value),
+               Ok(Value::ArrayValue(vec![px(8.0), px(8.0), px(8.0), px(8.0)])));
+    assert_eq!(border_radius_declaration().parse(b"border-radius: 1px 2px 3px;").map(|d| d.value),
+               Ok(Value::ArrayValue(vec![px(1.0), px(2.0), px(3.0), px(2.0)])));
+    assert_eq!(border_radius_declaration().parse(b"border-radius: 10px 50% / 10px 5px;").map(|d| d.value),
+               Ok(Value::ArrayValue(vec![px(10.0), Value::ArrayValue(vec![Length(50.0, Unit::Per), px(5.0)]),
+                                         px(10.0), Value::ArrayValue(vec![Length(50.0, Unit::Per), px(5.0)])])));
+    assert!(border_radius_declaration().parse(b"border-radius: 1px 2px 3px 4px 5px;").is_err());
+    assert!(border_radius_declaration().parse(b"border-radius: 1px /;").is_err());
+    assert!(border_radius_declaration().parse(b"border-radius: inherit;").is_err());
+}
+
+#[test]
+fn test_font_shorthand() {
+    let normal = || Keyword(String::from("normal"));
+    assert_eq!(font_declaration().parse(br#"font: italic 700 16px/1.5 "Open Sans", sans-serif;"#), Ok(Declaration {
+        name: String::from("font"),
+        value: Value::ArrayValue(vec![
+            Keyword(String::from("italic")), normal(), Value::Number(700.0), Length(16.0, Unit::Px), Value::Number(1.5),
+            Value::ArrayValue(vec![StringLiteral(String::from("Open Sans")), Keyword(String::from("sans-serif"))]),
+        ]),
+        important: false,
+    }));
+    assert_eq!(font_declaration().parse(b"font: small-caps bold 1.2em Times New Roman;"), Ok(Declaration {
+        name: String::from("font"),
+        value: Value::ArrayValue(vec![
+            normal(), Keyword(String::from("small-caps")), Keyword(String::from("bold")), Length(1.2, Unit::Em), normal(),
+            StringLiteral(String::from("Times New Roman")),
+        ]),
+        important: false,
+    }));
+    assert_eq!(font_declaration().parse(b"font: 12px serif;").map(|d| d.value),
+               Ok(Value::ArrayValue(vec![normal(), normal(), normal(), Length(12.0, Unit::Px), normal(), Keyword(String::from("serif"))])));
+    // a size and a family are required
+    assert!(font_declaration().parse(b"font: bold serif;").is_err());
+    assert!(font_declaration().parse(b"font: inherit;").is_err());
+    assert!(font_declaration().parse(b"font-size: 12px;").is_err());
+}
+
+// the inside of a rule's braces
+fn any_declaration<'a>() -> Parser<'a, u8, Declaration> {
+    font_declaration() | background_declaration() | border_radius_declaration() | animation_declaration()
+        | grid_template_declaration() | grid_line_declaration() | declaration() | unparsed_declaration()
+}
+
+// `name: value !important;`, parsed like any other declaration once the flag is taken off
+fn important_declaration<'a>() -> Parser<'a, u8, Declaration> {
+    let r = space()
+        * identifier()
+        - (space() - sym(b':') - space())
+        + none_of(b";}").repeat(1..)
+        - sym(b';').opt()
+        - space()
+        ;
+    r.convert(|(name, raw)| {
+        let raw = v2s(&raw);
+        let value = strip_important(&raw).ok_or("not important")?;
+        let text = format!("{}: {};", name, value);
+        let mut declaration = (any_declaration() - pom::parser::end()).parse(text.as_bytes()).map_err(|_| "not a declaration")?;
+        declaration.important = true;
+        Ok::<Declaration, &str>(declaration)
+    })
+}
+
+// the value before a trailing !important, if there is one
+fn strip_important(raw:&str) -> Option<&str> {
+    let raw = raw.trim_end();
+    let flag = raw.get(raw.len().checked_sub("important".len())?..)?;
+    if !flag.eq_ignore_ascii_case("important") {
+        return None;
+    }
+    raw[..raw.len() - flag.len()].trim_end().strip_suffix('!').map(str::trim_end)
+}
+
+fn declaration_list<'a>() -> Parser<'a, u8, Vec<Declaration>> {
+    (important_declaration() | any_declaration()).repeat(0..)
+}
+
+/// The declarations of a `style` attribute, which is a rule's body without the braces.
+pub fn parse_declarations(text:&str) -> Result<Vec<Declaration>, BrowserError> {
+    let p = space() * comment().opt() * declaration_list() - comment().opt() - space() - pom::parser::end();
+    Ok(p.parse(text.as_bytes())?)
+}
+
 fn rule<'a>() -> Parser<'a, u8, RuleType> {
     let r
         = list(selector(),sym(b','))
         - ws_sym(b'{')
         - comment().opt()
-        + declaration().repeat(0..)
+        + declaration_list()
         - comment().opt()
         - ws_sym(b'}')
         ;
@@ -893,6 +1960,24 @@ fn rule<'a>() -> Parser<'a, u8, RuleType> {
     }))
 }
 
+#[test]
+fn test_compound_selector() {
+    let simple = |text:&[u8]| match selector().parse(text) {
+        Ok(Selector::Simple(sel)) => sel,
+        other => panic!("not a simple selector {:#?}", other),
+    };
+    let sel = simple(b"div.card.featured");
+    assert_eq!(sel.tag_name, Some(String::from("div")));
+    assert_eq!(sel.class, vec![String::from("card"), String::from("featured")]);
+    let sel = simple(b"a.button#cta.big:hover");
+    assert_eq!((sel.id, sel.class, sel.pseudo_class), (Some(String::from("cta")),
+        vec![String::from("button"), String::from("big")], vec![String::from("hover")]));
+    let sel = simple(b"#main.note");
+    assert_eq!((sel.tag_name, sel.id, sel.class), (None, Some(String::from("main")), vec![String::from("note")]));
+    assert_eq!(Selector::Simple(simple(b"p.a.b#c")).specificity(), (1, 2, 1));
+    assert!(selector().parse(b"#a#b").is_err());
+}
+
 #[test]
 fn test_multipart_selector() {
     let input = br#"
@@ -909,13 +1994,15 @@ fn test_multipart_selector() {
                     tag_name: Some(String::from("div")),
                     id: None,
                     class: vec![String::from("epigraph")],
-                    pseudo_class: vec![]
+                    pseudo_class: vec![],
+                    pseudo_element: None
                 })),
                 child: Box::new(Selector::Simple(SimpleSelector{
                     tag_name: Some(String::from("blockquote")),
                     id: None,
                     class: vec![],
-                    pseudo_class: vec![]
+                    pseudo_class: vec![],
+                    pseudo_element: None
                 })),
                 immediate: true
             }),
@@ -925,7 +2012,8 @@ fn test_multipart_selector() {
                         tag_name: Some(String::from("div")),
                         id: None,
                         class: vec![String::from("epigraph")],
-                        pseudo_class: vec![]
+                        pseudo_class: vec![],
+                        pseudo_element: None
                     })),
                     child: Box::new(Selector::Ancestor(
                         AncestorSelector {
@@ -933,13 +2021,15 @@ fn test_multipart_selector() {
                                 tag_name: Some(String::from("blockquote")),
                                 id: None,
                                 class: vec![],
-                                pseudo_class: vec![]
+                                pseudo_class: vec![],
+                                pseudo_element: None
                             })),
                             child: Box::new(Selector::Simple(SimpleSelector{
                                 tag_name: Some(String::from("p")),
                                 id: None,
                                 class: vec![],
-                                pseudo_class: vec![]
+                                pseudo_class: vec![],
+                                pseudo_element: None
                             })),
                             immediate: true
                         }
@@ -951,7 +2041,8 @@ fn test_multipart_selector() {
         declarations: vec![
             Declaration{
                 name: "font-style".to_string(),
-                value: Value::Keyword("italic".to_string())
+                value: Value::Keyword("italic".to_string()),
+                important: false,
             }
         ]
     })))
@@ -983,8 +2074,117 @@ fn test_rule() {
     let input = b"div { border-width:1px; }";
     println!("{:#?}",rule().parse(input))
 }
+fn page_rule<'a>() -> Parser<'a, u8, RuleType> {
+    let p
+        = space()
+        * seq(b"@page")
+        * (space() * pseudo_class_string()).opt()
+        - ws_sym(b'{')
+        - comment().opt()
+        + (declaration() | unparsed_declaration()).repeat(0..)
+        - comment().opt()
+        - ws_sym(b'}');
+    p.map(|(pseudo_class, declarations)| RuleType::Page(PageRule { pseudo_class, declarations }))
+}
+
+#[test]
+fn test_page_rule() {
+    assert_eq!(page_rule().parse(b"@page { size: A4 landscape; margin: 1em; }"),
+        Ok(RuleType::Page(PageRule {
+            pseudo_class: None,
+            declarations: vec![
+                Declaration {
+                    name: String::from("size"),
+                    value: Value::ArrayValue(vec![Keyword(String::from("A4")), Keyword(String::from("landscape"))]),
+                    important: false,
+                },
+                Declaration { name: String::from("margin"), value: Length(1.0, Unit::Em), important: false },
+            ]
+        })));
+    assert_eq!(page_rule().parse(b"@page :first { margin-top: 0px; }"),
+        Ok(RuleType::Page(PageRule {
+            pseudo_class: Some(String::from("first")),
+            declarations: vec![
+                Declaration { name: String::from("margin-top"), value: Length(0.0, Unit::Px), important: false },
+            ]
+        })));
+}
+
+fn media_feature<'a>() -> Parser<'a, u8, MediaFeature> {
+    let value = space() * sym(b':') * space() * (length_unit() | simple_number() | keyword());
+    let p = space() * sym(b'(') * identifier() + value.opt() - space() - sym(b')');
+    p.map(|(name, value)| MediaFeature { name, value })
+}
+fn media_query<'a>() -> Parser<'a, u8, MediaQuery> {
+    let prefix = space() * (seq(b"not") | seq(b"only")) - space1();
+    let more_features = (space1() * seq(b"and") * media_feature()).repeat(0..);
+    let typed = (identifier() + more_features).map(|(media_type, features)| (Some(media_type), features));
+    let more_features = (space1() * seq(b"and") * media_feature()).repeat(0..);
+    let untyped = (media_feature() + more_features).map(|(first, rest)| {
+        let mut features = vec![first];
+        features.extend(rest);
+        (None, features)
+    });
+    let p = prefix.opt() + (untyped | typed);
+    p.map(|(prefix, (media_type, features))| MediaQuery {
+        negated: prefix == Some(b"not"),
+        media_type: media_type.map(|t| t.to_lowercase()),
+        features,
+    })
+}
+fn media_rule<'a>() -> Parser<'a, u8, RuleType> {
+    let p
+        = space()
+        * seq(b"@media")
+        * list(media_query(), ws_sym(b','))
+        - ws_sym(b'{')
+        + (comment() | rule() | supports_rule() | keyframes_rule() | call(media_rule)).repeat(0..)
+        - ws_sym(b'}');
+    p.map(|(queries, rules)| RuleType::Media(MediaRule { queries, rules }))
+}
+
+#[test]
+fn test_media_rule() {
+    let feature = |name:&str, value:Value| MediaFeature { name: String::from(name), value: Some(value) };
+    assert_eq!(
+        media_rule().parse(br#"@media screen and (min-width: 600px), print { body { margin: 3em; } }"#),
+        Ok(RuleType::Media(MediaRule {
+            queries: vec![
+                MediaQuery {
+                    negated: false,
+                    media_type: Some(String::from("screen")),
+                    features: vec![feature("min-width", Length(600.0, Unit::Px))],
+                },
+                MediaQuery { negated: false, media_type: Some(String::from("print")), features: vec![] },
+            ],
+            rules: vec![RuleType::Rule(Rule {
+                selectors: vec![Selector::Simple(SimpleSelector {
+                    tag_name: Some(String::from("body")),
+                    id: None,
+                    class: vec![],
+                    pseudo_class: vec![],
+                    pseudo_element: None,
+                })],
+                declarations: vec![Declaration { name: String::from("margin"), value: Length(3.0, Unit::Em), important: false }],
+            })],
+        }))
+    );
+    assert_eq!(
+        media_query().parse(b"not print"),
+        Ok(MediaQuery { negated: true, media_type: Some(String::from("print")), features: vec![] })
+    );
+    assert_eq!(
+        media_query().parse(b"(max-width:40em) and (min-width: 20em)"),
+        Ok(MediaQuery {
+            negated: false,
+            media_type: None,
+            features: vec![feature("max-width", Length(40.0, Unit::Em)), feature("min-width", Length(20.0, Unit::Em))],
+        })
+    );
+}
+
 fn stylesheet<'a>() -> Parser<'a, u8, Stylesheet> {
-    (comment() | rule() | import_rule() | at_rule()).repeat(0..).map(|rules| Stylesheet {
+    (comment() | rule() | import_rule() | supports_rule() | page_rule() | media_rule() | keyframes_rule() | at_rule()).repeat(0..).map(|rules| Stylesheet {
         rules,
         base_url: Url::parse("https://www.mozilla.com/").unwrap()
     })
@@ -1002,6 +2202,33 @@ fn test_font_style() {
     println!("{:#?}",stylesheet().parse(input))
 }
 
+pub fn parse_selector(text:&str) -> Result<Selector, BrowserError> {
+    Ok((selector() - pom::parser::end()).parse(text.as_ref())?)
+}
+/// `h1, h2 > a, .foo`
+pub fn parse_selector_list(text:&str) -> Result<Vec<Selector>, BrowserError> {
+    Ok((list(selector(), sym(b',')) - pom::parser::end()).parse(text.as_ref())?)
+}
+
+#[test]
+fn test_selector_list() {
+    let selectors = |input:&[u8]| match rule().parse(input) {
+        Ok(RuleType::Rule(rule)) => rule.selectors,
+        other => panic!("not a rule {:#?}", other),
+    };
+    assert_eq!(selectors(b"h1, h2, h3 { color: red; }").len(), 3);
+    assert_eq!(selectors(b"h1,h2{color:red;}").len(), 2);
+    assert_eq!(selectors(b"h1 ,\n  ul > li ,\n .foo:hover { color: red; }"), vec![
+        parse_selector("h1").unwrap(),
+        parse_selector("ul > li").unwrap(),
+        parse_selector(".foo:hover").unwrap(),
+    ]);
+    // a list inside a pseudo-class doesn't end the outer one
+    assert_eq!(selectors(b"a:not(.x, .y), b { color: red; }").len(), 2);
+    assert_eq!(parse_selector_list("p, div.cool").unwrap(),
+               vec![parse_selector("p").unwrap(), parse_selector("div.cool").unwrap()]);
+    assert!(parse_selector_list("p,").is_err());
+}
 pub fn parse_stylesheet_from_buffer(content:Vec<u8>) -> Result<Stylesheet, BrowserError> {
     Ok(stylesheet().parse(content.as_slice())?)
 }
@@ -1028,31 +2255,37 @@ fn test_file_load() {
                         tag_name: Some(String::from("body")),
                         id: None,
                         class: vec![],
-                        pseudo_class: vec![]
+                        pseudo_class: vec![],
+                        pseudo_element: None
                     }),
                     Selector::Simple(SimpleSelector{
                         tag_name: Some(String::from("div")),
                         id: None,
                         class: vec![],
-                        pseudo_class: vec![]
+                        pseudo_class: vec![],
+                        pseudo_element: None
                     })
                 ],
                 declarations: vec![
                     Declaration {
                         name: "background-color".to_string(),
                         value: Value::Keyword("white".to_string()),
+                        important: false,
                     },
                     Declaration {
                         name: "border-color".to_string(),
                         value: Value::Keyword("red".to_string()),
+                        important: false,
                     },
                     Declaration {
                         name: "border-width".to_string(),
                         value: Value::Length(1.0,Unit::Px),
+                        important: false,
                     },
                     Declaration {
                         name: "color".to_string(),
                         value: Value::Keyword("black".to_string()),
+                        important: false,
                     },
                 ],
             }),
@@ -1063,13 +2296,15 @@ fn test_file_load() {
                         tag_name: None,
                         id: None,
                         class: vec![String::from("cool")],
-                        pseudo_class: vec![]
+                        pseudo_class: vec![],
+                        pseudo_element: None
                     })
                 ],
                 declarations: vec![
                     Declaration {
                         name: "color".to_string(),
                         value: Value::Keyword("green".to_string()),
+                        important: false,
                     },
                 ],
             }
@@ -1091,30 +2326,35 @@ fn test_tufte_rules() {
 }
 
 fn import_rule<'a>() -> Parser<'a, u8, RuleType> {
-    let p =
-            - space()
-            - sym(b'@')
-            + identifier()
-            - space()
-            - seq(b"url")
-            - sym(b'(')
-            + url()
-            - sym(b')')
-            - sym(b';')
-        ;
-    p.map(|( (_a,name), url)| {
-        RuleType::AtRule(AtRule {
-            name,
+    // url(foo.css), url("foo.css") or just "foo.css"
+    let target
+        = (seq(b"url") * sym(b'(') * space() * (string_literal() | url()) - space() - sym(b')'))
+        | string_literal();
+    let p
+        = space()
+        * seq(b"@import")
+        * space()
+        * target
+        + (space() * list(media_query(), ws_sym(b','))).opt()
+        - space()
+        - sym(b';');
+    p.map(|(url, queries)| {
+        let import = RuleType::AtRule(AtRule {
+            name: String::from("import"),
             value: Some(Value::FunCall(FunCallValue{
                 name: String::from("url"),
                 arguments: vec![url]
             })),
             rules: vec![]
-        })
+        });
+        // an import for only some media is an @media block around it
+        match queries {
+            Some(queries) if !queries.is_empty() => RuleType::Media(MediaRule { queries, rules: vec![import] }),
+            _ => import,
+        }
     })
 }
 
-
 #[test]
 fn test_import_rule() {
     let input = br#"http://fonts.googleapis.com/css?family=Lato"#;
@@ -1150,8 +2390,122 @@ fn test_import_rule() {
             base_url: Url::parse("https://www.mozilla.com/").unwrap()
         }
     ));
+
+    let import = |href:&str| RuleType::AtRule(AtRule{
+        name: "import".to_string(),
+        value: Some(Value::FunCall(FunCallValue{
+            name: "url".to_string(),
+            arguments: vec![Value::StringLiteral(String::from(href))]
+        })),
+        rules: vec![]
+    });
+    assert_eq!(import_rule().parse(br#"@import url("other.css");"#), Ok(import("other.css")));
+    assert_eq!(import_rule().parse(br#"@import 'other.css';"#), Ok(import("other.css")));
+    match import_rule().parse(br#"@import "print.css" print, screen and (max-width: 400px);"#) {
+        Ok(RuleType::Media(block)) => {
+            assert_eq!(block.queries.len(), 2);
+            assert_eq!(block.rules, vec![import("print.css")]);
+        }
+        rule => panic!("expected an import inside a media block, got {:#?}", rule),
+    }
+}
+
+
+// (display: flex) or (not (display: grid))
+fn supports_in_parens<'a>() -> Parser<'a, u8, SupportsCondition> {
+    let decl = (identifier() - (space() - sym(b':') - space()) + value())
+        .map(|(name, value)| SupportsCondition::Declaration(Declaration { name, value, important: false }));
+    space() * sym(b'(') * space() * (decl | call(supports_condition)) - space() - sym(b')')
+}
+fn supports_not<'a>() -> Parser<'a, u8, SupportsCondition> {
+    (space() * seq(b"not") * space1() * supports_in_parens())
+        .map(|cond| SupportsCondition::Not(Box::new(cond)))
+}
+fn supports_condition<'a>() -> Parser<'a, u8, SupportsCondition> {
+    let op = space1() * (seq(b"and") | seq(b"or")) - space();
+    let p = (supports_not() | supports_in_parens()) + (op + supports_in_parens()).repeat(0..);
+    p.map(|(first, rest)| {
+        rest.into_iter().fold(first, |acc, (op, cond)| {
+            if op == b"and" {
+                SupportsCondition::And(Box::new(acc), Box::new(cond))
+            } else {
+                SupportsCondition::Or(Box::new(acc), Box::new(cond))
+            }
+        })
+    })
+}
+fn supports_rule<'a>() -> Parser<'a, u8, RuleType> {
+    let p
+        = space()
+        * seq(b"@supports")
+        * supports_condition()
+        - ws_sym(b'{')
+        + (comment() | rule() | keyframes_rule()).repeat(0..)
+        - ws_sym(b'}');
+    p.map(|(condition, rules)| RuleType::Supports(SupportsRule { condition, rules }))
 }
 
+#[test]
+fn test_supports_rule() {
+    let display_flex = SupportsCondition::Declaration(Declaration {
+        name: String::from("display"),
+        value: Keyword(String::from("flex")),
+        important: false,
+    });
+    assert_eq!(supports_condition().parse(b"(display: flex)"), Ok(SupportsCondition::Declaration(Declaration {
+        name: String::from("display"),
+        value: Keyword(String::from("flex")),
+        important: false,
+    })));
+    assert_eq!(supports_condition().parse(b"not (display: flex)"),
+               Ok(SupportsCondition::Not(Box::new(SupportsCondition::Declaration(Declaration {
+                   name: String::from("display"),
+                   value: Keyword(String::from("flex")),
+                   important: false,
+               })))));
+    assert_eq!(supports_condition().parse(b"(display: flex) or (color: red)"),
+               Ok(SupportsCondition::Or(
+                   Box::new(SupportsCondition::Declaration(Declaration {
+                       name: String::from("display"),
+                       value: Keyword(String::from("flex")),
+                       important: false,
+                   })),
+                   Box::new(SupportsCondition::Declaration(Declaration {
+                       name: String::from("color"),
+                       value: Keyword(String::from("red")),
+                       important: false,
+                   })),
+               )));
+    assert_eq!(stylesheet().parse(br#"@supports (display: flex) { div { color: red; } } p { color: blue; }"#),
+        Ok(Stylesheet {
+            rules: vec![
+                RuleType::Supports(SupportsRule {
+                    condition: display_flex,
+                    rules: vec![RuleType::Rule(Rule {
+                        selectors: vec![Selector::Simple(SimpleSelector {
+                            tag_name: Some(String::from("div")),
+                            id: None,
+                            class: vec![],
+                            pseudo_class: vec![],
+                            pseudo_element: None
+                        })],
+                        declarations: vec![Declaration { name: String::from("color"), value: Keyword(String::from("red")), important: false }]
+                    })]
+                }),
+                RuleType::Rule(Rule {
+                    selectors: vec![Selector::Simple(SimpleSelector {
+                        tag_name: Some(String::from("p")),
+                        id: None,
+                        class: vec![],
+                        pseudo_class: vec![],
+                        pseudo_element: None
+                    })],
+                    declarations: vec![Declaration { name: String::from("color"), value: Keyword(String::from("blue")), important: false }]
+                }),
+            ],
+            base_url: Url::parse("https://www.mozilla.com/").unwrap()
+        }));
+}
 
 //https://developer.mozilla.org/en-US/docs/Web/CSS/At-rule
 fn at_rule<'a>() -> Parser<'a, u8, RuleType> {
@@ -1203,10 +2557,12 @@ fn test_atrules() {
                 selectors: vec![],
                 declarations: vec![
                     Declaration { name: String::from("size"),
-                        value:Value::Keyword(String::from("letter"))},
+                        value:Value::Keyword(String::from("letter")),
+                        important: false,},
                     Declaration {
                         name: String::from("margin"),
-                        value: Value::ArrayValue(vec![Value::Number(1.0), Keyword(String::from("in"))]),
+                        value: Value::Length(1.0, Unit::In),
+                        important: false,
                     }
                 ]
             })]
@@ -1270,7 +2626,8 @@ fn test_fontface() {
             arguments: vec![
                 Value::StringLiteral(String::from("et-book/et-book-roman-line-figures/et-book-roman-line-figures.eot"))
             ]
-        })
+        }),
+        important: false,
     }),
                declaration().parse(br#"src: url("et-book/et-book-roman-line-figures/et-book-roman-line-figures.eot");"#));
 
@@ -1292,7 +2649,7 @@ fn test_fontface() {
             rules: vec![RuleType::Rule(Rule {
                 selectors: vec![],
                 declarations: vec![
-                    Declaration { name: String::from("font-family"), value: Value::StringLiteral(String::from("et-book")) },
+                    Declaration { name: String::from("font-family"), value: Value::StringLiteral(String::from("et-book")), important: false },
                     Declaration {
                         name: String::from("src"),
                         value: Value::FunCall(FunCallValue {
@@ -1300,11 +2657,12 @@ fn test_fontface() {
                             arguments: vec![
                                 Value::StringLiteral(String::from("et-book/et-book-roman-line-figures/et-book-roman-line-figures.eot")),
                             ]
-                        })
+                        }),
+                        important: false,
                     },
-                    Declaration { name: String::from("font-weight"), value: Keyword(String::from("normal")) },
-                    Declaration { name: String::from("font-style"), value: Keyword(String::from("normal")) },
-                    Declaration { name: String::from("font-display"), value: Keyword(String::from("swap")) },
+                    Declaration { name: String::from("font-weight"), value: Keyword(String::from("normal")), important: false },
+                    Declaration { name: String::from("font-style"), value: Keyword(String::from("normal")), important: false },
+                    Declaration { name: String::from("font-display"), value: Keyword(String::from("swap")), important: false },
                 ]
             })]
         })],
@@ -1319,7 +2677,8 @@ fn test_percentage() {
                length_unit().parse(br"100%").unwrap());
     assert_eq!(Declaration{
         name: String::from("width"),
-        value: (Value::Length(100.0, Unit::Per))
+        value: (Value::Length(100.0, Unit::Per)),
+        important: false,
     },
                declaration().parse(br"width:100%;").unwrap());
 }
@@ -1332,7 +2691,8 @@ fn test_rem() {
                length_unit().parse(br"40.0rem").unwrap());
     assert_eq!(Declaration{
         name: String::from("width"),
-        value: (Value::Length(99.90, Unit::Rem))
+        value: (Value::Length(99.90, Unit::Rem)),
+        important: false,
     },
                declaration().parse(br"width:99.9rem;").unwrap());
 }
@@ -1345,17 +2705,19 @@ fn test_multiple_selectors() {
                 tag_name: Some(String::from("a")),
                 id: None,
                 class: vec![],
-                pseudo_class: vec![]
+                pseudo_class: vec![],
+                pseudo_element: None
             }),
             Selector::Simple(SimpleSelector{
                 tag_name: Some(String::from("b")),
                 id: None,
                 class: vec![],
-                pseudo_class: vec![]
+                pseudo_class: vec![],
+                pseudo_element: None
             })
         ],
         declarations: vec![
-            Declaration{ name: String::from("foo"), value: Keyword(String::from("bar")) }
+            Declaration{ name: String::from("foo"), value: Keyword(String::from("bar")), important: false }
         ]
     });
     assert_eq!(answer, rule().parse(br"a,b { foo: bar; }").unwrap());
@@ -1387,7 +2749,8 @@ fn test_four_part_margin() {
             Length(2.0,Unit::Px),
             Length(3.0,Unit::Px),
             Length(4.0,Unit::Px),
-        ])
+        ]),
+        important: false,
     };
     assert_eq!(answer, declaration().parse(b"margin: 1px 2px 3px 4px;").unwrap());
     println!("parsed {:#?}", declaration().parse(b"margin: 1px 2px 3px 4em;"));
@@ -1398,7 +2761,8 @@ fn test_four_part_margin() {
             Length(2.0,Unit::Px),
             Length(3.0,Unit::Px),
             Length(4.0,Unit::Em),
-        ])
+        ]),
+        important: false,
     };
     assert_eq!(answer, declaration().parse(b"margin: 1px 2px 3px 4em;").unwrap());
 }
@@ -1412,7 +2776,8 @@ fn test_two_part_margin() {
         value: Value::ArrayValue(vec![
             Length(1.0,Unit::Px),
             Length(2.0,Unit::Px),
-        ])
+        ]),
+        important: false,
     };
     assert_eq!(answer, declaration().parse(b"margin: 1px 2px;").unwrap());
 }
@@ -1420,7 +2785,8 @@ fn test_two_part_margin() {
 fn test_one_part_margin() {
     let answer = Declaration {
         name: String::from("margin"),
-        value: Length(1.0,Unit::Px)
+        value: Length(1.0,Unit::Px),
+        important: false,
     };
     assert_eq!(answer, declaration().parse(b"margin: 1px;").unwrap());
 }
@@ -1435,7 +2801,8 @@ fn test_funcall_dec() {
                 Value::HexColor(String::from("#fffff8")),
                 Value::HexColor(String::from("#fffff8")),
             ]
-        })
+        }),
+        important: false,
     },
         declaration().parse(b"background: linear-gradient(#fffff8, #fffff8);").unwrap()
     )
@@ -1466,7 +2833,8 @@ fn test_linear_gradient() {
                     Value::Keyword(String::from("currentColor")),
                 ]
             }),
-        ])
+        ]),
+        important: false,
     }),
        declaration().parse(br"background: linear-gradient(#fffff8, #fffff8), linear-gradient(#fffff8, #fffff8), linear-gradient(currentColor, currentColor);")
     );
@@ -1484,7 +2852,8 @@ fn test_keyword_list() {
             Keyword(String::from("no-repeat")),
             Keyword(String::from("no-repeat")),
             Keyword(String::from("repeat-x")),
-        ])
+        ]),
+        important: false,
     };
     assert_eq!(answer, declaration().parse(b"background-repeat:no-repeat,no-repeat,repeat-x;").unwrap());
     assert_eq!(answer, declaration().parse(b"background-repeat: no-repeat, no-repeat, repeat-x;").unwrap());
@@ -1497,6 +2866,7 @@ fn test_font_weight() {
         Ok(Declaration{
             name: String::from("font-weight"),
             value: Value::Keyword(String::from("normal")),
+            important: false,
         }),
     );
     assert_eq!(
@@ -1504,6 +2874,7 @@ fn test_font_weight() {
         Ok(Declaration{
             name: String::from("font-weight"),
             value: Value::Number(400.0),
+            important: false,
         }),
     );
 }
@@ -1515,6 +2886,7 @@ fn test_unitless_number() {
         Ok(Declaration{
             name: String::from("line-height"),
             value: Value::Number(1.6),
+            important: false,
         }),
     );
 }
@@ -0,0 +1,430 @@
+use std::fmt::{self, Display, Formatter};
+use super::{Stylesheet, RuleType, Rule, AtRule, SupportsRule, SupportsCondition, PageRule, MediaRule, MediaQuery,
+            MediaFeature, Selector, SimpleSelector, Declaration, Value, CalcExpr, Unit, Color, FunCallValue,
+            KeyframesRule, Keyframe, FONT_LONGHANDS, BACKGROUND_LONGHANDS, ANIMATION_LONGHANDS,
+            BORDER_RADIUS_LONGHANDS};
+
+// Writing the parsed structures back out as css text, so a sheet can be shown in a devtools
+// panel or compared against a golden file. Parsing the text again gives back the same sheet,
+// apart from the arguments of functional pseudo-classes like :not(), which the parser drops.
+
+// nested rules are indented four spaces, like the sheets in tests/
+fn indent(text:&str) -> String {
+    text.lines()
+        .map(|line| if line.is_empty() { String::new() } else { format!("    {}", line) })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// the properties whose lists are separated by commas. the parser keeps either kind of list as an
+// ArrayValue, so which one it was comes from the property.
+const COMMA_LISTS:[&str;17] = ["font-family", "background", "background-image", "unicode-range", "will-change",
+    "transition-property", "transition-duration", "transition-timing-function", "transition-delay",
+    "animation-name", "animation-duration", "animation-timing-function", "animation-delay",
+    "animation-iteration-count", "animation-direction", "animation-fill-mode", "animation-play-state"];
+
+// -webkit-transition-property is a list the same way transition-property is
+fn is_comma_list(name:&str) -> bool {
+    let name = match name.strip_prefix('-').and_then(|rest| rest.split_once('-')) {
+        Some((_vendor, name)) => name,
+        None => name,
+    };
+    COMMA_LISTS.contains(&name)
+}
+
+fn join<T:Display>(items:&[T], sep:&str) -> String {
+    items.iter().map(|item| item.to_string()).collect::<Vec<String>>().join(sep)
+}
+
+fn block(f:&mut Formatter, rules:&[RuleType]) -> fmt::Result {
+    writeln!(f, "{{")?;
+    for rule in rules {
+        writeln!(f, "{}", indent(&rule.to_string()))?;
+    }
+    write!(f, "}}")
+}
+
+fn declaration_block(f:&mut Formatter, declarations:&[Declaration]) -> fmt::Result {
+    writeln!(f, "{{")?;
+    for dec in declarations {
+        writeln!(f, "    {};", dec)?;
+    }
+    write!(f, "}}")
+}
+
+impl Display for Stylesheet {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        for rule in self.rules.iter() {
+            writeln!(f, "{}", rule)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for RuleType {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        match self {
+            RuleType::Rule(rule) => rule.fmt(f),
+            RuleType::AtRule(rule) => rule.fmt(f),
+            RuleType::Supports(rule) => rule.fmt(f),
+            RuleType::Page(rule) => rule.fmt(f),
+            RuleType::Media(rule) => rule.fmt(f),
+            RuleType::Keyframes(rule) => rule.fmt(f),
+            RuleType::Comment(text) => write!(f, "/*{}*/", text),
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        // the rules inside at-rules like @font-face have no selectors
+        if !self.selectors.is_empty() {
+            write!(f, "{} ", join(&self.selectors, ", "))?;
+        }
+        declaration_block(f, &self.declarations)
+    }
+}
+
+impl Display for AtRule {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        write!(f, "@{}", self.name)?;
+        if let Some(value) = &self.value {
+            write!(f, " {}", value)?;
+        }
+        if self.rules.is_empty() {
+            return write!(f, ";");
+        }
+        for rule in self.rules.iter() {
+            write!(f, " {}", rule)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SupportsRule {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        write!(f, "@supports {} ", self.condition)?;
+        block(f, &self.rules)
+    }
+}
+
+// the parser wants parentheses around everything after the first term
+fn in_parens(cond:&SupportsCondition) -> String {
+    match cond {
+        SupportsCondition::Declaration(_) => cond.to_string(),
+        _ => format!("({})", cond),
+    }
+}
+
+impl Display for SupportsCondition {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        match self {
+            SupportsCondition::Declaration(dec) => write!(f, "({})", dec),
+            SupportsCondition::Not(cond) => write!(f, "not {}", in_parens(cond)),
+            SupportsCondition::And(a, b) => write!(f, "{} and {}", a, in_parens(b)),
+            SupportsCondition::Or(a, b) => write!(f, "{} or {}", a, in_parens(b)),
+        }
+    }
+}
+
+impl Display for PageRule {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        write!(f, "@page ")?;
+        if let Some(pseudo_class) = &self.pseudo_class {
+            write!(f, ":{} ", pseudo_class)?;
+        }
+        declaration_block(f, &self.declarations)
+    }
+}
+
+impl Display for MediaRule {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        write!(f, "@media {} ", join(&self.queries, ", "))?;
+        block(f, &self.rules)
+    }
+}
+
+impl Display for KeyframesRule {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        writeln!(f, "@keyframes {} {{", self.name)?;
+        for keyframe in self.keyframes.iter() {
+            writeln!(f, "{}", indent(&keyframe.to_string()))?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl Display for Keyframe {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        let offsets:Vec<String> = self.offsets.iter().map(|offset| format!("{}%", offset * 100.0)).collect();
+        write!(f, "{} ", offsets.join(", "))?;
+        declaration_block(f, &self.declarations)
+    }
+}
+
+impl Display for MediaQuery {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        let mut parts:Vec<String> = vec![];
+        if let Some(media_type) = &self.media_type {
+            let not = if self.negated { "not " } else { "" };
+            parts.push(format!("{}{}", not, media_type));
+        }
+        parts.extend(self.features.iter().map(|feature| feature.to_string()));
+        write!(f, "{}", parts.join(" and "))
+    }
+}
+
+impl Display for MediaFeature {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "({}: {})", self.name, value),
+            None => write!(f, "({})", self.name),
+        }
+    }
+}
+
+impl Display for Selector {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        match self {
+            Selector::Simple(simple) => simple.fmt(f),
+            Selector::Ancestor(anc) if anc.immediate => write!(f, "{} > {}", anc.ancestor, anc.child),
+            Selector::Ancestor(anc) => write!(f, "{} {}", anc.ancestor, anc.child),
+            Selector::Sibling(sib) if sib.adjacent => write!(f, "{} + {}", sib.previous, sib.next),
+            Selector::Sibling(sib) => write!(f, "{} ~ {}", sib.previous, sib.next),
+        }
+    }
+}
+
+impl Display for SimpleSelector {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        if let Some(tag_name) = &self.tag_name {
+            write!(f, "{}", tag_name)?;
+        }
+        if let Some(id) = &self.id {
+            write!(f, "#{}", id)?;
+        }
+        for class in self.class.iter() {
+            write!(f, ".{}", class)?;
+        }
+        for pseudo_class in self.pseudo_class.iter() {
+            write!(f, ":{}", pseudo_class)?;
+        }
+        if let Some(pseudo_element) = &self.pseudo_element {
+            write!(f, "::{}", pseudo_element)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Declaration {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.name)?;
+        match (self.name.as_str(), &self.value) {
+            ("font", Value::ArrayValue(values)) if values.len() == FONT_LONGHANDS.len() => write_font(f, values),
+            ("background", Value::ArrayValue(values)) if values.len() == BACKGROUND_LONGHANDS.len() => {
+                write!(f, "{} {} {} {} / {}", values[0], values[1], values[2], values[3], values[4])
+            }
+            (name, Value::ArrayValue(values)) if is_comma_list(name) => write!(f, "{}", join(values, ", ")),
+            ("animation", Value::ArrayValue(values)) if values.len() == ANIMATION_LONGHANDS.len() => write_animation(f, values),
+            ("border-radius", Value::ArrayValue(values)) if values.len() == BORDER_RADIUS_LONGHANDS.len() => write_border_radius(f, values),
+            (_, value) => value.fmt(f),
+        }?;
+        if self.important {
+            write!(f, " !important")?;
+        }
+        Ok(())
+    }
+}
+
+// every longhand is written, the defaults too, so a `none` name can't be mistaken for the fill mode
+fn write_animation(f:&mut Formatter, values:&[Value]) -> fmt::Result {
+    let layers = match &values[0] {
+        Value::ArrayValue(names) => names.len(),
+        _ => 1,
+    };
+    let layer = |i:usize| -> Vec<&Value> {
+        values.iter().map(|value| match value {
+            Value::ArrayValue(list) if layers > 1 => &list[i],
+            value => value,
+        }).collect()
+    };
+    let text:Vec<String> = (0..layers).map(|i| join(&layer(i), " ")).collect();
+    write!(f, "{}", text.join(", "))
+}
+
+// all four corners, then the vertical radii after a slash if any corner has its own
+fn write_border_radius(f:&mut Formatter, values:&[Value]) -> fmt::Result {
+    let radius = |value:&Value, i:usize| match value {
+        Value::ArrayValue(radii) if radii.len() == 2 => radii[i].to_string(),
+        value => value.to_string(),
+    };
+    let horizontal:Vec<String> = values.iter().map(|value| radius(value, 0)).collect();
+    write!(f, "{}", horizontal.join(" "))?;
+    if values.iter().any(|value| matches!(value, Value::ArrayValue(_))) {
+        let vertical:Vec<String> = values.iter().map(|value| radius(value, 1)).collect();
+        write!(f, " / {}", vertical.join(" "))?;
+    }
+    Ok(())
+}
+
+// the parser fills in what the shorthand leaves out with `normal`, so there's no need to write it
+fn write_font(f:&mut Formatter, values:&[Value]) -> fmt::Result {
+    let is_normal = |value:&Value| matches!(value, Value::Keyword(kw) if kw == "normal");
+    for value in values[0..3].iter().filter(|value| !is_normal(value)) {
+        write!(f, "{} ", value)?;
+    }
+    write!(f, "{}", values[3])?;
+    if !is_normal(&values[4]) {
+        write!(f, "/{}", values[4])?;
+    }
+    match &values[5] {
+        Value::ArrayValue(families) => write!(f, " {}", join(families, ", ")),
+        family => write!(f, " {}", family),
+    }
+}
+
+impl Display for Unit {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        let name = match self {
+            Unit::Px => "px",
+            Unit::Em => "em",
+            Unit::Per => "%",
+            Unit::Rem => "rem",
+            Unit::Pt => "pt",
+            Unit::Pc => "pc",
+            Unit::In => "in",
+            Unit::Cm => "cm",
+            Unit::Mm => "mm",
+            Unit::Ch => "ch",
+            Unit::Ex => "ex",
+            Unit::Fr => "fr",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for Color {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        if self.a == 255 {
+            write!(f, "rgb({}, {}, {})", self.r, self.g, self.b)
+        } else {
+            // three places is enough to get the same byte back
+            let alpha = format!("{:.3}", self.a as f32 / 255.0);
+            write!(f, "rgba({}, {}, {}, {})", self.r, self.g, self.b, alpha.trim_end_matches('0').trim_end_matches('.'))
+        }
+    }
+}
+
+impl Display for FunCallValue {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        match (self.name.as_str(), self.arguments.as_slice()) {
+            // a font's format() hint is kept as a second argument, but written after the url
+            ("url", [url, format]) => write!(f, "url({}) {}", url, format),
+            _ => write!(f, "{}({})", self.name, join(&self.arguments, ", ")),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        match self {
+            Value::Keyword(kw) => write!(f, "{}", kw),
+            Value::Length(v, unit) => write!(f, "{}{}", v, unit),
+            Value::ColorValue(color) => color.fmt(f),
+            Value::HexColor(hex) => write!(f, "{}", hex),
+            Value::ArrayValue(values) => write!(f, "{}", join(values, " ")),
+            Value::FunCall(call) => call.fmt(f),
+            Value::StringLiteral(text) => write!(f, "\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"")),
+            Value::UnicodeCodepoint(cp) => write!(f, "U+{:04X}", cp),
+            Value::UnicodeRange(start, end) => write!(f, "U+{:04X}-{:04X}", start, end),
+            Value::Number(v) => write!(f, "{}", v),
+            Value::Unparsed(raw) => write!(f, "{}", raw),
+            Value::Calc(expr) => write!(f, "calc({})", expr),
+            Value::Time(v) => write!(f, "{}s", v),
+            Value::Angle(v) => write!(f, "{}deg", v),
+        }
+    }
+}
+
+// nested operations are wrapped in parentheses rather than relying on precedence
+fn calc_operand(expr:&CalcExpr) -> String {
+    match expr {
+        CalcExpr::Length(_, _) | CalcExpr::Number(_) => expr.to_string(),
+        _ => format!("({})", expr),
+    }
+}
+
+impl Display for CalcExpr {
+    fn fmt(&self, f:&mut Formatter) -> fmt::Result {
+        match self {
+            CalcExpr::Length(v, unit) => write!(f, "{}{}", v, unit),
+            CalcExpr::Number(v) => write!(f, "{}", v),
+            CalcExpr::Sum(a, b) => write!(f, "{} + {}", calc_operand(a), calc_operand(b)),
+            CalcExpr::Difference(a, b) => write!(f, "{} - {}", calc_operand(a), calc_operand(b)),
+            CalcExpr::Product(a, b) => write!(f, "{} * {}", calc_operand(a), calc_operand(b)),
+            CalcExpr::Quotient(a, b) => write!(f, "{} / {}", calc_operand(a), calc_operand(b)),
+        }
+    }
+}
+
+#[test]
+fn test_serialize_rule() {
+    let sheet = super::parse_stylesheet("div, p.note > em:hover, h1 + h2 ~ h3::before { color: #ff0000; margin: 1px 2em 3% 0.5rem; }").unwrap();
+    assert_eq!(sheet.to_string(), "div, p.note > em:hover, h1 + h2 ~ h3::before {
+    color: #ff0000;
+    margin: 1px 2em 3% 0.5rem;
+}
+");
+    assert_eq!(Value::Calc(CalcExpr::Difference(Box::new(CalcExpr::Length(100.0, Unit::Per)),
+        Box::new(CalcExpr::Product(Box::new(CalcExpr::Number(2.0)), Box::new(CalcExpr::Length(1.5, Unit::Em))))))
+        .to_string(), "calc(100% - (2 * 1.5em))");
+    assert_eq!(Value::ColorValue(Color { r: 1, g: 2, b: 3, a: 255 }).to_string(), "rgb(1, 2, 3)");
+    assert_eq!(Value::ColorValue(Color { r: 1, g: 2, b: 3, a: 51 }).to_string(), "rgba(1, 2, 3, 0.2)");
+    assert_eq!(Value::ColorValue(Color { r: 0, g: 0, b: 0, a: 0 }).to_string(), "rgba(0, 0, 0, 0)");
+    assert_eq!(Value::UnicodeRange(0x100, 0x24f).to_string(), "U+0100-024F");
+    assert_eq!(Value::StringLiteral(String::from(r#"say "hi" \"#)).to_string(), r#""say \"hi\" \\""#);
+}
+
+#[test]
+fn test_serialize_round_trip() {
+    let text = r#"@charset "UTF-8";
+/* a comment */
+@import url("a.css");
+@font-face { font-family: "Open Sans"; src: url("a.woff2") format("woff2"); unicode-range: U+0000-00FF; }
+div, p.note > em:hover, h1 + h2 ~ h3::before {
+    color: #ff0000;
+    margin: 1px 2em 3% 0.5rem;
+    font-family: Georgia, "Palatino Linotype", serif;
+    width: calc(100% - 2 * 10px);
+    background-color: rgba(1, 2, 3, 0.5);
+    border: 1px solid hsl(120, 100%, 50%);
+    line-height: 1.5;
+    -webkit-thing: foo(bar) !important;
+}
+@media screen and (min-width: 600px), not print {
+    body { font: italic bold 16px/1.5 "Open Sans", serif; }
+    @media (color) { a { font: 12px serif; } }
+}
+@supports (display: grid) and (not (display: inline-grid)) {
+    a { background: red url(bg.png) no-repeat center / cover; }
+}
+@page :first { margin-top: 0px; }
+q::before { content: 'say "hi" \\ \201C'; }
+@keyframes spin { from { width: 0px; } 50%, 75% { opacity: 0.5; } to { width: 10px; } }
+p { animation: spin 2s steps(4, end) 300ms infinite, none 1s; border-radius: 4px 50% / 2px; transform: translate(-50%, 0) rotate(0.25turn); }
+"#;
+    let sheet = super::parse_stylesheet(text).unwrap();
+    assert_eq!(sheet.rules.len(), 11);
+    let written = sheet.to_string();
+    let reparsed = super::parse_stylesheet(&written).unwrap();
+    assert_eq!(sheet, reparsed, "written as\n{}", written);
+    // and writing it again changes nothing
+    assert_eq!(reparsed.to_string(), written);
+
+    // a real sheet, with layered backgrounds and vendor prefixes
+    let tufte = std::fs::read_to_string("tests/tufte/tufte.css").unwrap();
+    let sheet = super::parse_stylesheet(&tufte).unwrap();
+    let written = sheet.to_string();
+    assert!(written.contains("background: linear-gradient(#fffff8, #fffff8), linear-gradient(#fffff8, #fffff8), linear-gradient(currentColor, currentColor);"));
+    assert_eq!(super::parse_stylesheet(&written).unwrap(), sheet, "written as\n{}", written);
+}
@@ -45,6 +45,119 @@ pub struct Node {
     pub children: Vec<Node>,
 }
 
+impl Node {
+    pub fn node_at_path(&self, path:&[usize]) -> &Node {
+        match path.split_first() {
+            Some((first, rest)) => self.children[*first].node_at_path(rest),
+            None => self,
+        }
+    }
+    /// The node at the path, or None when the path goes past the tree.
+    pub fn node_at_path_mut(&mut self, path:&[usize]) -> Option<&mut Node> {
+        match path.split_first() {
+            Some((first, rest)) => self.children.get_mut(*first)?.node_at_path_mut(rest),
+            None => Some(self),
+        }
+    }
+    pub fn text_content(&self) -> String {
+        match &self.node_type {
+            NodeType::Text(txt) => txt.clone(),
+            _ => self.children.iter().map(|ch| ch.text_content()).collect(),
+        }
+    }
+    /// Sets the attribute of the element at the path, or removes it when value is None.
+    /// None when there's no element there or the attribute already was that.
+    pub fn set_attribute(&mut self, path:&[usize], name:&str, value:Option<&str>) -> Option<Mutation> {
+        let ed = match &mut self.node_at_path_mut(path)?.node_type {
+            NodeType::Element(ed) => ed,
+            _ => return None,
+        };
+        let before = match value {
+            Some(value) => ed.attributes.insert(String::from(name), String::from(value)),
+            None => ed.attributes.remove(name),
+        };
+        if before.as_deref() == value {
+            return None;
+        }
+        Some(Mutation::Attribute(path.to_vec(), String::from(name)))
+    }
+    /// Replaces the text of the text node at the path.
+    pub fn set_text(&mut self, path:&[usize], text:&str) -> Option<Mutation> {
+        match &mut self.node_at_path_mut(path)?.node_type {
+            NodeType::Text(txt) if txt != text => {
+                *txt = String::from(text);
+                Some(Mutation::Text(path.to_vec()))
+            },
+            _ => None,
+        }
+    }
+    /// Inserts node as child `index` of the node at the path, or last when index is past the end.
+    /// None when there's no node at the path.
+    pub fn insert_child(&mut self, path:&[usize], index:usize, node:Node) -> Option<Mutation> {
+        let parent = self.node_at_path_mut(path)?;
+        let index = index.min(parent.children.len());
+        parent.children.insert(index, node);
+        Some(Mutation::ChildList(path.to_vec()))
+    }
+    /// Removes child `index` of the node at the path.
+    pub fn remove_child(&mut self, path:&[usize], index:usize) -> Option<(Node, Mutation)> {
+        let parent = self.node_at_path_mut(path)?;
+        if index >= parent.children.len() {
+            return None;
+        }
+        Some((parent.children.remove(index), Mutation::ChildList(path.to_vec())))
+    }
+    /// Moves the state to the element at the path, and clears it everywhere else. Hover and
+    /// active also apply to the element's ancestors. Returns whether any element changed.
+    pub fn set_element_state(&mut self, path:Option<&[usize]>, state:ElementState) -> bool {
+        self.update_element_state(path, state).is_some()
+    }
+    /// Like set_element_state, but returns the path of the nearest element containing every
+    /// element that changed, or None when nothing did.
+    pub fn update_element_state(&mut self, path:Option<&[usize]>, state:ElementState) -> Option<Vec<usize>> {
+        let with_ancestors = state != ElementState::FOCUS;
+        let on = match path {
+            Some([]) => true,
+            Some(_) => with_ancestors,
+            None => false,
+        };
+        let mut changed = false;
+        if let NodeType::Element(ed) = &mut self.node_type {
+            let before = ed.state;
+            ed.state.set(state, on);
+            changed = before != ed.state;
+        }
+        let mut changed_children:Vec<Vec<usize>> = vec![];
+        for (i, ch) in self.children.iter_mut().enumerate() {
+            let rest = match path {
+                Some([first, rest @ ..]) if *first == i => Some(rest),
+                _ => None,
+            };
+            if let Some(mut below) = ch.update_element_state(rest, state) {
+                below.insert(0, i);
+                changed_children.push(below);
+            }
+        }
+        match changed_children.len() {
+            _ if changed => Some(vec![]),
+            0 => None,
+            1 => changed_children.pop(),
+            _ => Some(vec![]),
+        }
+    }
+}
+
+/// A change made to the document, so styles and layout can be brought up to date.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Mutation {
+    /// the named attribute of the element at the path was set or removed
+    Attribute(Vec<usize>, String),
+    /// the text node at the path has new text
+    Text(Vec<usize>),
+    /// children were added to or removed from the node at the path
+    ChildList(Vec<usize>),
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum NodeType {
     Text(String),
@@ -58,6 +171,37 @@ pub enum NodeType {
 pub struct ElementData {
     pub tag_name: String,
     pub attributes: AttrMap,
+    // what the user is doing to the element, for :hover and friends
+    pub state: ElementState,
+}
+
+/// Bits for the user interaction states that dynamic pseudo-classes match against.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct ElementState(pub u8);
+
+impl ElementState {
+    pub const HOVER:ElementState = ElementState(1);
+    pub const ACTIVE:ElementState = ElementState(2);
+    pub const FOCUS:ElementState = ElementState(4);
+    /// The state a dynamic pseudo-class like `hover` matches, if it is one.
+    pub fn from_pseudo_class(name:&str) -> Option<ElementState> {
+        match name {
+            "hover" => Some(ElementState::HOVER),
+            "active" => Some(ElementState::ACTIVE),
+            "focus" => Some(ElementState::FOCUS),
+            _ => None,
+        }
+    }
+    pub fn contains(&self, other:ElementState) -> bool {
+        self.0 & other.0 == other.0
+    }
+    pub fn set(&mut self, other:ElementState, on:bool) {
+        if on {
+            self.0 |= other.0;
+        } else {
+            self.0 &= !other.0;
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -172,14 +316,14 @@ fn test_empty_attribute_value() {
     let mut hm = AttrMap::new();
     hm.insert(String::from("foo"), String::from("bar"));
     assert_eq!(Ok(Node {
-        node_type: NodeType::Element(ElementData{ tag_name: "b".to_string(), attributes: hm }),
+        node_type: NodeType::Element(ElementData{ tag_name: "b".to_string(), attributes: hm, state: Default::default() }),
         children: vec![]
     }), element().parse(br#"<b foo="bar"></b>"#));
 
     let mut hm = AttrMap::new();
     hm.insert(String::from("foo"), String::from(""));
     assert_eq!(Ok(Node {
-        node_type: NodeType::Element(ElementData{ tag_name: "b".to_string(), attributes: hm }),
+        node_type: NodeType::Element(ElementData{ tag_name: "b".to_string(), attributes: hm, state: Default::default() }),
         children: vec![]
     }),element().parse(br#"<b foo=""></b>"#));
 }
@@ -213,10 +357,10 @@ fn text_content<'a>() -> Parser<'a, u8, Node> {
     })
 }
 fn element_child<'a>() -> Parser<'a, u8, Node> {
-    cdata() | comment() | meta_tag() | text_content() | selfclosed_element() | standalone_element() | element()
+    cdata() | comment() | meta_tag() | text_content() | selfclosed_element() | standalone_element() | pre_element() | element()
 }
 fn standalone_tag<'a>() -> Parser<'a, u8, String> {
-    (seq(b"img")|seq(b"link") | seq(b"input") | seq(b"hr") | seq(b"input"))
+    (seq(b"img")|seq(b"link") | seq(b"input") | seq(b"hr") | seq(b"br"))
         .map(|f| v2s(&f.to_vec()))
 }
 
@@ -233,6 +377,7 @@ fn selfclosed_element<'a>() -> Parser<'a, u8, Node> {
             node_type: NodeType::Element(ElementData{
                 tag_name,
                 attributes,
+                state: Default::default(),
             }),
             children: vec![],
         }
@@ -251,6 +396,7 @@ fn standalone_element<'a>() -> Parser<'a, u8, Node> {
             node_type: NodeType::Element(ElementData{
                 tag_name,
                 attributes,
+                state: Default::default(),
             }),
             children: vec![],
         }
@@ -263,6 +409,9 @@ fn test_standlone_elements() {
     assert!(standalone_element().parse(br#"<img src="foo.png">"#).is_ok());
     assert!(standalone_element().parse(b"<link>").is_ok());
     assert!(element_child().parse(b"<link/>").is_ok());
+    assert!(standalone_element().parse(b"<br>").is_ok());
+    let p = element().parse(b"<p>one<br>two</p>").unwrap();
+    assert_eq!(p.children.len(), 3);
 }
 
 #[test]
@@ -274,7 +423,6 @@ fn test_standalone_attribute() {
 fn element<'a>() -> Parser<'a, u8, Node> {
     let p
         = open_element()
-        - space()
         + call(element_child).repeat(0..)
         - space()
         + close_element();
@@ -285,6 +433,27 @@ fn element<'a>() -> Parser<'a, u8, Node> {
             node_type: NodeType::Element(ElementData{
                 tag_name,
                 attributes,
+                state: Default::default(),
+            })
+        }
+    })
+}
+
+// a pre keeps all of its whitespace, except for a newline right after the start tag
+fn pre_element<'a>() -> Parser<'a, u8, Node> {
+    let p
+        = open_element().convert(|(tag_name, attributes)| if tag_name == "pre" { Ok((tag_name, attributes)) } else { Err(tag_name) })
+        - (seq(b"\r\n") | seq(b"\n")).opt()
+        + call(element_child).repeat(0..)
+        + close_element();
+
+    p.map(|(((tag_name, attributes), children), _end_name)|{
+        Node {
+            children,
+            node_type: NodeType::Element(ElementData{
+                tag_name,
+                attributes,
+                state: Default::default(),
             })
         }
     })
@@ -399,7 +568,7 @@ fn doctype<'a>() -> Parser<'a, u8, ()> {
     (iseq(b"<!DOCTYPE") + none_of(b">").repeat(0..) + sym(b'>')).map(|_| ())
 }
 fn document<'a>() -> Parser<'a, u8, Document> {
-    (space().opt() + doctype().opt() + space() + element()).map(|(_,node)| Document {
+    (space().opt() + doctype().opt() + space() + (pre_element() | element())).map(|(_,node)| Document {
         root_node: node,
         base_url: Url::parse("https://www.mozilla.org/").unwrap(),
     })
@@ -438,6 +607,71 @@ fn test_metatag() {
     }, result.unwrap());
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub enum ViewportWidth {
+    DeviceWidth,
+    Pixels(f32),
+}
+
+/// The `content` of a `<meta name="viewport">`, ex: "width=device-width, initial-scale=1".
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ViewportMeta {
+    pub width: Option<ViewportWidth>,
+    pub initial_scale: Option<f32>,
+}
+
+impl ViewportMeta {
+    pub fn parse(content:&str) -> ViewportMeta {
+        let mut meta = ViewportMeta::default();
+        // browsers accept semicolons as separators too
+        for pair in content.split([',', ';']) {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim().to_lowercase();
+            let value = parts.next().unwrap_or("").trim();
+            match key.as_str() {
+                "width" if value == "device-width" => meta.width = Some(ViewportWidth::DeviceWidth),
+                "width" => meta.width = value.parse().ok().map(ViewportWidth::Pixels),
+                "initial-scale" => meta.initial_scale = value.parse().ok().filter(|s:&f32| *s > 0.0),
+                _ => {}
+            }
+        }
+        meta
+    }
+    /// The width to lay the page out at on a device this many pixels wide.
+    pub fn layout_width(&self, device_width:f32) -> f32 {
+        match (&self.width, self.initial_scale) {
+            (Some(ViewportWidth::DeviceWidth), _) => device_width,
+            (Some(ViewportWidth::Pixels(px)), _) => *px,
+            (None, Some(scale)) => device_width / scale,
+            (None, None) => DEFAULT_VIEWPORT_WIDTH,
+        }
+    }
+}
+
+/// What mobile browsers lay out pages without a viewport meta tag at.
+pub const DEFAULT_VIEWPORT_WIDTH:f32 = 980.0;
+
+pub fn find_viewport_meta(node:&Node) -> Option<ViewportMeta> {
+    if let NodeType::Meta(md) = &node.node_type {
+        if md.attributes.get("name").is_some_and(|name| name.eq_ignore_ascii_case("viewport")) {
+            return md.attributes.get("content").map(|content| ViewportMeta::parse(content));
+        }
+    }
+    node.children.iter().find_map(find_viewport_meta)
+}
+
+#[test]
+fn test_viewport_meta() {
+    let doc = load_doc_from_bytestring(br#"<html><head><meta charset="UTF-8"><meta name="viewport" content="width=device-width, initial-scale=1"></head><body></body></html>"#);
+    let meta = find_viewport_meta(&doc.root_node).unwrap();
+    assert_eq!(meta, ViewportMeta { width: Some(ViewportWidth::DeviceWidth), initial_scale: Some(1.0) });
+    assert_eq!(meta.layout_width(375.0), 375.0);
+    assert_eq!(ViewportMeta::parse("width=600").layout_width(375.0), 600.0);
+    assert_eq!(ViewportMeta::parse("initial-scale=0.5; user-scalable=no").layout_width(375.0), 750.0);
+    assert_eq!(ViewportMeta::parse("minimum-scale=1").layout_width(375.0), DEFAULT_VIEWPORT_WIDTH);
+    assert_eq!(find_viewport_meta(&load_doc_from_bytestring(b"<html><body></body></html>").root_node), None);
+}
+
 #[test]
 fn test_metatag_with_closing_element() {
     assert!(meta_tag().parse(b"<meta />").is_ok())
@@ -489,13 +723,13 @@ fn test_comment() {
 #[test]
 fn test_comment_2() {
     assert_eq!(Ok(Node{
-        node_type: NodeType::Element(ElementData{ tag_name: "foo".to_string(), attributes: Default::default() }),
+        node_type: NodeType::Element(ElementData{ tag_name: "foo".to_string(), attributes: Default::default(), state: Default::default() }),
         children: vec![
             Node{ node_type: NodeType::Comment(" a cool - comment".to_string()), children: vec![] }
         ]
     }), element().parse(br"<foo><!-- a cool - comment--></foo>"));
     assert_eq!(Ok(Node{
-        node_type: NodeType::Element(ElementData{ tag_name: "foo".to_string(), attributes: Default::default() }),
+        node_type: NodeType::Element(ElementData{ tag_name: "foo".to_string(), attributes: Default::default(), state: Default::default() }),
         children: vec![
             Node{
                 node_type: NodeType::Comment(String::from(" a cool - comment")),
@@ -508,7 +742,7 @@ fn test_comment_2() {
         ]
     }), element().parse(br"<foo><!-- a cool - comment-->after</foo>"));
     assert_eq!(Ok(Node{
-        node_type: NodeType::Element(ElementData{ tag_name: "foo".to_string(), attributes: Default::default() }),
+        node_type: NodeType::Element(ElementData{ tag_name: "foo".to_string(), attributes: Default::default(), state: Default::default() }),
         children: vec![
             Node{
                 node_type: NodeType::Text(String::from("before")),
@@ -532,9 +766,11 @@ fn test_style_parse() {
        }
     </style>
     </head>"#;
-    let result = document().parse(input);
-    println!("{:?}", result);
-    match &result.unwrap().root_node.children[0].children[0].node_type {
+    // the whitespace between the tags is kept until it's stripped
+    let mut doc = document().parse(input).unwrap();
+    strip_empty_nodes(&mut doc);
+    println!("{:?}", doc);
+    match &doc.root_node.children[0].children[0].node_type {
         NodeType::Text(txt) => {
             println!("got the text {}",txt);
             let ss = parse_stylesheet(txt);
@@ -552,7 +788,10 @@ fn test_simple_doc() {
     <head>
         <meta charset="UTF-8"></head></html>
     "#;
-    let result = document().parse(input);
+    let mut result = document().parse(input);
+    if let Ok(doc) = &mut result {
+        strip_empty_nodes(doc);
+    }
     println!("foo");
     println!("{:?}", result);
     let mut atts = HashMap::new();
@@ -561,13 +800,15 @@ fn test_simple_doc() {
         root_node: Node {
             node_type: NodeType::Element(ElementData{
                 tag_name: "html".to_string(),
-                attributes: Default::default()
+                attributes: Default::default(),
+                state: Default::default(),
             }),
             children: vec![
                 Node {
                     node_type: NodeType::Element(ElementData {
                         tag_name:"head".to_string(),
-                        attributes: Default::default()
+                        attributes: Default::default(),
+                        state: Default::default(),
                     }),
                     children: vec![
                         Node{
@@ -594,13 +835,15 @@ fn test_no_quotes_atts() {
         root_node: Node {
             node_type: NodeType::Element(ElementData{
                 tag_name: "html".to_string(),
-                attributes: Default::default()
+                attributes: Default::default(),
+                state: Default::default(),
             }),
             children: vec![
                 Node {
                     node_type: NodeType::Element(ElementData {
                         tag_name:"foo".to_string(),
                         attributes: atts,
+                        state: Default::default(),
                     }),
                     children: vec![
                     ]
@@ -622,19 +865,22 @@ fn test_file_load() {
         root_node: Node {
             node_type: NodeType::Element(ElementData {
                 tag_name: "html".to_string(),
-                attributes: HashMap::new()
+                attributes: HashMap::new(),
+                state: Default::default(),
             }),
             children: vec![
                 Node {
                     node_type: NodeType::Element(ElementData {
                         tag_name: "head".to_string(),
-                        attributes: Default::default()
+                        attributes: Default::default(),
+                        state: Default::default(),
                     }),
                     children: vec![
                         Node {
                             node_type: NodeType::Element(ElementData {
                                 tag_name: "title".to_string(),
-                                attributes: Default::default()
+                                attributes: Default::default(),
+                                state: Default::default(),
                             }),
                             children: vec![text("Title".to_string())]
                         },
@@ -643,7 +889,8 @@ fn test_file_load() {
                 Node {
                     node_type: NodeType::Element(ElementData {
                         tag_name: "body".to_string(),
-                        attributes: Default::default()
+                        attributes: Default::default(),
+                        state: Default::default(),
                     }),
                     children: vec![text("some text".to_string())
                     ],
@@ -693,6 +940,11 @@ pub fn strip_empty_nodes(doc:&mut Document) {
     strip_empty_nodes_helper(&mut doc.root_node);
 }
 fn strip_empty_nodes_helper(node:&mut Node) {
+    if let NodeType::Element(ed) = &node.node_type {
+        if ed.tag_name == "pre" {
+            return;
+        }
+    }
     node.children.retain(|ch| {
         match &ch.node_type {
             NodeType::Text(str) => !str.trim().is_empty(),
@@ -722,19 +974,22 @@ fn test_strip_empty_nodes() {
             root_node: Node {
                 node_type: NodeType::Element(ElementData{
                     tag_name: "html".to_string(),
-                    attributes: Default::default()
+                    attributes: Default::default(),
+                    state: Default::default(),
                 }),
                 children: vec![
                     Node {
                         node_type: NodeType::Element(ElementData {
                             tag_name:"body".to_string(),
-                            attributes: Default::default()
+                            attributes: Default::default(),
+                            state: Default::default(),
                         }),
                         children: vec![
                             Node {
                                 node_type: NodeType::Element(ElementData {
                                     tag_name:"div".to_string(),
-                                    attributes: Default::default()
+                                    attributes: Default::default(),
+                                    state: Default::default(),
                                 }),
                                 children: vec![
                                     Node {
@@ -762,6 +1017,7 @@ fn expand_entities_helper(node:&mut Node) {
             let mut str2 = String::from(str);
             str2 = str2.replace("&lt;","<");
             str2 = str2.replace("&gt;",">");
+            str2 = str2.replace("&quot;","\"");
             str2 = str2.replace("&amp;","&");
             ch.node_type = NodeType::Text(str2);
         }
@@ -787,19 +1043,22 @@ fn test_expand_entities() {
             root_node: Node {
                 node_type: NodeType::Element(ElementData{
                     tag_name: "html".to_string(),
-                    attributes: Default::default()
+                    attributes: Default::default(),
+                    state: Default::default(),
                 }),
                 children: vec![
                     Node {
                         node_type: NodeType::Element(ElementData {
                             tag_name:"body".to_string(),
-                            attributes: Default::default()
+                            attributes: Default::default(),
+                            state: Default::default(),
                         }),
                         children: vec![
                             Node {
                                 node_type: NodeType::Element(ElementData {
                                     tag_name:"div".to_string(),
-                                    attributes: Default::default()
+                                    attributes: Default::default(),
+                                    state: Default::default(),
                                 }),
                                 children: vec![
                                     Node {
@@ -819,4 +1078,138 @@ fn test_expand_entities() {
 
 }
 
+#[test]
+fn test_expand_quote_entity() {
+    let mut doc = document().parse(b"<html><body><div>say &quot;hi&quot; &amp;quot;</div></body></html>").unwrap();
+    expand_entities(&mut doc);
+    assert_eq!(getElementsByTagName(&doc.root_node, "div")[0].text_content(), "say \"hi\" &quot;");
+}
+
+
+
+// elements that never have a closing tag, so don't stay open
+const VOID_TAGS:[&str;6] = ["img", "link", "input", "hr", "br", "meta"];
+
+/// Collects a document's bytes as they arrive over the network. At any point the part
+/// received so far can be parsed on its own: it is cut after the last complete tag and
+/// the elements still open are closed, so the page can be shown before it has finished.
+#[derive(Default)]
+pub struct IncrementalParser {
+    buf: Vec<u8>,
+}
+
+impl IncrementalParser {
+    pub fn new() -> Self {
+        IncrementalParser { buf: vec![] }
+    }
+    pub fn feed(&mut self, bytes:&[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+    // where the complete markup ends, and the names of the elements open at that point
+    fn complete_prefix(&self) -> (usize, Vec<String>) {
+        let buf = &self.buf;
+        let mut open:Vec<String> = vec![];
+        let mut cut = 0;
+        let mut pos = 0;
+        while let Some(start) = buf[pos..].iter().position(|b| *b == b'<').map(|i| pos + i) {
+            let rest = &buf[start..];
+            let end = if rest.starts_with(b"<!--") {
+                find_bytes(rest, b"-->").map(|i| i + 3)
+            } else if rest.starts_with(b"<![CDATA[") {
+                find_bytes(rest, b"]]>").map(|i| i + 3)
+            } else {
+                tag_end(rest)
+            };
+            let end = match end {
+                Some(end) => start + end,
+                // the tag is still arriving
+                None => break,
+            };
+            let tag = &buf[start + 1..end - 1];
+            if let Some(name) = tag.strip_prefix(b"/") {
+                let name = tag_name(name);
+                if let Some(i) = open.iter().rposition(|n| *n == name) {
+                    open.truncate(i);
+                }
+            } else if !tag.starts_with(b"!") && !tag.ends_with(b"/") {
+                let name = tag_name(tag);
+                if !name.is_empty() && !VOID_TAGS.contains(&name.as_str()) {
+                    open.push(name);
+                }
+            }
+            cut = end;
+            pos = end;
+        }
+        (cut, open)
+    }
+    /// The document so far, or None if not enough has arrived to make one.
+    pub fn partial_document(&self) -> Option<Document> {
+        let (cut, open) = self.complete_prefix();
+        if open.is_empty() && cut == 0 {
+            return None;
+        }
+        let mut bytes = self.buf[..cut].to_vec();
+        for name in open.iter().rev() {
+            bytes.extend_from_slice(format!("</{}>", name).as_bytes());
+        }
+        let parser = document();
+        parser.parse(&bytes).ok()
+    }
+    /// Everything has arrived. Parses the whole thing.
+    pub fn finish(self) -> Result<Document, BrowserError> {
+        let doc = document().parse(&self.buf)?;
+        Ok(doc)
+    }
+}
+
+fn find_bytes(haystack:&[u8], needle:&[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+// the index just past the '>' closing the tag at the start, skipping any inside quotes
+fn tag_end(tag:&[u8]) -> Option<usize> {
+    let mut quote:Option<u8> = None;
+    for (i, b) in tag.iter().enumerate() {
+        match quote {
+            Some(q) if *b == q => quote = None,
+            Some(_) => {},
+            None if *b == b'"' || *b == b'\'' => quote = Some(*b),
+            None if *b == b'>' => return Some(i + 1),
+            None => {},
+        }
+    }
+    None
+}
 
+fn tag_name(tag:&[u8]) -> String {
+    let name:Vec<u8> = tag.iter().take_while(|b| b.is_ascii_alphanumeric()).cloned().collect();
+    v2s(&name).to_lowercase()
+}
+
+#[test]
+fn test_incremental_parser() {
+    let page = br#"<html><head><title>t</title></head><body><p class="a>b">first <b>bold</b></p><img src="x.png"><!-- a <p> comment --><p>second</p></body></html>"#;
+    let mut parser = IncrementalParser::new();
+    assert!(parser.partial_document().is_none());
+    let text_of = |doc:Document| doc.root_node.text_content();
+    // stops inside the first paragraph's bold text
+    let split = find_bytes(page, b"old</b>").unwrap();
+    parser.feed(&page[..split]);
+    let doc = parser.partial_document().unwrap();
+    let p = getElementsByTagName(&doc.root_node, "p");
+    assert_eq!(p.len(), 1);
+    assert_eq!(text_of(doc), "tfirst ");
+    // in the middle of the comment, which hides the p inside it
+    let split = find_bytes(page, b"comment").unwrap();
+    parser.feed(&page[parser.len()..split]);
+    assert_eq!(text_of(parser.partial_document().unwrap()), "tfirst bold");
+    parser.feed(&page[parser.len()..]);
+    assert_eq!(text_of(parser.partial_document().unwrap()), "tfirst boldsecond");
+    assert_eq!(text_of(parser.finish().unwrap()), "tfirst boldsecond");
+}
@@ -1,20 +1,28 @@
-use crate::dom::{NodeType, Document, load_doc_from_bytestring, strip_empty_nodes};
-use crate::style::{StyledNode, Display, dom_tree_to_stylednodes, expand_styles, StyledTree};
+use crate::dom::{NodeType, Document, ElementData, load_doc_from_bytestring, strip_empty_nodes};
+use crate::style::{StyledNode, Display, VerticalAlign, WhiteSpace, dom_tree_to_stylednodes, strip_collapsible_whitespace, expand_styles, StyledTree};
+use crate::style::transform::Matrix;
+use crate::style::counters::format_counter;
 use crate::css::{Color, Unit, Value, parse_stylesheet_from_bytestring, Stylesheet};
-use crate::layout::BoxType::{BlockNode, InlineNode, AnonymousBlock, InlineBlockNode, TableNode, TableRowGroupNode, TableRowNode, TableCellNode, ListItemNode};
+use crate::layout::BoxType::{BlockNode, InlineNode, AnonymousBlock, InlineBlockNode, TableNode, TableRowGroupNode, TableRowNode, TableCellNode, ListItemNode, FlexNode, GridNode};
 use crate::css::Value::{Keyword, Length};
 use crate::css::Unit::Px;
-use crate::render::{BLACK, FontCache};
+use crate::render::{BLACK, FontCache, TextRendering};
 use crate::image::{LoadedImage};
 use crate::dom::NodeType::{Text, Element};
 use crate::net::{load_image, load_stylesheet_from_net, relative_filepath_to_url, load_doc_from_net, BrowserError, StylesheetSet, load_stylesheets_new};
 use std::mem;
-use glium_glyph::glyph_brush::{Section, rusttype::{Scale, Font}};
+use std::hash::{Hash, Hasher};
+use glium_glyph::glyph_brush::{Section, FontId, rusttype::{Scale, Font, VMetrics}};
 use glium_glyph::glyph_brush::GlyphCruncher;
 use glium_glyph::glyph_brush::rusttype::Rect as GBRect;
+use glium_glyph::glyph_brush::{Layout, BuiltInLineBreaker, GlyphPositioner, FontMap, SectionGeometry, SectionText, Color as GlyphColor};
+use glium_glyph::glyph_brush::rusttype::{PositionedGlyph, point};
 use std::rc::Rc;
 
 const FUDGE:f32 = 2.0;
+// grid lines, repeats and spans past this many are cut down to it, so a page can't ask
+// for more tracks than there's memory for
+const MAX_GRID_TRACKS:usize = 1000;
 
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Dimensions {
@@ -44,13 +52,57 @@ pub struct Rect {
     pub height: f32,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ListMarker {
     Disc,
-    Decimal,
+    Circle,
+    Square,
+    /// the item's number in its list
+    Decimal(i32),
+    DecimalLeadingZero(i32),
+    LowerAlpha(i32),
+    UpperAlpha(i32),
+    LowerRoman(i32),
+    UpperRoman(i32),
     None,
 }
 
+impl ListMarker {
+    /// The text drawn for the marker, if it has one.
+    pub fn text(&self) -> Option<String> {
+        match self {
+            ListMarker::Disc => Some(String::from("•")),
+            ListMarker::Circle => Some(String::from("◦")),
+            ListMarker::Square => Some(String::from("▪")),
+            ListMarker::Decimal(n) => Some(format!("{}.", n)),
+            ListMarker::DecimalLeadingZero(n) => Some(format!("{}.", format_counter(*n, "decimal-leading-zero"))),
+            ListMarker::LowerAlpha(n) => Some(format!("{}.", format_counter(*n, "lower-alpha"))),
+            ListMarker::UpperAlpha(n) => Some(format!("{}.", format_counter(*n, "upper-alpha"))),
+            ListMarker::LowerRoman(n) => Some(format!("{}.", format_counter(*n, "lower-roman"))),
+            ListMarker::UpperRoman(n) => Some(format!("{}.", format_counter(*n, "upper-roman"))),
+            ListMarker::None => None,
+        }
+    }
+}
+
+fn list_marker(style:&StyledNode) -> ListMarker {
+    if !matches!(style.display(), Display::ListItem) {
+        return ListMarker::None;
+    }
+    match &*style.lookup_string("list-style-type", "none") {
+        "disc" => ListMarker::Disc,
+        "circle" => ListMarker::Circle,
+        "square" => ListMarker::Square,
+        "decimal" => ListMarker::Decimal(style.list_item_number()),
+        "decimal-leading-zero" => ListMarker::DecimalLeadingZero(style.list_item_number()),
+        "lower-alpha" | "lower-latin" => ListMarker::LowerAlpha(style.list_item_number()),
+        "upper-alpha" | "upper-latin" => ListMarker::UpperAlpha(style.list_item_number()),
+        "lower-roman" => ListMarker::LowerRoman(style.list_item_number()),
+        "upper-roman" => ListMarker::UpperRoman(style.list_item_number()),
+        _ => ListMarker::None,
+    }
+}
+
 impl Rect {
     pub fn with_inset(self, val:f32) -> Rect {
         Rect {
@@ -71,6 +123,100 @@ impl Rect {
     pub fn contains(self, x:f32, y:f32) -> bool {
         self.x <= x && self.x + self.width >= x && self.y <= y && self.y + self.height > y
     }
+    fn translate(&mut self, dx:f32, dy:f32) {
+        self.x += dx;
+        self.y += dy;
+    }
+    /// The part of the rect that is also in the other one, empty when they don't overlap.
+    pub fn intersection(self, other:Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        Rect { x, y, width: (right - x).max(0.0), height: (bottom - y).max(0.0) }
+    }
+}
+
+/// Something that can differ on each side of a box, like a border's color or style.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BorderSides<T> {
+    pub top: T,
+    pub right: T,
+    pub bottom: T,
+    pub left: T,
+}
+
+impl<T:Clone> BorderSides<T> {
+    pub fn all(value:T) -> Self {
+        BorderSides { top: value.clone(), right: value.clone(), bottom: value.clone(), left: value }
+    }
+}
+
+/// A line drawn around the outside of a box's border. Unlike a border it takes up no room,
+/// so it can't move anything around.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outline {
+    pub width: f32,
+    pub style: BorderStyle,
+    pub color: Color,
+}
+
+impl Outline {
+    /// where the outline goes, around the border box
+    pub fn rect(&self, border_box:Rect) -> Rect {
+        border_box.expanded_by(EdgeSizes { left: self.width, right: self.width, top: self.width, bottom: self.width })
+    }
+}
+
+/// The horizontal and vertical radii of one rounded corner, in px. Zero on either keeps it square.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CornerRadius {
+    pub horizontal: f32,
+    pub vertical: f32,
+}
+
+/// How much each corner of a box's border is rounded.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CornerRadii {
+    pub top_left: CornerRadius,
+    pub top_right: CornerRadius,
+    pub bottom_right: CornerRadius,
+    pub bottom_left: CornerRadius,
+}
+
+impl CornerRadii {
+    pub fn is_square(&self) -> bool {
+        [self.top_left, self.top_right, self.bottom_right, self.bottom_left].iter()
+            .all(|corner| corner.horizontal <= 0.0 || corner.vertical <= 0.0)
+    }
+    // where the corners on one side need more room than the side has, all of them shrink
+    // by the same factor so the curves just meet
+    fn fit(self, width:f32, height:f32) -> CornerRadii {
+        let ratio = |length:f32, a:f32, b:f32| if a + b > length { length / (a + b) } else { 1.0 };
+        let scale = ratio(width, self.top_left.horizontal, self.top_right.horizontal)
+            .min(ratio(width, self.bottom_left.horizontal, self.bottom_right.horizontal))
+            .min(ratio(height, self.top_left.vertical, self.bottom_left.vertical))
+            .min(ratio(height, self.top_right.vertical, self.bottom_right.vertical))
+            .max(0.0);
+        let shrink = |corner:CornerRadius| CornerRadius { horizontal: corner.horizontal * scale, vertical: corner.vertical * scale };
+        CornerRadii {
+            top_left: shrink(self.top_left),
+            top_right: shrink(self.top_right),
+            bottom_right: shrink(self.bottom_right),
+            bottom_left: shrink(self.bottom_left),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BorderStyle {
+    None,
+    Hidden,
+    #[default]
+    Solid,
+    Dashed,
+    Dotted,
+    Double,
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -81,11 +227,232 @@ pub struct EdgeSizes {
     pub bottom: f32,
 }
 
+/// The narrowest a box can be without overflowing (longest word), and the width it
+/// would take up if nothing wrapped. Both are for the margin box.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct IntrinsicWidths {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl IntrinsicWidths {
+    // siblings side by side on a line
+    fn beside(self, other:IntrinsicWidths) -> IntrinsicWidths {
+        IntrinsicWidths { min: self.min.max(other.min), max: self.max + other.max }
+    }
+    // siblings stacked vertically
+    fn above(self, other:IntrinsicWidths) -> IntrinsicWidths {
+        IntrinsicWidths { min: self.min.max(other.min), max: self.max.max(other.max) }
+    }
+    fn plus(self, extra:f32) -> IntrinsicWidths {
+        IntrinsicWidths { min: self.min + extra, max: self.max + extra }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FloatSide {
+    Left,
+    Right,
+}
+
+/// A floated box that has been placed: its side and its margin box, in page coordinates.
+#[derive(Clone, Copy, Debug)]
+pub struct FloatArea {
+    pub side: FloatSide,
+    pub rect: Rect,
+}
+
+impl FloatArea {
+    fn bottom(&self) -> f32 {
+        self.rect.y + self.rect.height
+    }
+    fn covers(&self, y:f32) -> bool {
+        self.rect.y <= y && y < self.bottom()
+    }
+}
+
+// the room left between left and right at height y, once the floats there take their share
+fn float_bounds(floats:&[FloatArea], y:f32, left:f32, right:f32) -> (f32, f32) {
+    floats.iter().filter(|area| area.covers(y)).fold((left, right), |(l, r), area| match area.side {
+        FloatSide::Left => (l.max(area.rect.x + area.rect.width), r),
+        FloatSide::Right => (l, r.min(area.rect.x)),
+    })
+}
+
+// CSS 2.1 9.5.1: a float goes as high as it can, but not above an earlier float, and moves
+// down past floats until it fits. Returns the top left of its margin box.
+fn float_position(floats:&[FloatArea], side:FloatSide, width:f32, top:f32, left:f32, right:f32) -> (f32, f32) {
+    let mut y = floats.iter().map(|area| area.rect.y).fold(top, f32::max);
+    loop {
+        let (l, r) = float_bounds(floats, y, left, right);
+        let next = floats.iter().filter(|area| area.covers(y)).map(|area| area.bottom()).fold(f32::INFINITY, f32::min);
+        if r - l >= width || next.is_infinite() {
+            return match side {
+                FloatSide::Left => (l, y),
+                FloatSide::Right => (r - width, y),
+            };
+        }
+        y = next;
+    }
+}
+
+/// Extra room from letter-spacing and word-spacing, in px.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextSpacing {
+    /// added after every character
+    pub letter: f32,
+    /// added to every space, on top of the letter spacing
+    pub word: f32,
+}
+
+impl TextSpacing {
+    fn extra_width(&self, text:&str) -> f32 {
+        let letters = text.chars().count() as f32;
+        let spaces = text.chars().filter(|ch| *ch == ' ').count() as f32;
+        letters * self.letter + spaces * self.word
+    }
+}
+
+fn text_spacing(style:&StyledNode) -> TextSpacing {
+    TextSpacing {
+        letter: style.lookup_spacing("letter-spacing"),
+        word: style.lookup_spacing("word-spacing"),
+    }
+}
+
+/// What the `ch` and `ex` units come to in a box's font.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontUnits {
+    pub ch: f32,
+    pub ex: f32,
+}
+
+impl FontUnits {
+    // for when there are no font metrics
+    fn half_em(font_size:f32) -> FontUnits {
+        FontUnits { ch: font_size / 2.0, ex: font_size / 2.0 }
+    }
+}
+
+/// What a box's lengths resolve against. Percentages are of the containing block's width,
+/// or of its height for heights, and font relative units come from the box's own font.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LengthContext {
+    pub font_size: f32,
+    /// the root element's font size, for rems
+    pub root_font_size: f32,
+    pub font_units: FontUnits,
+    pub containing_width: f32,
+    /// None while the containing block's height still depends on its content
+    pub containing_height: Option<f32>,
+}
+
+impl LengthContext {
+    pub fn new(style:&StyledNode, containing_width:f32, containing_height:Option<f32>) -> LengthContext {
+        let font_size = style.lookup_font_size();
+        LengthContext {
+            font_size,
+            root_font_size: style.lookup_root_font_size(),
+            font_units: FontUnits::half_em(font_size),
+            containing_width,
+            containing_height,
+        }
+    }
+    /// A length, percentage or calc() in px, with percentages taken of `percent_of`.
+    /// None for keywords like auto, and for a percentage of a size that isn't known.
+    pub fn to_px(&self, value:&Value, percent_of:Option<f32>) -> Option<f32> {
+        match value {
+            Length(v, Unit::Per) => percent_of.map(|base| base * v / 100.0),
+            Length(v, Unit::Px) => Some(*v),
+            Length(v, Unit::Em) => Some(v * self.font_size),
+            Length(v, Unit::Rem) => Some(v * self.root_font_size),
+            Length(v, Unit::Ch) => Some(v * self.font_units.ch),
+            Length(v, Unit::Ex) => Some(v * self.font_units.ex),
+            Length(v, unit) => Some(v * unit.px_per_unit().unwrap_or(0.0)),
+            Value::Calc(expr) => Some(expr.to_px(self.font_size, percent_of.unwrap_or(0.0))),
+            // a zero needs no unit
+            Value::Number(v) if *v == 0.0 => Some(0.0),
+            _ => None,
+        }
+    }
+    /// margins, padding, widths and left/right offsets, which all take percentages of the width
+    pub fn horizontal(&self, value:&Value) -> Option<f32> {
+        self.to_px(value, Some(self.containing_width))
+    }
+    /// heights and top/bottom offsets
+    pub fn vertical(&self, value:&Value) -> Option<f32> {
+        self.to_px(value, self.containing_height)
+    }
+}
+
 #[derive(Debug)]
 pub struct LayoutBox {
     pub dimensions: Dimensions,
     pub box_type: BoxType,
     pub children: Vec<LayoutBox>,
+    /// filled in by the measuring pass, before placement
+    pub intrinsic: IntrinsicWidths,
+    /// the content box's widths from the same pass, before a fixed width takes over
+    pub content_intrinsic: IntrinsicWidths,
+    /// also from the measuring pass. None before it.
+    pub font_units: Option<FontUnits>,
+    /// the containing block's height when it doesn't depend on this box, for percentages
+    pub containing_height: Option<f32>,
+    /// the floats placed so far that lines and cleared blocks in this box have to go around
+    pub floats: Vec<FloatArea>,
+    /// where an absolutely positioned box would have started in the flow, for auto offsets
+    pub static_position: (f32, f32),
+    /// set by a flex container, so the box fills its containing block's width whatever its
+    /// width says
+    pub flex_width: bool,
+    /// set by a flex container: the content height to use instead of the height property
+    pub flex_height: Option<f32>,
+    /// set by the containing block on its first box in the flow, whose first line text-indent
+    /// moves over
+    pub first_line: bool,
+}
+
+// a flex item being sized, by the index of its box among the container's children
+struct FlexItem {
+    index: usize,
+    // margin box sizes along the main axis: from the basis, the smallest it can shrink to
+    // without overflowing, and what flexing made of it
+    base: f32,
+    min: f32,
+    main: f32,
+    grow: f32,
+    shrink: f32,
+    align: String,
+    // in a column, the margin box width, and the vertical margin, border and padding that
+    // turn a margin box height into a content height
+    cross_width: f32,
+    vertical_edges: f32,
+}
+
+// a grid item's area, by the index of its box among the container's children. columns and
+// rows count from 0, and the area takes `columns` of them from `column` on.
+struct GridArea {
+    index: usize,
+    column: usize,
+    row: usize,
+    columns: usize,
+    rows: usize,
+}
+
+// the size of a grid track as far as the template says
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GridTrack {
+    Fixed(f32),
+    Fraction(f32),
+    Auto,
+}
+
+// where a grid item starts or ends in grid-column-start and the like
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GridLine {
+    Number(i32),
+    Span(usize),
+    Auto,
 }
 
 #[derive(Debug)]
@@ -99,6 +466,8 @@ pub enum BoxType {
     TableRowNode(Rc<StyledNode>),
     TableCellNode(Rc<StyledNode>),
     ListItemNode(Rc<StyledNode>),
+    FlexNode(Rc<StyledNode>),
+    GridNode(Rc<StyledNode>),
 }
 
 #[derive(Debug)]
@@ -114,17 +483,96 @@ pub enum QueryResult<'a> {
     Text(&'a RenderTextBox),
     None(),
 }
-impl QueryResult<'_> {
+impl<'a> QueryResult<'a> {
     fn is_none(&self) -> bool {
         match self {
             QueryResult::None() =>true,
             _ => false
         }
     }
+    /// The cursor to show over the result. `auto` is a pointer over links and a text cursor
+    /// over other text, and outside of any text it is the default arrow.
+    pub fn cursor(&self) -> &'a str {
+        match self {
+            QueryResult::Text(bx) if bx.cursor == "auto" && bx.link.is_some() => "pointer",
+            QueryResult::Text(bx) if bx.cursor == "auto" => "text",
+            QueryResult::Text(bx) => &bx.cursor,
+            QueryResult::None() => "default",
+        }
+    }
 }
 
 
 impl RenderBox {
+    pub fn is_fixed(&self) -> bool {
+        matches!(self, RenderBox::Block(block) if block.fixed)
+    }
+    /// The fixed boxes, in the order they are drawn. They are drawn over the page, apart
+    /// from it, because they don't scroll with it.
+    pub fn fixed_boxes(&self) -> Vec<&RenderBox> {
+        let mut found = vec![];
+        if let RenderBox::Block(block) = self {
+            for child in block.children.iter() {
+                if child.is_fixed() {
+                    found.push(child);
+                }
+                found.extend(child.fixed_boxes());
+            }
+        }
+        found
+    }
+    /// Scrolls the innermost scrolling box under the point by as much of dy as it can take,
+    /// then the ones around it by the rest. Returns what is left for the page.
+    pub fn scroll_at(&mut self, x:f32, y:f32, dy:f32) -> f32 {
+        let block = match self {
+            RenderBox::Block(block) => block,
+            _ => return dy,
+        };
+        let y = match &block.scroll {
+            Some(area) if !area.rect.contains(x, y) => return dy,
+            Some(area) => y + area.scroll_y,
+            None => y,
+        };
+        let mut left = dy;
+        for child in block.children.iter_mut().rev() {
+            left = child.scroll_at(x, y, dy);
+            if left != dy {
+                break;
+            }
+        }
+        match &mut block.scroll {
+            Some(area) if area.user_scrollable => area.scroll_by(left),
+            _ => left,
+        }
+    }
+    /// How far each scrolling box is scrolled, by its element.
+    pub fn scroll_offsets(&self) -> Vec<(Vec<usize>, f32)> {
+        let mut found = vec![];
+        if let RenderBox::Block(block) = self {
+            if let Some(area) = &block.scroll {
+                found.push((area.element_path.clone(), area.scroll_y));
+            }
+            for child in block.children.iter() {
+                found.extend(child.scroll_offsets());
+            }
+        }
+        found
+    }
+    /// Scrolls the boxes of the elements as far as they go towards the offsets. The ones
+    /// without an offset go back to the top.
+    pub fn set_scroll_offsets(&mut self, offsets:&[(Vec<usize>, f32)]) {
+        if let RenderBox::Block(block) = self {
+            if let Some(area) = &mut block.scroll {
+                area.scroll_y = 0.0;
+                if let Some((_, scroll_y)) = offsets.iter().find(|(path, _)| *path == area.element_path) {
+                    area.scroll_by(*scroll_y);
+                }
+            }
+            for child in block.children.iter_mut() {
+                child.set_scroll_offsets(offsets);
+            }
+        }
+    }
     pub fn find_box_containing(&self, x:f32, y:f32) -> QueryResult {
         match self {
             RenderBox::Block(bx) => bx.find_box_containing(x,y),
@@ -132,6 +580,63 @@ impl RenderBox {
             _ => QueryResult::None(),
         }
     }
+    /// What is under the point on the page, with the page scrolled by scroll_y. The fixed
+    /// boxes are over the page and don't scroll with it, so they are hit first, where they
+    /// show in the window.
+    pub fn find_box_on_page(&self, x:f32, y:f32, scroll_y:f32) -> QueryResult<'_> {
+        for fixed in self.fixed_boxes().into_iter().rev() {
+            let res = fixed.find_box_containing(x, y - scroll_y);
+            if !res.is_none() {
+                return res
+            }
+        }
+        self.find_box_containing(x, y)
+    }
+    /// The element whose text is under the point, for hovering and clicking.
+    pub fn find_element_containing(&self, x:f32, y:f32, scroll_y:f32) -> Option<usize> {
+        match self.find_box_on_page(x, y, scroll_y) {
+            QueryResult::Text(bx) => Some(bx.element),
+            QueryResult::None() => None,
+        }
+    }
+    /// The document path of the element whose text is under the point.
+    pub fn find_element_path(&self, x:f32, y:f32, scroll_y:f32) -> Option<Vec<usize>> {
+        match self.find_box_on_page(x, y, scroll_y) {
+            QueryResult::Text(bx) => Some(bx.element_path.clone()),
+            QueryResult::None() => None,
+        }
+    }
+    /// Every text fragment of an element, on all the lines it spans.
+    pub fn fragments_of(&self, element:usize) -> Vec<&RenderTextBox> {
+        let mut found = vec![];
+        self.collect_fragments(element, &mut found);
+        found
+    }
+    fn collect_fragments<'a>(&'a self, element:usize, found:&mut Vec<&'a RenderTextBox>) {
+        match self {
+            RenderBox::Block(bx) => bx.children.iter().for_each(|ch| ch.collect_fragments(element, found)),
+            RenderBox::Anonymous(bx) => {
+                for line in bx.children.iter() {
+                    for child in line.children.iter() {
+                        if let RenderInlineBoxType::Text(text) = child {
+                            if text.element == element {
+                                found.push(text);
+                            }
+                        }
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+    // moves the box and everything in it, used when fragmenting content into columns
+    fn translate(&mut self, dx:f32, dy:f32) {
+        match self {
+            RenderBox::Block(bx) => bx.translate(dx,dy),
+            RenderBox::Anonymous(bx) => bx.translate(dx,dy),
+            _ => {},
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -141,21 +646,111 @@ pub struct RenderBlockBox {
     pub margin:EdgeSizes,
     pub padding:EdgeSizes,
     pub background_color: Option<Color>,
-    pub border_color: Option<Color>,
+    pub border_color: BorderSides<Option<Color>>,
+    pub border_style: BorderSides<BorderStyle>,
     pub border_width: EdgeSizes,
-    pub valign:String,
+    pub valign:VerticalAlign,
     pub children: Vec<RenderBox>,
     pub marker:ListMarker,
+    /// where the marker starts, from the left of the content. outside markers are negative.
+    pub marker_offset:f32,
     pub color:Option<Color>,
     pub font_size:f32,
     pub font_family:String,
     pub font_weight:i32,
     pub font_style:String,
+    /// the block's own opacity. painting multiplies it with the opacity of the boxes around it.
+    pub opacity:f32,
+    /// false for visibility: hidden. the block's background, borders and marker aren't painted.
+    pub visible:bool,
+    pub outline:Option<Outline>,
+    /// the rounding of the border box's corners, already shrunk to fit it
+    pub border_radius:CornerRadii,
+    /// maps the page coordinates of the block and everything in it to where they are drawn
+    pub transform:Option<Matrix>,
+    /// position: fixed. it is placed in the viewport, and drawn without the page's scroll.
+    pub fixed:bool,
+    /// set when overflow isn't visible. the children are clipped to the padding box, and
+    /// scrolled inside it.
+    pub scroll:Option<ScrollArea>,
+}
+
+/// Where a box's content scrolls. The content is laid out unscrolled, and moved up by the
+/// scroll when it is drawn or hit tested. The page scrolls in the viewport the same way.
+#[derive(Clone, Debug)]
+pub struct ScrollArea {
+    /// the element it belongs to, to keep its scroll when the page is laid out again
+    pub element_path: Vec<usize>,
+    /// where the content shows, in the coordinates of the box's own content
+    pub rect: Rect,
+    /// how tall the content is, from the top of the rect
+    pub content_height: f32,
+    /// how far the content is scrolled up
+    pub scroll_y: f32,
+    /// false for overflow: hidden, which clips without letting the user scroll
+    pub user_scrollable: bool,
+}
+
+impl ScrollArea {
+    /// The page's scroll area. A viewport without a height is as tall as the page, so
+    /// nothing stops it scrolling down.
+    pub fn for_page(root:&RenderBox, viewport:Rect, scroll_y:f32) -> ScrollArea {
+        let content_height = match root {
+            RenderBox::Block(block) if viewport.height > 0.0 => {
+                let bottom = block.content_area_as_rect().expanded_by(block.margin);
+                bottom.y + bottom.height - viewport.y
+            },
+            _ => f32::INFINITY,
+        };
+        let mut area = ScrollArea { element_path: vec![], rect: viewport, content_height, scroll_y: 0.0, user_scrollable: true };
+        area.scroll_by(scroll_y);
+        area
+    }
+    pub fn max_scroll(&self) -> f32 {
+        (self.content_height - self.rect.height).max(0.0)
+    }
+    /// Scrolls by as much of dy as it can, and returns the rest for the area around it.
+    pub fn scroll_by(&mut self, dy:f32) -> f32 {
+        let before = self.scroll_y;
+        self.scroll_y = (self.scroll_y + dy).min(self.max_scroll()).max(0.0);
+        dy - (self.scroll_y - before)
+    }
+    /// Where the content shows, inside the clip around the box if there is one, in the
+    /// coordinates of the content as it scrolls.
+    pub fn content_clip(&self, outer:Option<Rect>) -> Rect {
+        let mut clip = outer.map_or(self.rect, |outer| outer.intersection(self.rect));
+        clip.translate(0.0, self.scroll_y);
+        clip
+    }
 }
 
 impl RenderBlockBox {
+    // paint only the sides this cell owns in a collapsed table, with the borders that won
+    fn apply_collapsed_borders(&mut self, sides:&BorderSides<Option<BorderEdge>>) {
+        let apply = |side:&Option<BorderEdge>, width:&mut f32, color:&mut Option<Color>, style:&mut BorderStyle| {
+            match side {
+                Some(edge) => {
+                    *width = edge.width;
+                    *color = edge.color.clone();
+                    *style = edge.style;
+                },
+                None => *color = None,
+            }
+        };
+        apply(&sides.top, &mut self.border_width.top, &mut self.border_color.top, &mut self.border_style.top);
+        apply(&sides.right, &mut self.border_width.right, &mut self.border_color.right, &mut self.border_style.right);
+        apply(&sides.bottom, &mut self.border_width.bottom, &mut self.border_color.bottom, &mut self.border_style.bottom);
+        apply(&sides.left, &mut self.border_width.left, &mut self.border_color.left, &mut self.border_style.left);
+    }
     pub fn find_box_containing(&self, x: f32, y: f32) -> QueryResult {
-        for child in self.children.iter() {
+        // clipped content can't be hit outside of the box
+        let y = match &self.scroll {
+            Some(area) if !area.rect.contains(x, y) => return QueryResult::None(),
+            Some(area) => y + area.scroll_y,
+            None => y,
+        };
+        //later children paint on top, so check them first. fixed ones aren't on the page.
+        for child in self.children.iter().rev().filter(|ch| !ch.is_fixed()) {
             let res = child.find_box_containing(x,y);
             if !res.is_none() {
                 return res
@@ -168,7 +763,13 @@ impl RenderBlockBox {
             x: self.rect.x - self.padding.left - self.border_width.left,
             y: self.rect.y - self.padding.top - self.border_width.top,
             width: self.rect.width + self.padding.left + self.padding.right + self.border_width.left + self.border_width.right,
-            height: self.rect.height + self.padding.top + self.padding.bottom + self.border_width.left + self.border_width.right,
+            height: self.rect.height + self.padding.top + self.padding.bottom + self.border_width.top + self.border_width.bottom,
+        }
+    }
+    fn translate(&mut self, dx:f32, dy:f32) {
+        self.rect.translate(dx,dy);
+        for child in self.children.iter_mut() {
+            child.translate(dx,dy);
         }
     }
 }
@@ -179,8 +780,73 @@ pub struct RenderAnonymousBox {
     pub children: Vec<RenderLineBox>,
 }
 impl RenderAnonymousBox {
+    /// One fragment per line for each decorated inline element, outer elements first so
+    /// that inner backgrounds paint on top. Unless the decoration is cloned, only the
+    /// element's first fragment gets the left edge and only its last gets the right.
+    pub fn inline_fragments(&self) -> Vec<InlineFragment> {
+        // every run of boxes sharing a decoration: depth, line, and the union of their rects
+        let mut runs:Vec<(usize, usize, Rc<InlineDecoration>, Rect)> = vec![];
+        for (line_index, line) in self.children.iter().enumerate() {
+            let mut open:Vec<usize> = vec![];
+            for child in line.children.iter() {
+                let text = match child {
+                    RenderInlineBoxType::Text(text) => text,
+                    _ => { open.clear(); continue; }
+                };
+                open.truncate(text.decorations.len());
+                for (depth, decoration) in text.decorations.iter().enumerate() {
+                    let continues = open.get(depth).is_some_and(|&run| Rc::ptr_eq(&runs[run].2, decoration));
+                    if continues {
+                        let rect = &mut runs[open[depth]].3;
+                        let right = (rect.x + rect.width).max(text.rect.x + text.rect.width);
+                        let bottom = (rect.y + rect.height).max(text.rect.y + text.rect.height);
+                        rect.x = rect.x.min(text.rect.x);
+                        rect.y = rect.y.min(text.rect.y);
+                        rect.width = right - rect.x;
+                        rect.height = bottom - rect.y;
+                    } else {
+                        open.truncate(depth);
+                        open.push(runs.len());
+                        runs.push((depth, line_index, Rc::clone(decoration), text.rect));
+                    }
+                }
+            }
+        }
+        runs.sort_by_key(|run| run.0);
+        let mut fragments = vec![];
+        for (index, (_, line, decoration, rect)) in runs.iter().enumerate() {
+            let same = |other:&(usize, usize, Rc<InlineDecoration>, Rect)| Rc::ptr_eq(&other.2, decoration);
+            let first = decoration.clone || !runs[..index].iter().any(|r| same(r) && r.1 < *line);
+            let last = decoration.clone || !runs[index + 1..].iter().any(|r| same(r) && r.1 > *line);
+            let mut border_width = decoration.border_width;
+            let mut border_color = decoration.border_color.clone();
+            let mut padding = decoration.padding;
+            if !first {
+                border_width.left = 0.0;
+                border_color.left = None;
+                padding.left = 0.0;
+            }
+            if !last {
+                border_width.right = 0.0;
+                border_color.right = None;
+                padding.right = 0.0;
+            }
+            fragments.push(InlineFragment {
+                rect: rect.expanded_by(padding).expanded_by(border_width),
+                background_color: decoration.background_color.clone(),
+                border_width,
+                border_color,
+                border_style: decoration.border_style.clone(),
+                opacity: decoration.opacity,
+                visible: decoration.visible,
+            });
+        }
+        fragments
+    }
+
     pub fn find_box_containing(&self, x: f32, y: f32) -> QueryResult {
-        for child in self.children.iter() {
+        //later children paint on top, so check them first
+        for child in self.children.iter().rev() {
             let res = child.find_box_containing(x,y);
             if !res.is_none() {
                 return res
@@ -188,6 +854,12 @@ impl RenderAnonymousBox {
         }
         QueryResult::None()
     }
+    fn translate(&mut self, dx:f32, dy:f32) {
+        self.rect.translate(dx,dy);
+        for child in self.children.iter_mut() {
+            child.translate(dx,dy);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -197,8 +869,30 @@ pub struct RenderLineBox {
     pub baseline:f32,
 }
 impl RenderLineBox {
-    pub fn find_box_containing(&self, x: f32, y: f32) -> QueryResult {
+    /// Where to underline each element on this line, as one line per element rather than one
+    /// per text run, so runs of different sizes in the same link share a single underline.
+    pub fn underlines(&self) -> Vec<(Rect, &RenderTextBox)> {
+        let mut underlines:Vec<(Rect, &RenderTextBox)> = vec![];
         for child in self.children.iter() {
+            let text = match child {
+                RenderInlineBoxType::Text(text) if text.text_decoration_line == "underline" => text,
+                _ => continue,
+            };
+            match underlines.iter_mut().find(|(_, first)| first.element == text.element) {
+                Some((rect, _)) => {
+                    let right = (rect.x + rect.width).max(text.rect.x + text.rect.width);
+                    let bottom = (rect.y + rect.height).max(text.rect.y + text.rect.height);
+                    rect.x = rect.x.min(text.rect.x);
+                    rect.width = right - rect.x;
+                    rect.height = bottom - rect.y;
+                },
+                None => underlines.push((text.rect, text)),
+            }
+        }
+        underlines
+    }
+    pub fn find_box_containing(&self, x: f32, y: f32) -> QueryResult {
+        for child in self.children.iter().rev() {
             let res = match child {
                 RenderInlineBoxType::Text(node) => node.find_box_containing(x,y),
                 _ => QueryResult::None()
@@ -209,6 +903,12 @@ impl RenderLineBox {
         }
         QueryResult::None()
     }
+    fn translate(&mut self, dx:f32, dy:f32) {
+        self.rect.translate(dx,dy);
+        for child in self.children.iter_mut() {
+            child.translate(dx,dy);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -219,6 +919,25 @@ pub enum RenderInlineBoxType {
     Error(RenderErrorBox),
 }
 
+impl RenderInlineBoxType {
+    fn rect(&self) -> &Rect {
+        match self {
+            RenderInlineBoxType::Text(bx) => &bx.rect,
+            RenderInlineBoxType::Image(bx) => &bx.rect,
+            RenderInlineBoxType::Block(bx) => &bx.rect,
+            RenderInlineBoxType::Error(bx) => &bx.rect,
+        }
+    }
+    fn translate(&mut self, dx:f32, dy:f32) {
+        match self {
+            RenderInlineBoxType::Text(bx) => bx.rect.translate(dx,dy),
+            RenderInlineBoxType::Image(bx) => bx.rect.translate(dx,dy),
+            RenderInlineBoxType::Block(bx) => bx.translate(dx,dy),
+            RenderInlineBoxType::Error(bx) => bx.rect.translate(dx,dy),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct RenderTextBox {
     pub rect:Rect,
@@ -228,34 +947,107 @@ pub struct RenderTextBox {
     pub font_size:f32,
     pub font_family:String,
     pub link:Option<String>,
+    // the link's target attribute, ex: "_blank" to open it in a new window
+    pub link_target:Option<String>,
     pub font_weight:i32,
     pub font_style:String,
-    pub valign:String,
+    pub valign:VerticalAlign,
+    // the line-height in px, None for normal, which is the font's own height
+    pub line_height:Option<f32>,
+    pub spacing:TextSpacing,
+    // the opacity of the inline elements the run is inside, multiplied together
+    pub opacity:f32,
+    // hidden text keeps its place on the line, but isn't painted or hit
+    pub visible:bool,
     pub text_decoration_line:String,
+    pub pointer_events:String,
+    pub cursor:String,
+    // the decorated inline elements this run is inside, outermost first
+    pub decorations:Vec<Rc<InlineDecoration>>,
+    // identifies the element the run came from, its link if it is in one. all the
+    // fragments of an element share it, even when the element wraps across lines.
+    pub element:usize,
+    // the document path of the innermost element the run is in, for hover and focus
+    pub element_path:Vec<usize>,
 }
 impl RenderTextBox {
+    /// Where each character of the text is drawn, as an offset from the left of the box, with
+    /// the letter and word spacing added in. Only needed when there is spacing to add.
+    pub fn glyph_offsets(&self, text:&str, fc:&mut FontCache) -> Vec<(char, f32)> {
+        let font = *fc.lookup_font(&self.font_family, self.font_weight, &self.font_style);
+        let advances = fc.brush.advance_widths(font, self.font_size, text);
+        let mut x = 0.0;
+        text.chars().zip(advances).map(|(ch, advance)| {
+            let offset = x;
+            x += advance + self.spacing.extra_width(ch.encode_utf8(&mut [0; 4]));
+            (ch, offset)
+        }).collect()
+    }
     pub fn find_box_containing(&self, x: f32, y: f32) -> QueryResult {
+        if self.pointer_events == "none" || !self.visible {
+            return QueryResult::None()
+        }
         if self.rect.contains(x,y) {
             return QueryResult::Text(&self)
         }
         QueryResult::None()
     }
+    pub fn opens_new_window(&self) -> bool {
+        self.link_target.as_deref() == Some("_blank")
+    }
+}
+
+/// The background, padding and borders of an inline element, shared by all its text runs.
+#[derive(Debug)]
+pub struct InlineDecoration {
+    pub background_color:Option<Color>,
+    pub padding:EdgeSizes,
+    pub border_width:EdgeSizes,
+    pub border_color:BorderSides<Option<Color>>,
+    pub border_style:BorderSides<BorderStyle>,
+    // box-decoration-break: clone gives every fragment the full padding and borders
+    pub clone:bool,
+    // the element's opacity times that of the inline elements around it
+    pub opacity:f32,
+    pub visible:bool,
+}
+
+/// The part of a decorated inline element that falls on one line, ready to paint.
+#[derive(Debug)]
+pub struct InlineFragment {
+    pub rect:Rect,
+    pub background_color:Option<Color>,
+    pub border_width:EdgeSizes,
+    pub border_color:BorderSides<Option<Color>>,
+    pub border_style:BorderSides<BorderStyle>,
+    pub opacity:f32,
+    pub visible:bool,
+}
+
+/// How an image is sampled when it is drawn at a different size than it really is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageRendering {
+    Smooth,
+    Pixelated,
 }
 
 #[derive(Debug)]
 pub struct RenderImageBox {
     pub rect:Rect,
-    pub image:LoadedImage,
-    pub valign:String,
+    pub image:Rc<LoadedImage>,
+    pub valign:VerticalAlign,
+    pub rendering:ImageRendering,
+    pub opacity:f32,
+    pub visible:bool,
 }
 #[derive(Debug)]
 pub struct RenderErrorBox {
     pub rect:Rect,
-    pub valign:String,
+    pub valign:VerticalAlign,
 }
 
 pub fn build_layout_tree<'a>(style_node: &Rc<StyledNode>, doc:&Document) -> LayoutBox {
-    let mut root = LayoutBox::new(match style_node.display() {
+    let root = LayoutBox::new(match style_node.display() {
         Display::Block => BlockNode(Rc::clone(style_node)),
         Display::Inline => InlineNode(Rc::clone(style_node)),
         Display::InlineBlock => InlineBlockNode(Rc::clone(style_node)),
@@ -264,32 +1056,138 @@ pub fn build_layout_tree<'a>(style_node: &Rc<StyledNode>, doc:&Document) -> Layo
         Display::TableRowGroup => TableRowGroupNode(Rc::clone(style_node)),
         Display::TableRow => TableRowNode(Rc::clone(style_node)),
         Display::TableCell => TableCellNode(Rc::clone(style_node)),
+        Display::Flex => FlexNode(Rc::clone(style_node)),
+        Display::Grid => GridNode(Rc::clone(style_node)),
         Display::None => panic!("Root node has display none.")
     });
+    build_children(root, style_node, doc)
+}
 
-
+fn build_children(mut root:LayoutBox, style_node: &Rc<StyledNode>, doc:&Document) -> LayoutBox {
     for child in style_node.children.borrow().iter() {
+        let position = child.lookup_string("position", "static");
+        let out_of_flow = child.lookup_string("float", "none") != "none" || position == "absolute" || position == "fixed";
+        if !matches!(child.display(), Display::None) && out_of_flow {
+            root.children.push(build_blockified(child, doc));
+            continue;
+        }
+        // every child of a flex or grid container is a block, and text gets an anonymous one
+        if matches!(root.box_type, FlexNode(_) | GridNode(_)) {
+            match &child.node.node_type {
+                Element(_) if matches!(child.display(), Display::Inline | Display::InlineBlock) => {
+                    root.children.push(build_blockified(child, doc));
+                    continue;
+                },
+                Text(text) if text.trim().is_empty() => continue,
+                _ => {},
+            }
+        }
         match child.display() {
             Display::Block =>  root.children.push(build_layout_tree(child, doc)),
             Display::ListItem =>  root.children.push(build_layout_tree(child, doc)),
-            Display::Inline => root.get_inline_container().children.push(build_layout_tree(&child, doc)),
+            Display::Inline => {
+                let inline = build_layout_tree(&child, doc);
+                if inline.contains_block_level() {
+                    for piece in split_inline(inline) {
+                        if piece.is_block_level() {
+                            root.children.push(piece)
+                        } else {
+                            root.get_inline_container().children.push(piece)
+                        }
+                    }
+                } else {
+                    root.get_inline_container().children.push(inline)
+                }
+            },
             Display::InlineBlock => root.get_inline_container().children.push(build_layout_tree(&child, doc)),
             Display::Table => root.children.push(build_layout_tree(&child,doc)),
             Display::TableRowGroup => root.children.push(build_layout_tree(&child, doc)),
             Display::TableRow => root.children.push(build_layout_tree(&child,doc)),
             Display::TableCell => root.children.push(build_layout_tree(&child,doc)),
+            Display::Flex => root.children.push(build_layout_tree(child, doc)),
+            Display::Grid => root.children.push(build_layout_tree(child, doc)),
             Display::None => {  },
         }
     }
+    if matches!(root.box_type, TableNode(_)) {
+        root.children.sort_by_key(table_child_rank);
+    }
     root
 }
 
+// where a table's child goes, whatever the document order: captions on top, then the header
+// rows, the body, the footer rows and the captions at the bottom
+fn table_child_rank(child:&LayoutBox) -> usize {
+    let style = child.get_style_node();
+    match style.lookup_string("display", "inline").as_str() {
+        "table-caption" if style.lookup_string("caption-side", "top") == "bottom" => 4,
+        "table-caption" => 0,
+        "table-header-group" => 1,
+        "table-footer-group" => 3,
+        _ => 2,
+    }
+}
+
+// floats and absolutely positioned boxes are block boxes, whatever their display says. an
+// image keeps its inline box inside a block, so the line it's on is the image's alone.
+fn build_blockified(style_node: &Rc<StyledNode>, doc:&Document) -> LayoutBox {
+    match style_node.display() {
+        Display::Inline => build_children(LayoutBox::new(BlockNode(Rc::clone(style_node))), style_node, doc),
+        Display::InlineBlock => {
+            let mut block = LayoutBox::new(BlockNode(Rc::clone(style_node)));
+            let mut anon = LayoutBox::new(AnonymousBlock(Rc::clone(style_node)));
+            anon.children.push(build_layout_tree(style_node, doc));
+            block.children.push(anon);
+            block
+        },
+        _ => build_layout_tree(style_node, doc),
+    }
+}
+
+// CSS 2.1 9.2.1.1: an inline box containing a block box is broken around it. Returns the
+// pieces in order, each either a fragment of the inline or a block-level box lifted out of it.
+fn split_inline(inline:LayoutBox) -> Vec<LayoutBox> {
+    let style = Rc::clone(inline.get_style_node());
+    let mut pieces:Vec<LayoutBox> = vec![];
+    let mut fragment = LayoutBox::new(InlineNode(Rc::clone(&style)));
+    for child in inline.children {
+        let parts = if child.is_block_level() || !child.contains_block_level() {
+            vec![child]
+        } else {
+            split_inline(child)
+        };
+        for part in parts {
+            if part.is_block_level() {
+                if !fragment.children.is_empty() {
+                    pieces.push(mem::replace(&mut fragment, LayoutBox::new(InlineNode(Rc::clone(&style)))));
+                }
+                pieces.push(part);
+            } else {
+                fragment.children.push(part);
+            }
+        }
+    }
+    if !fragment.children.is_empty() {
+        pieces.push(fragment);
+    }
+    pieces
+}
+
 impl LayoutBox {
     fn new(box_type: BoxType) -> LayoutBox {
         LayoutBox {
             box_type,
             dimensions: Default::default(),
             children: Vec::new(),
+            intrinsic: Default::default(),
+            content_intrinsic: Default::default(),
+            font_units: None,
+            containing_height: None,
+            floats: vec![],
+            static_position: (0.0, 0.0),
+            flex_width: false,
+            flex_height: None,
+            first_line: false,
         }
     }
     fn get_style_node(&self) -> &Rc<StyledNode> {
@@ -302,10 +1200,24 @@ impl LayoutBox {
             | InlineNode(node)
             | InlineBlockNode(node)
             | ListItemNode(node)
+            | FlexNode(node)
+            | GridNode(node)
             | AnonymousBlock(node) => &node
         }
     }
 
+    fn is_block_level(&self) -> bool {
+        !matches!(self.box_type, InlineNode(_) | InlineBlockNode(_) | AnonymousBlock(_))
+    }
+
+    // is there a block-level box inside this inline, at any depth
+    fn contains_block_level(&self) -> bool {
+        match self.box_type {
+            InlineNode(_) => self.children.iter().any(|ch| ch.is_block_level() || ch.contains_block_level()),
+            _ => false,
+        }
+    }
+
     fn get_inline_container(&mut self) -> &mut LayoutBox {
         match &self.box_type {
             InlineNode(_) | InlineBlockNode(_) | AnonymousBlock(_) => self,
@@ -314,20 +1226,12 @@ impl LayoutBox {
             | TableNode(node)
             | TableCellNode(node)
             | TableRowGroupNode(node)
-            | TableRowNode(node) => {
-                // if last child is anonymous block, keep using it
-                let last = self.children.last();
-                let is_anon = match last {
-                    Some(ch) => {
-                        match ch.box_type {
-                            AnonymousBlock(_) => true,
-                            _ => {false}
-                        }
-                    },
-                    _ => {
-                        false
-                    }
-                };
+            | TableRowNode(node)
+            | FlexNode(node)
+            | GridNode(node) => {
+                // consecutive inlines share an anonymous block. a block sibling in between
+                // ends it, so the next inline starts a new one right after that block.
+                let is_anon = matches!(self.children.last(), Some(LayoutBox { box_type: AnonymousBlock(_), .. }));
                 if !is_anon {
                     // make new anon block
                     self.children.push(LayoutBox::new(AnonymousBlock(Rc::clone(node))))
@@ -337,10 +1241,53 @@ impl LayoutBox {
         }
     }
 
-    pub fn layout(&mut self, containing: &mut Dimensions, font:&mut FontCache, doc:&Document) -> RenderBox {
-        match &self.box_type {
+    /// Lays out the tree in two passes. First every box measures its min and max-content
+    /// widths, bottom up. Then boxes are placed top down inside their containing blocks.
+    /// The containing block is the viewport, and its height, if known, is the viewport's.
+    pub fn layout(&mut self, containing: &Dimensions, font:&mut FontCache, doc:&Document) -> RenderBox {
+        self.measure(font, doc);
+        // the root's percentage heights are of the viewport, when it has one
+        self.containing_height = Some(containing.content.height).filter(|height| *height > 0.0);
+        let flow = Dimensions { content: Rect { height: 0.0, ..containing.content }, ..*containing };
+        let mut bx = self.place(&flow, font, doc);
+        if let RenderBox::Block(block) = &mut bx {
+            // the root's overflow is the viewport's, which the page scrolls in
+            block.scroll = None;
+            // without a positioned ancestor, absolute boxes are placed in the initial containing
+            // block. that's the viewport, as tall as the page when the viewport's height is unknown.
+            let viewport = match containing.content.height {
+                height if height > 0.0 => containing.content,
+                _ => Rect { height: self.dimensions.margin_box().height, ..containing.content },
+            };
+            if !self.is_positioned() {
+                let page = Rect { height: viewport.height.max(self.dimensions.margin_box().height), ..viewport };
+                self.layout_absolute_descendants(page, &mut block.children, font, doc);
+            }
+            self.layout_fixed_descendants(viewport, &mut block.children, font, doc);
+        }
+        bx
+    }
+
+    // The placement pass. The containing block is an input only: its content x and width are
+    // the space to fit into, and its content height is how far down earlier siblings reached.
+    fn place(&mut self, containing: &Dimensions, font:&mut FontCache, doc:&Document) -> RenderBox {
+        // a flex container can lay an item out more than once, and every time starts afresh
+        self.dimensions = Default::default();
+        let mut bx = match &self.box_type {
             BlockNode(_node) =>         RenderBox::Block(self.layout_block(containing, font, doc)),
-            TableNode(_node) =>         RenderBox::Block(self.layout_block(containing, font, doc)),
+            TableNode(node) => {
+                let collapse = node.lookup_string("border-collapse", "separate") == "collapse";
+                let (_, spacing) = border_spacing(node);
+                let mut table = self.layout_block(containing, font, doc);
+                if collapse {
+                    self.collapse_borders(&mut table);
+                } else if self.specified_height(containing).is_none() {
+                    // each row has the spacing above it, and the last one has it below too
+                    self.dimensions.content.height += spacing;
+                    table.rect.height += spacing;
+                }
+                RenderBox::Block(table)
+            },
             TableRowGroupNode(_node) => RenderBox::Block(self.layout_block(containing, font, doc)),
             TableRowNode(_node) =>      RenderBox::Block(self.layout_table_row(containing, font, doc)),
             TableCellNode(_node) =>     RenderBox::Block(self.layout_block(containing, font, doc)),
@@ -348,16 +1295,134 @@ impl LayoutBox {
             InlineBlockNode(_node) =>   RenderBox::InlineBlock(),
             AnonymousBlock(_node) =>    RenderBox::Anonymous(self.layout_anonymous_2(containing, font, doc)),
             ListItemNode(_node) =>      RenderBox::Block(self.layout_block(containing, font, doc)),
+            FlexNode(_node) =>          RenderBox::Block(self.layout_block(containing, font, doc)),
+            GridNode(_node) =>          RenderBox::Block(self.layout_block(containing, font, doc)),
+        };
+        // a positioned box is the containing block of the absolute boxes inside it. they go
+        // after its own content, so they are painted above it.
+        if self.is_positioned() {
+            if let RenderBox::Block(block) = &mut bx {
+                let padding_box = self.dimensions.padding_box();
+                self.layout_absolute_descendants(padding_box, &mut block.children, font, doc);
+            }
+        }
+        // a relative box is moved after the flow is done with it, so its siblings stay
+        // where they were. what it contains moves along with it.
+        if self.position() == "relative" {
+            let (dx, dy) = self.relative_offset(containing);
+            bx.translate(dx, dy);
         }
+        bx
     }
-    fn debug_calculate_element_name(&self) -> String{
-        match &self.box_type {
+
+    // left wins over right and top over bottom. percentages are of the containing block.
+    fn relative_offset(&self, containing:&Dimensions) -> (f32, f32) {
+        let context = self.length_context(containing);
+        let style = self.get_style_node();
+        let horizontal = |name| style.value(name).and_then(|value| context.horizontal(&value));
+        let vertical = |name| style.value(name).and_then(|value| context.vertical(&value));
+        let dx = horizontal("left").or_else(|| horizontal("right").map(|right| -right)).unwrap_or(0.0);
+        let dy = vertical("top").or_else(|| vertical("bottom").map(|bottom| -bottom)).unwrap_or(0.0);
+        (dx, dy)
+    }
+
+    fn position(&self) -> String {
+        if !self.is_block_level() {
+            return String::from("static");
+        }
+        self.get_style_node().lookup_string("position", "static")
+    }
+    fn is_positioned(&self) -> bool {
+        self.position() != "static"
+    }
+    // fixed boxes are absolutely positioned too, in the viewport instead of an ancestor
+    fn is_absolute(&self) -> bool {
+        let position = self.position();
+        position == "absolute" || position == "fixed"
+    }
+    fn is_fixed(&self) -> bool {
+        self.position() == "fixed"
+    }
+
+    // the absolute boxes whose containing block is cb, the ones without a positioned box
+    // between them and here
+    fn layout_absolute_descendants(&mut self, cb:Rect, out:&mut Vec<RenderBox>, font:&mut FontCache, doc:&Document) {
+        for child in self.children.iter_mut() {
+            if child.is_fixed() {
+                continue;
+            }
+            if child.is_absolute() {
+                out.push(child.layout_absolute(cb, font, doc));
+            } else if !child.is_positioned() {
+                child.layout_absolute_descendants(cb, out, font, doc);
+            }
+        }
+    }
+
+    // every fixed box, however deep, placed in the viewport
+    fn layout_fixed_descendants(&mut self, viewport:Rect, out:&mut Vec<RenderBox>, font:&mut FontCache, doc:&Document) {
+        for child in self.children.iter_mut() {
+            if child.is_fixed() {
+                let mut bx = child.layout_absolute(viewport, font, doc);
+                if let RenderBox::Block(block) = &mut bx {
+                    block.fixed = true;
+                }
+                out.push(bx);
+            }
+            child.layout_fixed_descendants(viewport, out, font, doc);
+        }
+    }
+
+    // CSS 2.1 10.3.7 and 10.6.4, simplified: the offsets place the margin box inside the
+    // containing block. an auto width with both left and right set fills the room between
+    // them, or else shrinks to fit. with both offsets auto the box stays where the flow had it.
+    fn layout_absolute(&mut self, cb:Rect, font:&mut FontCache, doc:&Document) -> RenderBox {
+        self.containing_height = Some(cb.height);
+        let context = self.length_context(&Dimensions { content: cb, ..Default::default() });
+        let style = Rc::clone(self.get_style_node());
+        let offset = |name| style.value(name).and_then(|value| match name {
+            "top" | "bottom" => context.vertical(&value),
+            _ => context.horizontal(&value),
+        });
+        let (top, right, bottom, left) = (offset("top"), offset("right"), offset("bottom"), offset("left"));
+        let available = cb.width - left.unwrap_or(0.0) - right.unwrap_or(0.0);
+        let width = match (left, right) {
+            (Some(_), Some(_)) if style.value("width").and_then(|value| context.horizontal(&value)).is_none() => available,
+            _ => self.outer_width(&context, available),
+        };
+        let x = match (left, right) {
+            (Some(left), _) => cb.x + left,
+            (None, Some(right)) => cb.x + cb.width - right - width,
+            (None, None) => self.static_position.0,
+        };
+        let y = top.map_or(self.static_position.1, |top| cb.y + top);
+        let placed = Dimensions { content: Rect { x, y, width, height: 0.0 }, ..Default::default() };
+        self.floats = vec![];
+        let mut bx = self.place(&placed, font, doc);
+        if let (None, Some(bottom)) = (top, bottom) {
+            let height = self.dimensions.margin_box().height;
+            bx.translate(0.0, cb.y + cb.height - bottom - height - y);
+        }
+        bx
+    }
+
+    // the margin box width from the width property, or else shrunk to fit the content
+    fn outer_width(&self, context:&LengthContext, available:f32) -> f32 {
+        match self.get_style_node().value("width").and_then(|value| context.horizontal(&value)) {
+            Some(width) => width + self.horizontal_edges(),
+            None => self.intrinsic.max.min(available.max(self.intrinsic.min)),
+        }
+    }
+    fn debug_calculate_element_name(&self) -> String{
+        match &self.box_type {
             BlockNode(sn)
             | TableNode(sn)
             | TableRowGroupNode(sn)
             | TableRowNode(sn)
             | TableCellNode(sn)
             | InlineNode(sn)
+            | FlexNode(sn)
+            | GridNode(sn)
             => match &sn.node.node_type {
                 NodeType::Element(data) => data.tag_name.clone(),
                 _ => "non-element".to_string(),
@@ -365,13 +1430,46 @@ impl LayoutBox {
             _ => "non-element".to_string(),
         }
     }
-    fn layout_block(&mut self, containing_block: &mut Dimensions, font_cache:&mut FontCache, doc:&Document) -> RenderBlockBox {
+    fn layout_block(&mut self, containing_block: &Dimensions, font_cache:&mut FontCache, doc:&Document) -> RenderBlockBox {
         self.calculate_block_width(containing_block);
         self.calculate_block_position(containing_block);
-        let children:Vec<RenderBox> = self.layout_block_children(font_cache, doc);
-        self.calculate_block_height();
+        let height = self.flex_height.or_else(|| self.specified_height(containing_block));
+        for child in self.children.iter_mut() {
+            child.containing_height = height;
+        }
+        let mut children:Vec<RenderBox> = match (&self.box_type, self.column_layout()) {
+            (FlexNode(_), _) => self.layout_flex_items(height, font_cache, doc),
+            (GridNode(_), _) => self.layout_grid_items(height, font_cache, doc),
+            (_, Some((count, gap))) => self.layout_block_columns(count, gap, font_cache, doc),
+            (_, None) => self.layout_block_children(font_cache, doc),
+        };
+        let content_height = self.dimensions.content.height;
+        self.calculate_block_height(containing_block);
         let zero = Length(0.0, Px);
         let style = self.get_style_node();
+        let marker = list_marker(style);
+        let marker_offset = match marker.text() {
+            Some(text) => {
+                let font_size = style.lookup_font_size();
+                let font_family = style.lookup_font_family(font_cache);
+                let width = calculate_word_length(&text, font_cache, font_size,
+                    &font_family, style.lookup_font_weight(400),
+                    &style.lookup_string("font-style", "normal"), &TextSpacing::default());
+                let gap = font_size / 2.0;
+                if style.lookup_string("list-style-position", "outside") == "inside" {
+                    // the marker takes the start of the first line
+                    if let Some(RenderBox::Anonymous(anon)) = children.first_mut() {
+                        if let Some(line) = anon.children.first_mut() {
+                            line.translate(width + gap, 0.0);
+                        }
+                    }
+                    0.0
+                } else {
+                    -(width + gap)
+                }
+            },
+            None => 0.0,
+        };
         // println!("border top for block is {} {:#?}", self.debug_calculate_element_name(), &style.lookup("border-top", "border-width", &zero));
         RenderBlockBox{
             rect:self.dimensions.content,
@@ -380,34 +1478,54 @@ impl LayoutBox {
             children,
             title: self.debug_calculate_element_name(),
             background_color: style.color("background-color"),
-            border_width: EdgeSizes {
-                top: style.lookup_length_as_px("border-width-top", 0.0),
-                bottom: style.lookup_length_as_px("border-width-bottom",0.0),
-                left: style.lookup_length_as_px("border-width-top",0.0),
-                right: style.lookup_length_as_px("border-width-bottom",0.0),
-            },
-            border_color: style.color("border-color"),
-            valign: String::from("baseline"),
-            marker: if style.lookup_string("display","block") == "list-item" {
-                match &*style.lookup_string("list-style-type", "none") {
-                    "disc" => ListMarker::Disc,
-                    _ => ListMarker::None,
-                }
-            } else {
-                ListMarker::None
-            },
+            border_width: border_widths(style),
+            border_color: border_colors(style),
+            border_style: border_styles(style),
+            valign: VerticalAlign::Baseline,
+            marker,
+            marker_offset,
             color: Some(style.lookup_color("color", &BLACK)),
             font_family: style.lookup_font_family(font_cache),
             font_weight : style.lookup_font_weight(400),
             font_style : style.lookup_string("font-style", "normal"),
             font_size: style.lookup_font_size(),
+            opacity: style.lookup_opacity(),
+            visible: style.is_visible(),
+            outline: outline(style),
+            border_radius: border_radius(style, self.dimensions.border_box()),
+            transform: transform(style, self.dimensions.border_box()),
+            fixed: false,
+            scroll: self.scroll_area(content_height),
         }
     }
 
-    fn layout_table_row(&mut self, cb:&mut Dimensions, font_cache:&mut FontCache, doc: &Document) -> RenderBlockBox {
+    // where the content scrolls when overflow isn't visible. it goes as far down as the
+    // content did before the height cut it off.
+    fn scroll_area(&self, content_height:f32) -> Option<ScrollArea> {
+        let style = self.get_style_node();
+        let overflow = style.lookup_keyword("overflow", &Keyword(String::from("visible")));
+        let user_scrollable = match overflow {
+            Keyword(kw) if kw == "hidden" => false,
+            Keyword(kw) if kw == "scroll" || kw == "auto" => true,
+            _ => return None,
+        };
+        let d = &self.dimensions;
+        Some(ScrollArea {
+            element_path: style.path.clone(),
+            rect: d.padding_box(),
+            content_height: content_height.max(d.content.height) + d.padding.top + d.padding.bottom,
+            scroll_y: 0.0,
+            user_scrollable,
+        })
+    }
+
+    fn layout_table_row(&mut self, cb:&Dimensions, font_cache:&mut FontCache, doc: &Document) -> RenderBlockBox {
         // println!("layout_table_row");
         self.calculate_block_width(cb);
         self.calculate_block_position(cb);
+        let (spacing_x, spacing_y) = border_spacing(self.get_style_node());
+        self.dimensions.margin.top += spacing_y;
+        self.dimensions.content.y += spacing_y;
         self.dimensions.content.height = 50.0;
         let mut children:Vec<RenderBox> = vec![];
 
@@ -419,13 +1537,14 @@ impl LayoutBox {
                 count+= 1
             }
         }
-        let child_width = self.dimensions.content.width / count as f32;
+        // the cells share what the spacing before, between and after them leaves
+        let child_width = (self.dimensions.content.width - spacing_x * (count + 1) as f32) / count as f32;
         for (index,child) in self.children.iter_mut().enumerate() {
             match child.box_type {
                 BoxType::TableCellNode(_) => {
-                    let mut cb = Dimensions {
+                    let cb = Dimensions {
                         content: Rect {
-                            x: self.dimensions.content.x + child_width * (index as f32),
+                            x: self.dimensions.content.x + spacing_x + (child_width + spacing_x) * (index as f32),
                             y: self.dimensions.content.y,
                             width: child_width,
                             height: 0.0
@@ -435,7 +1554,7 @@ impl LayoutBox {
                         margin: Default::default()
                     };
                     // println!("table cell child with count {} w = {} index = {} cb = {:#?}",count, child_width,index, cb);
-                    let bx = child.layout(&mut cb, font_cache, doc);
+                    let bx = child.place(&cb, font_cache, doc);
                     // println!("table cell child created {:#?}",bx);
                     children.push(bx)
                 }
@@ -452,21 +1571,76 @@ impl LayoutBox {
             margin: self.dimensions.margin,
             padding: self.dimensions.padding,
             background_color: self.get_style_node().color("background-color"),
-            border_width: EdgeSizes {
-                top: style.lookup_length_as_px("border-width-top", 0.0),
-                bottom: style.lookup_length_as_px("border-width-bottom",0.0),
-                left: style.lookup_length_as_px("border-width-top",0.0),
-                right: style.lookup_length_as_px("border-width-bottom",0.0),
-            },
-            border_color: self.get_style_node().color("border-color"),
-            valign: String::from("baseline"),
+            border_width: border_widths(style),
+            border_color: border_colors(style),
+            border_style: border_styles(style),
+            valign: VerticalAlign::Baseline,
             children: children,
             marker: ListMarker::None,
+            marker_offset: 0.0,
             color: Some(style.lookup_color("color", &BLACK)),
             font_family: style.lookup_font_family(font_cache),
             font_weight : style.lookup_font_weight(400),
             font_style : style.lookup_string("font-style", "normal"),
             font_size: style.lookup_font_size(),
+            opacity: style.lookup_opacity(),
+            visible: style.is_visible(),
+            outline: outline(style),
+            border_radius: border_radius(style, self.dimensions.border_box()),
+            transform: transform(style, self.dimensions.border_box()),
+            fixed: false,
+            scroll: None,
+        }
+    }
+
+    // The collapsing border model. Every edge between cells gets the single border that wins
+    // among the boxes meeting there, painted once by one of the cells. Rows, row groups and
+    // the table itself contribute to the edges but paint nothing of their own.
+    fn collapse_borders(&self, table:&mut RenderBlockBox) {
+        let mut rows:Vec<(BorderSides<BorderEdge>, Vec<BorderSides<BorderEdge>>)> = vec![];
+        self.collect_table_rows(&mut rows);
+        let resolved = resolve_collapsed_borders(&border_edges(self.get_style_node()), &rows);
+        table.border_color = Default::default();
+        let mut row_boxes:Vec<&mut RenderBlockBox> = vec![];
+        self.collect_table_row_boxes(table, &mut row_boxes);
+        for (row, cells) in row_boxes.into_iter().zip(resolved) {
+            row.border_color = Default::default();
+            for (cell, sides) in row.children.iter_mut().zip(cells) {
+                if let RenderBox::Block(cell) = cell {
+                    cell.apply_collapsed_borders(&sides);
+                }
+            }
+        }
+    }
+
+    // the borders of each row of a table and of its cells, looking inside row groups
+    fn collect_table_rows(&self, rows:&mut Vec<(BorderSides<BorderEdge>, Vec<BorderSides<BorderEdge>>)>) {
+        for child in self.children.iter() {
+            match &child.box_type {
+                TableRowGroupNode(_) => child.collect_table_rows(rows),
+                TableRowNode(style) => {
+                    let cells = child.children.iter().filter_map(|cell| match &cell.box_type {
+                        TableCellNode(cell_style) => Some(border_edges(cell_style)),
+                        _ => None,
+                    }).collect();
+                    rows.push((border_edges(style), cells));
+                },
+                _ => {},
+            }
+        }
+    }
+
+    // the render boxes of the rows found by collect_table_rows, in the same order
+    fn collect_table_row_boxes<'a>(&self, render:&'a mut RenderBlockBox, rows:&mut Vec<&'a mut RenderBlockBox>) {
+        for (child, bx) in self.children.iter().zip(render.children.iter_mut()) {
+            match (&child.box_type, bx) {
+                (TableRowGroupNode(_), RenderBox::Block(group)) => {
+                    group.border_color = Default::default();
+                    child.collect_table_row_boxes(group, rows);
+                },
+                (TableRowNode(_), RenderBox::Block(row)) => rows.push(row),
+                _ => {},
+            }
         }
     }
 
@@ -474,6 +1648,8 @@ impl LayoutBox {
         match &self.box_type {
             BoxType::AnonymousBlock(styled)
             | BoxType::ListItemNode(styled)
+            | BoxType::FlexNode(styled)
+            | BoxType::GridNode(styled)
             | BoxType::BlockNode(styled)
             | BoxType::TableNode(styled)
             | BoxType::TableRowGroupNode(styled)
@@ -484,7 +1660,7 @@ impl LayoutBox {
         }
     }
 
-    fn layout_anonymous_2(&mut self, dim:&mut Dimensions, font_cache:&mut FontCache, doc:&Document) -> RenderAnonymousBox {
+    fn layout_anonymous_2(&mut self, dim:&Dimensions, font_cache:&mut FontCache, doc:&Document) -> RenderAnonymousBox {
         // println!("parent is {:#?}",self.get_type());
         // println!("parent style node is {:#?}",self.get_style_node());
         let mut looper = Looper {
@@ -508,10 +1684,25 @@ impl LayoutBox {
             current_start: dim.content.x,
             current_end: dim.content.x,
             current_bottom: dim.content.y + dim.content.height,
+            floats: self.floats.clone(),
             font_cache:font_cache,
             doc,
             style_node:Rc::clone(self.get_style_node()),
+            block_style:Rc::clone(self.get_style_node()),
+            decorations: vec![],
+            opacity: 1.0,
+            link: link_of(self.get_style_node()),
+            hard_break: false,
         };
+        looper.fit_current_line();
+        if self.first_line {
+            let style = Rc::clone(self.get_style_node());
+            let indent = style.value("text-indent")
+                .and_then(|value| self.length_context(dim).to_px(&value, Some(dim.content.width)))
+                .unwrap_or(0.0);
+            looper.current_start += indent;
+            looper.current_end += indent;
+        }
         for child in self.children.iter_mut() {
             // println!("working on child {:#?}", child.get_type());
             // println!("current start and end is {} {} ",looper.current_start, looper.current_end);
@@ -522,12 +1713,8 @@ impl LayoutBox {
             }
             // println!("and now after it is {} {}", looper.current_start, looper.current_end)
         }
-        looper.adjust_current_line_vertical();
-        looper.adjust_current_line_horizontal();
-        let old = looper.current;
-        looper.current_bottom += old.rect.height;
-        looper.extents.height += old.rect.height;
-        looper.lines.push(old);
+        looper.finish_last_line();
+        looper.lines.push(looper.current);
         self.dimensions.content.y = looper.extents.y;
         self.dimensions.content.width = looper.extents.width;
         self.dimensions.content.height = looper.current_bottom - looper.extents.y ;
@@ -543,27 +1730,43 @@ impl LayoutBox {
         }
     }
 
+    // the size given by css, or else by the width/height attribute. None means auto.
+    fn specified_image_size(&self, data:&ElementData, name:&str, context:&LengthContext) -> Option<f32> {
+        let size = self.get_style_node().value(name).and_then(|value| match name {
+            "height" => context.vertical(&value),
+            _ => context.horizontal(&value),
+        });
+        if size.is_some() {
+            return size;
+        }
+        data.attributes.get(name)
+            .and_then(|attr| attr.trim().trim_end_matches("px").parse::<f32>().ok())
+    }
+
+    // min-width, max-width, min-height and max-height in px. a max is never under its min.
+    fn size_limits(&self, context:&LengthContext) -> (f32, f32, f32, f32) {
+        let style = self.get_style_node();
+        let limit = |name, vertical:bool| style.value(name)
+            .and_then(|value| if vertical { context.vertical(&value) } else { context.horizontal(&value) })
+            .map(|px| px.max(0.0));
+        let (min_width, min_height) = (limit("min-width", false).unwrap_or(0.0), limit("min-height", true).unwrap_or(0.0));
+        (min_width, limit("max-width", false).unwrap_or(f32::INFINITY).max(min_width),
+         min_height, limit("max-height", true).unwrap_or(f32::INFINITY).max(min_height))
+    }
+
     fn do_inline_block(&mut self, looper:&mut Looper) {
-        let mut image_size = Rect { x:0.0, y:0.0, width: 30.0, height:30.0};
+        let mut specified_width:Option<f32> = None;
+        let mut specified_height:Option<f32> = None;
         let mut src = String::from("");
         // let w = 100.0;
         if let InlineBlockNode(styled) = &self.box_type {
             if let Element(data) = &styled.node.node_type {
                 match data.tag_name.as_str() {
                     "img" => {
-                        let width = if data.attributes.contains_key("width") {
-                            data.attributes.get("width").unwrap().parse::<u32>().unwrap()
-                        } else {
-                            100
-                        };
-                        image_size.width = width as f32;
-                        let height = if data.attributes.contains_key("height") {
-                            data.attributes.get("height").unwrap().parse::<u32>().unwrap()
-                        } else {
-                            100
-                        };
-                        image_size.height = height as f32;
-                        src = data.attributes.get("src").unwrap().clone();
+                        let context = self.length_context(&Dimensions { content: looper.extents, ..Default::default() });
+                        specified_width = self.specified_image_size(data, "width", &context);
+                        specified_height = self.specified_image_size(data, "height", &context);
+                        src = data.attributes.get("src").cloned().unwrap_or_default();
                     },
                     "button" => {
                         // let font_family = self.find_font_family(looper.font_cache);
@@ -578,7 +1781,7 @@ impl LayoutBox {
                             NodeType::Text(str) => str,
                             _ => panic!("can't do inline block layout if child isn't text"),
                         };
-                        let w: f32 = calculate_word_length(&text, looper.font_cache, font_size, &font_family, font_weight, &font_style);
+                        let w: f32 = calculate_word_length(&text, looper.font_cache, font_size, &font_family, font_weight, &font_style, &text_spacing(self.get_style_node()));
                         // println!("calculated width is {}",w);
                         looper.current_end += w;
                         let mut containing_block = Dimensions {
@@ -610,31 +1813,9 @@ impl LayoutBox {
         let bx = match load_image(looper.doc, &src) {
             Ok(image) => {
                 println!("Loaded the image {} {}", image.width, image.height);
-                let width_prop = self.get_style_node().lookup("width", "max-width", &Value::Keyword(String::from("auto")));
-                let mut width = image.width as f32;
-                //calculate width as a percentage
-                if let Value::Length(v,u) = width_prop {
-                    match u {
-                        Unit::Per => width = looper.extents.width * v/100.0,
-                        _ => { }
-                    }
-                }
-                let mut height = image.height as f32;
-                let height_prop = self.get_style_node().lookup("height","height",&Value::Keyword(String::from("auto")));
-                //calculate height as a percentage
-                if let Value::Length(v,u) = &height_prop {
-                    match u {
-                        Unit::Per => height = looper.extents.height * v/100.0,
-                        _ => {  }
-                    }
-                }
-                //calculate height from width to preserve aspect ratio
-                if let Value::Keyword(keyword) = &height_prop {
-                    match keyword.as_str() {
-                        "auto" => height = width * (image.height as f32) / (image.width as f32),
-                        _ => {}
-                    }
-                }
+                let context = LengthContext::new(self.get_style_node(), looper.extents.width, None);
+                let (width, height) = replaced_size((specified_width, specified_height),
+                    (image.width as f32, image.height as f32), self.size_limits(&context));
                 RenderInlineBoxType::Image(RenderImageBox {
                     rect: Rect {
                         x:looper.current_start,
@@ -642,82 +1823,68 @@ impl LayoutBox {
                         width,
                         height,
                     },
-                    valign: self.get_style_node().lookup_string("vertical-align","baseline"),
+                    valign: self.get_style_node().lookup_vertical_align(),
+                    rendering: match self.get_style_node().lookup_string("image-rendering", "auto").as_str() {
+                        "pixelated" | "crisp-edges" => ImageRendering::Pixelated,
+                        _ => ImageRendering::Smooth,
+                    },
+                    opacity: looper.opacity * self.get_style_node().lookup_opacity(),
+                    visible: self.get_style_node().is_visible(),
                     image
                 })
             },
+            //blocked images collapse instead of leaving an error box
+            Err(BrowserError::Blocked(_)) => return,
             Err(err) => {
                 println!("error loading the image for {} : {:#?}", src, err);
                 RenderInlineBoxType::Error(RenderErrorBox {
                     rect: Rect {
                         x:looper.current_start,
                         y: looper.current.rect.y,
-                        width: image_size.width,
-                        height: image_size.height,
+                        width: specified_width.unwrap_or(100.0),
+                        height: specified_height.unwrap_or(100.0),
                     },
-                    valign: self.get_style_node().lookup_string("vertical-align","baseline"),
+                    valign: self.get_style_node().lookup_vertical_align(),
                 })
             }
         };
-        if looper.current_end + image_size.width > looper.extents.width {
-            looper.adjust_current_line_vertical();
-            looper.adjust_current_line_horizontal();
+        let box_width = match &bx {
+            RenderInlineBoxType::Image(img) => img.rect.width,
+            RenderInlineBoxType::Error(err) => err.rect.width,
+            _ => 0.0,
+        };
+        if looper.current_end + box_width > looper.line_right() {
+            looper.finish_line();
             looper.start_new_line();
             looper.add_box_to_current_line(bx);
         } else {
-            looper.current_end += image_size.width;
+            looper.current_end += box_width;
             looper.add_box_to_current_line(bx);
         }
     }
 
-    fn do_pre_layout(&self, looper:&mut Looper, txt:&str, link:&Option<String>) {
-        let color = looper.style_node.lookup_color("color", &BLACK);
-        let font_size = looper.style_node.lookup_font_size();
-        // println!("font size is {:#?} ",font_size, color);
-        let font_family = looper.style_node.lookup_font_family(looper.font_cache);
-
-        let font_weight = looper.style_node.lookup_font_weight(400);
-        let font_style = looper.style_node.lookup_string("font-style", "normal");
-        let valign = looper.style_node.lookup_string("vertical-align", "baseline");
-        for line in txt.split_terminator('\n') {
-            let bounds = calculate_text_bounds(line, looper.font_cache, font_size, &font_family, font_weight, &font_style);
-            if let Some(bounds) = bounds {
-                let bx = RenderInlineBoxType::Text(RenderTextBox {
-                    rect: Rect {
-                        x: looper.current_start + bounds.min.x,
-                        y: looper.current_bottom + bounds.min.y,
-                        width: looper.extents.width,
-                        height: font_size
-                    },
-                    text: line.to_string(),
-                    color: Some(color.clone()),
-                    background_color: looper.style_node.color("background-color"),
-                    font_size,
-                    font_family: font_family.clone(),
-                    link: link.clone(),
-                    font_weight,
-                    font_style:font_style.clone(),
-                    valign:valign.clone(),
-                    text_decoration_line: looper.style_node.lookup_text_decoration_line(),
-                });
-                looper.add_box_to_current_line(bx);
-                looper.current_bottom += looper.current.rect.height;
-                looper.extents.height += looper.current.rect.height;
-                looper.adjust_current_line_vertical();
-                looper.adjust_current_line_horizontal();
+    // breaks the text into lines and words as white-space says, then lays out the words
+    fn do_text_layout(&self, looper:&mut Looper, txt:&str, link:&Option<String>) {
+        let white_space = looper.style_node.lookup_white_space();
+        for (i, segment) in text_segments(txt, white_space).iter().enumerate() {
+            if i > 0 {
+                looper.hard_break = true;
+                looper.finish_line();
                 looper.start_new_line();
             }
+            self.do_normal_inline_layout(looper, text_words(segment, white_space), white_space.wraps(), link);
         }
     }
 
-    fn do_normal_inline_layout(&self, looper:&mut Looper, txt:&str, link:&Option<String>) {
+    // each word carries the space before it. without wrap the words all stay on one line.
+    fn do_normal_inline_layout(&self, looper:&mut Looper, words:Vec<String>, wrap:bool, link:&Option<String>) {
         // println!("processing text '{}'", txt);
         let font_family = looper.style_node.lookup_font_family(looper.font_cache);
         // println!("using font family {}", font_family);
         let font_weight = looper.style_node.lookup_font_weight(400);
         let font_size = looper.style_node.lookup_font_size();
         let font_style = looper.style_node.lookup_string("font-style", "normal");
-        let vertical_align = looper.style_node.lookup_string("vertical-align","baseline");
+        let vertical_align = looper.style_node.lookup_vertical_align();
         let line_height = font_size;
         // let line_height = looper.style_node.lookup_length_px("line-height", line_height);
         let color = looper.style_node.lookup_color("color", &BLACK);
@@ -725,14 +1892,13 @@ impl LayoutBox {
         // println!("styles={:#?}",looper.style_node);
         // println!("parent={:#?}", parent.get_style_node());
         // println!("looper is {} {} {}",looper.current_start, looper.current_end, looper.current_start);
+        let spacing = text_spacing(&looper.style_node);
         let mut curr_text = String::new();
-        for word in txt.split_whitespace() {
-            let mut word2 = String::from(" ");
-            word2.push_str(word);
-            let w: f32 = calculate_word_length(word2.as_str(), looper.font_cache, font_size, &font_family, font_weight, &font_style);
+        for word2 in words {
+            let w: f32 = calculate_word_length(word2.as_str(), looper.font_cache, font_size, &font_family, font_weight, &font_style, &spacing);
             //if it's too long then we need to wrap
             // println!("end = {} w = {} extents.width = {}", looper.current_end, w, looper.extents.x + looper.extents.width);
-            if looper.current_end + w > looper.extents.x + looper.extents.width {
+            if wrap && looper.current_end + w > looper.line_right() {
                 //add current text to the current line
                 // println!("wrapping: {} cb = {}", curr_text, looper.current_bottom);
                 let bx = RenderInlineBoxType::Text(RenderTextBox{
@@ -749,19 +1915,25 @@ impl LayoutBox {
                     font_family: font_family.clone(),
                     font_style: font_style.clone(),
                     link: link.clone(),
+                    link_target: looper.link_target(),
                     font_weight,
                     valign: vertical_align.clone(),
+                    line_height: looper.style_node.lookup_line_height(),
+                    spacing,
+                    opacity: looper.opacity,
+                    visible: looper.style_node.is_visible(),
                     text_decoration_line: looper.style_node.lookup_text_decoration_line(),
+                    pointer_events: looper.style_node.lookup_string("pointer-events", "auto"),
+                    cursor: looper.style_node.lookup_string("cursor", "auto"),
+                    decorations: looper.decorations.clone(),
+                    element: looper.element(),
+                    element_path: looper.style_node.path.clone(),
                 });
                 looper.add_box_to_current_line(bx);
                 //make new current text with the current word
                 curr_text = String::new();
                 curr_text.push_str(&word2);
-                curr_text.push_str(" ");
-                looper.current_bottom += looper.current.rect.height;
-                looper.extents.height += looper.current.rect.height;
-                looper.adjust_current_line_vertical();
-                looper.adjust_current_line_horizontal();
+                looper.finish_line();
                 looper.start_new_line();
                 looper.current_end += w;
             } else {
@@ -782,10 +1954,20 @@ impl LayoutBox {
             font_size,
             font_family,
             link: link.clone(),
+            link_target: looper.link_target(),
             font_weight,
             font_style,
             valign: vertical_align.clone(),
+            line_height: looper.style_node.lookup_line_height(),
+            spacing,
+            opacity: looper.opacity,
+            visible: looper.style_node.is_visible(),
             text_decoration_line: looper.style_node.lookup_text_decoration_line(),
+            pointer_events: looper.style_node.lookup_string("pointer-events", "auto"),
+            cursor: looper.style_node.lookup_string("cursor", "auto"),
+            decorations: looper.decorations.clone(),
+            element: looper.element(),
+            element_path: looper.style_node.path.clone(),
         });
         // println!("added text box {:#?}",bx);
         looper.add_box_to_current_line(bx);
@@ -793,44 +1975,44 @@ impl LayoutBox {
 
     fn do_inline(&self, looper:&mut Looper) {
         // println!("doing inline {:#?}", &self.debug_calculate_element_name());
-        let link:Option<String> = match &looper.style_node.node.node_type {
-            Text(_) => None,
-            NodeType::Comment(_) => None,
-            NodeType::Cdata(_) => None,
-            Element(ed) => {
-                if ed.tag_name == "a" {
-                    ed.attributes.get("href").map(String::from)
-                } else {
-                    None
-                }
-            },
-            NodeType::Meta(_) => None,
-        };
+        let link:Option<String> = looper.link.as_ref().map(|(href, _, _)| href.clone());
         if let BoxType::InlineNode(snode) = &self.box_type {
             match &snode.node.node_type {
-                 NodeType::Text(txt) => {
-                     let whitespace = looper.style_node.lookup_keyword("white-space", &Keyword(String::from("normal")));
-                     // println!("laying out using whitespace {:#?}", whitespace);
-                     match whitespace {
-                         Keyword(str) => {
-                             match &*str {
-                                 "pre" => return self.do_pre_layout(looper,txt,&link),
-                                 _ => return self.do_normal_inline_layout(looper,txt,&link),
-                             }
-                         },
-                         _ => {
-                             println!("invalid whitespace type");
-                         }
-                     }
-                }
+                 NodeType::Text(txt) => self.do_text_layout(looper, txt, &link),
                 //     if child is element
-                NodeType::Element(_ed) => {
+                NodeType::Element(ed) => {
                     // println!("recursing");
                     let old = Rc::clone(&looper.style_node);
                     looper.style_node = Rc::clone(snode);
-                    for ch in self.children.iter() {
-                        ch.do_inline(looper);
+                    // everything inside a link is part of it, however deeply nested
+                    let old_link = looper.link.clone();
+                    if let Some(link) = link_of(snode) {
+                        looper.link = Some(link);
+                    }
+                    let old_opacity = looper.opacity;
+                    looper.opacity *= snode.lookup_opacity();
+                    let context = LengthContext::new(snode, looper.extents.width, None);
+                    let decoration = inline_decoration(snode, &context, looper.opacity);
+                    if let Some(decoration) = &decoration {
+                        // the left padding and border take up room on the line
+                        looper.current_end += decoration.padding.left + decoration.border_width.left;
+                        looper.current_start = looper.current_end;
+                        looper.decorations.push(Rc::clone(decoration));
+                    }
+                    if ed.tag_name == "ruby" {
+                        self.do_ruby(looper, &link);
+                    } else {
+                        for ch in self.children.iter() {
+                            ch.do_inline(looper);
+                        }
                     }
+                    if let Some(decoration) = &decoration {
+                        looper.decorations.pop();
+                        looper.current_end += decoration.padding.right + decoration.border_width.right;
+                        looper.current_start = looper.current_end;
+                    }
+                    looper.opacity = old_opacity;
+                    looper.link = old_link;
                     looper.style_node =  old;
                 }
                 _ => {}
@@ -838,126 +2020,390 @@ impl LayoutBox {
         }
     }
 
+    // Each run of base content followed by an rt is laid out as one unit, with the annotation
+    // centered over the base and raised to sit on top of it. The raise makes the line taller.
+    fn do_ruby(&self, looper:&mut Looper, link:&Option<String>) {
+        let mut base:Vec<&LayoutBox> = vec![];
+        for ch in self.children.iter() {
+            let is_rt = match &ch.get_style_node().node.node_type {
+                Element(ed) => ed.tag_name == "rt",
+                _ => false,
+            };
+            if !is_rt {
+                base.push(ch);
+                continue;
+            }
+            let base_text:String = base.iter().map(|b| b.get_style_node().node.text_content()).collect();
+            let base_text = base_text.trim().to_string();
+            let rt_text = ch.get_style_node().node.text_content().trim().to_string();
+            base.clear();
+            let base_style = Rc::clone(&looper.style_node);
+            let rt_style = Rc::clone(ch.get_style_node());
+            let base_width = ruby_text_width(looper, &base_style, &base_text);
+            let rt_width = ruby_text_width(looper, &rt_style, &rt_text);
+            let width = base_width.max(rt_width);
+            if looper.current_end + width > looper.line_right() {
+                looper.finish_line();
+                looper.start_new_line();
+            }
+            let start = looper.current_start;
+            looper.current_end += width;
+
+            let mut base_box = ruby_text_box(looper, &base_style, base_text, start + (width - base_width) / 2.0, base_width, link);
+            let mut rt_box = ruby_text_box(looper, &rt_style, rt_text, start + (width - rt_width) / 2.0, rt_width, link);
+            // the annotation's baseline goes just above the top of the base text
+            let (base_ascent, _) = font_metrics(looper.font_cache, base_box.font_size, &base_box.font_family, base_box.font_weight, &base_box.font_style);
+            let (_, rt_descent) = font_metrics(looper.font_cache, rt_box.font_size, &rt_box.font_family, rt_box.font_weight, &rt_box.font_style);
+            let base_raise = match base_box.valign {
+                VerticalAlign::Raise(px) => px,
+                _ => {
+                    base_box.valign = VerticalAlign::Baseline;
+                    0.0
+                }
+            };
+            rt_box.valign = VerticalAlign::Raise(base_raise + base_ascent + rt_descent);
+            looper.add_box_to_current_line(RenderInlineBoxType::Text(base_box));
+            looper.add_box_to_current_line(RenderInlineBoxType::Text(rt_box));
+        }
+        // base content without an annotation is just inline content
+        for ch in base {
+            ch.do_inline(looper);
+        }
+    }
+
+
+    /// The measuring pass. Works out min-content and max-content widths of this box's
+    /// margin box and of everything inside it, and remembers them in `intrinsic`.
+    pub fn measure(&mut self, font_cache:&mut FontCache, doc:&Document) -> IntrinsicWidths {
+        self.font_units = Some(font_units(font_cache, self.get_style_node()));
+        let widths = match &self.box_type {
+            AnonymousBlock(style) => {
+                let style = Rc::clone(style);
+                self.children.iter_mut()
+                    .map(|ch| ch.measure_inline(&style, font_cache, doc))
+                    .fold(IntrinsicWidths::default(), IntrinsicWidths::beside)
+            }
+            // inlines are measured through their anonymous block. placement skips them anywhere else.
+            InlineNode(_) | InlineBlockNode(_) => IntrinsicWidths::default(),
+            TableRowNode(_) => {
+                let cells = self.children.iter_mut()
+                    .map(|ch| ch.measure(font_cache, doc))
+                    .fold(IntrinsicWidths::default(), IntrinsicWidths::beside);
+                self.block_intrinsic_widths(cells)
+            }
+            // a row of items can shrink each one to its narrowest, or wrap them onto lines
+            FlexNode(style) if !style.lookup_string("flex-direction", "row").starts_with("column") => {
+                let wrap = style.lookup_string("flex-wrap", "nowrap") != "nowrap";
+                let items:Vec<IntrinsicWidths> = self.children.iter_mut()
+                    .map(|ch| (ch.measure(font_cache, doc), ch.is_absolute()))
+                    .filter(|(_, absolute)| !absolute)
+                    .map(|(widths, _)| widths)
+                    .collect();
+                let min = if wrap {
+                    items.iter().map(|item| item.min).fold(0.0, f32::max)
+                } else {
+                    sum(items.iter().map(|item| item.min))
+                };
+                self.block_intrinsic_widths(IntrinsicWidths { min, max: sum(items.iter().map(|item| item.max)) })
+            }
+            // the columns side by side, each as wide as its fixed size or its widest item
+            GridNode(style) => {
+                let style = Rc::clone(style);
+                let items:Vec<IntrinsicWidths> = self.children.iter_mut().map(|ch| ch.measure(font_cache, doc)).collect();
+                let (areas, count) = self.grid_areas();
+                let context = LengthContext::new(&style, 0.0, None);
+                let tracks = grid_tracks(&style, "grid-template-columns", count, &context, None);
+                let gaps = grid_gap(&style, "column-gap", &context, None) * (count - 1) as f32;
+                let mut widths = IntrinsicWidths { min: gaps, max: gaps };
+                for (i, track) in tracks.iter().enumerate() {
+                    let (min, max) = match track {
+                        GridTrack::Fixed(size) => (*size, *size),
+                        _ => areas.iter()
+                            .filter(|area| area.column == i && area.columns == 1)
+                            .fold((0.0, 0.0), |(min, max), area| (items[area.index].min.max(min), items[area.index].max.max(max))),
+                    };
+                    widths.min += min;
+                    widths.max += max;
+                }
+                self.block_intrinsic_widths(widths)
+            }
+            _ => {
+                let children = self.children_intrinsic_widths(font_cache, doc);
+                self.block_intrinsic_widths(children)
+            }
+        };
+        self.intrinsic = widths;
+        widths
+    }
+
+    // absolute children are measured for their own sake, but take no room here
+    fn children_intrinsic_widths(&mut self, font_cache:&mut FontCache, doc:&Document) -> IntrinsicWidths {
+        self.children.iter_mut()
+            .map(|ch| (ch.measure(font_cache, doc), ch.is_absolute()))
+            .map(|(widths, absolute)| if absolute { IntrinsicWidths::default() } else { widths })
+            .fold(IntrinsicWidths::default(), IntrinsicWidths::above)
+    }
+
+    // a fixed width wins over the content. then add the horizontal edges.
+    fn block_intrinsic_widths(&mut self, content:IntrinsicWidths) -> IntrinsicWidths {
+        self.content_intrinsic = content;
+        let style = self.get_style_node();
+        let content = match style.value("width") {
+            Some(value @ Length(_, _)) if !matches!(value, Length(_, Unit::Per)) => {
+                let w = self.length_to_px(&value);
+                IntrinsicWidths { min: w, max: w }
+            }
+            _ => content,
+        };
+        content.plus(self.horizontal_edges())
+    }
+
+    // margin, border and padding, ignoring percentages since there is nothing to resolve them against yet
+    fn horizontal_edges(&self) -> f32 {
+        let style = self.get_style_node();
+        sum(["margin-left", "margin-right", "border-width-left", "border-width-right",
+            "padding-left", "padding-right"].iter()
+            .map(|name| match style.value(name) {
+                Some(Length(_, Unit::Per)) => 0.0,
+                Some(value) => self.length_to_px(&value),
+                None => 0.0,
+            }))
+    }
+
+    // text and inline content, using the style of the enclosing element like the Looper does
+    fn measure_inline(&mut self, style:&Rc<StyledNode>, font_cache:&mut FontCache, doc:&Document) -> IntrinsicWidths {
+        self.font_units = Some(font_units(font_cache, self.get_style_node()));
+        let widths = match &self.box_type {
+            InlineBlockNode(snode) => {
+                let snode = Rc::clone(snode);
+                let image_width = match &snode.node.node_type {
+                    Element(data) if data.tag_name == "img" => {
+                        let context = LengthContext::new(&snode, 0.0, None);
+                        let specified = (self.specified_image_size(data, "width", &context), self.specified_image_size(data, "height", &context));
+                        // an image that won't load has no size of its own, but still has its limits
+                        let natural = data.attributes.get("src").and_then(|src| load_image(doc, src).ok())
+                            .map_or((0.0, 0.0), |image| (image.width as f32, image.height as f32));
+                        Some(replaced_size(specified, natural, self.size_limits(&context)).0)
+                    },
+                    _ => None,
+                };
+                match image_width {
+                    Some(width) => IntrinsicWidths { min: width, max: width },
+                    None => self.children.iter_mut()
+                        .map(|ch| ch.measure_inline(&snode, font_cache, doc))
+                        .fold(IntrinsicWidths::default(), IntrinsicWidths::beside),
+                }
+            }
+            InlineNode(snode) => match &snode.node.node_type {
+                Text(txt) => {
+                    let font_family = style.lookup_font_family(font_cache);
+                    let font_weight = style.lookup_font_weight(400);
+                    let font_size = style.lookup_font_size();
+                    let font_style = style.lookup_string("font-style", "normal");
+                    let white_space = style.lookup_white_space();
+                    let spacing = text_spacing(style);
+                    let mut widths = IntrinsicWidths::default();
+                    for segment in text_segments(txt, white_space) {
+                        let mut line = IntrinsicWidths::default();
+                        for word in text_words(&segment, white_space) {
+                            let w = calculate_word_length(&word, font_cache, font_size, &font_family, font_weight, &font_style, &spacing);
+                            line = line.beside(IntrinsicWidths { min: w, max: w });
+                        }
+                        // a line that can't wrap is as wide as all of its words
+                        if !white_space.wraps() {
+                            line.min = line.max;
+                        }
+                        widths = widths.above(line);
+                    }
+                    widths
+                }
+                Element(_) => {
+                    let snode = Rc::clone(snode);
+                    self.children.iter_mut()
+                        .map(|ch| ch.measure_inline(&snode, font_cache, doc))
+                        .fold(IntrinsicWidths::default(), IntrinsicWidths::beside)
+                }
+                _ => IntrinsicWidths::default(),
+            },
+            _ => return self.measure(font_cache, doc),
+        };
+        self.intrinsic = widths;
+        widths
+    }
+
+    // min-content, max-content and fit-content, as a content-box width in px. needs the measuring
+    // pass, which knows how the content sits: stacked in a block, side by side in a flex row or
+    // a grid's columns. the content's own widths, so a fixed width doesn't leak into max-width.
+    fn resolve_intrinsic_keyword(&self, value:&Value, available:f32) -> Option<Value> {
+        let keyword = match value {
+            Keyword(kw) if kw == "min-content" || kw == "max-content" || kw == "fit-content" => kw,
+            _ => return None,
+        };
+        let (min, max) = (self.content_intrinsic.min, self.content_intrinsic.max);
+        let width = match keyword.as_str() {
+            "min-content" => min,
+            "max-content" => max,
+            _ => max.min(min.max(available - self.horizontal_edges())),
+        };
+        Some(Length(width, Px))
+    }
+
+    fn length_context(&self, containing:&Dimensions) -> LengthContext {
+        let mut context = LengthContext::new(self.get_style_node(), containing.content.width, self.containing_height);
+        if let Some(units) = self.font_units {
+            context.font_units = units;
+        }
+        context
+    }
+
+    // a percentage or calc() value as pixels, against the containing block's width
+    fn resolve_horizontal(&self, value:Value, context:&LengthContext) -> Value {
+        match value {
+            Length(_, Unit::Per) | Value::Calc(_) => Length(context.horizontal(&value).unwrap_or(0.0), Px),
+            value => value,
+        }
+    }
 
     /// Calculate the width of a block-level non-replaced element in normal flow.
     ///
     /// http://www.w3.org/TR/CSS2/visudet.html#blockwidth
     ///
     /// Sets the horizontal margin/padding/border dimensions, and the `width`.
-    fn calculate_block_width(&mut self, containing:&mut Dimensions) {
-        let style = self.get_style_node();
+    fn calculate_block_width(&mut self, containing:&Dimensions) {
+        let style = Rc::clone(self.get_style_node());
 
         // 'width' has initial value 'auto'
         let auto = Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or_else(||auto.clone());
-        // println!("width set to {:#?}",width);
-        //width percentage
-        if let Length(per, Unit::Per) = width {
-            // println!("its a percentage width {} {}",per,containing.content.width);
-            width = Length(containing.content.width*(per/100.0), Px);
+        let context = self.length_context(containing);
+        let mut width = self.resolve_horizontal(style.value("width").unwrap_or_else(||auto.clone()), &context);
+        if let Some(intrinsic) = self.resolve_intrinsic_keyword(&width, containing.content.width) {
+            width = intrinsic;
+        }
+        if self.flex_width {
+            width = auto.clone();
         }
+        // println!("width set to {:#?}",width);
 
         // margin, border, and padding have initial value of 0
         let zero = Length(0.0, Px);
-        let mut margin_left = style.lookup("margin-left","margin", &zero);
-        let mut margin_right = style.lookup("margin-right","margin", &zero);
-        let border_left = style.lookup("border-width-left","border-width", &zero);
-        let border_right = style.lookup("border-width-right","border-width", &zero);
-        let padding_left = style.lookup("padding-left","padding", &zero);
-        let padding_right = style.lookup("padding-right","padding", &zero);
-
-        // If width is not auto and the total is wider than the container, treat auto margins as 0.
-        let total = sum([&margin_left, &margin_right, &border_left, &border_right,
-            &padding_left, &padding_right, &width].iter().map(|v| self.length_to_px(v)));
-        if width != auto && total > containing.content.width {
-            if margin_left == auto {
-                margin_left = Length(0.0, Px);
-            }
-            if margin_right == auto {
-                margin_right = Length(0.0,Px);
-            }
-        }
-
-        // Adjust used values so that the above sum equals `containing_block.width`.
-        // Each arm of the `match` should increase the total width by exactly `underflow`,
-        // and afterward all values should be absolute lengths in px.
-        let underflow = containing.content.width - total;
-        // println!("underflow = {}",underflow);
-
-        match (width == auto, margin_left == auto, margin_right == auto) {
-            (false,false,false) => {
-                margin_right = Length(self.length_to_px(&margin_right) + underflow, Px);
-            }
-            (false,false,true) => { margin_right = Length(underflow, Px); }
-            (false,true,false) => { margin_left = Length(underflow, Px); }
-            (true, _, _) => {
-                if margin_left == auto { margin_left = Length(0.0, Px); }
-                if margin_right == auto { margin_right = Length(0.0, Px); }
-                if underflow >= 0.0 {
-                    width = Length(underflow, Px);
-                } else {
-                    width = Length(0.0, Px);
-                    margin_right = Length(self.length_to_px(&margin_right) + underflow, Px);
+        let edge = |name| self.resolve_horizontal(style.value(name).unwrap_or_else(|| zero.clone()), &context);
+        let margin_left = edge("margin-left");
+        let margin_right = edge("margin-right");
+        // a calc() can still come out below zero, where only margins may go
+        let border_left = non_negative(edge("border-width-left"));
+        let border_right = non_negative(edge("border-width-right"));
+        let padding_left = non_negative(edge("padding-left"));
+        let padding_right = non_negative(edge("padding-right"));
+        width = non_negative(width);
+
+        // the width and margins for a given width, which may be auto
+        let resolve = |mut width:Value| {
+            let (mut margin_left, mut margin_right) = (margin_left.clone(), margin_right.clone());
+            // If width is not auto and the total is wider than the container, treat auto margins as 0.
+            let total = sum([&margin_left, &margin_right, &border_left, &border_right,
+                &padding_left, &padding_right, &width].iter().map(|v| self.length_to_px(v)));
+            if width != auto && total > containing.content.width {
+                if margin_left == auto {
+                    margin_left = Length(0.0, Px);
+                }
+                if margin_right == auto {
+                    margin_right = Length(0.0,Px);
                 }
             }
-            (false, true, true) => {
-                margin_left = Length(underflow / 2.0, Px);
-                margin_right = Length(underflow / 2.0, Px);
+
+            // Adjust used values so that the above sum equals `containing_block.width`.
+            // Each arm of the `match` should increase the total width by exactly `underflow`,
+            // and afterward all values should be absolute lengths in px.
+            let underflow = containing.content.width - total;
+
+            match (width == auto, margin_left == auto, margin_right == auto) {
+                (false,false,false) => {
+                    margin_right = Length(self.length_to_px(&margin_right) + underflow, Px);
+                }
+                (false,false,true) => { margin_right = Length(underflow, Px); }
+                (false,true,false) => { margin_left = Length(underflow, Px); }
+                (true, _, _) => {
+                    if margin_left == auto { margin_left = Length(0.0, Px); }
+                    if margin_right == auto { margin_right = Length(0.0, Px); }
+                    if underflow >= 0.0 {
+                        width = Length(underflow, Px);
+                    } else {
+                        width = Length(0.0, Px);
+                        margin_right = Length(self.length_to_px(&margin_right) + underflow, Px);
+                    }
+                }
+                (false, true, true) => {
+                    margin_left = Length(underflow / 2.0, Px);
+                    margin_right = Length(underflow / 2.0, Px);
+                }
             }
+            (self.length_to_px(&width), margin_left, margin_right)
+        };
+        let limit = |name:&str| match style.value(name).map(|value| self.resolve_horizontal(value, &context)) {
+            Some(value) => self.resolve_intrinsic_keyword(&value, containing.content.width)
+                .or(Some(value))
+                .and_then(|value| if let Length(_, _) = value { Some(self.length_to_px(&value).max(0.0)) } else { None }),
+            None => None,
+        };
+        // CSS 2.1 10.4: a width over max-width is worked out again with max-width as the width,
+        // so auto margins still center the box, and then the same for under min-width
+        let (mut used, mut margin_left, mut margin_right) = resolve(width);
+        if let Some(max) = limit("max-width").filter(|max| used > *max) {
+            (used, margin_left, margin_right) = resolve(Length(max, Px));
         }
-        // println!("final margin left is {:#?}",margin_left);
-        // println!("width set to {:#?}",width);
-
-        self.dimensions.content.width = self.length_to_px(&width);
+        if let Some(min) = limit("min-width").filter(|min| used < *min) {
+            (used, margin_left, margin_right) = resolve(Length(min, Px));
+        }
+        self.dimensions.content.width = used;
         self.dimensions.padding.left = self.length_to_px(&padding_left);
         self.dimensions.padding.right = self.length_to_px(&padding_right);
         self.dimensions.border.left = self.length_to_px(&border_left);
         self.dimensions.border.right = self.length_to_px(&border_right);
-        self.dimensions.margin.left = self.length_to_px_size(&margin_left, &width);
-        self.dimensions.margin.right = self.length_to_px_size(&margin_right,&width);
+        self.dimensions.margin.left = self.length_to_px(&margin_left);
+        self.dimensions.margin.right = self.length_to_px(&margin_right);
         // println!("final width is width= {} padding = {} margin: {}",
         //          self.dimensions.content.width,
         //          self.dimensions.padding.left,
         //          self.dimensions.margin.left);
     }
 
-    fn length_to_px_size(&self, value:&Value, dimension:&Value) -> f32 {
-        match value {
-            Length(v, Unit::Per) => self.length_to_px(dimension)*v/100.0,
-            _ => self.length_to_px(value),
-        }
-    }
     fn length_to_px(&self, value:&Value) -> f32{
         let font_size = self.get_style_node().lookup_font_size();
         match value {
             Length(v, Unit::Px) => *v,
             Length(v, Unit::Em) => (*v)*font_size,
-            Length(v, Unit::Rem) => (*v)*font_size, // TODO: use real document font size
+            Length(v, Unit::Rem) => (*v)*self.get_style_node().lookup_root_font_size(),
             Length(_v, Unit::Per) => {
                 println!("WARNING: percentage in length_to_px. should have be converted to pixels already");
                 0.0
             }
+            Length(v, Unit::Ch) => v * self.font_units.unwrap_or_else(|| FontUnits::half_em(font_size)).ch,
+            Length(v, Unit::Ex) => v * self.font_units.unwrap_or_else(|| FontUnits::half_em(font_size)).ex,
+            Length(v, unit) => v * unit.px_per_unit().unwrap_or(0.0),
+            // percentages inside need the LengthContext, which knows the containing block
+            Value::Calc(expr) => expr.to_px(font_size, 0.0),
             _ => {0.0}
         }
     }
-    fn calculate_block_position(&mut self, containing: &mut Dimensions) {
-        let zero = Length(0.0, Px);
+    fn calculate_block_position(&mut self, containing: &Dimensions) {
         let style = self.get_style_node();
-        //println!("caculating block position {:#?} border {:#?}",style, style.lookup("border-width-top","border-width",&zero));
+        let context = self.length_context(containing);
+        // vertical margins and padding are percentages of the width too
+        let edge = |name| style.value(name).and_then(|value| context.horizontal(&value)).unwrap_or(0.0);
         let margin = EdgeSizes {
-            top: style.lookup_length_as_px("margin-top",0.0),
-            bottom: style.lookup_length_as_px("margin-bottom",0.0),
+            top: edge("margin-top"),
+            bottom: edge("margin-bottom"),
             ..(self.dimensions.margin)
         };
 
         let border = EdgeSizes {
-            top: style.lookup_length_as_px("border-width-top",0.0),
-            bottom: style.lookup_length_as_px("border-width-bottom",0.0),
+            top: edge("border-width-top").max(0.0),
+            bottom: edge("border-width-bottom").max(0.0),
             ..(self.dimensions.border)
         };
         let padding = EdgeSizes {
-            top: style.lookup_length_as_px("padding-top",0.0),
-            bottom: style.lookup_length_as_px("padding-bottom",0.0),
+            top: edge("padding-top").max(0.0),
+            bottom: edge("padding-bottom").max(0.0),
             ..(self.dimensions.padding)
         };
 
@@ -969,188 +2415,1433 @@ impl LayoutBox {
         d.content.y = containing.content.height + containing.content.y + d.margin.top + d.border.top + d.padding.top;
     }
 
+    // Floats are placed beside the flow and don't add to the height. The other children get
+    // the floats so far, to fit their lines around, and hand back any they placed themselves.
     fn layout_block_children(&mut self, font_cache:&mut FontCache, doc:&Document) -> Vec<RenderBox>{
         let d = &mut self.dimensions;
         let mut children:Vec<RenderBox> = vec![];
+        let mut first_line = true;
         for child in self.children.iter_mut() {
-            let bx = child.layout(d, font_cache, doc);
+            // an absolute box takes no room, and is placed later by its containing block
+            if child.is_absolute() {
+                child.static_position = (d.content.x, d.content.y + d.content.height);
+                continue;
+            }
+            if let Some(side) = child.float_side() {
+                let (bx, area) = child.layout_float(side, d, &self.floats, font_cache, doc);
+                self.floats.push(area);
+                children.push(bx);
+                continue;
+            }
+            child.first_line = mem::replace(&mut first_line, false);
+            // clearance moves the block below the floats on the sides it clears
+            let clear = child.clear_sides();
+            let cleared = self.floats.iter()
+                .filter(|area| clear.contains(&area.side))
+                .map(|area| area.bottom())
+                .fold(d.content.y + d.content.height, f32::max);
+            d.content.height = cleared - d.content.y;
+            child.floats = mem::take(&mut self.floats);
+            let bx = child.place(d, font_cache, doc);
+            self.floats = mem::take(&mut child.floats);
             d.content.height += child.dimensions.margin_box().height;
             children.push(bx)
         };
         children
     }
 
-    fn calculate_block_height(&mut self) {
-        if let Some(val) = self.get_style_node().value("height") {
-            self.dimensions.content.height = self.length_to_px(&val);
+    // which side the box floats to. anonymous and inline boxes share an element's style
+    // without being its box, so they never float.
+    fn float_side(&self) -> Option<FloatSide> {
+        if !self.is_block_level() || self.is_absolute() {
+            return None;
+        }
+        match self.get_style_node().lookup_string("float", "none").as_str() {
+            "left" => Some(FloatSide::Left),
+            "right" => Some(FloatSide::Right),
+            _ => None,
         }
     }
 
-}
-
-fn calculate_word_length(text:&str, fc:&mut FontCache, font_size:f32, font_family:&str, font_weight:i32, font_style:&str) -> f32 {
-    let scale = Scale::uniform(font_size  as f32);
-    let font = fc.lookup_font(font_family,font_weight, font_style);
-    let sec = Section {
-        text,
-        scale,
-        font_id:*font,
-        ..Section::default()
-    };
-    let glyph_bounds = fc.brush.glyph_bounds(sec);
-    match &glyph_bounds {
-        Some(rect) => rect.max.x as f32 + FUDGE,
-        None => 0.0,
+    fn clear_sides(&self) -> Vec<FloatSide> {
+        if !self.is_block_level() {
+            return vec![];
+        }
+        match self.get_style_node().lookup_string("clear", "none").as_str() {
+            "left" => vec![FloatSide::Left],
+            "right" => vec![FloatSide::Right],
+            "both" => vec![FloatSide::Left, FloatSide::Right],
+            _ => vec![],
+        }
     }
-}
-fn calculate_text_bounds(text:&str, fc:&mut FontCache, font_size:f32, font_family:&str, font_weight:i32, font_style:&str) -> Option<GBRect<f32>> {
-    let scale = Scale::uniform(font_size  as f32);
-    let font = fc.lookup_font(font_family,font_weight, font_style);
-    let sec = Section {
-        text,
-        scale,
-        font_id: *font,
-        ..Section::default()
-    };
-    fc.brush.glyph_bounds(sec)
-}
-
-struct Looper<'a> {
-    lines:Vec<RenderLineBox>,
-    current: RenderLineBox,
-    extents:Rect,
-    current_start:f32,
-    current_end:f32,
-    current_bottom:f32,
-    font_cache:&'a mut FontCache,
-    doc: &'a Document,
-    style_node: Rc<StyledNode>,
-}
 
-impl Looper<'_> {
-    fn start_new_line(&mut self) {
-        let old = mem::replace(&mut self.current, RenderLineBox {
-            rect: Rect{
-                x: self.extents.x,
-                y: self.current_bottom,
-                width: self.extents.width,
-                height: 0.0
-            },
-            baseline:0.0,
-            children: vec![],
-        });
-        self.lines.push(old);
-        self.current_start = self.extents.x;
-        self.current_end = self.extents.x;
-    }
-    fn add_box_to_current_line(&mut self, bx:RenderInlineBoxType) {
-        let rect = match &bx {
-            RenderInlineBoxType::Text(bx) => &bx.rect,
-            RenderInlineBoxType::Error(bx) => &bx.rect,
-            RenderInlineBoxType::Image(bx) => &bx.rect,
-            RenderInlineBoxType::Block(bx) => &bx.rect,
+    // A float is as wide as its width says, or else shrinks to fit its content. It starts where
+    // the next block in the flow would, then moves out of the way of the floats before it.
+    // The floats inside it stay inside it.
+    fn layout_float(&mut self, side:FloatSide, containing:&Dimensions, floats:&[FloatArea],
+                    font_cache:&mut FontCache, doc:&Document) -> (RenderBox, FloatArea) {
+        let available = containing.content.width;
+        let width = self.outer_width(&self.length_context(containing), available);
+        let (x, y) = float_position(floats, side, width, containing.content.y + containing.content.height,
+                                    containing.content.x, containing.content.x + available);
+        let cb = Dimensions {
+            content: Rect { x, y, width, height: 0.0 },
+            ..Default::default()
         };
-        self.current.rect.height = self.current.rect.height.max(rect.height);
-        self.current.children.push(bx);
-        self.current_start = self.current_end;
+        self.floats = vec![];
+        let bx = self.place(&cb, font_cache, doc);
+        let height = self.dimensions.margin_box().height;
+        (bx, FloatArea { side, rect: Rect { x, y, width, height } })
     }
-    fn adjust_current_line_vertical(&mut self) {
-        for ch in self.current.children.iter_mut() {
-            let (mut rect, string) =  match ch {
-                RenderInlineBoxType::Text(bx)    => (&mut bx.rect,&bx.valign),
-                RenderInlineBoxType::Error(bx)  => (&mut bx.rect,&bx.valign),
-                RenderInlineBoxType::Image(bx) => (&mut bx.rect,&bx.valign),
-                RenderInlineBoxType::Block(bx)  => (&mut bx.rect,&bx.valign),
+
+    // CSS Flexbox 9, simplified. Items get their basis along the main axis and are put on
+    // lines, more than one only when they wrap. On each line the free space is shared out by
+    // flex-grow, or an overflow taken back by flex-shrink weighted by the basis, and then
+    // justify-content spaces the items. A line is as big across as its biggest item, and
+    // align-items places or stretches each item within it.
+    fn layout_flex_items(&mut self, height:Option<f32>, font_cache:&mut FontCache, doc:&Document) -> Vec<RenderBox> {
+        let style = Rc::clone(self.get_style_node());
+        let direction = style.lookup_string("flex-direction", "row");
+        let row = !direction.starts_with("column");
+        let wrap = style.lookup_string("flex-wrap", "nowrap") != "nowrap";
+        // reversed, the items are in the opposite order and packed from the other end
+        let reverse = direction.ends_with("-reverse");
+        let justify = match style.lookup_string("justify-content", "flex-start").as_str() {
+            "flex-start" | "start" | "normal" if reverse => String::from("flex-end"),
+            "flex-end" | "end" if reverse => String::from("flex-start"),
+            justify => String::from(justify),
+        };
+        let align_items = style.lookup_string("align-items", "stretch");
+        let content = self.dimensions.content;
+        let cb = Dimensions { content: Rect { height: 0.0, ..content }, ..Default::default() };
+        // how much room there is along the main axis. a column without a height has no limit.
+        let available = if row { Some(content.width) } else { height };
+
+        let mut items:Vec<FlexItem> = vec![];
+        for (index, child) in self.children.iter_mut().enumerate() {
+            if child.is_absolute() {
+                child.static_position = (content.x, content.y);
+                continue;
+            }
+            items.push(child.flex_item(index, row, &cb, &align_items, font_cache, doc));
+        }
+        let mut lines:Vec<Vec<FlexItem>> = vec![];
+        let mut used = 0.0;
+        for item in items {
+            let new_line = match lines.last() {
+                None => true,
+                Some(line) => wrap && !line.is_empty() && available.is_some_and(|room| used + item.base > room),
+            };
+            if new_line {
+                lines.push(vec![]);
+                used = 0.0;
+            }
+            used += item.base;
+            if let Some(line) = lines.last_mut() {
+                line.push(item);
+            }
+        }
+        for line in lines.iter_mut() {
+            let free = available.map_or(0.0, |room| room - sum(line.iter().map(|item| item.base)));
+            let grow = sum(line.iter().map(|item| item.grow));
+            let shrink = sum(line.iter().map(|item| item.shrink * item.base));
+            for item in line.iter_mut() {
+                item.main = if free > 0.0 && grow > 0.0 {
+                    item.base + free * item.grow / grow
+                } else if free < 0.0 && shrink > 0.0 {
+                    (item.base + free * item.shrink * item.base / shrink).max(item.min)
+                } else {
+                    item.base
+                };
+            }
+            if reverse {
+                line.reverse();
+            }
+        }
+
+        let single = lines.len() == 1;
+        let mut cross_start = if row { content.y } else { content.x };
+        let mut bottom = content.y;
+        let mut children:Vec<(usize, RenderBox)> = vec![];
+        for line in lines {
+            let free = available.map_or(0.0, |room| (room - sum(line.iter().map(|item| item.main))).max(0.0));
+            let n = line.len() as f32;
+            let (start, gap) = match justify.as_str() {
+                "flex-end" | "end" => (free, 0.0),
+                "center" => (free / 2.0, 0.0),
+                "space-between" if n > 1.0 => (0.0, free / (n - 1.0)),
+                "space-around" => (free / n / 2.0, free / n),
+                "space-evenly" => (free / (n + 1.0), free / (n + 1.0)),
+                _ => (0.0, 0.0),
             };
-            match string.as_str() {
-                "bottom" => {
-                    rect.y = self.current.rect.y + self.current.rect.height - rect.height;
+            let mut pos = start + if row { content.x } else { content.y };
+            let mut placed:Vec<(FlexItem, Rect, RenderBox)> = vec![];
+            for item in line {
+                let child = &mut self.children[item.index];
+                child.flex_width = true;
+                let rect = if row {
+                    child.flex_height = None;
+                    Rect { x: pos, y: cross_start, width: item.main, height: 0.0 }
+                } else {
+                    child.flex_height = Some((item.main - item.vertical_edges).max(0.0));
+                    Rect { x: cross_start, y: pos, width: item.cross_width, height: 0.0 }
+                };
+                let bx = child.place(&Dimensions { content: rect, ..Default::default() }, font_cache, doc);
+                pos += item.main + gap;
+                placed.push((item, rect, bx));
+            }
+            let cross_size = |item:&FlexItem, child:&LayoutBox| if row { child.dimensions.margin_box().height } else { item.cross_width };
+            let line_cross = match (row, height) {
+                (true, Some(height)) if single => height,
+                (false, _) if single => content.width,
+                _ => placed.iter().map(|(item, _, _)| cross_size(item, &self.children[item.index])).fold(0.0, f32::max),
+            };
+            for (item, rect, mut bx) in placed {
+                let child = &mut self.children[item.index];
+                let size = cross_size(&item, child);
+                let auto_height = !matches!(child.get_style_node().value("height"), Some(Length(_, _)));
+                if row && item.align == "stretch" && auto_height && size < line_cross {
+                    let edges = size - child.dimensions.content.height;
+                    child.flex_height = Some((line_cross - edges).max(0.0));
+                    bx = child.place(&Dimensions { content: rect, ..Default::default() }, font_cache, doc);
+                } else {
+                    let offset = match item.align.as_str() {
+                        "flex-end" | "end" => line_cross - size,
+                        "center" => (line_cross - size) / 2.0,
+                        _ => 0.0,
+                    };
+                    if row { bx.translate(0.0, offset) } else { bx.translate(offset, 0.0) }
+                }
+                let margin_box = child.dimensions.margin_box();
+                bottom = bottom.max(margin_box.y + margin_box.height);
+                children.push((item.index, bx));
+            }
+            cross_start += line_cross;
+        }
+        self.dimensions.content.height = if row { cross_start - content.y } else { bottom - content.y };
+        // painted in document order, whatever order they were laid out in
+        children.sort_by_key(|(index, _)| *index);
+        children.into_iter().map(|(_, bx)| bx).collect()
+    }
+
+    // the item's basis and how it flexes. an anonymous block of text has the container's
+    // style, so it goes by its content and the defaults.
+    fn flex_item(&mut self, index:usize, row:bool, cb:&Dimensions, align_items:&str, font_cache:&mut FontCache, doc:&Document) -> FlexItem {
+        let style = Rc::clone(self.get_style_node());
+        let anonymous = matches!(self.box_type, AnonymousBlock(_));
+        let number = |name, default| match style.value(name) {
+            Some(Value::Number(n)) if !anonymous => n,
+            _ => default,
+        };
+        let align = match style.lookup_string("align-self", "auto") {
+            align if align == "auto" || anonymous => String::from(align_items),
+            align => align,
+        };
+        let context = self.length_context(cb);
+        let basis = |size:&str| match style.value("flex-basis") {
+            _ if anonymous => None,
+            Some(Keyword(kw)) if kw == "auto" => style.value(size),
+            basis => basis,
+        };
+        let mut item = FlexItem {
+            index, base: 0.0, min: 0.0, main: 0.0, grow: number("flex-grow", 0.0), shrink: number("flex-shrink", 1.0),
+            align, cross_width: 0.0, vertical_edges: 0.0,
+        };
+        if row {
+            let basis = basis("width").and_then(|value| context.horizontal(&value));
+            item.base = basis.map_or(self.intrinsic.max, |basis| basis + self.horizontal_edges());
+            item.min = self.intrinsic.min.min(item.base);
+        } else {
+            // the content height comes from laying the item out across the column
+            let auto_width = !matches!(style.value("width"), Some(Length(_, _)));
+            item.cross_width = if item.align == "stretch" && auto_width {
+                cb.content.width
+            } else {
+                self.outer_width(&context, cb.content.width)
+            };
+            self.flex_width = true;
+            self.flex_height = None;
+            self.place(&Dimensions { content: Rect { width: item.cross_width, ..cb.content }, ..Default::default() }, font_cache, doc);
+            let height = self.dimensions.margin_box().height;
+            item.vertical_edges = height - self.dimensions.content.height;
+            let basis = basis("height").and_then(|value| context.vertical(&value));
+            item.base = basis.map_or(height, |basis| basis + item.vertical_edges);
+            item.min = height.min(item.base);
+        }
+        item
+    }
+
+    // Places the items of a grid container, first the ones with both a row and a column, then
+    // the ones with just a row, then the rest in order, each in the first free area from where
+    // the one before it went, moving along the row and then down. Returns the areas and the
+    // number of columns, which is more than the template has if an item needs it.
+    fn grid_areas(&self) -> (Vec<GridArea>, usize) {
+        let style = self.get_style_node();
+        let explicit = grid_template(style.value("grid-template-columns")).len();
+        let explicit_rows = grid_template(style.value("grid-template-rows")).len();
+        let mut items = vec![];
+        for (index, child) in self.children.iter().enumerate() {
+            if child.is_absolute() {
+                continue;
+            }
+            // an anonymous block of text has the container's style, so it goes wherever there's room
+            let anonymous = matches!(child.box_type, AnonymousBlock(_));
+            let child_style = child.get_style_node();
+            let line = |name| if anonymous { GridLine::Auto } else { grid_line(child_style.value(name)) };
+            let column = grid_span(line("grid-column-start"), line("grid-column-end"), explicit);
+            let row = grid_span(line("grid-row-start"), line("grid-row-end"), explicit_rows);
+            items.push((index, column, row));
+        }
+        let count = items.iter()
+            .map(|(_, (start, span), _)| start.unwrap_or(0).saturating_add(*span))
+            .fold(explicit, usize::max)
+            .max(1);
+        let free = |areas:&[GridArea], column:usize, row:usize, columns:usize, rows:usize| {
+            column + columns <= count && !areas.iter().any(|area| column < area.column + area.columns
+                && area.column < column + columns && row < area.row + area.rows && area.row < row + rows)
+        };
+        let mut areas:Vec<GridArea> = vec![];
+        for (index, (column, columns), (row, rows)) in items.iter().copied() {
+            if let (Some(column), Some(row)) = (column, row) {
+                areas.push(GridArea { index, column, row, columns, rows });
+            }
+        }
+        for (index, (column, columns), (row, rows)) in items.iter().copied() {
+            if let (None, Some(row)) = (column, row) {
+                let column = (0..count).find(|&column| free(&areas, column, row, columns, rows)).unwrap_or(0);
+                areas.push(GridArea { index, column, row, columns, rows });
+            }
+        }
+        let (mut cursor_row, mut cursor_column) = (0, 0);
+        for (index, (column, columns), (row, rows)) in items.iter().copied() {
+            if row.is_some() {
+                continue;
+            }
+            match column {
+                Some(column) => {
+                    if column < cursor_column {
+                        cursor_row += 1;
+                    }
+                    while !free(&areas, column, cursor_row, columns, rows) {
+                        cursor_row += 1;
+                    }
+                    cursor_column = column;
                 },
-                "sub" => {
-                    rect.y = self.current.rect.y + self.current.rect.height - rect.height + 10.0;
+                None => {
+                    while !free(&areas, cursor_column, cursor_row, columns, rows) {
+                        cursor_column += 1;
+                        if cursor_column + columns > count {
+                            cursor_column = 0;
+                            cursor_row += 1;
+                        }
+                    }
                 },
-                "baseline" => {
-                    rect.y = self.current.rect.y + self.current.rect.height - rect.height;
+            }
+            areas.push(GridArea { index, column: cursor_column, row: cursor_row, columns, rows });
+            cursor_column += columns;
+        }
+        (areas, count)
+    }
+
+    // CSS Grid without named lines or areas. The columns are sized across the container's
+    // width first. Then each item is laid out across its columns, which says how tall the rows
+    // have to be, and laid out again in its area, stretched to fill it unless align-self says
+    // otherwise.
+    fn layout_grid_items(&mut self, height:Option<f32>, font_cache:&mut FontCache, doc:&Document) -> Vec<RenderBox> {
+        let style = Rc::clone(self.get_style_node());
+        let align_items = style.lookup_string("align-items", "stretch");
+        let content = self.dimensions.content;
+        let context = self.length_context(&Dimensions { content, ..Default::default() });
+        let (areas, count) = self.grid_areas();
+        for child in self.children.iter_mut() {
+            if child.is_absolute() {
+                child.static_position = (content.x, content.y);
+            }
+        }
+
+        let column_gap = grid_gap(&style, "column-gap", &context, Some(content.width));
+        let column_tracks = grid_tracks(&style, "grid-template-columns", count, &context, Some(content.width));
+        let column_content:Vec<(f32, f32)> = (0..count).map(|i| areas.iter()
+            .filter(|area| area.column == i && area.columns == 1)
+            .map(|area| self.children[area.index].intrinsic)
+            .fold((0.0, 0.0), |(min, max), widths| (widths.min.max(min), widths.max.max(max))))
+            .collect();
+        let columns = size_grid_tracks(&column_tracks, &column_content, Some(content.width), column_gap);
+
+        // each item's margin box height across its columns, and the edges around its content
+        let mut heights = vec![(0.0, 0.0); self.children.len()];
+        for area in areas.iter() {
+            let width = grid_span_size(&columns, column_gap, area.column, area.columns);
+            let child = &mut self.children[area.index];
+            child.flex_height = None;
+            child.place(&Dimensions { content: Rect { x: 0.0, y: 0.0, width, height: 0.0 }, ..Default::default() }, font_cache, doc);
+            let margin_box = child.dimensions.margin_box();
+            heights[area.index] = (margin_box.height, margin_box.height - child.dimensions.content.height);
+        }
+        let row_count = areas.iter().map(|area| area.row + area.rows)
+            .fold(grid_template(style.value("grid-template-rows")).len(), usize::max);
+        let row_gap = grid_gap(&style, "row-gap", &context, height);
+        let row_tracks = grid_tracks(&style, "grid-template-rows", row_count, &context, height);
+        let row_content:Vec<(f32, f32)> = (0..row_count).map(|i| areas.iter()
+            .filter(|area| area.row == i && area.rows == 1)
+            .map(|area| heights[area.index].0)
+            .fold(0.0, f32::max))
+            .map(|height| (height, height))
+            .collect();
+        let mut rows = size_grid_tracks(&row_tracks, &row_content, height, row_gap);
+        // an item across several rows that doesn't fit in them makes the last that can grow taller
+        for area in areas.iter().filter(|area| area.rows > 1) {
+            let missing = heights[area.index].0 - grid_span_size(&rows, row_gap, area.row, area.rows);
+            let last = (area.row..area.row + area.rows).rev().find(|&i| !matches!(row_tracks[i], GridTrack::Fixed(_)));
+            if let (true, Some(last)) = (missing > 0.0, last) {
+                rows[last] += missing;
+            }
+        }
+
+        // where a track starts, after the ones before it and their gaps
+        let offset = |sizes:&[f32], gap:f32, n:usize| sum(sizes[..n].iter().copied()) + gap * n as f32;
+        let mut children:Vec<(usize, RenderBox)> = vec![];
+        for area in areas {
+            let x = content.x + offset(&columns, column_gap, area.column);
+            let y = content.y + offset(&rows, row_gap, area.row);
+            let width = grid_span_size(&columns, column_gap, area.column, area.columns);
+            let area_height = grid_span_size(&rows, row_gap, area.row, area.rows);
+            let child = &mut self.children[area.index];
+            let anonymous = matches!(child.box_type, AnonymousBlock(_));
+            let align = match child.get_style_node().lookup_string("align-self", "auto") {
+                align if align == "auto" || anonymous => align_items.clone(),
+                align => align,
+            };
+            let auto_height = !matches!(child.get_style_node().value("height"), Some(Length(_, _)));
+            let (size, edges) = heights[area.index];
+            if (align == "stretch" || align == "normal") && auto_height {
+                child.flex_height = Some((area_height - edges).max(0.0));
+            }
+            // the area is the item's containing block, and its height is known by now
+            child.containing_height = Some(area_height);
+            let rect = Rect { x, y, width, height: 0.0 };
+            let mut bx = child.place(&Dimensions { content: rect, ..Default::default() }, font_cache, doc);
+            let offset = match align.as_str() {
+                "end" | "flex-end" => area_height - size,
+                "center" => (area_height - size) / 2.0,
+                _ => 0.0,
+            };
+            bx.translate(0.0, offset);
+            children.push((area.index, bx));
+        }
+        self.dimensions.content.height = grid_span_size(&rows, row_gap, 0, rows.len());
+        // painted in document order, whatever order they were placed in
+        children.sort_by_key(|(index, _)| *index);
+        children.into_iter().map(|(_, bx)| bx).collect()
+    }
+
+    // the number of columns and the gap between them, if this is a multi-column container
+    fn column_layout(&self) -> Option<(usize, f32)> {
+        let style = self.get_style_node();
+        let count = match style.value("column-count") {
+            Some(Value::Number(n)) if n >= 1.0 => Some(n as usize),
+            _ => None,
+        };
+        let width = match style.value("column-width") {
+            Some(value @ Length(_, _)) => Some(self.length_to_px(&value)),
+            _ => None,
+        };
+        if count.is_none() && width.is_none() {
+            return None;
+        }
+        let gap = match style.value("column-gap") {
+            Some(value @ Length(_, _)) => self.length_to_px(&value),
+            _ => style.lookup_font_size(),
+        };
+        let available = self.dimensions.content.width;
+        let count = match (count, width) {
+            (Some(count), None) => count,
+            (count, Some(width)) => {
+                let fits = (((available + gap) / (width + gap)).floor() as usize).max(1);
+                count.map_or(fits, |count| count.min(fits))
+            },
+            (None, None) => 1,
+        };
+        Some((count, gap))
+    }
+
+    // Lays the children out in one column of the column width, then breaks that between lines
+    // and blocks into `count` columns side by side, as short as they can be.
+    fn layout_block_columns(&mut self, count:usize, gap:f32, font_cache:&mut FontCache, doc:&Document) -> Vec<RenderBox> {
+        let full_width = self.dimensions.content.width;
+        let column_width = (full_width - gap * (count - 1) as f32) / count as f32;
+        self.dimensions.content.width = column_width;
+        let children = self.layout_block_children(font_cache, doc);
+        self.dimensions.content.width = full_width;
+        let top = self.dimensions.content.y;
+
+        // the pieces that can go in different columns, in order
+        let mut pieces:Vec<ColumnPiece> = vec![];
+        for (source, child) in children.into_iter().enumerate() {
+            match child {
+                RenderBox::Anonymous(anon) => {
+                    for line in anon.children {
+                        pieces.push(ColumnPiece::Line(source, anon.rect.x, line));
+                    }
                 },
-                "super" => {
-                    rect.y = self.current.rect.y + self.current.rect.height - rect.height - 10.0;
+                other => pieces.push(ColumnPiece::Whole(Box::new(other))),
+            }
+        }
+        let tallest = pieces.iter().map(|p| p.bounds().1).fold(0.0, f32::max);
+        let total = pieces.last().map_or(0.0, |p| p.bounds().0 + p.bounds().1 - top);
+        let mut height = (total / count as f32).max(tallest);
+        let starts = loop {
+            match pack_columns(&pieces, height, count) {
+                Ok(starts) => break starts,
+                Err(overflow) => height += overflow.max(0.5),
+            }
+        };
+
+        let mut columns:Vec<RenderBox> = vec![];
+        // which anonymous box and column the last regrouped line came from
+        let mut last_line:Option<(usize, usize)> = None;
+        let mut column = 0;
+        for (index, mut piece) in pieces.into_iter().enumerate() {
+            while column + 1 < starts.len() && starts[column + 1].0 <= index {
+                column += 1;
+            }
+            let dx = column as f32 * (column_width + gap);
+            let dy = top - starts[column].1;
+            match &mut piece {
+                ColumnPiece::Whole(bx) => bx.translate(dx, dy),
+                ColumnPiece::Line(_, _, line) => line.translate(dx, dy),
+            }
+            match piece {
+                ColumnPiece::Whole(bx) => {
+                    last_line = None;
+                    columns.push(*bx);
                 },
-                "middle" => {
-                    rect.y = self.current.rect.y + (self.current.rect.height - rect.height)/2.0;
+                ColumnPiece::Line(source, x, line) => {
+                    if last_line == Some((source, column)) {
+                        if let Some(RenderBox::Anonymous(anon)) = columns.last_mut() {
+                            anon.rect.height += line.rect.height;
+                            anon.children.push(line);
+                            continue;
+                        }
+                    }
+                    last_line = Some((source, column));
+                    columns.push(RenderBox::Anonymous(RenderAnonymousBox {
+                        rect: Rect { x: x + dx, y: line.rect.y, width: column_width, height: line.rect.height },
+                        children: vec![line],
+                    }));
+                }
+            }
+        }
+        self.dimensions.content.height = height;
+        columns
+    }
+
+    // the height from the style, or None for auto and for percentages of an unknown height
+    fn specified_height(&self, containing:&Dimensions) -> Option<f32> {
+        let value = self.get_style_node().value("height")?;
+        let height = self.length_context(containing).vertical(&value)?;
+        Some(self.clamp_height(height.max(0.0), containing))
+    }
+
+    // by max-height and then min-height, so min-height wins. a percentage of a height that
+    // isn't known doesn't limit anything.
+    fn clamp_height(&self, height:f32, containing:&Dimensions) -> f32 {
+        let style = self.get_style_node();
+        let context = self.length_context(containing);
+        let limit = |name| style.value(name).and_then(|value| context.vertical(&value)).map(|limit| limit.max(0.0));
+        let height = limit("max-height").map_or(height, |max| height.min(max));
+        limit("min-height").map_or(height, |min| height.max(min))
+    }
+
+    // the specified height, or else the height of the content, within min-height and max-height
+    fn calculate_block_height(&mut self, containing:&Dimensions) {
+        let height = self.flex_height
+            .or_else(|| self.specified_height(containing))
+            .unwrap_or(self.dimensions.content.height);
+        self.dimensions.content.height = self.clamp_height(height, containing);
+    }
+
+}
+
+// a length that can't go below zero, like padding from a calc()
+fn non_negative(value:Value) -> Value {
+    match value {
+        Length(v, unit) if v < 0.0 || v.is_nan() => Length(0.0, unit),
+        value => value,
+    }
+}
+
+// the track sizes of grid-template-columns or -rows, with repeat() spelled out
+fn grid_template(value:Option<Value>) -> Vec<Value> {
+    let values = match value {
+        Some(Value::ArrayValue(values)) => values,
+        Some(Keyword(kw)) if kw == "none" => vec![],
+        Some(value) => vec![value],
+        None => vec![],
+    };
+    values.into_iter().flat_map(|value| match value {
+        Value::FunCall(call) if call.name == "repeat" => {
+            let count = match call.arguments.first() {
+                Some(Value::Number(n)) if *n >= 1.0 => (*n as usize).min(MAX_GRID_TRACKS),
+                _ => 0,
+            };
+            let sizes = &call.arguments[1.min(call.arguments.len())..];
+            sizes.iter().cloned().cycle().take(count * sizes.len()).collect()
+        },
+        value => vec![value],
+    }).take(MAX_GRID_TRACKS).collect()
+}
+
+// `count` tracks, the template's and then auto ones. percentages are of `size`, and are auto
+// when it isn't known.
+fn grid_tracks(style:&StyledNode, name:&str, count:usize, context:&LengthContext, size:Option<f32>) -> Vec<GridTrack> {
+    let mut tracks:Vec<GridTrack> = grid_template(style.value(name)).iter().map(|value| match value {
+        Length(v, Unit::Fr) => GridTrack::Fraction(*v),
+        value => context.to_px(value, size).map_or(GridTrack::Auto, |px| GridTrack::Fixed(px.max(0.0))),
+    }).collect();
+    tracks.resize(count.max(tracks.len()), GridTrack::Auto);
+    tracks
+}
+
+// row-gap or column-gap. normal is no gap in a grid.
+fn grid_gap(style:&StyledNode, name:&str, context:&LengthContext, size:Option<f32>) -> f32 {
+    style.value(name).and_then(|value| context.to_px(&value, size)).unwrap_or(0.0).max(0.0)
+}
+
+fn grid_line(value:Option<Value>) -> GridLine {
+    match value {
+        Some(Value::Number(n)) if n != 0.0 => GridLine::Number((n as i32).clamp(-(MAX_GRID_TRACKS as i32), MAX_GRID_TRACKS as i32)),
+        Some(Value::ArrayValue(values)) => match values.as_slice() {
+            [Keyword(kw), Value::Number(n)] if kw == "span" && *n >= 1.0 => GridLine::Span((*n as usize).min(MAX_GRID_TRACKS)),
+            _ => GridLine::Auto,
+        },
+        _ => GridLine::Auto,
+    }
+}
+
+// the first track an item is in, if its lines say, and how many it takes. negative line
+// numbers count back from the end of the explicit grid, which has `explicit` tracks.
+fn grid_span(start:GridLine, end:GridLine, explicit:usize) -> (Option<usize>, usize) {
+    let track = |n:i32| if n > 0 { n as usize - 1 } else { (explicit as i32 + 1 + n).max(0) as usize };
+    match (start, end) {
+        (GridLine::Number(a), GridLine::Number(b)) => {
+            let (a, b) = (track(a), track(b));
+            (Some(a.min(b)), a.abs_diff(b).max(1))
+        },
+        (GridLine::Number(a), GridLine::Span(n)) => (Some(track(a)), n),
+        (GridLine::Number(a), GridLine::Auto) => (Some(track(a)), 1),
+        (GridLine::Span(n), GridLine::Number(b)) => (Some(track(b).saturating_sub(n)), n),
+        (GridLine::Auto, GridLine::Number(b)) => (Some(track(b).saturating_sub(1)), 1),
+        (GridLine::Span(n), _) | (GridLine::Auto, GridLine::Span(n)) => (None, n),
+        (GridLine::Auto, GridLine::Auto) => (None, 1),
+    }
+}
+
+// Sizes the tracks along one axis. Fixed tracks keep their size and auto tracks get what the
+// items in them need, `content` being the least and the most for each track. If that's more
+// than `room` the auto tracks shrink toward the least. What's left over goes to the fr
+// tracks, or when there are none, is shared between the auto tracks.
+fn size_grid_tracks(tracks:&[GridTrack], content:&[(f32, f32)], room:Option<f32>, gap:f32) -> Vec<f32> {
+    let gaps = gap * tracks.len().saturating_sub(1) as f32;
+    let mut sizes:Vec<f32> = tracks.iter().zip(content).map(|(track, (_, max))| match track {
+        GridTrack::Fixed(size) => *size,
+        // without room to share, an fr track sizes to its content like an auto one
+        GridTrack::Fraction(_) if room.is_some() => 0.0,
+        _ => *max,
+    }).collect();
+    let room = match room {
+        Some(room) => room - gaps,
+        None => return sizes,
+    };
+    let autos:Vec<usize> = (0..tracks.len()).filter(|&i| tracks[i] == GridTrack::Auto).collect();
+    let used = sum(sizes.iter().copied());
+    if used > room {
+        let least = sum(autos.iter().map(|&i| content[i].0));
+        let most = sum(autos.iter().map(|&i| content[i].1));
+        let ratio = if most > least { ((room - (used - most) - least) / (most - least)).clamp(0.0, 1.0) } else { 0.0 };
+        for &i in autos.iter() {
+            sizes[i] = content[i].0 + (content[i].1 - content[i].0) * ratio;
+        }
+    }
+    let free = room - sum(sizes.iter().copied());
+    let fractions = sum(tracks.iter().map(|track| match track {
+        GridTrack::Fraction(fr) => *fr,
+        _ => 0.0,
+    }));
+    if free > 0.0 && fractions > 0.0 {
+        for (size, track) in sizes.iter_mut().zip(tracks) {
+            if let GridTrack::Fraction(fr) = track {
+                *size = free * fr / fractions.max(1.0);
+            }
+        }
+    } else if free > 0.0 && !autos.is_empty() {
+        for &i in autos.iter() {
+            sizes[i] += free / autos.len() as f32;
+        }
+    }
+    sizes
+}
+
+// the size of `count` tracks from `start` on, with the gaps between them
+fn grid_span_size(sizes:&[f32], gap:f32, start:usize, count:usize) -> f32 {
+    let end = (start + count).min(sizes.len());
+    sum(sizes[start.min(end)..end].iter().copied()) + gap * count.saturating_sub(1) as f32
+}
+
+// The used size of an image from its specified width and height, its natural size and its
+// (min-width, max-width, min-height, max-height). A missing dimension comes from the other one
+// through the aspect ratio. With neither given, CSS 2.1 10.4's table keeps the ratio inside the
+// limits where it can. Otherwise each dimension is limited on its own, and a derived one follows
+// the limited one it came from.
+fn replaced_size(specified:(Option<f32>, Option<f32>), natural:(f32, f32), limits:(f32, f32, f32, f32)) -> (f32, f32) {
+    let (iw, ih) = natural;
+    let (min_w, max_w, min_h, max_h) = limits;
+    let height_for = |w:f32| if iw > 0.0 { w * ih / iw } else { ih };
+    let width_for = |h:f32| if ih > 0.0 { h * iw / ih } else { iw };
+    match specified {
+        (Some(w), Some(h)) => (w.clamp(min_w, max_w), h.clamp(min_h, max_h)),
+        (Some(w), None) => {
+            let w = w.clamp(min_w, max_w);
+            (w, height_for(w).clamp(min_h, max_h))
+        },
+        (None, Some(h)) => {
+            let h = h.clamp(min_h, max_h);
+            (width_for(h).clamp(min_w, max_w), h)
+        },
+        (None, None) if iw <= 0.0 || ih <= 0.0 => (iw.clamp(min_w, max_w), ih.clamp(min_h, max_h)),
+        (None, None) => {
+            let (w, h) = (iw, ih);
+            match (w > max_w, w < min_w, h > max_h, h < min_h) {
+                (true, _, true, _) if max_w / w <= max_h / h => (max_w, min_h.max(max_w * h / w)),
+                (true, _, true, _) => (min_w.max(max_h * w / h), max_h),
+                (_, true, _, true) if min_w / w <= min_h / h => (max_w.min(min_h * w / h), min_h),
+                (_, true, _, true) => (min_w, max_h.min(min_w * h / w)),
+                (_, true, true, _) => (min_w, max_h),
+                (true, _, _, true) => (max_w, min_h),
+                (true, _, _, _) => (max_w, min_h.max(max_w * h / w)),
+                (_, true, _, _) => (min_w, max_h.min(min_w * h / w)),
+                (_, _, true, _) => (min_w.max(max_h * w / h), max_h),
+                (_, _, _, true) => (max_w.min(min_h * w / h), min_h),
+                _ => (w, h),
+            }
+        },
+    }
+}
+
+// the pieces of text between newlines, if they start new lines. a text that ends in a newline
+// ends with an empty piece, so what comes after it goes on the next line.
+fn text_segments(txt:&str, white_space:WhiteSpace) -> Vec<String> {
+    if white_space.keeps_newlines() {
+        txt.split('\n').map(|line| line.replace('\t', "        ")).collect()
+    } else {
+        vec![txt.to_string()]
+    }
+}
+
+// the words of one line, each with the space before it. collapsed spaces become a single one.
+fn text_words(segment:&str, white_space:WhiteSpace) -> Vec<String> {
+    if white_space.collapses_spaces() {
+        return segment.split_whitespace().map(|word| format!(" {}", word)).collect();
+    }
+    let mut words = vec![];
+    let mut word = String::new();
+    for ch in segment.chars() {
+        if ch.is_whitespace() && word.chars().last().is_some_and(|last| !last.is_whitespace()) {
+            words.push(mem::take(&mut word));
+        }
+        word.push(ch);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn calculate_word_length(text:&str, fc:&mut FontCache, font_size:f32, font_family:&str, font_weight:i32, font_style:&str, spacing:&TextSpacing) -> f32 {
+    let scale = Scale::uniform(font_size  as f32);
+    let font = fc.lookup_font(font_family,font_weight, font_style);
+    let sec = Section {
+        text,
+        scale,
+        font_id:*font,
+        ..Section::default()
+    };
+    let glyph_bounds = fc.brush.glyph_bounds(sec);
+    match &glyph_bounds {
+        Some(rect) => rect.max.x as f32 + FUDGE + spacing.extra_width(text),
+        None => 0.0,
+    }
+}
+
+fn border_widths(style:&StyledNode) -> EdgeSizes {
+    EdgeSizes {
+        top: style.lookup_length_as_px("border-width-top", 0.0).max(0.0),
+        bottom: style.lookup_length_as_px("border-width-bottom",0.0).max(0.0),
+        left: style.lookup_length_as_px("border-width-left",0.0).max(0.0),
+        right: style.lookup_length_as_px("border-width-right",0.0).max(0.0),
+    }
+}
+
+// the horizontal and vertical space between the cells of a table with separate borders
+fn border_spacing(style:&StyledNode) -> (f32, f32) {
+    if style.lookup_string("border-collapse", "separate") == "collapse" {
+        return (0.0, 0.0);
+    }
+    let context = LengthContext::new(style, 0.0, None);
+    let px = |value:&Value| context.to_px(value, None).unwrap_or(0.0).max(0.0);
+    match style.value("border-spacing") {
+        Some(Value::ArrayValue(values)) if values.len() == 2 => (px(&values[0]), px(&values[1])),
+        Some(value) => (px(&value), px(&value)),
+        None => (0.0, 0.0),
+    }
+}
+
+// each side's own color, falling back to border-color
+fn border_colors(style:&StyledNode) -> BorderSides<Option<Color>> {
+    let side = |name:&str| style.color(&format!("border-color-{}", name)).or_else(|| style.color("border-color"));
+    BorderSides { top: side("top"), right: side("right"), bottom: side("bottom"), left: side("left") }
+}
+
+fn border_style_keyword(kw:&str) -> BorderStyle {
+    match kw {
+        "none" => BorderStyle::None,
+        "hidden" => BorderStyle::Hidden,
+        "dashed" => BorderStyle::Dashed,
+        "dotted" => BorderStyle::Dotted,
+        "double" => BorderStyle::Double,
+        _ => BorderStyle::Solid,
+    }
+}
+
+// each side's own style, falling back to border-style and then solid
+fn border_styles(style:&StyledNode) -> BorderSides<BorderStyle> {
+    let side = |name:&str| match style.lookup(&format!("border-style-{}", name), "border-style", &Keyword(String::from("solid"))) {
+        Keyword(kw) => border_style_keyword(&kw),
+        _ => BorderStyle::Solid,
+    };
+    BorderSides { top: side("top"), right: side("right"), bottom: side("bottom"), left: side("left") }
+}
+
+// percentages are of the border box, horizontal radii of its width and vertical ones of its height
+fn border_radius(style:&StyledNode, border_box:Rect) -> CornerRadii {
+    let context = LengthContext::new(style, border_box.width, Some(border_box.height));
+    let corner = |name:&str| {
+        let (horizontal, vertical) = match style.value(name) {
+            Some(Value::ArrayValue(radii)) if radii.len() == 2 => (radii[0].clone(), radii[1].clone()),
+            Some(radius) => (radius.clone(), radius),
+            None => return CornerRadius::default(),
+        };
+        CornerRadius {
+            horizontal: context.horizontal(&horizontal).unwrap_or(0.0).max(0.0),
+            vertical: context.vertical(&vertical).unwrap_or(0.0).max(0.0),
+        }
+    };
+    CornerRadii {
+        top_left: corner("border-top-left-radius"),
+        top_right: corner("border-top-right-radius"),
+        bottom_right: corner("border-bottom-right-radius"),
+        bottom_left: corner("border-bottom-left-radius"),
+    }.fit(border_box.width, border_box.height)
+}
+
+fn transform(style:&StyledNode, border_box:Rect) -> Option<Matrix> {
+    style.transform(border_box.x, border_box.y, border_box.width, border_box.height)
+}
+
+// None unless outline-style is set. `auto` is drawn solid, and the color defaults to the text color.
+fn outline(style:&StyledNode) -> Option<Outline> {
+    let outline_style = match style.value("outline-style") {
+        Some(Keyword(kw)) if kw != "none" && kw != "hidden" => border_style_keyword(&kw),
+        _ => return None,
+    };
+    // the width keywords are already px
+    let width = style.lookup_length_as_px("outline-width", 3.0);
+    if width <= 0.0 {
+        return None;
+    }
+    let color = style.color("outline-color").unwrap_or_else(|| style.lookup_color("color", &BLACK));
+    Some(Outline { width, style: outline_style, color })
+}
+
+// an identity for a styled element, good for as long as the styled tree is
+fn element_id(style:&Rc<StyledNode>) -> usize {
+    Rc::as_ptr(style) as usize
+}
+
+// the href of a link element, and the element
+fn link_of(style:&Rc<StyledNode>) -> Option<(String, usize, Option<String>)> {
+    match &style.node.node_type {
+        Element(ed) if ed.tag_name == "a" => ed.attributes.get("href")
+            .map(|href| (href.clone(), element_id(style), ed.attributes.get("target").cloned())),
+        _ => None,
+    }
+}
+
+// the decoration of an inline element, if it has anything to paint or any padding
+fn inline_decoration(style:&StyledNode, context:&LengthContext, opacity:f32) -> Option<Rc<InlineDecoration>> {
+    let edge = |name| style.value(name).and_then(|value| context.horizontal(&value)).unwrap_or(0.0).max(0.0);
+    let padding = EdgeSizes {
+        top: edge("padding-top"),
+        bottom: edge("padding-bottom"),
+        left: edge("padding-left"),
+        right: edge("padding-right"),
+    };
+    let border_width = border_widths(style);
+    let border_color = border_colors(style);
+    let background_color = style.color("background-color");
+    let has_padding = padding.top + padding.bottom + padding.left + padding.right > 0.0;
+    let has_border = border_color != BorderSides::default()
+        && border_width.top + border_width.bottom + border_width.left + border_width.right > 0.0;
+    if background_color.is_none() && !has_padding && !has_border {
+        return None;
+    }
+    Some(Rc::new(InlineDecoration {
+        background_color,
+        padding,
+        border_width,
+        border_color,
+        border_style: border_styles(style),
+        clone: style.lookup_string("box-decoration-break", "slice") == "clone",
+        opacity,
+        visible: style.is_visible(),
+    }))
+}
+
+fn border_edges(style:&StyledNode) -> BorderSides<BorderEdge> {
+    let (widths, colors, styles) = (border_widths(style), border_colors(style), border_styles(style));
+    BorderSides {
+        top: BorderEdge { width: widths.top, color: colors.top, style: styles.top },
+        right: BorderEdge { width: widths.right, color: colors.right, style: styles.right },
+        bottom: BorderEdge { width: widths.bottom, color: colors.bottom, style: styles.bottom },
+        left: BorderEdge { width: widths.left, color: colors.left, style: styles.left },
+    }
+}
+
+// one side of a box's border, as a candidate in border conflict resolution
+#[derive(Clone, Debug, PartialEq)]
+struct BorderEdge {
+    width: f32,
+    color: Option<Color>,
+    style: BorderStyle,
+}
+
+impl BorderEdge {
+    fn prominence(&self) -> (bool, f32, u8) {
+        let rank = match self.style {
+            BorderStyle::None => 0,
+            BorderStyle::Dotted => 1,
+            BorderStyle::Dashed => 2,
+            BorderStyle::Solid => 3,
+            BorderStyle::Double => 4,
+            BorderStyle::Hidden => 5,
+        };
+        let width = if self.style == BorderStyle::None { 0.0 } else { self.width };
+        (self.style == BorderStyle::Hidden, width, rank)
+    }
+}
+
+// CSS 2.1 17.6.2.1: hidden beats everything, then the wider border, then the more prominent
+// style. On a full tie the earlier candidate wins, so cells are passed before rows and tables.
+fn winning_border(candidates:&[&BorderEdge]) -> BorderEdge {
+    let mut best = candidates[0];
+    for candidate in candidates.iter().skip(1) {
+        if candidate.prominence().partial_cmp(&best.prominence()) == Some(std::cmp::Ordering::Greater) {
+            best = candidate;
+        }
+    }
+    match best.style {
+        BorderStyle::Hidden | BorderStyle::None => BorderEdge { width: 0.0, color: None, style: best.style },
+        _ => best.clone(),
+    }
+}
+
+// For every cell, the borders it paints. A cell paints its top and left edges, plus its
+// bottom and right ones where nothing is below or beside it. None means a neighbor paints it.
+fn resolve_collapsed_borders(table:&BorderSides<BorderEdge>, rows:&[(BorderSides<BorderEdge>, Vec<BorderSides<BorderEdge>>)])
+        -> Vec<Vec<BorderSides<Option<BorderEdge>>>> {
+    let mut resolved = vec![];
+    for (r, (row, cells)) in rows.iter().enumerate() {
+        let above = if r > 0 { Some(&rows[r - 1]) } else { None };
+        let below = rows.get(r + 1);
+        let mut resolved_row = vec![];
+        for (c, cell) in cells.iter().enumerate() {
+            let mut top = vec![&cell.top];
+            match above.and_then(|(above_row, above_cells)| above_cells.get(c).map(|a| (above_row, a))) {
+                Some((above_row, above_cell)) => {
+                    top.push(&above_cell.bottom);
+                    top.push(&row.top);
+                    top.push(&above_row.bottom);
                 },
-                "top" => {
-                    rect.y = self.current.rect.y;
+                None if r == 0 => {
+                    top.push(&row.top);
+                    top.push(&table.top);
                 },
-                _ => {}
+                None => top.push(&row.top),
+            }
+            let mut left = vec![&cell.left];
+            if c > 0 {
+                left.push(&cells[c - 1].right);
+            } else {
+                left.push(&row.left);
+                left.push(&table.left);
             }
+            let bottom = match below.and_then(|(_, below_cells)| below_cells.get(c)) {
+                Some(_) => None,
+                None if r + 1 == rows.len() => Some(winning_border(&[&cell.bottom, &row.bottom, &table.bottom])),
+                None => Some(winning_border(&[&cell.bottom, &row.bottom])),
+            };
+            let right = if c + 1 == cells.len() {
+                Some(winning_border(&[&cell.right, &row.right, &table.right]))
+            } else {
+                None
+            };
+            resolved_row.push(BorderSides {
+                top: Some(winning_border(&top)),
+                right,
+                bottom,
+                left: Some(winning_border(&left)),
+            });
         }
+        resolved.push(resolved_row);
     }
-    fn adjust_current_line_horizontal(&mut self) {
-        let text_align = self.style_node.lookup_keyword("text-align",&Value::Keyword(String::from("none")));
-        // println!("fixing horiz {:#?}", text_align);
-        if let Keyword(text_align) = text_align {
-            match text_align.as_str() {
-                "center" => {
-                    println!("going to center it. line.x={} line.width={}",
-                             self.current.rect.x, self.current.rect.width);
-                    let mut left:f32 =   10000.0;
-                    let mut right:f32 = -10000.0;
-                    for ch in self.current.children.iter_mut() {
-                        if let RenderInlineBoxType::Text(ch) = ch {
-                            left = left.min(ch.rect.x);
-                            right = right.max(ch.rect.x+ch.rect.width);
-                        }
-                    }
-                    let shift_x = (self.current.rect.width - (right-left))/2.0;
-                    for ch in self.current.children.iter_mut() {
-                        if let RenderInlineBoxType::Text(ch) = ch {
-                            ch.rect.x += shift_x;
-                        }
-                    }
+    resolved
+}
+
+fn ruby_text_width(looper:&mut Looper, style:&StyledNode, text:&str) -> f32 {
+    let font_family = style.lookup_font_family(looper.font_cache);
+    calculate_word_length(text, looper.font_cache, style.lookup_font_size(), &font_family,
+                          style.lookup_font_weight(400), &style.lookup_string("font-style", "normal"), &text_spacing(style))
+}
+
+fn ruby_text_box(looper:&mut Looper, style:&StyledNode, text:String, x:f32, width:f32, link:&Option<String>) -> RenderTextBox {
+    let font_size = style.lookup_font_size();
+    RenderTextBox {
+        rect: Rect { x, y: looper.current_bottom, width, height: font_size },
+        text,
+        color: Some(style.lookup_color("color", &BLACK)),
+        background_color: style.color("background-color"),
+        font_size,
+        font_family: style.lookup_font_family(looper.font_cache),
+        link: link.clone(),
+        link_target: looper.link_target(),
+        font_weight: style.lookup_font_weight(400),
+        font_style: style.lookup_string("font-style", "normal"),
+        valign: style.lookup_vertical_align(),
+        line_height: style.lookup_line_height(),
+        spacing: text_spacing(style),
+        opacity: looper.opacity,
+        visible: style.is_visible(),
+        text_decoration_line: style.lookup_text_decoration_line(),
+        pointer_events: style.lookup_string("pointer-events", "auto"),
+        cursor: style.lookup_string("cursor", "auto"),
+        decorations: looper.decorations.clone(),
+        element: looper.element(),
+        element_path: style.path.clone(),
+    }
+}
+
+// the space added above and below glyphs of the given height to make up the line-height.
+// negative when the line-height is smaller than the font.
+fn half_leading(line_height:Option<f32>, glyph_height:f32) -> f32 {
+    line_height.map(|lh| (lh - glyph_height) / 2.0).unwrap_or(0.0)
+}
+
+// ascent above and descent below the baseline, both positive
+fn font_metrics(fc:&mut FontCache, font_size:f32, font_family:&str, font_weight:i32, font_style:&str) -> (f32, f32) {
+    let key = fc.make_key(font_family, font_weight, font_style);
+    match fc.fonts.get(&key) {
+        Some(font) => {
+            let metrics = fc.brush.v_metrics(*font, font_size);
+            (metrics.ascent, -metrics.descent)
+        }
+        None => (font_size * 0.8, font_size * 0.2),
+    }
+}
+// the width of `0` and the height of `x` in the style's font
+fn font_units(fc:&mut FontCache, style:&StyledNode) -> FontUnits {
+    let font_size = style.lookup_font_size();
+    let font_family = style.lookup_font_family(fc);
+    let key = fc.make_key(&font_family, style.lookup_font_weight(400), &style.lookup_string("font-style", "normal"));
+    match fc.fonts.get(&key) {
+        Some(font) => fc.brush.font_units(*font, font_size),
+        None => FontUnits::half_em(font_size),
+    }
+}
+struct Looper<'a> {
+    lines:Vec<RenderLineBox>,
+    current: RenderLineBox,
+    extents:Rect,
+    current_start:f32,
+    current_end:f32,
+    current_bottom:f32,
+    // the floats the lines are shortened around
+    floats: Vec<FloatArea>,
+    font_cache:&'a mut FontCache,
+    doc: &'a Document,
+    style_node: Rc<StyledNode>,
+    // the block the lines belong to. its font gives each line a minimum height and baseline.
+    block_style: Rc<StyledNode>,
+    // the decorated inline elements being laid out, outermost first
+    decorations: Vec<Rc<InlineDecoration>>,
+    // the opacity of the inline elements being laid out, multiplied together
+    opacity: f32,
+    // the link being laid out, if any, the element it belongs to, and its target
+    link: Option<(String, usize, Option<String>)>,
+    // the current line ends at a newline the text keeps, so even an empty one takes up room
+    hard_break: bool,
+}
+
+impl Looper<'_> {
+    // the element the text being laid out belongs to
+    fn element(&self) -> usize {
+        match &self.link {
+            Some((_, element, _)) => *element,
+            None => element_id(&self.style_node),
+        }
+    }
+    fn link_target(&self) -> Option<String> {
+        self.link.as_ref().and_then(|(_, _, target)| target.clone())
+    }
+    fn start_new_line(&mut self) {
+        let old = mem::replace(&mut self.current, RenderLineBox {
+            rect: Rect{
+                x: self.extents.x,
+                y: self.current_bottom,
+                width: self.extents.width,
+                height: 0.0
+            },
+            baseline:0.0,
+            children: vec![],
+        });
+        self.lines.push(old);
+        self.hard_break = false;
+        self.fit_current_line();
+    }
+    // narrows the current line to the room the floats leave at its top, and starts it there
+    fn fit_current_line(&mut self) {
+        let (left, right) = float_bounds(&self.floats, self.current.rect.y,
+                                         self.extents.x, self.extents.x + self.extents.width);
+        self.current.rect.x = left;
+        self.current.rect.width = (right - left).max(0.0);
+        self.current_start = left;
+        self.current_end = left;
+    }
+    // where the current line ends
+    fn line_right(&self) -> f32 {
+        self.current.rect.x + self.current.rect.width
+    }
+    fn add_box_to_current_line(&mut self, bx:RenderInlineBoxType) {
+        self.current.rect.height = self.current.rect.height.max(bx.rect().height);
+        self.current.children.push(bx);
+        self.current_start = self.current_end;
+    }
+    // line up the boxes on the current line and move down past it
+    fn finish_line(&mut self) {
+        self.close_line(true);
+    }
+    // the last line of a block isn't justified
+    fn finish_last_line(&mut self) {
+        self.close_line(false);
+    }
+    fn close_line(&mut self, justify:bool) {
+        self.adjust_current_line_vertical();
+        self.adjust_current_line_horizontal(justify && !self.hard_break);
+        self.current_bottom += self.current.rect.height;
+        self.extents.height += self.current.rect.height;
+    }
+    // puts every box on a shared baseline using real font metrics, then sizes the line to fit
+    fn adjust_current_line_vertical(&mut self) {
+        // a line with nothing on it takes up no room, so empty anonymous blocks collapse. a blank
+        // line in preformatted text is as tall as the font's line.
+        if self.current.children.is_empty() && !self.hard_break {
+            self.current.rect.height = 0.0;
+            return;
+        }
+        let style = Rc::clone(&self.block_style);
+        let font_size = style.lookup_font_size();
+        let font_family = style.lookup_font_family(self.font_cache);
+        let (strut_ascent, strut_descent) = font_metrics(self.font_cache, font_size, &font_family,
+            style.lookup_font_weight(400), &style.lookup_string("font-style", "normal"));
+        let strut_leading = half_leading(style.lookup_line_height(), strut_ascent + strut_descent);
+        let mut above = strut_ascent + strut_leading;
+        let mut below = strut_descent + strut_leading;
+        let mut tallest:f32 = 0.0;
+        // how far each box's baseline is raised above the line's, or None for top and bottom
+        let mut raises:Vec<(Option<f32>, f32)> = vec![];
+        for ch in self.current.children.iter_mut() {
+            // text takes up its line-height, split evenly above and below the glyphs
+            let (ascent, descent, leading, valign) = match ch {
+                RenderInlineBoxType::Text(bx) => {
+                    let (a, d) = font_metrics(self.font_cache, bx.font_size, &bx.font_family, bx.font_weight, &bx.font_style);
+                    bx.rect.height = a + d;
+                    (a, d, half_leading(bx.line_height, a + d), &bx.valign)
+                },
+                RenderInlineBoxType::Error(bx)  => (bx.rect.height, 0.0, 0.0, &bx.valign),
+                RenderInlineBoxType::Image(bx) => (bx.rect.height, 0.0, 0.0, &bx.valign),
+                RenderInlineBoxType::Block(bx)  => (bx.rect.height, 0.0, 0.0, &bx.valign),
+            };
+            let raise = match valign {
+                VerticalAlign::Baseline => Some(0.0),
+                VerticalAlign::Sub => Some(-font_size / 5.0),
+                VerticalAlign::Super => Some(font_size / 3.0),
+                VerticalAlign::TextTop => Some(strut_ascent - ascent),
+                VerticalAlign::TextBottom => Some(descent - strut_descent),
+                // center on half the x-height, taken as half an em
+                VerticalAlign::Middle => Some(font_size / 4.0 - (ascent - descent) / 2.0),
+                VerticalAlign::Raise(px) => Some(*px),
+                VerticalAlign::Top | VerticalAlign::Bottom => None,
+            };
+            match raise {
+                Some(raise) => {
+                    above = above.max(ascent + leading + raise);
+                    below = below.max(descent + leading - raise);
                 }
-                "right" => {
-                    let mut left:f32 =   10000.0;
-                    let mut right:f32 = -10000.0;
-                    for ch in self.current.children.iter_mut() {
-                        if let RenderInlineBoxType::Text(ch) = ch {
-                            left = left.min(ch.rect.x);
-                            right = right.max(ch.rect.x+ch.rect.width);
-                        }
-                    }
-                    let shift_x = self.current.rect.width - (right-left);
-                    for ch in self.current.children.iter_mut() {
-                        if let RenderInlineBoxType::Text(ch) = ch {
-                            ch.rect.x += shift_x;
-                        }
+                None => tallest = tallest.max(ascent + descent + leading * 2.0),
+            }
+            raises.push((raise, ascent));
+        }
+        let height = (above + below).max(tallest);
+        let top = self.current.rect.y;
+        for (ch, (raise, ascent)) in self.current.children.iter_mut().zip(raises) {
+            let (rect, valign) = match ch {
+                RenderInlineBoxType::Text(bx)    => (&mut bx.rect,&bx.valign),
+                RenderInlineBoxType::Error(bx)  => (&mut bx.rect,&bx.valign),
+                RenderInlineBoxType::Image(bx) => (&mut bx.rect,&bx.valign),
+                RenderInlineBoxType::Block(bx)  => (&mut bx.rect,&bx.valign),
+            };
+            rect.y = match (raise, valign) {
+                (Some(raise), _) => top + above - raise - ascent,
+                (None, VerticalAlign::Bottom) => top + height - rect.height,
+                (None, _) => top,
+            };
+        }
+        self.current.rect.height = height;
+        self.current.baseline = above;
+    }
+    // moves the line's boxes over for text-align, or widens the spaces between its words to
+    // fill it for justify
+    fn adjust_current_line_horizontal(&mut self, justify:bool) {
+        let right = self.current.children.iter()
+            .map(|ch| ch.rect().x + ch.rect().width)
+            .fold(f32::MIN, f32::max);
+        let free = self.line_right() - right;
+        // a line that overflows stays at the start
+        if self.current.children.is_empty() || free <= 0.0 {
+            return;
+        }
+        let shift_x = match self.block_style.lookup_keyword("text-align", &Keyword(String::from("left"))) {
+            Keyword(align) if align == "right" => free,
+            Keyword(align) if align == "center" => free / 2.0,
+            Keyword(align) if align == "justify" && justify => {
+                let gaps = |ch:&RenderInlineBoxType| match ch {
+                    RenderInlineBoxType::Text(text) => text.text.chars().filter(|ch| *ch == ' ').count() as f32,
+                    _ => 0.0,
+                };
+                let total:f32 = self.current.children.iter().map(gaps).sum();
+                if total == 0.0 {
+                    return;
+                }
+                let extra = free / total;
+                let mut shift_x = 0.0;
+                for ch in self.current.children.iter_mut() {
+                    ch.translate(shift_x, 0.0);
+                    let widened = gaps(ch) * extra;
+                    if let RenderInlineBoxType::Text(text) = ch {
+                        text.rect.width += widened;
+                        text.spacing.word += extra;
                     }
+                    shift_x += widened;
+                }
+                return;
+            }
+            _ => return,
+        };
+        for ch in self.current.children.iter_mut() {
+            ch.translate(shift_x, 0.0);
+        }
+    }
+
+}
+
+// a piece of a multi-column container's content that can't be split between columns
+enum ColumnPiece {
+    Whole(Box<RenderBox>),
+    // index of the anonymous box it came from, that box's x, and the line
+    Line(usize, f32, RenderLineBox),
+}
+
+impl ColumnPiece {
+    // top and height, including margins
+    fn bounds(&self) -> (f32, f32) {
+        match self {
+            ColumnPiece::Line(_, _, line) => (line.rect.y, line.rect.height),
+            ColumnPiece::Whole(whole) => match whole.as_ref() {
+                RenderBox::Block(bx) => {
+                    let outer = bx.rect.expanded_by(bx.padding).expanded_by(bx.border_width).expanded_by(bx.margin);
+                    (outer.y, outer.height)
+                },
+                RenderBox::Anonymous(bx) => (bx.rect.y, bx.rect.height),
+                _ => (0.0, 0.0),
+            },
+        }
+    }
+}
 
+// Greedily fills columns of the given height. Returns the index of the first piece in each
+// column with the y it starts at, or how much taller the columns would need to be to make
+// the content fit in `count` of them.
+fn pack_columns(pieces:&[ColumnPiece], height:f32, count:usize) -> Result<Vec<(usize, f32)>, f32> {
+    let mut starts:Vec<(usize, f32)> = vec![];
+    let mut min_overflow = f32::MAX;
+    for (index, piece) in pieces.iter().enumerate() {
+        let (top, piece_height) = piece.bounds();
+        let overflow = starts.last().map(|&(_, start)| top + piece_height - start - height);
+        match overflow {
+            Some(over) if over <= 0.001 => {},
+            _ => {
+                if let Some(over) = overflow {
+                    min_overflow = min_overflow.min(over);
                 }
-                _ => {}
+                starts.push((index, top));
             }
         }
     }
-
+    if starts.len() > count {
+        return Err(min_overflow);
+    }
+    if starts.is_empty() {
+        starts.push((0, 0.0));
+    }
+    Ok(starts)
 }
 
 fn sum<I>(iter: I) -> f32 where I: Iterator<Item=f32> {
     iter.fold(0., |a, b| a + b)
 }
 
+// lays text out as usual, but draws only what's inside the clip. glyphs are cut to the
+// bounds the layout gives.
+struct ClippedLayout {
+    layout: Layout<BuiltInLineBreaker>,
+    clip: GBRect<f32>,
+}
+
+impl Hash for ClippedLayout {
+    fn hash<H:Hasher>(&self, state:&mut H) {
+        self.layout.hash(state);
+        for edge in [self.clip.min.x, self.clip.min.y, self.clip.max.x, self.clip.max.y] {
+            edge.to_bits().hash(state);
+        }
+    }
+}
+
+impl GlyphPositioner for ClippedLayout {
+    fn calculate_glyphs<'font, F:FontMap<'font>>(&self, fonts:&F, geometry:&SectionGeometry, sections:&[SectionText<'_>]) -> Vec<(PositionedGlyph<'font>, GlyphColor, FontId)> {
+        self.layout.calculate_glyphs(fonts, geometry, sections)
+    }
+    fn bounds_rect(&self, geometry:&SectionGeometry) -> GBRect<f32> {
+        let bounds = self.layout.bounds_rect(geometry);
+        let min = point(bounds.min.x.max(self.clip.min.x), bounds.min.y.max(self.clip.min.y));
+        let max = point(bounds.max.x.min(self.clip.max.x).max(min.x), bounds.max.y.min(self.clip.max.y).max(min.y));
+        GBRect { min, max }
+    }
+}
+
 pub enum Brush {
     Style1(glium_glyph::GlyphBrush<'static, 'static>),
     Style2(glium_glyph::glyph_brush::GlyphBrush<'static, Font<'static>>),
+    // LCD antialiasing. each brush draws one color channel, with the text moved over by its offset.
+    Lcd(Vec<(f32, glium_glyph::GlyphBrush<'static, 'static>)>),
 }
 impl Brush {
+    /// A brush drawing into `display` the way `rendering` says, with `fonts` under the same ids.
+    pub fn for_display(display:&glium::Display, rendering:TextRendering, fonts:Vec<Font<'static>>) -> Brush {
+        let build = |color_mask| glium_glyph::GlyphBrushBuilder::using_fonts(fonts.clone())
+            .gpu_cache_position_tolerance(rendering.position_tolerance())
+            .params(glium::DrawParameters { blend: glium::Blend::alpha_blending(), color_mask, ..Default::default() })
+            .build(display);
+        if rendering.lcd_antialiasing {
+            // red, green and blue. the alpha is written just once, with green.
+            let masks = [(true, false, false, false), (false, true, false, true), (false, false, true, false)];
+            Brush::Lcd(rendering.channel_offsets().into_iter().zip(masks.iter()).map(|(offset, mask)| (offset, build(*mask))).collect())
+        } else {
+            Brush::Style1(build((true, true, true, true)))
+        }
+    }
+    pub fn fonts(&self) -> Vec<Font<'static>> {
+        match self {
+            Brush::Style1(b) => b.fonts().to_vec(),
+            Brush::Style2(b) => b.fonts().to_vec(),
+            Brush::Lcd(bs) => bs[0].1.fonts().to_vec(),
+        }
+    }
+    pub fn add_font(&mut self, font:Font<'static>) -> FontId {
+        match self {
+            Brush::Style1(b) => b.add_font(font),
+            Brush::Style2(b) => b.add_font(font),
+            Brush::Lcd(bs) => bs.iter_mut().map(|(_, b)| b.add_font(font.clone())).last().unwrap(),
+        }
+    }
+    fn v_metrics(&self, font:FontId, font_size:f32) -> VMetrics {
+        let scale = Scale::uniform(font_size);
+        match self {
+            Brush::Style1(b) => b.fonts()[font.0].v_metrics(scale),
+            Brush::Style2(b) => b.fonts()[font.0].v_metrics(scale),
+            Brush::Lcd(bs) => bs[0].1.fonts()[font.0].v_metrics(scale),
+        }
+    }
+    fn font_units(&self, font:FontId, font_size:f32) -> FontUnits {
+        let scale = Scale::uniform(font_size);
+        let units = |font:&Font| FontUnits {
+            ch: font.glyph('0').scaled(scale).h_metrics().advance_width,
+            ex: font.glyph('x').scaled(scale).exact_bounding_box()
+                .map(|bounds| -bounds.min.y)
+                .unwrap_or(font_size / 2.0),
+        };
+        match self {
+            Brush::Style1(b) => units(&b.fonts()[font.0]),
+            Brush::Style2(b) => units(&b.fonts()[font.0]),
+            Brush::Lcd(bs) => units(&bs[0].1.fonts()[font.0]),
+        }
+    }
+    // how far each character moves the pen, without kerning
+    fn advance_widths(&self, font:FontId, font_size:f32, text:&str) -> Vec<f32> {
+        let scale = Scale::uniform(font_size);
+        let advances = |font:&Font| text.chars()
+            .map(|ch| font.glyph(ch).scaled(scale).h_metrics().advance_width)
+            .collect();
+        match self {
+            Brush::Style1(b) => advances(&b.fonts()[font.0]),
+            Brush::Style2(b) => advances(&b.fonts()[font.0]),
+            Brush::Lcd(bs) => advances(&bs[0].1.fonts()[font.0]),
+        }
+    }
     fn glyph_bounds(&mut self, sec:Section) -> Option<GBRect<f32>> {
         match self {
             Brush::Style1(b) => b.glyph_bounds(sec),
             Brush::Style2(b) => b.glyph_bounds(sec),
+            Brush::Lcd(bs) => bs[0].1.glyph_bounds(sec),
         }
     }
     pub fn queue(&mut self, sec:Section) {
         match self {
             Brush::Style1(b) => b.queue(sec),
             Brush::Style2(b) => b.queue(sec),
+            Brush::Lcd(bs) => for (offset, b) in bs.iter_mut() {
+                b.queue(channel_section(sec, *offset));
+            },
+        }
+    }
+    /// Queues the text with the parts of its glyphs outside of clip, in the section's own
+    /// pixels, cut off.
+    pub fn queue_clipped(&mut self, sec:Section, clip:GBRect<f32>) {
+        let layout = ClippedLayout { layout: sec.layout, clip };
+        match self {
+            Brush::Style1(b) => b.queue_custom_layout(sec, &layout),
+            Brush::Style2(b) => b.queue_custom_layout(sec, &layout),
+            Brush::Lcd(bs) => for (offset, b) in bs.iter_mut() {
+                b.queue_custom_layout(channel_section(sec, *offset), &layout);
+            },
         }
     }
     pub fn draw_queued_with_transform(&mut self, mat:[[f32;4];4],
@@ -1161,10 +3852,19 @@ impl Brush {
             Brush::Style2(_b) => {
                 panic!("cant actuually draw with style two")
             },
+            Brush::Lcd(bs) => for (_, b) in bs.iter_mut() {
+                b.draw_queued_with_transform(mat,facade,frame);
+            },
         }
     }
 }
 
+// the section as one color channel draws it, moved over to that channel's stripe of the pixel
+fn channel_section(sec:Section, offset:f32) -> Section {
+    let (x, y) = sec.screen_position;
+    Section { screen_position: (x + offset, y), ..sec }
+}
+
 pub fn standard_test_run_no_default(html:&[u8], css:&[u8]) -> Result<(Document, StylesheetSet, StyledTree, LayoutBox, RenderBox),BrowserError> {
     let open_sans_light: &[u8] = include_bytes!("../tests/fonts/Open_Sans/OpenSans-Light.ttf");
     let open_sans_reg: &[u8] = include_bytes!("../tests/fonts/Open_Sans/OpenSans-Regular.ttf");
@@ -1183,7 +3883,7 @@ pub fn standard_test_run_no_default(html:&[u8], css:&[u8]) -> Result<(Document,
     let mut stylesheets = load_stylesheets_new(&doc, &mut font_cache)?;
     let styled = dom_tree_to_stylednodes(&doc.root_node, &stylesheets);
     // println!("styled nodes {:#?}",styled);
-    let mut viewport = Dimensions {
+    let viewport = Dimensions {
         content: Rect {
             x: 0.0,
             y: 0.0,
@@ -1198,7 +3898,7 @@ pub fn standard_test_run_no_default(html:&[u8], css:&[u8]) -> Result<(Document,
     font_cache.install_font(Font::from_bytes(open_sans_light)?,"sans-serif",100, "normal");
     font_cache.install_font(Font::from_bytes(open_sans_reg)?,"sans-serif",400, "normal");
     font_cache.install_font(Font::from_bytes(open_sans_bold)?,"sans-serif",700, "normal");
-    let render_box = root_box.layout(&mut viewport, &mut font_cache, &doc);
+    let render_box = root_box.layout(&viewport, &mut font_cache, &doc);
     Ok((doc,stylesheets,styled,root_box,render_box))
 }
 
@@ -1219,12 +3919,14 @@ pub fn standard_test_run(html:&[u8], css:&[u8]) -> Result<(Document, StylesheetS
     };
 
     let mut doc = load_doc_from_bytestring(html);
-    strip_empty_nodes(&mut doc);
     let mut stylesheets = load_stylesheets_new(&doc, &mut font_cache)?;
     stylesheets.append_from_bytestring(&mut font_cache, css);
-    let styled = dom_tree_to_stylednodes(&doc.root_node, &stylesheets);
+    let mut styled = dom_tree_to_stylednodes(&doc.root_node, &stylesheets);
+    if strip_collapsible_whitespace(&mut doc.root_node, &styled.root.borrow()) {
+        styled = dom_tree_to_stylednodes(&doc.root_node, &stylesheets);
+    }
     // println!("styled nodes {:#?}",styled);
-    let mut viewport = Dimensions {
+    let viewport = Dimensions {
         content: Rect {
             x: 0.0,
             y: 0.0,
@@ -1235,97 +3937,790 @@ pub fn standard_test_run(html:&[u8], css:&[u8]) -> Result<(Document, StylesheetS
         border: Default::default(),
         margin: Default::default()
     };
-    let mut root_box = build_layout_tree(&styled.root.borrow(), &doc);
-    font_cache.install_font(Font::from_bytes(open_sans_light)?,"sans-serif",100, "normal");
-    font_cache.install_font(Font::from_bytes(open_sans_reg)?,"sans-serif",400, "normal");
-    font_cache.install_font(Font::from_bytes(open_sans_bold)?,"sans-serif",700, "normal");
-    font_cache.install_font(Font::from_bytes(monospace)?,
-                            "monospace",400,"normal");
-    font_cache.install_font(Font::from_bytes(monospace_bold)?,
-                            "monospace",700,"normal");
-    let render_box = root_box.layout(&mut viewport, &mut font_cache, &doc);
-    Ok((doc,stylesheets,styled,root_box,render_box))
+    let mut root_box = build_layout_tree(&styled.root.borrow(), &doc);
+    font_cache.install_font(Font::from_bytes(open_sans_light)?,"sans-serif",100, "normal");
+    font_cache.install_font(Font::from_bytes(open_sans_reg)?,"sans-serif",400, "normal");
+    font_cache.install_font(Font::from_bytes(open_sans_bold)?,"sans-serif",700, "normal");
+    font_cache.install_font(Font::from_bytes(monospace)?,
+                            "monospace",400,"normal");
+    font_cache.install_font(Font::from_bytes(monospace_bold)?,
+                            "monospace",700,"normal");
+    let render_box = root_box.layout(&viewport, &mut font_cache, &doc);
+    Ok((doc,stylesheets,styled,root_box,render_box))
+}
+
+// the box as a block, for tests that know what they laid out
+#[cfg(test)]
+fn expect_block(bx:&RenderBox) -> &RenderBlockBox {
+    match bx {
+        RenderBox::Block(block) => block,
+        _ => panic!("expected a block"),
+    }
+}
+
+// a block's own rect and its children's, lines of text included, in order
+#[cfg(test)]
+fn child_rects(bx:&RenderBox) -> (Rect, Vec<Rect>) {
+    let block = expect_block(bx);
+    (block.rect, block.children.iter().map(|ch| match ch {
+        RenderBox::Anonymous(anon) => anon.rect,
+        ch => expect_block(ch).rect,
+    }).collect())
+}
+
+#[test]
+fn test_insets() {
+    let (doc,sss,stree,lbox, rbox) = standard_test_run(
+        br#"<body></body>"#,
+        br#"body { display:block; margin: 50px; padding: 50px; border-width: 50px; } "#
+    ).unwrap();
+    println!("it all ran! {:#?}",rbox);
+    match rbox {
+        RenderBox::Block(bx) => {
+            assert_eq!(bx.margin.left,50.0);
+            assert_eq!(bx.padding.left,50.0);
+            assert_eq!(bx.border_width.left,50.0);
+        }
+        _ => {
+            panic!("this should have been a block box");
+        }
+    }
+    // assert_eq!(render_box.calculate_insets().left,100);
+
+}
+
+#[test]
+fn test_font_weight() {
+    let (doc,sss,stree,lbox, rbox) = standard_test_run(
+        br#"<body>text</body>"#,
+        br#"body { display:block; font-weight: bold; } "#
+    ).unwrap();
+    println!("it all ran! {:#?}",rbox);
+}
+
+#[test]
+fn test_blue_text() {
+    let (doc,sss,stree,lbox, rbox) = standard_test_run(
+        br#"<body><a>link</a></body>"#,
+        br#" a { color: blue; } body { display: block; color: red; }"#
+    ).unwrap();
+    println!("it all ran! {:#?}",rbox);
+}
+#[test]
+fn test_pre_code_text() {
+    let (doc,sss,stree,lbox, rbox) = standard_test_run(
+        br#"<pre><code>for i in node.children().iter() {
+    println!("this is some rust code");
+}
+</code></pre>
+"#,
+br#"pre {
+    display:block;
+    white-space: pre;
+}
+code {
+    font-size: 20px;
+    white-space: inherit;
+}"#,
+    ).unwrap();
+    println!("pre code demo is {:#?}",rbox);
+}
+
+#[test]
+fn test_unordered_listitem() {
+    let (doc,sss,stree,lbox, rbox) = standard_test_run(
+        br#"<ul>
+    <li>item one</li>
+    <li>item two</li>
+</ul>"#,
+        br#"        ul {
+        display:block;
+            padding-left: 40px;
+            border: 1px solid black;
+            list-style-type: disc;
+        }
+        li {
+            display: list-item;
+            border: 1px solid red;
+        }
+"#,
+    ).unwrap();
+    println!("ul render is {:#?}", rbox);
+
+}
+
+#[test]
+fn test_list_markers() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<ol><li>one</li><li>two</li><li class="in">three</li><li class="none">four</li></ol>"#,
+        br#"ol { display: block; padding-left: 40px; list-style: decimal; }
+        li { display: list-item; list-style-type: inherit; }
+        li.in { list-style: square inside; }
+        li.none { list-style-type: none; }"#,
+    ).unwrap();
+    let items = match rbox {
+        RenderBox::Block(ol) => ol.children,
+        _ => panic!("the list should be a block"),
+    };
+    let markers:Vec<(ListMarker, f32)> = items.iter().filter_map(|item| match item {
+        RenderBox::Block(li) => Some((li.marker, li.marker_offset)),
+        _ => None,
+    }).collect();
+    assert_eq!(markers.len(), 4);
+    assert_eq!(markers[0].0, ListMarker::Decimal(1));
+    assert_eq!(markers[1].0, ListMarker::Decimal(2));
+    assert!(markers[1].1 < 0.0);
+    assert_eq!(markers[2], (ListMarker::Square, 0.0));
+    assert_eq!(markers[3].0, ListMarker::None);
+    assert_eq!(markers[0].0.text(), Some(String::from("1.")));
+    // an inside marker pushes the first line over
+    if let (RenderBox::Block(outside), RenderBox::Block(inside)) = (&items[0], &items[2]) {
+        let first_x = |li:&RenderBlockBox| match li.children.first() {
+            Some(RenderBox::Anonymous(anon)) => anon.children[0].rect.x,
+            _ => panic!("expected a line of text"),
+        };
+        assert!(first_x(inside) > first_x(outside));
+    }
+}
+
+#[test]
+fn test_numbered_list_markers() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div><ol start="3"><li>a</li><li value="10">b</li><li>c</li></ol><ol reversed="" class="roman"><li>a</li><li>b</li><li>c</li></ol></div>"#,
+        br#"ol { display: block; list-style-type: decimal; }
+        ol.roman { list-style-type: upper-roman; }
+        li { display: list-item; list-style-type: inherit; }"#,
+    ).unwrap();
+    let lists = match rbox {
+        RenderBox::Block(div) => div.children,
+        _ => panic!("the div should be a block"),
+    };
+    let markers = |list:&RenderBox| -> Vec<Option<String>> { match list {
+        RenderBox::Block(ol) => ol.children.iter().filter_map(|item| match item {
+            RenderBox::Block(li) => Some(li.marker.text()),
+            _ => None,
+        }).collect(),
+        _ => panic!("the list should be a block"),
+    }};
+    let text = |texts:&[&str]| -> Vec<Option<String>> { texts.iter().map(|text| Some(String::from(*text))).collect() };
+    // counting starts at start, and goes on from an item's own value
+    assert_eq!(markers(&lists[0]), text(&["3.", "10.", "11."]));
+    // a reversed list counts down to 1
+    assert_eq!(markers(&lists[1]), text(&["III.", "II.", "I."]));
+}
+
+#[test]
+fn test_float_and_clear() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div><aside>quote</aside><span class="side">note</span><p>one two three four five six seven eight nine ten eleven twelve thirteen fourteen fifteen sixteen seventeen</p><p class="below">after</p></div>"#,
+        br#"div { display: block; width: 300px; font-family: sans-serif; }
+        aside { display: block; float: right; width: 100px; height: 50px; margin-left: 10px; }
+        .side { float: right; height: 20px; }
+        p { display: block; margin: 0; }
+        .below { clear: right; }"#,
+    ).unwrap();
+    let children = match rbox {
+        RenderBox::Block(div) => div.children,
+        _ => panic!("the div should be a block"),
+    };
+    let block = |bx:&RenderBox| match bx {
+        RenderBox::Block(block) => block.rect,
+        _ => panic!("expected a block"),
+    };
+    // the floats are out of the flow, at the right edge and side by side
+    let (aside, note) = (block(&children[0]), block(&children[1]));
+    assert_eq!((aside.x, aside.y, aside.width), (200.0, 0.0, 100.0));
+    assert_eq!(note.y, 0.0);
+    assert!(note.x + note.width <= 190.0 && note.width < 100.0);
+    // the paragraph starts at the top, and its lines stop short of the floats until they end
+    let p = match &children[2] {
+        RenderBox::Block(p) => p,
+        _ => panic!("the p should be a block"),
+    };
+    assert_eq!(p.rect.y, 0.0);
+    let lines = match p.children.first() {
+        Some(RenderBox::Anonymous(anon)) => &anon.children,
+        _ => panic!("the p should have lines"),
+    };
+    assert_eq!(lines[0].rect.x + lines[0].rect.width, note.x);
+    let last = lines.last().unwrap();
+    assert!(last.rect.y >= 50.0 && last.rect.width == 300.0);
+    // the cleared paragraph goes below the floats
+    assert!(block(&children[3]).y >= 50.0);
+}
+
+#[test]
+fn test_absolute_position() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div><p>first</p><section><span class="corner">x</span><p>second</p><span class="bottom">y</span><p class="static"></p></section></div>"#,
+        br#"div { display: block; margin: 0; font-family: sans-serif; }
+        p { display: block; margin: 0; height: 20px; }
+        section { display: block; position: relative; margin-left: 50px; padding: 10px; height: 100px; width: 200px; }
+        .corner { position: absolute; top: 5px; right: 5px; width: 30px; height: 30px; }
+        .bottom { position: absolute; bottom: 0; left: 10%; width: 40px; height: 10px; }
+        .static { position: absolute; width: 10px; }"#,
+    ).unwrap();
+    let children = match rbox {
+        RenderBox::Block(div) => div.children,
+        _ => panic!("the div should be a block"),
+    };
+    let section = match &children[1] {
+        RenderBox::Block(section) => section,
+        _ => panic!("the section should be a block"),
+    };
+    let rects:Vec<Rect> = section.children.iter().filter_map(|ch| match ch {
+        RenderBox::Block(block) => Some(block.rect),
+        _ => None,
+    }).collect();
+    // the flow has only the paragraph, with the absolute boxes after it
+    assert_eq!(rects.len(), 4);
+    assert_eq!((rects[0].x, rects[0].y), (60.0, 30.0));
+    assert_eq!(section.rect.height, 100.0);
+    // offsets are from the padding box of the positioned section
+    assert_eq!((rects[1].x, rects[1].y), (50.0 + 220.0 - 5.0 - 30.0, 20.0 + 5.0));
+    assert_eq!((rects[2].x, rects[2].y), (50.0 + 22.0, 20.0 + 120.0 - 10.0));
+    // with no offsets, the box stays where the flow would have put it
+    assert_eq!((rects[3].x, rects[3].y), (60.0, 50.0));
+}
+
+#[test]
+fn test_fixed_position() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div><section><p>text</p><span class="header">top</span></section></div>"#,
+        br#"div { display: block; margin: 0; font-family: sans-serif; }
+        section { display: block; position: relative; margin-top: 200px; padding: 10px; }
+        p { display: block; margin: 0; height: 300px; }
+        .header { position: fixed; top: 0; left: 0; right: 0; height: 20px; }"#,
+    ).unwrap();
+    // the fixed box is in the viewport, not in the positioned section around it
+    let fixed = rbox.fixed_boxes();
+    assert_eq!(fixed.len(), 1);
+    match fixed[0] {
+        RenderBox::Block(header) => {
+            assert_eq!((header.rect.x, header.rect.y, header.rect.width), (0.0, 0.0, 500.0));
+        },
+        _ => panic!("the header should be a block"),
+    }
+    match &rbox {
+        RenderBox::Block(div) => {
+            assert!(div.children.last().unwrap().is_fixed());
+            match &div.children[0] {
+                RenderBox::Block(section) => assert!(!section.children.iter().any(|ch| ch.is_fixed())),
+                _ => panic!("the section should be a block"),
+            }
+        },
+        _ => panic!("the div should be a block"),
+    }
+    // scrolled down, the header is still found at the top of the window
+    assert_eq!(rbox.find_element_path(10.0, 105.0, 100.0), Some(vec![0, 1]));
+    assert_eq!(rbox.find_element_path(10.0, 5.0, 0.0), Some(vec![0, 1]));
+    assert!(rbox.find_box_containing(10.0, 5.0).is_none());
+}
+
+#[test]
+fn test_relative_position() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div><p>one</p><p class="moved">two<span class="badge">!</span></p><p>three</p></div>"#,
+        br#"div { display: block; margin: 0; font-family: sans-serif; }
+        p { display: block; margin: 0; height: 20px; }
+        .moved { position: relative; top: 10px; right: 5px; }
+        .badge { position: absolute; top: 0; left: 100px; width: 10px; }"#,
+    ).unwrap();
+    let blocks = match &rbox {
+        RenderBox::Block(div) => div.children.iter().map(|ch| match ch {
+            RenderBox::Block(block) => block,
+            _ => panic!("expected a block"),
+        }).collect::<Vec<_>>(),
+        _ => panic!("the div should be a block"),
+    };
+    // the moved paragraph shifts, and its siblings stay where the flow put them
+    assert_eq!((blocks[0].rect.x, blocks[0].rect.y), (0.0, 0.0));
+    assert_eq!((blocks[1].rect.x, blocks[1].rect.y), (-5.0, 30.0));
+    assert_eq!((blocks[2].rect.x, blocks[2].rect.y), (0.0, 40.0));
+    // it is the containing block of the absolute box, which moves with it
+    match blocks[1].children.last() {
+        Some(RenderBox::Block(badge)) => assert_eq!((badge.rect.x, badge.rect.y), (95.0, 30.0)),
+        _ => panic!("the badge should be a block"),
+    }
+    // and it is found where it is drawn
+    match rbox.find_box_containing(1.0, 35.0) {
+        QueryResult::Text(text) => assert_eq!(text.text.trim(), "two"),
+        _ => panic!("the moved text should be under the point"),
+    }
+}
+
+#[test]
+fn test_flex_layout() {
+    let rects = |html:&[u8], css:&str| -> (Rect, Vec<Rect>) {
+        let css = format!("div {{ display: block; margin: 0; font-family: sans-serif; }} p {{ display: block; margin: 0; }} {}", css);
+        let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(html, css.as_bytes()).unwrap();
+        match rbox {
+            RenderBox::Block(div) => match div.children.first() {
+                Some(RenderBox::Block(flex)) => (flex.rect, flex.children.iter().map(|ch| match ch {
+                    RenderBox::Block(item) => item.rect,
+                    RenderBox::Anonymous(anon) => anon.rect,
+                    _ => panic!("flex items are blocks"),
+                }).collect()),
+                _ => panic!("the flex container should be a block"),
+            },
+            _ => panic!("the div should be a block"),
+        }
+    };
+    let three = br#"<div><section><p class="a">a</p><span class="b">b</span><p class="c">c</p></section></div>"#;
+
+    // free space goes to the items that grow, in proportion
+    let (flex, items) = rects(three, "section { display: flex; width: 400px; }
+        .a { width: 100px; height: 40px; } .b { flex: 1; } .c { flex: 3 1 0; height: 20px; }");
+    assert_eq!(flex.height, 40.0);
+    assert_eq!(items.iter().map(|r| (r.x, r.width)).collect::<Vec<_>>(), vec![(0.0, 100.0), (100.0, 75.0), (175.0, 225.0)]);
+    // the items without a height stretch to the line
+    assert_eq!(items[1].height, 40.0);
+    assert_eq!(items[2].height, 20.0);
+
+    // overflow is taken back in proportion to the basis
+    let (_, items) = rects(three, "section { display: flex; width: 200px; }
+        .a, .b, .c { flex-basis: 100px; } .c { flex-basis: 200px; }");
+    assert_eq!(items.iter().map(|r| r.width).collect::<Vec<_>>(), vec![50.0, 50.0, 100.0]);
+
+    // justify-content and align-items place items with room to spare
+    let (_, items) = rects(three, "section { display: flex; width: 400px; height: 100px;
+        justify-content: space-between; align-items: center; } .a, .b, .c { width: 50px; height: 20px; }");
+    assert_eq!(items.iter().map(|r| (r.x, r.y)).collect::<Vec<_>>(), vec![(0.0, 40.0), (175.0, 40.0), (350.0, 40.0)]);
+    let (_, items) = rects(three, "section { display: flex; flex-direction: row-reverse; width: 400px; }
+        .a, .b, .c { width: 50px; height: 20px; }");
+    assert_eq!(items.iter().map(|r| r.x).collect::<Vec<_>>(), vec![350.0, 300.0, 250.0]);
+
+    // wrapped items go on new lines as tall as their tallest item
+    let (flex, items) = rects(three, "section { display: flex; flex-wrap: wrap; width: 120px; }
+        .a, .b, .c { width: 50px; height: 20px; } .b { height: 30px; }");
+    assert_eq!(items.iter().map(|r| (r.x, r.y)).collect::<Vec<_>>(), vec![(0.0, 0.0), (50.0, 0.0), (0.0, 30.0)]);
+    assert_eq!(flex.height, 50.0);
+
+    // a column stacks the items, and fills its height when they grow
+    let (flex, items) = rects(three, "section { display: flex; flex-direction: column; width: 300px; height: 200px; }
+        .a { height: 40px; } .b { flex-grow: 1; } .c { height: 60px; width: 100px; align-self: flex-end; }");
+    assert_eq!(flex.height, 200.0);
+    assert_eq!(items.iter().map(|r| (r.y, r.height)).collect::<Vec<_>>(), vec![(0.0, 40.0), (40.0, 100.0), (140.0, 60.0)]);
+    assert_eq!((items[0].width, items[2].x), (300.0, 200.0));
+}
+
+#[test]
+fn test_grid_layout() {
+    let rects = |html:&[u8], css:&str| -> (Rect, Vec<Rect>) {
+        let css = format!("div {{ display: block; margin: 0; font-family: sans-serif; }} p {{ display: block; margin: 0; }} {}", css);
+        let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(html, css.as_bytes()).unwrap();
+        child_rects(&expect_block(&rbox).children[0])
+    };
+    let four = br#"<div><section><p class="a">a</p><p class="b">b</p><p class="c">c</p><p class="d">d</p></section></div>"#;
+
+    // fixed and fr columns, auto placed in rows as tall as their tallest item
+    let (grid, items) = rects(four, "section { display: grid; width: 400px; grid-template-columns: 100px 1fr 2fr; gap: 10px 30px; }
+        .a { height: 30px; } .b { height: 50px; } .d { height: 20px; }");
+    assert_eq!(items.iter().map(|r| (r.x, r.y, r.width)).collect::<Vec<_>>(),
+        vec![(0.0, 0.0, 100.0), (130.0, 0.0, 80.0), (240.0, 0.0, 160.0), (0.0, 60.0, 100.0)]);
+    // the items without a height stretch to their row
+    assert_eq!(items[2].height, 50.0);
+    assert_eq!(grid.height, 80.0);
+
+    // explicit placement, with spans, and the auto items fill the gaps around it
+    let (grid, items) = rects(four, "section { display: grid; width: 300px;
+        grid-template-columns: repeat(3, 1fr); grid-template-rows: 40px 40px; }
+        .a { grid-column: 2 / span 2; } .b { grid-row: 1 / 3; grid-column: 1; } .c, .d { align-self: start; height: 10px; }");
+    assert_eq!(items.iter().map(|r| (r.x, r.y, r.width, r.height)).collect::<Vec<_>>(),
+        vec![(100.0, 0.0, 200.0, 40.0), (0.0, 0.0, 100.0, 80.0), (100.0, 40.0, 100.0, 10.0), (200.0, 40.0, 100.0, 10.0)]);
+    assert_eq!(grid.height, 80.0);
+
+    // percentage and auto columns, where the auto one takes what's left
+    let (_, items) = rects(four, "section { display: grid; width: 400px; grid-template-columns: 25% auto; }");
+    assert_eq!(items.iter().map(|r| (r.x, r.width)).collect::<Vec<_>>(),
+        vec![(0.0, 100.0), (100.0, 300.0), (0.0, 100.0), (100.0, 300.0)]);
+
+    // huge repeats, lines and spans are cut down to a thousand tracks
+    let (_, items) = rects(four, "section { display: grid; width: 400px; grid-template-columns: repeat(100000000000000000000, 1px); }
+        .a { grid-column: span 1000000000; } .b { grid-row: 2000000000; grid-column: 2000000000 / span 3; }");
+    assert_eq!(items[0].width, 1000.0);
+    assert!(items.iter().all(|r| r.x.is_finite() && r.y.is_finite()));
+}
+
+#[test]
+fn test_white_space() {
+    let lines = |white_space:&str| {
+        let css = format!("div {{ display: block; width: 100px; white-space: {}; }}", white_space);
+        let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+            b"<div>one two  three four five six\nseven</div>", css.as_bytes()).unwrap();
+        let div = match rbox {
+            RenderBox::Block(div) => div,
+            _ => panic!("the div should be a block"),
+        };
+        match div.children.first() {
+            Some(RenderBox::Anonymous(anon)) => anon.children.iter()
+                .map(|line| line.children.iter().map(|inline| match inline {
+                    RenderInlineBoxType::Text(text) => text.text.clone(),
+                    _ => String::new(),
+                }).collect::<String>())
+                .collect::<Vec<String>>(),
+            _ => panic!("expected lines of text"),
+        }
+    };
+    let normal = lines("normal");
+    assert!(normal.len() > 2);
+    assert!(normal.iter().all(|line| !line.contains("  ") && !line.contains('\n')));
+    assert_eq!(lines("nowrap"), vec![String::from(" one two three four five six seven")]);
+    assert_eq!(lines("pre"), vec![String::from("one two  three four five six"), String::from("seven")]);
+    let pre_wrap = lines("pre-wrap");
+    assert!(pre_wrap.len() > 2);
+    assert!(pre_wrap.concat().contains("two  three"));
+    assert_eq!(pre_wrap.last(), Some(&String::from("seven")));
+    let pre_line = lines("pre-line");
+    assert!(pre_line.len() > 2);
+    assert!(!pre_line.concat().contains("  "));
+    assert_eq!(pre_line.last(), Some(&String::from(" seven")));
+}
+
+#[test]
+fn test_preformatted_text() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        b"<pre>\n  a\n\n<span>b</span>\n<span>c</span></pre>", b"pre { margin: 0; }").unwrap();
+    let pre = match rbox {
+        RenderBox::Block(pre) => pre,
+        _ => panic!("the pre should be a block"),
+    };
+    let lines = match pre.children.first() {
+        Some(RenderBox::Anonymous(anon)) => &anon.children,
+        _ => panic!("expected lines of text"),
+    };
+    let texts:Vec<String> = lines.iter()
+        .map(|line| line.children.iter().map(|inline| match inline {
+            RenderInlineBoxType::Text(text) => text.text.clone(),
+            _ => String::new(),
+        }).collect::<String>())
+        .collect();
+    assert_eq!(texts, vec!["  a", "", "b", "c"]);
+    // the blank line still takes up a line
+    assert!(lines[1].rect.height > 0.0);
+    assert!(lines[2].rect.y >= lines[1].rect.y + lines[1].rect.height);
+}
+
+#[test]
+fn test_whitespace_kept_by_style() {
+    // the text of each line in the first block
+    let lines = |html:&[u8], css:&[u8]| {
+        let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(html, css).unwrap();
+        let block = match rbox {
+            RenderBox::Block(block) => block,
+            _ => panic!("the root should be a block"),
+        };
+        match block.children.first() {
+            Some(RenderBox::Anonymous(anon)) => anon.children.iter()
+                .map(|line| line.children.iter().map(|inline| match inline {
+                    RenderInlineBoxType::Text(text) => text.text.clone(),
+                    _ => String::new(),
+                }).collect::<String>())
+                .collect::<Vec<String>>(),
+            _ => panic!("expected lines of text"),
+        }
+    };
+    // the elements inside a pre keep the indentation after their start tags
+    assert_eq!(lines(b"<pre><code>    indented\n  less</code></pre>", b"pre { margin: 0; }"),
+               vec!["    indented", "  less"]);
+    // and so does anything styled to keep spaces, down to the text that's only whitespace
+    assert_eq!(lines(b"<div><b>a</b> <b>b</b></div>", b"div { white-space: pre; }"), vec!["a b"]);
+}
+
+#[test]
+fn test_text_align() {
+    // the left and right of each line's content
+    let lines = |text_align:&str| {
+        let css = format!("div {{ display: block; width: 100px; text-align: {}; }}", text_align);
+        let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+            b"<div>one two three four five six</div>", css.as_bytes()).unwrap();
+        let div = match rbox {
+            RenderBox::Block(div) => div,
+            _ => panic!("the div should be a block"),
+        };
+        match div.children.first() {
+            Some(RenderBox::Anonymous(anon)) => anon.children.iter()
+                .map(|line| {
+                    let first = line.children.first().unwrap().rect();
+                    let last = line.children.last().unwrap().rect();
+                    (first.x, last.x + last.width)
+                })
+                .collect::<Vec<(f32, f32)>>(),
+            _ => panic!("expected lines of text"),
+        }
+    };
+    let left = lines("left");
+    assert!(left.len() > 1);
+    assert!(left.iter().all(|(start, end)| *start == 0.0 && *end < 100.0));
+    let right = lines("right");
+    assert!(right.iter().all(|(_, end)| (end - 100.0).abs() < 0.01));
+    let center = lines("center");
+    assert!(center.iter().all(|(start, end)| (start - (100.0 - end)).abs() < 0.01));
+    // every line but the last fills the whole width
+    let justify = lines("justify");
+    let (last, full) = justify.split_last().unwrap();
+    assert!(full.iter().all(|(start, end)| *start == 0.0 && (end - 100.0).abs() < 0.01));
+    assert_eq!(*last, *left.last().unwrap());
+}
+
+#[test]
+fn test_text_indent() {
+    // where each line starts, in each anonymous box of the div
+    let starts = |html:&[u8], text_indent:&str| {
+        let css = format!("div {{ display: block; width: 100px; text-indent: {}; }}", text_indent);
+        let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(html, css.as_bytes()).unwrap();
+        let div = match rbox {
+            RenderBox::Block(div) => div,
+            _ => panic!("the div should be a block"),
+        };
+        div.children.iter().filter_map(|child| match child {
+            RenderBox::Anonymous(anon) => Some(anon.children.iter()
+                .map(|line| line.children.first().unwrap().rect().x)
+                .collect::<Vec<f32>>()),
+            _ => None,
+        }).collect::<Vec<Vec<f32>>>()
+    };
+    let text = b"<div>one two three four five six</div>";
+    let indented = starts(text, "20px");
+    assert!(indented[0].len() > 1);
+    assert_eq!(indented[0][0], 20.0);
+    assert!(indented[0][1..].iter().all(|x| *x == 0.0));
+    assert_eq!(starts(text, "10%")[0][0], 10.0);
+    assert_eq!(starts(text, "-5px")[0][0], -5.0);
+    // only the block's first line, not the first after a child block
+    let split = starts(b"<div>one<div>two</div>three</div>", "20px");
+    assert_eq!(split, vec![vec![20.0], vec![0.0]]);
+}
+
+#[test]
+fn test_line_height() {
+    let line_heights = |line_height:&str| {
+        let css = format!("div {{ display: block; font-size: 20px; line-height: {}; }}", line_height);
+        let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(b"<div>one</div>", css.as_bytes()).unwrap();
+        match rbox {
+            RenderBox::Block(div) => match div.children.first() {
+                Some(RenderBox::Anonymous(anon)) => anon.children[0].rect.height,
+                _ => panic!("expected a line of text"),
+            },
+            _ => panic!("the div should be a block"),
+        }
+    };
+    let close = |a:f32, b:f32| (a - b).abs() < 0.01;
+    assert!(close(line_heights("2"), 40.0));
+    assert!(close(line_heights("150%"), 30.0));
+    assert!(close(line_heights("32px"), 32.0));
+    assert!(close(line_heights("1.5em"), 30.0));
+    let normal = line_heights("normal");
+    assert!((20.0..32.0).contains(&normal), "normal line height was {}", normal);
+}
+
+#[test]
+fn test_text_spacing() {
+    let first_line = |spacing:&str| {
+        let css = format!("div {{ display: block; width: 200px; {} }}", spacing);
+        let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+            b"<div>tracking heavy headers wrap sooner</div>", css.as_bytes()).unwrap();
+        match rbox {
+            RenderBox::Block(div) => match div.children.into_iter().next() {
+                Some(RenderBox::Anonymous(mut anon)) => match anon.children.remove(0).children.remove(0) {
+                    RenderInlineBoxType::Text(text) => text,
+                    _ => panic!("expected text"),
+                },
+                _ => panic!("expected a line of text"),
+            },
+            _ => panic!("the div should be a block"),
+        }
+    };
+    let plain = first_line("");
+    let tracked = first_line("letter-spacing: 4px;");
+    assert_eq!(tracked.spacing, TextSpacing { letter: 4.0, word: 0.0 });
+    assert!(tracked.text.len() < plain.text.len());
+    let spaced = first_line("word-spacing: 10px; letter-spacing: normal;");
+    assert_eq!(spaced.spacing.word, 10.0);
+    assert!(spaced.text.len() < plain.text.len());
+    // each character moves over by its spacing
+    let mut font_cache = FontCache {
+        brush: Brush::Style2(glium_glyph::glyph_brush::GlyphBrushBuilder::without_fonts().build()),
+        families: Default::default(),
+        fonts: Default::default(),
+    };
+    let open_sans_reg: &[u8] = include_bytes!("../tests/fonts/Open_Sans/OpenSans-Regular.ttf");
+    font_cache.install_font(Font::from_bytes(open_sans_reg).unwrap(), &plain.font_family, plain.font_weight, &plain.font_style);
+    let offsets = tracked.glyph_offsets("ab c", &mut font_cache);
+    let plain_offsets = plain.glyph_offsets("ab c", &mut font_cache);
+    assert_eq!(offsets.len(), 4);
+    assert!((offsets[3].1 - plain_offsets[3].1 - 12.0).abs() < 0.01);
+}
+
+#[test]
+fn test_opacity() {
+    let css = br#"div { display: block; opacity: 0.5; }
+        .outer { opacity: 50%; background-color: red; }
+        .inner { opacity: 0.5; }
+        .loud { opacity: 7; }"#;
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div>plain <span class="outer">faded <span class="inner">more</span></span> <span class="loud">loud</span></div>"#, css).unwrap();
+    let div = match rbox {
+        RenderBox::Block(div) => div,
+        _ => panic!("the div should be a block"),
+    };
+    assert_eq!(div.opacity, 0.5);
+    let anon = match div.children.first() {
+        Some(RenderBox::Anonymous(anon)) => anon,
+        _ => panic!("expected a line of text"),
+    };
+    // the opacity of the inline elements is carried onto their text, multiplied together
+    let opacities:Vec<(String, f32)> = anon.children[0].children.iter().filter_map(|inline| match inline {
+        RenderInlineBoxType::Text(text) if !text.text.trim().is_empty() => Some((text.text.trim().to_string(), text.opacity)),
+        _ => None,
+    }).collect();
+    assert_eq!(opacities, vec![(String::from("plain"), 1.0), (String::from("faded"), 0.5),
+                               (String::from("more"), 0.25), (String::from("loud"), 1.0)]);
+    let fragments = anon.inline_fragments();
+    assert_eq!(fragments.len(), 1);
+    assert_eq!(fragments[0].opacity, 0.5);
 }
 
 #[test]
-fn test_insets() {
-    let (doc,sss,stree,lbox, rbox) = standard_test_run(
-        br#"<body></body>"#,
-        br#"body { display:block; margin: 50px; padding: 50px; border-width: 50px; } "#
-    ).unwrap();
-    println!("it all ran! {:#?}",rbox);
+fn test_visibility() {
+    let css = br#"div { display: block; }
+        .menu { visibility: hidden; }
+        .back { visibility: visible; }"#;
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div>shown <span class="menu">gone <span class="back">back</span></span></div>"#, css).unwrap();
+    let div = match &rbox {
+        RenderBox::Block(div) => div,
+        _ => panic!("the div should be a block"),
+    };
+    assert!(div.visible);
+    let line = match div.children.first() {
+        Some(RenderBox::Anonymous(anon)) => &anon.children[0],
+        _ => panic!("expected a line of text"),
+    };
+    let texts:Vec<&RenderTextBox> = line.children.iter().filter_map(|inline| match inline {
+        RenderInlineBoxType::Text(text) if !text.text.trim().is_empty() => Some(text),
+        _ => None,
+    }).collect();
+    let visible:Vec<(&str, bool)> = texts.iter().map(|text| (text.text.trim(), text.visible)).collect();
+    assert_eq!(visible, vec![("shown", true), ("gone", false), ("back", true)]);
+    // the hidden text still takes up its room on the line, but can't be hit
+    assert!(texts[1].rect.width > 0.0);
+    assert!(texts[2].rect.x >= texts[1].rect.x + texts[1].rect.width);
+    let (x, y) = (texts[1].rect.x + 1.0, texts[1].rect.y + 1.0);
+    assert!(rbox.find_box_containing(x, y).is_none());
+
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div class="menu"><div>item</div></div>"#, css).unwrap();
     match rbox {
-        RenderBox::Block(bx) => {
-            assert_eq!(bx.margin.left,50.0);
-            assert_eq!(bx.padding.left,50.0);
-            assert_eq!(bx.border_width.left,50.0);
-        }
-        _ => {
-            panic!("this should have been a block box");
-        }
+        RenderBox::Block(div) => {
+            assert!(!div.visible);
+            assert!(matches!(&div.children[0], RenderBox::Block(child) if !child.visible));
+        },
+        _ => panic!("the div should be a block"),
     }
-    // assert_eq!(render_box.calculate_insets().left,100);
-
 }
 
 #[test]
-fn test_font_weight() {
-    let (doc,sss,stree,lbox, rbox) = standard_test_run(
-        br#"<body>text</body>"#,
-        br#"body { display:block; font-weight: bold; } "#
-    ).unwrap();
-    println!("it all ran! {:#?}",rbox);
+fn test_cursor() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div>plain <a href="next.html">link</a> <span class="busy">busy <span>inner</span></span></div>"#,
+        br#"div { display: block; } .busy { cursor: wait; }"#).unwrap();
+    let line = match &rbox {
+        RenderBox::Block(div) => match div.children.first() {
+            Some(RenderBox::Anonymous(anon)) => &anon.children[0],
+            _ => panic!("expected a line of text"),
+        },
+        _ => panic!("the div should be a block"),
+    };
+    let cursors:Vec<(&str, &str)> = line.children.iter().filter_map(|inline| match inline {
+        RenderInlineBoxType::Text(text) if !text.text.trim().is_empty() => {
+            let (x, y) = (text.rect.x + text.rect.width / 2.0, text.rect.y + 1.0);
+            Some((text.text.trim(), rbox.find_box_containing(x, y).cursor()))
+        },
+        _ => None,
+    }).collect();
+    // the cursor is inherited, and auto depends on what is under the mouse
+    assert_eq!(cursors, vec![("plain", "text"), ("link", "pointer"), ("busy", "wait"), ("inner", "wait")]);
+    assert_eq!(rbox.find_box_containing(-10.0, -10.0).cursor(), "default");
 }
 
 #[test]
-fn test_blue_text() {
-    let (doc,sss,stree,lbox, rbox) = standard_test_run(
-        br#"<body><a>link</a></body>"#,
-        br#" a { color: blue; } body { display: block; color: red; }"#
-    ).unwrap();
-    println!("it all ran! {:#?}",rbox);
+fn test_outline() {
+    let css = br#"div { display: block; }
+        .ring { outline: 4px dotted red; }
+        .focus { outline: auto; color: blue; }
+        .none { outline: 0px solid red; }"#;
+    let blocks = |html:&[u8]| -> Vec<(Rect, Option<Outline>)> {
+        let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(html, css).unwrap();
+        match rbox {
+            RenderBox::Block(div) => div.children.into_iter().filter_map(|ch| match ch {
+                RenderBox::Block(block) => Some((block.rect, block.outline)),
+                _ => None,
+            }).collect(),
+            _ => panic!("the div should be a block"),
+        }
+    };
+    let outlined = blocks(br#"<div><div class="ring">a</div><div class="focus">b</div><div class="none">c</div><div>d</div></div>"#);
+    let plain = blocks(br#"<div><div>a</div><div>b</div><div>c</div><div>d</div></div>"#);
+    assert_eq!(outlined[0].1, Some(Outline { width: 4.0, style: BorderStyle::Dotted, color: Color::from_hex("#ff0000") }));
+    assert_eq!(outlined[1].1, Some(Outline { width: 3.0, style: BorderStyle::Solid, color: Color::from_hex("#0000ff") }));
+    assert_eq!(outlined[2].1, None);
+    assert_eq!(outlined[3].1, None);
+    // outlines don't change the layout
+    for (a, b) in outlined.iter().zip(plain.iter()) {
+        assert_eq!((a.0.x, a.0.y, a.0.width, a.0.height), (b.0.x, b.0.y, b.0.width, b.0.height));
+    }
+    let ring = outlined[0].1.as_ref().unwrap().rect(outlined[0].0);
+    assert_eq!((ring.x, ring.width), (outlined[0].0.x - 4.0, outlined[0].0.width + 8.0));
 }
+
 #[test]
-fn test_pre_code_text() {
-    let (doc,sss,stree,lbox, rbox) = standard_test_run(
-        br#"<pre><code>for i in node.children().iter() {
-    println!("this is some rust code");
-}
-</code></pre>
-"#,
-br#"pre {
-    display:block;
-    white-space: pre;
-}
-code {
-    font-size: 20px;
-    white-space: inherit;
-}"#,
-    ).unwrap();
-    println!("pre code demo is {:#?}",rbox);
+fn test_border_radius() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div><p class="round">a</p><p class="oval">b</p><p class="big">c</p><p>d</p></div>"#,
+        br#"div { display: block; width: 200px; } p { display: block; height: 50px; }
+            .round { border-radius: 8px; }
+            .oval { border-radius: 10px 50% / 20px; border-top-left-radius: 4px 6px; }
+            .big { border-radius: 100px 0 0 100px; }"#).unwrap();
+    let radii:Vec<CornerRadii> = match rbox {
+        RenderBox::Block(div) => div.children.iter().filter_map(|child| match child {
+            RenderBox::Block(p) => Some(p.border_radius),
+            _ => None,
+        }).collect(),
+        _ => panic!("the div should be a block"),
+    };
+    let corner = |horizontal, vertical| CornerRadius { horizontal, vertical };
+    assert_eq!(radii[0].top_left, corner(8.0, 8.0));
+    assert_eq!(radii[0].bottom_left, corner(8.0, 8.0));
+    // the longhand after the shorthand replaces one corner, and percentages are of the box
+    assert_eq!(radii[1].top_left, corner(4.0, 6.0));
+    assert_eq!(radii[1].top_right, corner(100.0, 20.0));
+    assert_eq!(radii[1].bottom_right, corner(10.0, 20.0));
+    // the left corners would need 200px of the 50px height, so everything shrinks to a quarter
+    assert_eq!(radii[2].top_left, corner(25.0, 25.0));
+    assert_eq!(radii[2].bottom_left, corner(25.0, 25.0));
+    assert!(radii[3].is_square());
 }
 
 #[test]
-fn test_unordered_listitem() {
-    let (doc,sss,stree,lbox, rbox) = standard_test_run(
-        br#"<ul>
-    <li>item one</li>
-    <li>item two</li>
-</ul>"#,
-        br#"        ul {
-        display:block;
-            padding-left: 40px;
-            border: 1px solid black;
-            list-style-type: disc;
-        }
-        li {
-            display: list-item;
-            border: 1px solid red;
-        }
-"#,
-    ).unwrap();
-    println!("ul render is {:#?}", rbox);
-
+fn test_negative_lengths_clamped() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div><p>some words here</p></div>"#,
+        br#"div { display: block; width: 100px; }
+            p { display: block; padding: -50px; padding-left: calc(10px - 30px); border-width: -3px;
+                width: calc(10px - 50px); height: -10px; min-width: calc(0px - 5px); }"#).unwrap();
+    let p = match rbox {
+        RenderBox::Block(mut div) => match div.children.remove(0) {
+            RenderBox::Block(p) => p,
+            _ => panic!("the p should be a block"),
+        },
+        _ => panic!("the div should be a block"),
+    };
+    assert_eq!(p.padding.left, 0.0);
+    assert_eq!(p.padding.top, 0.0);
+    assert_eq!(p.border_width.top, 0.0);
+    assert_eq!(p.rect.width, 0.0);
+    assert!(p.rect.height >= 0.0);
 }
 
 #[test]
@@ -1360,11 +4755,40 @@ fn test_margin_percentage() {
     println!("ul render is {:#?}", rbox);
     if let RenderBox::Block(rbx) = rbox {
         assert_eq!(rbx.rect.x, 250.0);
-        assert_eq!(rbx.rect.width, 500.0);
+        assert_eq!(rbx.rect.width, 250.0);
         assert_eq!(rbx.font_size, 20.0);
     }
 }
 
+#[test]
+fn test_percentages_of_containing_block() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div><p>some words</p><section>more words</section></div>"#,
+        br#"div { display: block; width: 200px; height: 100px; }
+            p { display: block; margin: 10% 0; padding: 5%; height: 50%; }
+            section { display: block; padding-top: calc(10% + 1px); }"#).unwrap();
+    let mut div = match rbox {
+        RenderBox::Block(div) => div,
+        _ => panic!("the div should be a block"),
+    };
+    let section = match div.children.remove(1) {
+        RenderBox::Block(section) => section,
+        _ => panic!("the section should be a block"),
+    };
+    let p = match div.children.remove(0) {
+        RenderBox::Block(p) => p,
+        _ => panic!("the p should be a block"),
+    };
+    // vertical margins and padding are percentages of the width, heights of the height
+    assert_eq!(p.margin.top, 20.0);
+    assert_eq!(p.margin.bottom, 20.0);
+    assert_eq!(p.padding.top, 10.0);
+    assert_eq!(p.padding.left, 10.0);
+    assert_eq!(p.rect.width, 180.0);
+    assert_eq!(p.rect.height, 50.0);
+    assert_eq!(section.padding.top, 21.0);
+}
+
 #[test]
 fn test_text_position() {
     let (doc,sss,stree,lbox, render_box) = standard_test_run(
@@ -1404,3 +4828,680 @@ fn test_text_position() {
         panic!("invalid");
     }
 }
+
+#[test]
+fn test_pointer_events() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<html><body><p><a href="first.html">first</a></p><p><a class="ghost" href="second.html">second</a></p></body></html>"#,
+        br#".ghost { pointer-events: none; }"#
+    ).unwrap();
+    fn find_links<'a>(bx:&'a RenderBox, links:&mut Vec<&'a RenderTextBox>) {
+        match bx {
+            RenderBox::Block(block) => block.children.iter().for_each(|ch| find_links(ch, links)),
+            RenderBox::Anonymous(anon) => {
+                for line in anon.children.iter() {
+                    for inline in line.children.iter() {
+                        if let RenderInlineBoxType::Text(text) = inline {
+                            if text.link.is_some() {
+                                links.push(text);
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut links = vec![];
+    find_links(&rbox, &mut links);
+    assert_eq!(links.len(), 2);
+    let first = links[0].rect;
+    match rbox.find_box_containing(first.x + 1.0, first.y + 1.0) {
+        QueryResult::Text(text) => assert_eq!(text.link, Some(String::from("first.html"))),
+        _ => panic!("the first link should be clickable"),
+    }
+    let second = links[1].rect;
+    assert!(rbox.find_box_containing(second.x + 1.0, second.y + 1.0).is_none());
+}
+
+#[test]
+fn test_image_rendering() {
+    let url = relative_filepath_to_url("tests/image-rendering.html").unwrap();
+    let browser = crate::app::Browser::open_headless(&url, 1000.0).unwrap();
+    let mut modes = vec![];
+    if let RenderBox::Block(html) = &browser.render_root {
+        for bx in html.children.iter() {
+            if let RenderBox::Block(body) = bx {
+                for child in body.children.iter() {
+                    if let RenderBox::Anonymous(anon) = child {
+                        for line in anon.children.iter() {
+                            for inline in line.children.iter() {
+                                if let RenderInlineBoxType::Image(img) = inline {
+                                    modes.push(img.rendering);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    assert_eq!(modes, vec![ImageRendering::Smooth, ImageRendering::Pixelated, ImageRendering::Pixelated, ImageRendering::Smooth]);
+}
+
+#[test]
+fn test_image_intrinsic_size() {
+    let url = relative_filepath_to_url("tests/image-sizing.html").unwrap();
+    let browser = crate::app::Browser::open_headless(&url, 1000.0).unwrap();
+    fn find_images(bx:&RenderBox, sizes:&mut Vec<(f32,f32)>) {
+        match bx {
+            RenderBox::Block(block) => block.children.iter().for_each(|ch| find_images(ch, sizes)),
+            RenderBox::Anonymous(anon) => {
+                for line in anon.children.iter() {
+                    for inline in line.children.iter() {
+                        if let RenderInlineBoxType::Image(img) = inline {
+                            sizes.push((img.rect.width, img.rect.height));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut sizes = vec![];
+    find_images(&browser.render_root, &mut sizes);
+    // cat.jpg is 100x67
+    assert_eq!(sizes, vec![
+        (100.0, 67.0),
+        (200.0, 134.0),
+        (50.0, 33.5),
+        (200.0, 134.0),
+        (10.0, 10.0),
+        (50.0, 33.5),
+        // a max-height keeps the ratio too, and of two limits the tighter one wins
+        (100.0 * 20.0 / 67.0, 20.0),
+        (40.0 * 100.0 / 67.0, 40.0),
+        // a width from the height still has to be at least min-width
+        (150.0, 50.0),
+    ]);
+}
+
+#[test]
+fn test_intrinsic_width_keywords() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<html><body><div id="min">a few words</div><div id="max">a few words</div><div id="fit">a few words</div><div id="capped">a few words</div><div id="fixed">a few words</div></body></html>"#,
+        br#"
+        #min { width: min-content; }
+        #max { width: max-content; }
+        #fit { width: fit-content; padding-left: 10px; }
+        #capped { max-width: min-content; }
+        #fixed { width: 300px; max-width: min-content; }
+        "#
+    ).unwrap();
+    let body = match &rbox {
+        RenderBox::Block(html) => match &html.children[0] {
+            RenderBox::Block(body) => body,
+            _ => panic!("expected body"),
+        },
+        _ => panic!("expected html"),
+    };
+    let widths:Vec<f32> = body.children.iter().map(|ch| match ch {
+        RenderBox::Block(bx) => bx.rect.width,
+        _ => panic!("expected a block"),
+    }).collect();
+    let (min, max, fit, capped) = (widths[0], widths[1], widths[2], widths[3]);
+    assert!(min > 0.0 && min < max);
+    assert_eq!(fit, max);
+    assert_eq!(capped, min);
+    // the keyword is the content's width, not the box's own
+    assert_eq!(widths[4], min);
+    assert!(max < 500.0);
+
+    // a flex row's items are side by side, so its max-content width is theirs added up
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div><section><p>a</p><p>b</p></section></div>"#,
+        br#"div { display: block; } p { display: block; margin: 0; width: 40px; padding: 0 5px; }
+        section { display: flex; width: max-content; padding: 0 7px; }"#
+    ).unwrap();
+    let section = match &rbox {
+        RenderBox::Block(div) => match &div.children[0] {
+            RenderBox::Block(section) => section.rect,
+            _ => panic!("expected the section"),
+        },
+        _ => panic!("expected a block"),
+    };
+    assert_eq!(section.width, 100.0);
+
+    // an image's own width is held to its max-width when measured, as when it's laid out
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div><img src="missing.png"></div>"#,
+        br#"div { display: block; width: min-content; } img { width: 300px; max-width: 100px; }"#
+    ).unwrap();
+    match &rbox {
+        RenderBox::Block(div) => assert_eq!(div.rect.width, 100.0),
+        _ => panic!("expected a block"),
+    }
+}
+
+#[test]
+fn test_calc_lengths() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<html><body><div id="a">a</div><div id="b">b</div><div id="c">c</div></body></html>"#,
+        br#"
+        #a { width: calc(100% - 40px); }
+        #b { width: calc((100% + 2em) / 2); font-size: 10px; }
+        #c { max-width: calc(50% - 10px); }
+        "#
+    ).unwrap();
+    let body = match &rbox {
+        RenderBox::Block(html) => match &html.children[0] {
+            RenderBox::Block(body) => body,
+            _ => panic!("expected body"),
+        },
+        _ => panic!("expected html"),
+    };
+    let widths:Vec<f32> = body.children.iter().map(|ch| match ch {
+        RenderBox::Block(bx) => bx.rect.width,
+        _ => panic!("expected a block"),
+    }).collect();
+    assert_eq!(widths[0], body.rect.width - 40.0);
+    assert_eq!(widths[1], (body.rect.width + 20.0) / 2.0);
+    assert_eq!(widths[2], body.rect.width / 2.0 - 10.0);
+}
+
+#[test]
+fn test_min_max_sizes() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div><p id="a">a</p><p id="b">b</p><p id="c">c</p><p id="d">d</p></div>"#,
+        br#"
+        div { display: block; width: 400px; font-family: sans-serif; }
+        p { display: block; margin: 0; }
+        #a { max-width: 200px; margin: 0 auto; }
+        #b { width: 100px; min-width: 150px; max-width: 120px; }
+        #c { height: 300px; max-height: 50px; }
+        #d { min-height: 80px; }
+        "#
+    ).unwrap();
+    let rects:Vec<Rect> = match &rbox {
+        RenderBox::Block(div) => div.children.iter().map(|ch| match ch {
+            RenderBox::Block(bx) => bx.rect,
+            _ => panic!("expected a block"),
+        }).collect(),
+        _ => panic!("expected a block"),
+    };
+    // a capped width is worked out again, so the auto margins center it
+    assert_eq!((rects[0].x, rects[0].width), (100.0, 200.0));
+    // min-width wins over max-width
+    assert_eq!(rects[1].width, 150.0);
+    assert_eq!(rects[2].height, 50.0);
+    assert_eq!(rects[3].height, 80.0);
+}
+
+#[test]
+fn test_percentage_heights() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div><section id="a"><p id="b">b</p><p id="c">c</p></section><section id="d"><p id="e">e</p></section></div>"#,
+        br#"
+        div { display: block; width: 400px; font-family: sans-serif; }
+        section, p { display: block; margin: 0; }
+        #a { height: 200px; }
+        #b { height: 50%; }
+        #c { height: 10px; min-height: 25%; }
+        #e { height: 50%; min-height: 30px; }
+        "#
+    ).unwrap();
+    let blocks = |bx:&RenderBox| -> Vec<Rect> { match bx {
+        RenderBox::Block(block) => block.children.iter().map(|ch| match ch {
+            RenderBox::Block(child) => child.rect,
+            _ => panic!("expected a block"),
+        }).collect(),
+        _ => panic!("expected a block"),
+    }};
+    let sections = match &rbox {
+        RenderBox::Block(div) => &div.children,
+        _ => panic!("expected a block"),
+    };
+    // against a definite height, percentages and percent minimums resolve
+    assert_eq!(blocks(&sections[0]).iter().map(|r| r.height).collect::<Vec<_>>(), vec![100.0, 50.0]);
+    // against an auto height they are auto, and a px minimum still applies
+    assert_eq!(blocks(&sections[1])[0].height, 30.0);
+}
+
+#[test]
+fn test_absolute_and_font_units() {
+    let (_doc, _sss, _stree, lbox, rbox) = standard_test_run(
+        br#"<html><body><div id="a">a</div><div id="b">b</div><div id="c">c</div><div id="d">d</div></body></html>"#,
+        br#"
+        #a { width: 72pt; }
+        #b { width: 2.54cm; padding-left: 6pc; }
+        #c { width: 10ch; }
+        #d { width: 4ex; font-size: 30pt; }
+        "#
+    ).unwrap();
+    let body = match &rbox {
+        RenderBox::Block(html) => match &html.children[0] {
+            RenderBox::Block(body) => body,
+            _ => panic!("expected body"),
+        },
+        _ => panic!("expected html"),
+    };
+    let widths:Vec<f32> = body.children.iter().map(|ch| match ch {
+        RenderBox::Block(bx) => bx.rect.width,
+        _ => panic!("expected a block"),
+    }).collect();
+    assert_eq!(widths[0], 96.0);
+    assert!((widths[1] - 96.0).abs() < 0.01);
+    let units:Vec<FontUnits> = lbox.children[0].children.iter().map(|ch| ch.font_units.unwrap()).collect();
+    assert!(units[2].ch > 0.0 && units[2].ex > 0.0);
+    assert_eq!(widths[2], 10.0 * units[2].ch);
+    assert_eq!(widths[3], 4.0 * units[3].ex);
+    // a 40px font has a bigger ex than the default size
+    assert!(units[3].ex > units[2].ex);
+}
+
+#[test]
+fn test_measure_pass() {
+    let (_doc, _sss, _stree, lbox, _rbox) = standard_test_run(
+        br#"<html><body><div>short</div><div class="wide">a much longer line of text</div></body></html>"#,
+        br#".wide { padding-left: 20px; }"#
+    ).unwrap();
+    let body = &lbox.children[0];
+    let (short, wide) = (body.children[0].intrinsic, body.children[1].intrinsic);
+    assert!(short.min > 0.0 && short.min == short.max);
+    assert!(wide.min < wide.max);
+    assert!(body.intrinsic.max >= wide.max);
+    assert_eq!(lbox.intrinsic, body.intrinsic);
+}
+
+#[test]
+fn test_vertical_align_metrics() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<html><body><p>base <span class="up">up</span> <span class="sup">sup</span> <span class="sub">sub</span> <span class="tt">tt</span> <span class="tb">tb</span></p></body></html>"#,
+        br#"
+        p, .up, .sup, .sub { font-size: 20px; }
+        .up { vertical-align: 5px; }
+        .sup { vertical-align: super; }
+        .sub { vertical-align: sub; }
+        .tt { vertical-align: text-top; font-size: 10px; }
+        .tb { vertical-align: text-bottom; font-size: 10px; }
+        "#
+    ).unwrap();
+    fn find_texts(bx:&RenderBox, texts:&mut Vec<(String, Rect)>) {
+        match bx {
+            RenderBox::Block(block) => block.children.iter().for_each(|ch| find_texts(ch, texts)),
+            RenderBox::Anonymous(anon) => {
+                for line in anon.children.iter() {
+                    for inline in line.children.iter() {
+                        if let RenderInlineBoxType::Text(text) = inline {
+                            texts.push((text.text.trim().to_string(), text.rect));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut texts = vec![];
+    find_texts(&rbox, &mut texts);
+    let rect = |name:&str| texts.iter().find(|(t, _)| t == name).unwrap().1;
+    let base = rect("base");
+    let close = |a:f32, b:f32| (a - b).abs() < 0.01;
+    assert!(close(rect("up").y, base.y - 5.0));
+    assert!(rect("sup").y < base.y);
+    assert!(rect("sub").y > base.y);
+    assert!(close(rect("tt").y, base.y));
+    assert!(close(rect("tb").y + rect("tb").height, base.y + base.height));
+}
+
+#[test]
+fn test_generated_content_layout() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<html><body><p>one <q>two</q></p></body></html>"#,
+        br#"q::before { content: "<<"; } q::after { content: ">>"; }"#
+    ).unwrap();
+    fn find_texts(bx:&RenderBox, texts:&mut Vec<(String, Rect)>) {
+        match bx {
+            RenderBox::Block(block) => block.children.iter().for_each(|ch| find_texts(ch, texts)),
+            RenderBox::Anonymous(anon) => {
+                for line in anon.children.iter() {
+                    for inline in line.children.iter() {
+                        if let RenderInlineBoxType::Text(text) = inline {
+                            texts.push((text.text.trim().to_string(), text.rect));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut texts = vec![];
+    find_texts(&rbox, &mut texts);
+    let words:Vec<&str> = texts.iter().map(|(t, _)| t.as_str()).filter(|t| !t.is_empty()).collect();
+    assert_eq!(words, vec!["one", "<<", "two", ">>"]);
+    // all on one line, left to right
+    assert!(texts.windows(2).all(|pair| pair[0].1.x <= pair[1].1.x));
+}
+
+#[test]
+fn test_block_in_inline() {
+    let (_doc, _sss, _stree, lbox, _rbox) = standard_test_run(
+        br#"<html><body><a href="x">before<b>bold<div>inside</div>after bold</b>after</a></body></html>"#,
+        br#""#
+    ).unwrap();
+    let body = &lbox.children[0];
+    let kinds:Vec<String> = body.children.iter().map(|ch| match &ch.box_type {
+        AnonymousBlock(_) => String::from("anon"),
+        BlockNode(_) => ch.debug_calculate_element_name(),
+        _ => String::from("other"),
+    }).collect();
+    assert_eq!(kinds, vec!["anon", "div", "anon"]);
+    // the pieces on each side are fragments of the a, with the b fragment nested inside
+    let before = &body.children[0].children[0];
+    assert_eq!(before.debug_calculate_element_name(), "a");
+    assert_eq!(before.children.len(), 2);
+    assert_eq!(before.children[1].debug_calculate_element_name(), "b");
+    let after = &body.children[2].children[0];
+    assert_eq!(after.debug_calculate_element_name(), "a");
+    assert_eq!(after.children.len(), 2);
+    assert_eq!(after.children[0].debug_calculate_element_name(), "b");
+}
+
+#[test]
+fn test_anonymous_blocks_keep_order() {
+    let (_doc, _sss, _stree, lbox, _rbox) = standard_test_run(
+        br#"<html><body>one <span>one b</span><p>two</p>three<p>four</p><img src="missing.png"> five</body></html>"#,
+        br#""#
+    ).unwrap();
+    let body = &lbox.children[0];
+    let kinds:Vec<String> = body.children.iter().map(|ch| match &ch.box_type {
+        AnonymousBlock(_) => format!("anon {}", ch.children.len()),
+        _ => ch.debug_calculate_element_name(),
+    }).collect();
+    assert_eq!(kinds, vec!["anon 2", "p", "anon 1", "p", "anon 2"]);
+}
+
+#[test]
+fn test_empty_anonymous_block_collapses() {
+    let (_doc, _sss, _stree, lbox, _rbox) = standard_test_run(
+        br#"<html><body><div>one</div><span></span><div>two</div></body></html>"#,
+        br#""#
+    ).unwrap();
+    let body = &lbox.children[0];
+    assert_eq!(body.children.len(), 3);
+    assert_eq!(body.children[1].dimensions.content.height, 0.0);
+    assert_eq!(body.children[2].dimensions.content.y,
+               body.children[0].dimensions.content.y + body.children[0].dimensions.content.height);
+}
+
+#[test]
+fn test_multi_column() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<html><body><div id="cols"><div class="c"></div><div class="c"></div><div class="c"></div><div class="big"></div><div class="c"></div></div></body></html>"#,
+        br#"#cols { column-count: 2; column-gap: 20px; } .c { height: 20px; } .big { height: 40px; }"#
+    ).unwrap();
+    let body = match &rbox { RenderBox::Block(html) => match &html.children[0] { RenderBox::Block(body) => body, _ => panic!() }, _ => panic!() };
+    let cols = match &body.children[0] { RenderBox::Block(cols) => cols, _ => panic!() };
+    // 120px of content balances into two 60px columns sharing the 484px around the gap
+    assert_eq!(cols.rect.height, 60.0);
+    let placed:Vec<(f32, f32, f32)> = cols.children.iter().map(|ch| match ch {
+        RenderBox::Block(bx) => (bx.rect.x - cols.rect.x, bx.rect.y - cols.rect.y, bx.rect.width),
+        _ => panic!(),
+    }).collect();
+    assert_eq!(placed, vec![
+        (0.0, 0.0, 232.0), (0.0, 20.0, 232.0), (0.0, 40.0, 232.0),
+        (252.0, 0.0, 232.0), (252.0, 40.0, 232.0),
+    ]);
+}
+
+#[test]
+fn test_ruby_annotation() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<html><body><p>plain</p><p>a <ruby>base<rp>(</rp><rt>note</rt><rp>)</rp></ruby> b</p></body></html>"#,
+        br#""#
+    ).unwrap();
+    let body = match &rbox { RenderBox::Block(html) => match &html.children[0] { RenderBox::Block(body) => body, _ => panic!() }, _ => panic!() };
+    let line = |n:usize| match &body.children[n] {
+        RenderBox::Block(p) => match &p.children[0] {
+            RenderBox::Anonymous(anon) => &anon.children[0],
+            _ => panic!(),
+        },
+        _ => panic!(),
+    };
+    let texts:Vec<(&str, f32, Rect)> = line(1).children.iter().filter_map(|ch| match ch {
+        RenderInlineBoxType::Text(t) => Some((t.text.trim(), t.font_size, t.rect)),
+        _ => None,
+    }).collect();
+    let names:Vec<&str> = texts.iter().map(|t| t.0).collect();
+    assert_eq!(names, vec!["a", "base", "note", "b"]);
+    let (_, base_size, base) = texts[1];
+    let (_, note_size, note) = texts[2];
+    assert_eq!(note_size, base_size / 2.0);
+    // the annotation sits above the base, centered over it
+    assert!(note.y + note.height <= base.y + 0.01);
+    assert!((note.x + note.width / 2.0 - (base.x + base.width / 2.0)).abs() < 0.01);
+    assert!(line(1).rect.height > line(0).rect.height);
+}
+
+#[test]
+fn test_border_sides() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<html><body><div id="box"></div></body></html>"#,
+        br#"#box { border-width: 1px 2px 3px 4px; border-color: red; border-left-color: blue; border-style: solid double; border-top: 5px dashed green; }"#
+    ).unwrap();
+    let body = match &rbox { RenderBox::Block(html) => match &html.children[0] { RenderBox::Block(body) => body, _ => panic!() }, _ => panic!() };
+    let bx = match &body.children[0] { RenderBox::Block(bx) => bx, _ => panic!() };
+    assert_eq!((bx.border_width.top, bx.border_width.right, bx.border_width.bottom, bx.border_width.left), (5.0, 2.0, 3.0, 4.0));
+    let color = |name:&str| crate::style::find_color_lazy_static(name);
+    assert_eq!(bx.border_color, BorderSides { top: color("green"), right: color("red"), bottom: color("red"), left: color("blue") });
+    assert_eq!(bx.border_style, BorderSides {
+        top: BorderStyle::Dashed, right: BorderStyle::Double,
+        bottom: BorderStyle::Solid, left: BorderStyle::Double,
+    });
+}
+
+#[test]
+fn test_collapsed_table_borders() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<html><body><table><tr><td>a</td><td class="wide">b</td></tr><tr><td>c</td><td>d</td></tr></table></body></html>"#,
+        br#"table { border-collapse: collapse; border: 3px solid red; } .wide { border: 2px dashed blue; }"#
+    ).unwrap();
+    fn collect<'a>(bx:&'a RenderBox, found:&mut Vec<&'a RenderBlockBox>) {
+        if let RenderBox::Block(block) = bx {
+            found.push(block);
+            block.children.iter().for_each(|ch| collect(ch, found));
+        }
+    }
+    let mut boxes = vec![];
+    collect(&rbox, &mut boxes);
+    // only the cells paint anything
+    for bx in boxes.iter().filter(|bx| bx.title == "table" || bx.title == "tr") {
+        assert_eq!(bx.border_color, BorderSides::default());
+    }
+    let cells:Vec<&&RenderBlockBox> = boxes.iter().filter(|bx| bx.title == "td").collect();
+    assert_eq!(cells.len(), 4);
+    let side = |cell:&RenderBlockBox, n:usize| match n {
+        0 => cell.border_color.top.as_ref().map(|c| (cell.border_width.top, c.clone(), cell.border_style.top)),
+        1 => cell.border_color.right.as_ref().map(|c| (cell.border_width.right, c.clone(), cell.border_style.right)),
+        2 => cell.border_color.bottom.as_ref().map(|c| (cell.border_width.bottom, c.clone(), cell.border_style.bottom)),
+        _ => cell.border_color.left.as_ref().map(|c| (cell.border_width.left, c.clone(), cell.border_style.left)),
+    };
+    let color = |name:&str| crate::style::find_color_lazy_static(name).unwrap();
+    let red = Some((3.0, color("red"), BorderStyle::Solid));
+    let blue = Some((2.0, color("blue"), BorderStyle::Dashed));
+    let black = Some((1.0, color("black"), BorderStyle::Solid));
+    // top, right, bottom, left. shared edges belong to the cell below or to the right.
+    let expected = vec![
+        vec![red.clone(), None, None, red.clone()],
+        vec![red.clone(), red.clone(), None, blue.clone()],
+        vec![black.clone(), None, red.clone(), red.clone()],
+        vec![blue.clone(), red.clone(), red.clone(), black.clone()],
+    ];
+    for (cell, expected) in cells.iter().zip(expected) {
+        let sides:Vec<_> = (0..4).map(|n| side(cell, n)).collect();
+        assert_eq!(sides, expected);
+    }
+}
+
+#[test]
+fn test_table_border_spacing() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div><table><tr><td>a</td><td>b</td></tr><tr><td>c</td><td>d</td></tr></table></div>"#,
+        br#"div { display: block; }
+        table, tbody, tr, td { padding: 0; border-width: 0; }
+        table { width: 210px; border-spacing: 10px 4px; }"#
+    ).unwrap();
+    fn collect<'a>(bx:&'a RenderBox, found:&mut Vec<&'a RenderBlockBox>) {
+        if let RenderBox::Block(block) = bx {
+            found.push(block);
+            block.children.iter().for_each(|ch| collect(ch, found));
+        }
+    }
+    let mut boxes = vec![];
+    collect(&rbox, &mut boxes);
+    let rects = |title:&str| -> Vec<Rect> { boxes.iter().filter(|bx| bx.title == title).map(|bx| bx.rect).collect() };
+    // the spacing goes around the cells as well as between them
+    assert_eq!(rects("td").iter().map(|r| (r.x, r.width)).collect::<Vec<_>>(),
+        vec![(10.0, 90.0), (110.0, 90.0), (10.0, 90.0), (110.0, 90.0)]);
+    assert_eq!(rects("tr").iter().map(|r| r.y).collect::<Vec<_>>(), vec![4.0, 58.0]);
+    assert_eq!(rects("table")[0].height, 112.0);
+}
+
+#[test]
+fn test_table_caption_and_row_groups() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        br#"<div><table><caption class="below">below</caption><tfoot><tr><td>foot</td></tr></tfoot><tbody><tr><td>body</td></tr></tbody><thead><tr><td>head</td></tr></thead><caption>above</caption></table></div>"#,
+        br#"div { display: block; font-family: sans-serif; } .below { caption-side: bottom; }"#
+    ).unwrap();
+    let table = match &rbox {
+        RenderBox::Block(div) => match &div.children[0] {
+            RenderBox::Block(table) => table,
+            _ => panic!("expected the table"),
+        },
+        _ => panic!("expected a block"),
+    };
+    let children:Vec<(String, f32)> = table.children.iter().map(|ch| match ch {
+        RenderBox::Block(bx) => (bx.title.clone(), bx.rect.y),
+        _ => panic!("expected a block"),
+    }).collect();
+    assert_eq!(children.iter().map(|(title, _)| title.as_str()).collect::<Vec<_>>(),
+        vec!["caption", "thead", "tbody", "tfoot", "caption"]);
+    assert!(children.windows(2).all(|pair| pair[0].1 < pair[1].1));
+}
+
+#[test]
+fn test_overflow_scrolling() {
+    let run = |overflow:&str| {
+        let css = format!(".box {{ height: 50px; padding: 5px; overflow: {}; }}
+            p {{ margin: 0; height: 40px; }}", overflow);
+        let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+            b"<div><div class='box'><p>a</p><p>b</p><p>c</p></div><p>after</p></div>", css.as_bytes()).unwrap();
+        rbox
+    };
+    let text_at = |rbox:&RenderBox, y:f32| match rbox.find_box_containing(10.0, y) {
+        QueryResult::Text(text) => Some(text.text.trim().to_string()),
+        QueryResult::None() => None,
+    };
+    let area = |rbox:&RenderBox| match rbox {
+        RenderBox::Block(root) => match &root.children[0] {
+            RenderBox::Block(bx) => bx.scroll.clone(),
+            _ => panic!("the box should be a block"),
+        },
+        _ => panic!("the root should be a block"),
+    };
+    assert!(area(&run("visible")).is_none());
+    assert_eq!(text_at(&run("visible"), 90.0), Some(String::from("c")));
+
+    let mut rbox = run("auto");
+    let scroll = area(&rbox).unwrap();
+    assert_eq!((scroll.rect.y, scroll.rect.height), (0.0, 60.0));
+    assert_eq!(scroll.max_scroll(), 70.0);
+    // the content below the box is clipped, and the box keeps its height
+    assert_eq!(text_at(&rbox, 90.0), None);
+    assert_eq!(text_at(&rbox, 65.0), Some(String::from("after")));
+    assert_eq!(text_at(&rbox, 10.0), Some(String::from("a")));
+    // the box takes what it can of the scroll, and leaves the rest for the page
+    assert_eq!(rbox.scroll_at(10.0, 10.0, 100.0), 30.0);
+    assert_eq!(rbox.scroll_at(10.0, 65.0, 10.0), 10.0);
+    assert_eq!(area(&rbox).unwrap().scroll_y, 70.0);
+    assert_eq!(text_at(&rbox, 20.0), Some(String::from("c")));
+    assert_eq!(area(&rbox).unwrap().content_clip(None).y, 70.0);
+
+    // laid out again, it stays scrolled
+    let mut again = run("auto");
+    again.set_scroll_offsets(&rbox.scroll_offsets());
+    assert_eq!(area(&again).unwrap().scroll_y, 70.0);
+
+    let mut hidden = run("hidden");
+    assert!(!area(&hidden).unwrap().user_scrollable);
+    assert_eq!(text_at(&hidden, 90.0), None);
+    assert_eq!(hidden.scroll_at(10.0, 10.0, 20.0), 20.0);
+}
+
+#[test]
+fn test_inline_decoration_fragments() {
+    let words = "word ".repeat(60);
+    let html = format!(r#"<html><body><p>aa <span class="x">bb</span> cc</p><p><span class="x">{}</span></p><p><span class="x y">{}</span></p></body></html>"#, words, words);
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        html.as_bytes(),
+        br#".x { background-color: gray; padding: 2px 4px; border: 1px solid black; } .y { box-decoration-break: clone; }"#
+    ).unwrap();
+    let body = match &rbox { RenderBox::Block(html) => match &html.children[0] { RenderBox::Block(body) => body, _ => panic!() }, _ => panic!() };
+    let anon = |n:usize| match &body.children[n] {
+        RenderBox::Block(p) => match &p.children[0] { RenderBox::Anonymous(anon) => anon, _ => panic!() },
+        _ => panic!(),
+    };
+    // the padding and border push the span's text along, and the fragment wraps it
+    let texts:Vec<&RenderTextBox> = anon(0).children[0].children.iter().filter_map(|ch| match ch {
+        RenderInlineBoxType::Text(t) => Some(t),
+        _ => None,
+    }).collect();
+    assert_eq!(texts.len(), 3);
+    assert_eq!(texts[1].rect.x, texts[0].rect.x + texts[0].rect.width + 5.0);
+    assert_eq!(texts[2].rect.x, texts[1].rect.x + texts[1].rect.width + 5.0);
+    let frags = anon(0).inline_fragments();
+    assert_eq!(frags.len(), 1);
+    assert_eq!(frags[0].rect.x, texts[1].rect.x - 5.0);
+    assert_eq!(frags[0].rect.width, texts[1].rect.width + 10.0);
+    assert_eq!(frags[0].rect.y, texts[1].rect.y - 3.0);
+    assert_eq!(frags[0].background_color, crate::style::find_color_lazy_static("gray"));
+
+    // sliced at the wraps, only the ends get the left and right edges
+    let sliced = anon(1).inline_fragments();
+    assert!(sliced.len() > 1);
+    assert_eq!(sliced.len(), anon(1).children.len());
+    let edges:Vec<(f32, f32)> = sliced.iter().map(|f| (f.border_width.left, f.border_width.right)).collect();
+    assert_eq!(edges.first(), Some(&(1.0, 0.0)));
+    assert_eq!(edges.last(), Some(&(0.0, 1.0)));
+    assert!(sliced.iter().all(|f| f.border_color.top.is_some()));
+    // cloned, every fragment is a complete box
+    let cloned = anon(2).inline_fragments();
+    assert_eq!(cloned.len(), sliced.len());
+    assert!(cloned.iter().all(|f| f.border_width.left == 1.0 && f.border_width.right == 1.0));
+}
+
+#[test]
+fn test_link_fragments_across_lines() {
+    let words = "word ".repeat(60);
+    let html = format!(r#"<html><body><p>aa <a href="next.html">bb <b>cc</b> {}</a> dd</p></body></html>"#, words);
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(html.as_bytes(), br#""#).unwrap();
+    let body = match &rbox { RenderBox::Block(html) => match &html.children[0] { RenderBox::Block(body) => body, _ => panic!() }, _ => panic!() };
+    let anon = match &body.children[0] {
+        RenderBox::Block(p) => match &p.children[0] { RenderBox::Anonymous(anon) => anon, _ => panic!() },
+        _ => panic!(),
+    };
+    assert!(anon.children.len() > 1);
+    let first = match &anon.children[0].children[1] { RenderInlineBoxType::Text(t) => t, _ => panic!() };
+    assert_eq!(first.text.trim(), "bb");
+    // the nested bold text and the fragment on the next line are all part of the link
+    let frags = rbox.fragments_of(first.element);
+    assert!(frags.iter().any(|f| f.text.trim() == "cc"));
+    assert!(frags.iter().all(|f| f.link == Some(String::from("next.html"))));
+    assert!(frags.iter().all(|f| !f.opens_new_window()));
+    let last = frags.last().unwrap();
+    assert!(last.rect.y > first.rect.y);
+    assert_eq!(rbox.find_element_containing(last.rect.x + 1.0, last.rect.y + 1.0, 0.0), Some(first.element));
+    // one underline for the link on its first line, though it has runs in two fonts
+    let underlines = anon.children[0].underlines();
+    assert_eq!(underlines.len(), 1);
+    assert_eq!(underlines[0].0.x, first.rect.x);
+}
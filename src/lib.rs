@@ -10,3 +10,5 @@ pub mod net;
 pub mod image;
 pub mod globals;
 pub mod app;
+pub mod scheduler;
+pub mod watch;
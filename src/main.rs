@@ -2,17 +2,22 @@
 extern crate glium;
 extern crate glium_glyph;
 
-use rust_minibrowser::layout::{Dimensions, Rect, RenderBox, QueryResult, RenderInlineBoxType, EdgeSizes, Brush, ListMarker};
-use rust_minibrowser::render::{FontCache};
-use rust_minibrowser::net::{calculate_url_from_doc, BrowserError};
+use rust_minibrowser::layout::{Dimensions, Rect, RenderBox, ScrollArea, QueryResult, RenderInlineBoxType, EdgeSizes, BorderSides, BorderStyle, Brush, ImageRendering, TextSpacing};
+use rust_minibrowser::render::{FontCache, TextRendering, text_rendering};
+use rust_minibrowser::net::{BrowserError, set_network_config, NetworkConfig, ProxyConfig};
 
 
-use rust_minibrowser::app::{parse_args, navigate_to_doc, install_standard_fonts};
+use rust_minibrowser::app::{parse_args, parse_device_arg, parse_color_scheme_arg, install_standard_fonts, Browser, LinkAction, DeviceEmulation, PARTIAL_LAYOUT_INTERVAL};
+use rust_minibrowser::scheduler::{FrameScheduler, Damage};
+use rust_minibrowser::style::{set_color_scheme, ColorScheme};
+use std::time::{Duration, Instant};
 
 use cgmath::{Matrix4, Vector3};
 use glium::glutin::{
-    event_loop::ControlFlow,
+    event_loop::{ControlFlow, EventLoopWindowTarget},
     event::WindowEvent,
+    event::DeviceEvent,
+    event::ModifiersState,
     event::MouseScrollDelta::{PixelDelta, LineDelta},
     event::VirtualKeyCode,
     event::KeyboardInput,
@@ -20,21 +25,27 @@ use glium::glutin::{
     dpi::PhysicalPosition,
     event::ElementState,
     event::MouseButton,
+    window::{CursorIcon, WindowId},
 };
+use url::Url;
 use glium::{glutin, Display};
 use glium::Surface;
-use glium_glyph::GlyphBrush;
 use glium_glyph::glyph_brush::{Section,
                                rusttype::{
-                                   Scale
+                                   Scale, Rect as GBRect, point
                                }};
 use rust_minibrowser::css::Color;
 use std::collections::HashMap;
 use glium::texture::{Texture2d, RawImage2d};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
 use std::rc::Rc;
 
 const WIDTH:i32 = 800;
 const HEIGHT:i32 = 800;
+// optional proxy settings, see ProxyConfig::from_config_file
+const PROXY_CONFIG:&str = "proxy.json";
+// how often to look for changes to local pages being previewed
+const FILE_CHECK_INTERVAL:Duration = Duration::from_millis(250);
 
 #[derive(Copy, Clone)]
 pub struct Vertex {
@@ -54,6 +65,8 @@ implement_vertex!(ImageVertex, position, tex_coords);        // don't forget to
 struct ImageRect {
     vertices:Vec<ImageVertex>,
     texture:Rc<Texture2d>,
+    rendering:ImageRendering,
+    opacity:f32,
 }
 
 pub fn make_box(shape:&mut Vec<Vertex>, rect:&Rect, color:&Color) {
@@ -70,10 +83,15 @@ pub fn make_box2(shape:&mut Vec<Vertex>, x1:f32,y1:f32,x2:f32,y2:f32, color:&Col
     shape.push( Vertex { position: [x1,  y1], color:color.to_array() });
 }
 
-fn make_image_box(images:&mut Vec<ImageRect>, rect:&Rect, tex:&Rc<Texture2d>) {
-    make_image_box2(images, rect.x, rect.y, rect.x+rect.width, rect.y+rect.height, tex);
+fn make_image_box(images:&mut Vec<ImageRect>, rect:&Rect, tex:&Rc<Texture2d>, rendering:ImageRendering, opacity:f32) {
+    images.push(ImageRect {
+        vertices:image_vertices(rect.x, rect.y, rect.x+rect.width, rect.y+rect.height),
+        texture:Rc::clone(tex),
+        rendering,
+        opacity,
+    })
 }
-fn make_image_box2(images:&mut Vec<ImageRect>, x1:f32, y1:f32, x2:f32, y2:f32, tex:&Rc<Texture2d>) {
+fn image_vertices(x1:f32, y1:f32, x2:f32, y2:f32) -> Vec<ImageVertex> {
 
     let vertex1 = ImageVertex { position: [x1, y1], tex_coords: [0.0, 0.0] };
     let vertex2 = ImageVertex { position: [x2, y1], tex_coords: [1.0, 0.0] };
@@ -82,45 +100,79 @@ fn make_image_box2(images:&mut Vec<ImageRect>, x1:f32, y1:f32, x2:f32, y2:f32, t
     let vertex4 = ImageVertex { position: [x2, y2], tex_coords: [1.0, 1.0] };
     let vertex5 = ImageVertex { position: [x1, y2], tex_coords: [0.0, 1.0] };
     let vertex6 = ImageVertex { position: [x1, y1], tex_coords: [0.0, 0.0] };
-    let ir = ImageRect {
-        vertices:vec![vertex1, vertex2, vertex3, vertex4, vertex5, vertex6],
-        texture:Rc::clone(tex),
-    };
-    images.push(ir)
+    vec![vertex1, vertex2, vertex3, vertex4, vertex5, vertex6]
 }
 
 
-pub fn make_border(shapes:&mut Vec<Vertex>, rect:&Rect, border_width:&EdgeSizes, color:&Color) {
-    // println!("making border {:#?} {:#?}",border_width,color);
+pub fn make_border(shapes:&mut Vec<Vertex>, rect:&Rect, border_width:&EdgeSizes, colors:&BorderSides<Option<Color>>, styles:&BorderSides<BorderStyle>, opacity:f32) {
+    // println!("making border {:#?} {:#?}",border_width,colors);
     //left
-    make_box(shapes, &Rect {
+    make_border_side(shapes, &Rect {
         x: rect.x,
         y: rect.y,
         width: border_width.left,
         height: rect.height
-    }, color);
+    }, false, styles.left, &colors.left, opacity);
     //right
-    make_box(shapes, &Rect {
+    make_border_side(shapes, &Rect {
         x: rect.x + rect.width - border_width.right,
         y: rect.y,
         width: border_width.right,
         height: rect.height
-    }, color);
+    }, false, styles.right, &colors.right, opacity);
 
     //top
-    make_box(shapes, &Rect {
+    make_border_side(shapes, &Rect {
         x: rect.x,
         y: rect.y,
         width: rect.width,
         height: border_width.top
-    }, color);
+    }, true, styles.top, &colors.top, opacity);
     //bottom
-    make_box(shapes, &Rect {
+    make_border_side(shapes, &Rect {
         x: rect.x,
         y: rect.y+rect.height - border_width.bottom,
         width: rect.width,
         height: border_width.bottom
-    }, color);
+    }, true, styles.bottom, &colors.bottom, opacity);
+}
+
+// one side of a border. `rect` is the whole strip, running along x when horizontal.
+fn make_border_side(shapes:&mut Vec<Vertex>, rect:&Rect, horizontal:bool, style:BorderStyle, color:&Option<Color>, opacity:f32) {
+    let color = match color {
+        Some(color) => color.with_opacity(opacity),
+        None => return,
+    };
+    let (length, thickness) = if horizontal { (rect.width, rect.height) } else { (rect.height, rect.width) };
+    if thickness <= 0.0 {
+        return;
+    }
+    // a piece of the strip, as offsets along and across it
+    let mut piece = |along:f32, along_len:f32, across:f32, across_len:f32| {
+        let piece = if horizontal {
+            Rect { x: rect.x + along, y: rect.y + across, width: along_len, height: across_len }
+        } else {
+            Rect { x: rect.x + across, y: rect.y + along, width: across_len, height: along_len }
+        };
+        make_box(shapes, &piece, &color);
+    };
+    match style {
+        BorderStyle::None | BorderStyle::Hidden => {},
+        BorderStyle::Dashed | BorderStyle::Dotted => {
+            let (on, off) = if style == BorderStyle::Dotted { (thickness, thickness) } else { (thickness * 3.0, thickness * 2.0) };
+            let mut pos = 0.0;
+            while pos < length {
+                piece(pos, on.min(length - pos), 0.0, thickness);
+                pos += on + off;
+            }
+        },
+        BorderStyle::Double if thickness >= 3.0 => {
+            let line = thickness / 3.0;
+            piece(0.0, length, 0.0, line);
+            piece(0.0, length, thickness - line, line);
+        },
+        _ => piece(0.0, length, 0.0, thickness),
+    }
 }
 
 pub fn make_line(shapes:&mut Vec<Vertex>,rect:&Rect,yoff:f32,color:&Color) {
@@ -133,29 +185,138 @@ pub fn make_line(shapes:&mut Vec<Vertex>,rect:&Rect,yoff:f32,color:&Color) {
 }
 
 
-fn draw_render_box(bx:&RenderBox, gb:&mut FontCache, img:&mut HashMap<String, Rc<Texture2d>>, width:f32, height:f32, shapes:&mut Vec<Vertex>, images:&mut Vec<ImageRect>, text_scale:f32, display:&Display) {
+// draws the page in batches. everything queued between flushes shares one scroll and one clip.
+struct Painter<'a> {
+    target: &'a mut glium::Frame,
+    display: &'a Display,
+    rect_program: &'a glium::Program,
+    tex_program: &'a glium::Program,
+    font_cache: &'a mut FontCache,
+    image_cache: &'a mut HashMap<String, Rc<Texture2d>>,
+    // the framebuffer size
+    width: f32,
+    height: f32,
+    zoom: f32,
+    text_scale: f32,
+    shapes: Vec<Vertex>,
+    images: Vec<ImageRect>,
+    // how far the boxes being queued are scrolled up, in page pixels
+    yoff: f32,
+    // where they show, in their own coordinates
+    clip: Option<Rect>,
+}
+
+impl Painter<'_> {
+    fn queue_text(&mut self, section:Section) {
+        match self.clip {
+            Some(clip) => {
+                let scale = self.text_scale;
+                let clip = GBRect {
+                    min: point(clip.x * scale, clip.y * scale),
+                    max: point((clip.x + clip.width) * scale, (clip.y + clip.height) * scale),
+                };
+                self.font_cache.brush.queue_clipped(section, clip);
+            },
+            None => self.font_cache.brush.queue(section),
+        }
+    }
+    // draws the content of a scrolling box with its scroll, clipped to the box, between
+    // what comes before and after it
+    fn scrolled(&mut self, area:&ScrollArea, draw:impl FnOnce(&mut Painter)) {
+        self.flush();
+        let (yoff, clip) = (self.yoff, self.clip);
+        self.clip = Some(area.content_clip(clip));
+        self.yoff += area.scroll_y;
+        draw(self);
+        self.flush();
+        self.yoff = yoff;
+        self.clip = clip;
+    }
+    fn flush(&mut self) {
+        let (w, h, zoom, yoff) = (self.width, self.height, self.zoom, self.yoff);
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+
+        // the boxes move up by their scroll, in page pixels
+        let shift = 2.0*2.0*zoom*yoff/h;
+        let box_translate = Matrix4::from_translation(Vector3{x: - 1.0, y:shift + 1.0, z:0.0});
+        let box_scale = Matrix4::from_nonuniform_scale(2.0*2.0*zoom/w,-2.0*2.0*zoom/h,1.0);
+        let box_trans: [[f32; 4]; 4] = (box_translate * box_scale).into();
+        let uniforms = uniform! { matrix: box_trans  };
+        // from the clip in page pixels to window pixels, which go up from the bottom
+        let scissor = self.clip.map(|clip| {
+            let scale = 2.0 * zoom;
+            // a clip off the window, or only partly on it, is cut to the part inside
+            let top = ((clip.y - yoff) * scale).clamp(0.0, h);
+            let bottom = ((clip.y + clip.height - yoff) * scale).clamp(top, h);
+            let left = (clip.x * scale).clamp(0.0, w);
+            let right = ((clip.x + clip.width) * scale).clamp(left, w);
+            glium::Rect {
+                left: left as u32,
+                bottom: (h - bottom) as u32,
+                width: (right - left) as u32,
+                height: (bottom - top) as u32,
+            }
+        });
+        // translucent colors and faded subtrees mix with what is already drawn
+        let params = glium::DrawParameters { blend: glium::Blend::alpha_blending(), scissor, ..Default::default() };
+        if !self.shapes.is_empty() {
+            let vertex_buffer = glium::VertexBuffer::new(self.display, &self.shapes).unwrap();
+            self.target.draw(&vertex_buffer, &indices, self.rect_program, &uniforms, &params).unwrap();
+            self.shapes.clear();
+        }
+
+        for image in self.images.drain(..) {
+            // pixelated images scale with nearest neighbor sampling, everything else is filtered
+            let (magnify, minify) = match image.rendering {
+                ImageRendering::Pixelated => (MagnifySamplerFilter::Nearest, MinifySamplerFilter::Nearest),
+                ImageRendering::Smooth => (MagnifySamplerFilter::Linear, MinifySamplerFilter::Linear),
+            };
+            let tex = image.texture.sampled().magnify_filter(magnify).minify_filter(minify);
+            let image_uniforms = uniform! { matrix: box_trans, tex: tex, opacity: image.opacity };
+            let img_vertex_buffer = glium::VertexBuffer::new(self.display, &image.vertices).unwrap();
+            self.target.draw(&img_vertex_buffer, &indices, self.tex_program, &image_uniforms, &params).unwrap();
+        }
+
+        //draw fonts. their glyphs are already cut to the clip.
+        let scale = Matrix4::from_nonuniform_scale(2.0/w,  2.0/h, 1.0);
+        let translate = Matrix4::from_translation(Vector3{ x: -1.0,  y: -1.0 - shift,  z:0.0 });
+        let transform: [[f32; 4]; 4] = (translate * scale).into();
+        self.font_cache.brush.draw_queued_with_transform(transform, self.display, self.target);
+    }
+}
+
+// `opacity` is that of the boxes around this one. each box multiplies in its own, and every
+// color in the subtree is drawn that much more transparent.
+fn draw_render_box(bx:&RenderBox, p:&mut Painter, opacity:f32) {
     match bx {
         RenderBox::Block(rbx) => {
             // println!("box is {} border width {} {:#?}",rbx.title, rbx.border_width, rbx.padding);
-            if let Some(color) = &rbx.background_color {
-                make_box(shapes, &rbx.content_area_as_rect(), color);
+            let opacity = opacity * rbx.opacity;
+            if opacity <= 0.0 {
+                return;
             }
-            if rbx.border_color.is_some() {
-                let color = rbx.border_color.as_ref().unwrap();
-                make_border(shapes, &rbx.content_area_as_rect(), &rbx.border_width, &color);
+            // a hidden block skips its own painting, but its children may be visible again
+            if rbx.visible {
+                if let Some(color) = &rbx.background_color {
+                    make_box(&mut p.shapes, &rbx.content_area_as_rect(), &color.with_opacity(opacity));
+                }
+                make_border(&mut p.shapes, &rbx.content_area_as_rect(), &rbx.border_width, &rbx.border_color, &rbx.border_style, opacity);
             }
-            for ch in rbx.children.iter() {
-                draw_render_box(ch, gb, img,width, height, shapes, images, text_scale, display);
+            // fixed boxes are drawn in a layer of their own
+            let children = rbx.children.iter().filter(|ch| !ch.is_fixed());
+            match &rbx.scroll {
+                Some(area) => p.scrolled(area, |p| children.for_each(|ch| draw_render_box(ch, p, opacity))),
+                None => children.for_each(|ch| draw_render_box(ch, p, opacity)),
             }
-            if let ListMarker::Disc = rbx.marker {
-                let font = gb.lookup_font(&rbx.font_family, rbx.font_weight, &rbx.font_style);
-                let color = rbx.color.as_ref().unwrap().clone();
+            if let Some(marker) = rbx.marker.text().filter(|_| rbx.visible) {
+                let font = p.font_cache.lookup_font(&rbx.font_family, rbx.font_weight, &rbx.font_style);
+                let color = rbx.color.as_ref().unwrap().with_opacity(opacity);
                 let section = Section{
-                    text: "•",
-                    scale: Scale::uniform(rbx.font_size*text_scale),
+                    text: &marker,
+                    scale: Scale::uniform(rbx.font_size*p.text_scale),
                     font_id:*font,
-                    screen_position: (rbx.rect.x* text_scale - 20.0, rbx.rect.y* text_scale),
-                    bounds: (rbx.rect.width * text_scale, rbx.rect.height * text_scale),
+                    screen_position: ((rbx.rect.x + rbx.marker_offset) * p.text_scale, rbx.rect.y* p.text_scale),
+                    bounds: (rbx.rect.width * p.text_scale, rbx.rect.height * p.text_scale),
                     color: [
                         (color.r as f32)/255.0,
                         (color.g as f32)/255.0,
@@ -164,63 +325,92 @@ fn draw_render_box(bx:&RenderBox, gb:&mut FontCache, img:&mut HashMap<String, Rc
                     ],
                     ..Section::default()
                 };
-                gb.brush.queue(section);
+                p.queue_text(section);
+            }
+            // outlines take no room, and are drawn over whatever they overlap
+            if let Some(outline) = rbx.outline.as_ref().filter(|_| rbx.visible) {
+                make_border(&mut p.shapes, &outline.rect(rbx.content_area_as_rect()), &EdgeSizes { left: outline.width, right: outline.width, top: outline.width, bottom: outline.width },
+                            &BorderSides::all(Some(outline.color.clone())), &BorderSides::all(outline.style), opacity);
             }
         }
         RenderBox::Anonymous(bx) => {
+            for frag in bx.inline_fragments().iter().filter(|frag| frag.visible) {
+                let opacity = opacity * frag.opacity;
+                if let Some(color) = &frag.background_color {
+                    make_box(&mut p.shapes, &frag.rect, &color.with_opacity(opacity));
+                }
+                make_border(&mut p.shapes, &frag.rect, &frag.border_width, &frag.border_color, &frag.border_style, opacity);
+            }
             for lb in bx.children.iter() {
-                // make_box(shapes, &lb.rect, &Color::from_hex("#ff0000"));
+                // make_box(&mut p.shapes, &lb.rect, &Color::from_hex("#ff0000"));
+                // each element gets one underline per line, even across runs of different sizes
+                for (rect, text) in lb.underlines().into_iter().filter(|(_, text)| text.visible) {
+                    if let Some(color) = &text.color {
+                        make_line(&mut p.shapes, &rect, -text.font_size*0.1, &color.with_opacity(opacity * text.opacity));
+                    }
+                }
                 for inline in lb.children.iter() {
                     match inline {
+                        RenderInlineBoxType::Text(text) if !text.visible => {}
                         RenderInlineBoxType::Text(text) => {
                             if text.color.is_some() && !text.text.is_empty() {
-                                let color = text.color.as_ref().unwrap().clone();
-                                let scale = Scale::uniform(text.font_size* text_scale);
-                                let font = gb.lookup_font(&text.font_family, text.font_weight, &text.font_style);
-                                let section = Section {
-                                    text: &text.text.trim(),
-                                    scale,
-                                    font_id:*font,
-                                    screen_position: (text.rect.x* text_scale, text.rect.y* text_scale),
-                                    bounds: (text.rect.width* text_scale, text.rect.height* text_scale),
-                                    color: [
-                                        (color.r as f32)/255.0,
-                                        (color.g as f32)/255.0,
-                                        (color.b as f32)/255.0,
-                                        (color.a as f32)/255.0,
-                                    ],
-                                    ..Section::default()
+                                let color = text.color.as_ref().unwrap().with_opacity(opacity * text.opacity);
+                                let scale = Scale::uniform(text.font_size* p.text_scale);
+                                // with letter or word spacing each character is placed on its own
+                                let pieces:Vec<(String, f32)> = if text.spacing == TextSpacing::default() {
+                                    vec![(text.text.trim().to_string(), 0.0)]
+                                } else {
+                                    text.glyph_offsets(text.text.trim(), p.font_cache).into_iter()
+                                        .map(|(ch, offset)| (ch.to_string(), offset))
+                                        .collect()
                                 };
-                                gb.brush.queue(section);
-                                match text.text_decoration_line.as_str() {
-                                    "line-through" => make_line(shapes,&text.rect,-text.font_size*0.5,&color),
-                                    "underline" => make_line(shapes,&text.rect,-text.font_size*0.1,&color),
-                                    _ => {}
+                                let font = *p.font_cache.lookup_font(&text.font_family, text.font_weight, &text.font_style);
+                                let rendering = text_rendering();
+                                for (piece, offset) in pieces.iter() {
+                                    let section = Section {
+                                        text: piece,
+                                        scale,
+                                        font_id:font,
+                                        screen_position: (rendering.snap((text.rect.x + offset)* p.text_scale), rendering.snap(text.rect.y* p.text_scale)),
+                                        bounds: (text.rect.width* p.text_scale, text.rect.height* p.text_scale),
+                                        color: [
+                                            (color.r as f32)/255.0,
+                                            (color.g as f32)/255.0,
+                                            (color.b as f32)/255.0,
+                                            (color.a as f32)/255.0,
+                                        ],
+                                        ..Section::default()
+                                    };
+                                    p.queue_text(section);
                                 }
-                                // make_box(shapes, &text.rect, &Color::from_hex("#ff00ff"));
+                                if text.text_decoration_line == "line-through" {
+                                    make_line(&mut p.shapes,&text.rect,-text.font_size*0.5,&color);
+                                }
+                                // make_box(&mut p.shapes, &text.rect, &Color::from_hex("#ff00ff"));
                             }
-                            if text.background_color.is_some() {
-                                make_box(shapes, &text.rect, text.background_color.as_ref().unwrap());
+                            if text.background_color.is_some() && text.decorations.is_empty() {
+                                make_box(&mut p.shapes, &text.rect, &text.background_color.as_ref().unwrap().with_opacity(opacity * text.opacity));
                             }
                         }
+                        RenderInlineBoxType::Image(image) if !image.visible => {}
                         RenderInlineBoxType::Image(image) => {
-                            if !img.contains_key(&*image.image.path) {
+                            if !p.image_cache.contains_key(&*image.image.path) {
                                 println!("must install the image");
                                 let size = image.image.image2d.dimensions();
                                 let data = image.image.image2d.clone().into_raw();
                                 let tex_data:RawImage2d<u8> = RawImage2d::from_raw_rgba(data, size);
-                                let texture = glium::texture::Texture2d::new(display, tex_data).unwrap();
-                                img.insert(image.image.path.clone(),Rc::new(texture));
+                                let texture = glium::texture::Texture2d::new(p.display, tex_data).unwrap();
+                                p.image_cache.insert(image.image.path.clone(),Rc::new(texture));
                             }
-                            let tex_ref:&Rc<Texture2d> = img.get(image.image.path.as_str()).unwrap();
-                            make_image_box(images, &image.rect, &tex_ref);
-                            make_box(shapes, &image.rect, &Color::from_hex("#ff00ff"))
+                            let tex_ref:&Rc<Texture2d> = p.image_cache.get(image.image.path.as_str()).unwrap();
+                            make_image_box(&mut p.images, &image.rect, &tex_ref, image.rendering, opacity * image.opacity);
+                            make_box(&mut p.shapes, &image.rect, &Color::from_hex("#ff00ff"))
                         }
                         RenderInlineBoxType::Error(err) => {
-                            make_box(shapes, &err.rect, &Color::from_hex("#ff00ff"))
+                            make_box(&mut p.shapes, &err.rect, &Color::from_hex("#ff00ff"))
                         }
                         RenderInlineBoxType::Block(block) => {
-                            make_box(shapes, &block.rect, &Color::from_hex("#0000ff"))
+                            make_box(&mut p.shapes, &block.rect, &Color::from_hex("#0000ff"))
                         }
                     }
                 }
@@ -231,44 +421,7 @@ fn draw_render_box(bx:&RenderBox, gb:&mut FontCache, img:&mut HashMap<String, Rc
 }
 
 
-fn main() -> Result<(),BrowserError>{
-    let start_page = parse_args().unwrap();
-    println!("using the start page {}",start_page);
-
-    //make an event loop
-    let event_loop = glutin::event_loop::EventLoop::new();
-    //build the window
-    let window = glutin::window::WindowBuilder::new()
-        .with_title("some title")
-        .with_inner_size(glutin::dpi::LogicalSize::new(WIDTH, HEIGHT));
-    let context = glutin::ContextBuilder::new();
-    let display = glium::Display::new(window, context, &event_loop).unwrap();
-
-    //load a font
-    let mut font_cache =  FontCache {
-        brush: Brush::Style1(GlyphBrush::new(&display, vec![])),
-        families: Default::default(),
-        fonts: Default::default()
-    };
-    install_standard_fonts(&mut font_cache);
-
-    let start_page = parse_args().unwrap();
-    let screen_dims = display.get_framebuffer_dimensions();
-    let mut containing_block = Dimensions {
-        content: Rect {
-            x: 0.0,
-            y: 0.0,
-            width: screen_dims.0 as f32 / 2.0,
-            height: 0.0,
-        },
-        padding: Default::default(),
-        border: Default::default(),
-        margin: Default::default()
-    };
-    let (mut doc, mut render_root) = navigate_to_doc(&start_page, &mut font_cache, containing_block).unwrap();
-
-
-    let rect_vertex_shader_src = r#"
+const RECT_VERTEX_SHADER_SRC:&str = r#"
         #version 140
 
         in vec2 position;
@@ -282,7 +435,7 @@ fn main() -> Result<(),BrowserError>{
         }
     "#;
 
-    let rect_fragment_shader_src = r#"
+const RECT_FRAGMENT_SHADER_SRC:&str = r#"
         #version 140
 
         out vec4 color;
@@ -294,9 +447,7 @@ fn main() -> Result<(),BrowserError>{
         }
     "#;
 
-    let rect_program = glium::Program::from_source(&display, rect_vertex_shader_src, rect_fragment_shader_src, None).unwrap();
-
-    let tex_vertex_shader_src = r#"
+const TEX_VERTEX_SHADER_SRC:&str = r#"
         #version 140
 
         in vec2 position;
@@ -310,127 +461,421 @@ fn main() -> Result<(),BrowserError>{
             gl_Position = matrix * vec4(position, 0.0, 1.0);
         }
     "#;
-    let tex_fragment_shader_src = r#"
+const TEX_FRAGMENT_SHADER_SRC:&str = r#"
         #version 140
 
         in vec2 v_tex_coords;
         out vec4 color;
 
         uniform sampler2D tex;
+        uniform float opacity;
 
         void main() {
-            color = texture(tex, v_tex_coords);
+            color = texture(tex, v_tex_coords) * vec4(1.0, 1.0, 1.0, opacity);
         }
     "#;
-    let tex_program = glium::Program::from_source(&display, tex_vertex_shader_src, tex_fragment_shader_src, None).unwrap();
 
+// the window's cursor for a css cursor keyword
+fn cursor_icon(cursor:&str) -> CursorIcon {
+    match cursor {
+        "pointer" => CursorIcon::Hand,
+        "text" => CursorIcon::Text,
+        "vertical-text" => CursorIcon::VerticalText,
+        "wait" => CursorIcon::Wait,
+        "progress" => CursorIcon::Progress,
+        "help" => CursorIcon::Help,
+        "context-menu" => CursorIcon::ContextMenu,
+        "cell" => CursorIcon::Cell,
+        "crosshair" => CursorIcon::Crosshair,
+        "alias" => CursorIcon::Alias,
+        "copy" => CursorIcon::Copy,
+        "move" => CursorIcon::Move,
+        "no-drop" => CursorIcon::NoDrop,
+        "not-allowed" => CursorIcon::NotAllowed,
+        "grab" => CursorIcon::Grab,
+        "grabbing" => CursorIcon::Grabbing,
+        "all-scroll" => CursorIcon::AllScroll,
+        "col-resize" => CursorIcon::ColResize,
+        "row-resize" => CursorIcon::RowResize,
+        "n-resize" => CursorIcon::NResize,
+        "e-resize" => CursorIcon::EResize,
+        "s-resize" => CursorIcon::SResize,
+        "w-resize" => CursorIcon::WResize,
+        "ne-resize" => CursorIcon::NeResize,
+        "nw-resize" => CursorIcon::NwResize,
+        "se-resize" => CursorIcon::SeResize,
+        "sw-resize" => CursorIcon::SwResize,
+        "ew-resize" => CursorIcon::EwResize,
+        "ns-resize" => CursorIcon::NsResize,
+        "nesw-resize" => CursorIcon::NeswResize,
+        "nwse-resize" => CursorIcon::NwseResize,
+        "zoom-in" => CursorIcon::ZoomIn,
+        "zoom-out" => CursorIcon::ZoomOut,
+        _ => CursorIcon::Default,
+    }
+}
 
-    let mut yoff:f32 = 0.0;
-    let zero:f32 = 0.0;
-    let mut prev_w = screen_dims.0 as f32/2.0;
-    let mut prev_h = screen_dims.1 as f32/2.0;
-    let mut last_mouse:PhysicalPosition<f64> = PhysicalPosition{ x: 0.0, y: 0.0 };
-    let mut image_cache:HashMap<String,Rc<Texture2d>> = HashMap::new();
-    // main event loop
-    event_loop.run(move |event, _tgt, control_flow| {
-        *control_flow = ControlFlow::Wait;
+// what a window asks of the event loop after handling an event
+enum WindowCommand {
+    Nothing,
+    Close,
+    Open(Url),
+}
+
+// One window and the page in it. Windows share nothing but the network cache: each has
+// its own GL context, so its own font brush and image textures too.
+struct BrowserWindow {
+    display: Display,
+    browser: Browser,
+    rect_program: glium::Program,
+    tex_program: glium::Program,
+    image_cache: HashMap<String,Rc<Texture2d>>,
+    last_mouse: PhysicalPosition<f64>,
+    // the link under the mouse. all of its fragments highlight together.
+    hovered: Option<usize>,
+    // the css cursor showing over the page
+    cursor: String,
+    scheduler: FrameScheduler,
+    // what the font brush was built for
+    text_rendering: TextRendering,
+}
+
+impl BrowserWindow {
+    fn open(target:&EventLoopWindowTarget<()>, url:&Url, device:&Option<DeviceEmulation>) -> Result<BrowserWindow, BrowserError> {
+        //build the window. an emulated device gets a window with as many pixels as its screen.
+        let window = glutin::window::WindowBuilder::new().with_title("some title");
+        let window = match device {
+            Some(device) => window.with_inner_size(glutin::dpi::PhysicalSize::new(
+                (device.width * device.device_pixel_ratio) as u32,
+                (device.height * device.device_pixel_ratio) as u32)),
+            None => window.with_inner_size(glutin::dpi::LogicalSize::new(WIDTH, HEIGHT)),
+        };
+        let context = glutin::ContextBuilder::new().build_windowed(window, target).unwrap();
+        let display = Display::from_gl_window(context).unwrap();
+
+        //load a font
+        let mut font_cache =  FontCache {
+            brush: Brush::for_display(&display, text_rendering(), vec![]),
+            families: Default::default(),
+            fonts: Default::default()
+        };
+        install_standard_fonts(&mut font_cache)?;
+
+        let screen_dims = display.get_framebuffer_dimensions();
+        let containing_block = Dimensions {
+            content: Rect {
+                x: 0.0,
+                y: 0.0,
+                width: screen_dims.0 as f32 / 2.0,
+                height: screen_dims.1 as f32 / 2.0,
+            },
+            padding: Default::default(),
+            border: Default::default(),
+            margin: Default::default()
+        };
+        let mut browser = Browser::open_progressively(url, font_cache, containing_block)?;
+        browser.set_auto_reload(true)?;
+        if device.is_some() {
+            browser.emulate(device.clone())?;
+        }
+
+        let rect_program = glium::Program::from_source(&display, RECT_VERTEX_SHADER_SRC, RECT_FRAGMENT_SHADER_SRC, None).unwrap();
+        let tex_program = glium::Program::from_source(&display, TEX_VERTEX_SHADER_SRC, TEX_FRAGMENT_SHADER_SRC, None).unwrap();
+
+        // pace frames to the fastest refresh rate the monitor offers
+        let refresh_rate = display.gl_window().window().current_monitor().video_modes()
+            .map(|mode| mode.refresh_rate() as u32).max().unwrap_or(60);
+        let mut scheduler = FrameScheduler::new(refresh_rate);
+        scheduler.damage(Damage::Navigation);
+        Ok(BrowserWindow {
+            display,
+            browser,
+            rect_program,
+            tex_program,
+            image_cache: HashMap::new(),
+            last_mouse: PhysicalPosition{ x: 0.0, y: 0.0 },
+            hovered: None,
+            cursor: String::from("default"),
+            scheduler,
+            text_rendering: text_rendering(),
+        })
+    }
+
+    fn id(&self) -> WindowId {
+        self.display.gl_window().window().id()
+    }
+
+    // from window pixels to where that is on the laid out page
+    fn page_position(&self, position:PhysicalPosition<f64>) -> (f32, f32) {
+        let scale = 2.0 * self.browser.zoom();
+        ((position.x as f32) / scale, (position.y as f32) / scale + self.browser.scroll_y())
+    }
+
+    fn handle_event(&mut self, event:WindowEvent, modifiers:ModifiersState) -> WindowCommand {
         match event {
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::KeyboardInput {
-                    input:
-                    KeyboardInput {
-                        virtual_keycode: Some(VirtualKeyCode::Escape),
-                        ..
-                    },
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    virtual_keycode: Some(VirtualKeyCode::Escape),
                     ..
-                }
-                | WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                WindowEvent::MouseWheel {
-                    delta,
+                },
+                ..
+            }
+            | WindowEvent::CloseRequested => return WindowCommand::Close,
+            // ctrl-n, or cmd-n on mac, opens the current page in a new window
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    virtual_keycode: Some(VirtualKeyCode::N),
+                    state: ElementState::Pressed,
                     ..
-                } => {
-                    match delta {
-                        LineDelta(_x, y) => yoff = zero.max(yoff - y * 30.0),
-                        PixelDelta(lp) => yoff = zero.max( yoff - lp.y as f32),
-                    }
                 },
+                ..
+            } if modifiers.ctrl() || modifiers.logo() => return WindowCommand::Open(self.browser.url.clone()),
+            WindowEvent::MouseWheel {
+                delta,
+                ..
+            } => {
+                // the box under the mouse scrolls first, then the page
+                let (x, y) = self.page_position(self.last_mouse);
+                match delta {
+                    LineDelta(_x, dy) => self.browser.scroll_at(x, y, -dy * 30.0),
+                    PixelDelta(lp) => self.browser.scroll_at(x, y, -lp.y as f32),
+                }
+                self.scheduler.damage(Damage::Input);
+            },
 
-                WindowEvent::CursorMoved {
-                    device_id, position, modifiers
-                } => {
-                    last_mouse = position;
+            WindowEvent::CursorMoved {
+                position, ..
+            } => {
+                self.last_mouse = position;
+                let (x, y) = self.page_position(position);
+                // restyling for :hover lays the page out again, so do it before looking for links
+                match self.browser.hover_at(x, y) {
+                    Ok(true) => self.scheduler.damage(Damage::Input),
+                    Ok(false) => {}
+                    Err(err) => println!("couldn't restyle for hover {:#?}", err),
                 }
-                WindowEvent::MouseInput {
-                    device_id, state, button, modifiers
-                } => {
-                    // println!("mouse click {:#?}", button);
-                    if let ElementState::Pressed = state {
-                        if let MouseButton::Left = button {
-                            let res = render_root.find_box_containing((last_mouse.x / 2.0) as f32, (last_mouse.y / 2.0) as f32);
-                            if let QueryResult::Text(bx) = res {
-                                if let Some(href) = &bx.link {
-                                    println!("following the link {:#?}", href);
-                                    let url = calculate_url_from_doc(&doc, href).unwrap();
-                                    let res = navigate_to_doc(&url, &mut font_cache, containing_block).unwrap();
-                                    doc = res.0;
-                                    render_root = res.1;
-                                }
-                            }
+                let render_root = &self.browser.render_root;
+                let cursor = render_root.find_box_on_page(x, y, self.browser.scroll_y()).cursor().to_string();
+                if cursor != self.cursor {
+                    let window = self.display.gl_window();
+                    window.window().set_cursor_visible(cursor != "none");
+                    window.window().set_cursor_icon(cursor_icon(&cursor));
+                    self.cursor = cursor;
+                }
+                let over = render_root.find_element_containing(x, y, self.browser.scroll_y());
+                let over_link = over.filter(|el| render_root.fragments_of(*el).iter().any(|frag| frag.link.is_some()));
+                if over_link != self.hovered {
+                    self.hovered = over_link;
+                    self.scheduler.damage(Damage::Input);
+                }
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let (x, y) = self.page_position(self.last_mouse);
+                match self.browser.press_at(x, y) {
+                    Ok(true) => self.scheduler.damage(Damage::Input),
+                    Ok(false) => {}
+                    Err(err) => println!("couldn't restyle for the press {:#?}", err),
+                }
+                let res = self.browser.render_root.find_box_on_page(x, y, self.browser.scroll_y());
+                let link = match res {
+                    QueryResult::Text(bx) => bx.link.clone().map(|href| (href, bx.opens_new_window())),
+                    _ => None,
+                };
+                if let Some((href, new_window)) = link {
+                    println!("following the link {:#?}", href);
+                    match self.browser.follow_link(&href, new_window) {
+                        Ok(LinkAction::OpenWindow(url)) => return WindowCommand::Open(url),
+                        Ok(LinkAction::Navigated) => {
+                            self.hovered = None;
+                            self.scheduler.damage(Damage::Navigation);
                         }
+                        Err(err) => println!("couldn't follow the link {:#?}", err),
                     }
                 }
-                _ => (),
-            },
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button: MouseButton::Left,
+                ..
+            } => {
+                match self.browser.release() {
+                    Ok(true) => self.scheduler.damage(Damage::Input),
+                    Ok(false) => {}
+                    Err(err) => println!("couldn't restyle for the release {:#?}", err),
+                }
+            }
+            WindowEvent::Resized(_) => {
+                let screen_dims = self.display.get_framebuffer_dimensions();
+                self.browser.resize(screen_dims.0 as f32/2.0, screen_dims.1 as f32/2.0).unwrap();
+                self.hovered = None;
+                self.scheduler.damage(Damage::Resize);
+            }
             _ => (),
         }
-        let screen_dims = display.get_framebuffer_dimensions();
-        let new_w = screen_dims.0 as f32/2.0;
-        let new_h = screen_dims.1 as f32/2.0;
-        if prev_w != new_w || prev_h != new_h {
-            containing_block.content.width = new_w;
-            let (doc2, render_root2) = navigate_to_doc(&start_page, &mut font_cache, containing_block).unwrap();
-            doc = doc2;
-            render_root = render_root2;
-        }
-        prev_w = new_w;
-        prev_h = new_h;
-
-        let mut shape:Vec<Vertex> = Vec::new();
-        let mut images:Vec<ImageRect> = Vec::new();
+        WindowCommand::Nothing
+    }
 
-        draw_render_box(&render_root, &mut font_cache, &mut image_cache,
-                        new_w, new_h, &mut shape,  &mut images,2.0, &display);
-        let mut target = display.draw();
+    fn draw(&mut self, now:Instant) {
+        let (w,h) = self.display.get_framebuffer_dimensions();
+        let w = w as f32;
+        let h = h as f32;
+        let zoom = self.browser.zoom();
+        // the glyph cache and its draw parameters are fixed when a brush is built, so a change
+        // in how text renders needs a new one. it gets the same fonts under the same ids.
+        let rendering = text_rendering();
+        if rendering != self.text_rendering {
+            let font_cache = &mut self.browser.font_cache;
+            font_cache.brush = Brush::for_display(&self.display, rendering, font_cache.brush.fonts());
+            self.text_rendering = rendering;
+        }
+        let mut target = self.display.draw();
         target.clear_color(1.0, 1.0, 1.0, 1.0);
 
-        let vertex_buffer = glium::VertexBuffer::new(&display, &shape).unwrap();
-        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+        // the page is drawn scrolled, with the content of scrolling boxes clipped to them in
+        // between, then the fixed boxes over it all without any scroll
+        let page_scroll = self.browser.scroll_y();
+        let render_root = &self.browser.render_root;
+        let mut painter = Painter {
+            target: &mut target,
+            display: &self.display,
+            rect_program: &self.rect_program,
+            tex_program: &self.tex_program,
+            font_cache: &mut self.browser.font_cache,
+            image_cache: &mut self.image_cache,
+            width: w,
+            height: h,
+            zoom,
+            text_scale: 2.0*zoom,
+            shapes: vec![],
+            images: vec![],
+            yoff: page_scroll,
+            clip: None,
+        };
+        draw_render_box(render_root, &mut painter, 1.0);
+        if let Some(element) = self.hovered {
+            for frag in render_root.fragments_of(element) {
+                if let Some(color) = &frag.color {
+                    make_line(&mut painter.shapes, &frag.rect, -frag.font_size*0.1, color);
+                }
+            }
+        }
+        painter.flush();
+        painter.yoff = 0.0;
+        for bx in render_root.fixed_boxes() {
+            draw_render_box(bx, &mut painter, 1.0);
+        }
+        painter.flush();
+        target.finish().unwrap();
+        self.scheduler.frame_drawn(now);
+    }
+}
 
-        let (w,h) = display.get_framebuffer_dimensions();
-        let w = w as f32;
-        let h = h as f32;
+fn main() -> Result<(),BrowserError>{
+    let start_page = parse_args().unwrap();
+    println!("using the start page {}",start_page);
+    let device = parse_device_arg();
+    set_color_scheme(parse_color_scheme_arg().unwrap_or_else(ColorScheme::from_environment));
+    let mut network = NetworkConfig::default();
+    if std::path::Path::new(PROXY_CONFIG).exists() {
+        network.proxy = ProxyConfig::from_config_file(PROXY_CONFIG)?;
+    }
+    if let Some(device) = &device {
+        println!("emulating {:?}", device);
+        network.user_agent = Some(device.user_agent.clone());
+    }
+    set_network_config(network)?;
 
-        let box_translate = Matrix4::from_translation(Vector3{x: - 1.0, y:yoff/h + 1.0, z:0.0});
-        let box_scale = Matrix4::from_nonuniform_scale(2.0*2.0/w,-2.0*2.0/h,1.0);
-        let box_trans: [[f32; 4]; 4] = (box_translate * box_scale).into();
-        let uniforms = uniform! { matrix: box_trans  };
-        target.draw(&vertex_buffer, &indices, &rect_program, &uniforms, &Default::default()).unwrap();
+    //make an event loop
+    let event_loop = glutin::event_loop::EventLoop::new();
+    let first = BrowserWindow::open(&event_loop, &start_page, &device)?;
+    let mut windows:HashMap<WindowId, BrowserWindow> = HashMap::new();
+    windows.insert(first.id(), first);
+    let mut modifiers = ModifiersState::default();
 
-        for image in images {
-            let tex:&Texture2d = &image.texture;
-            let image_uniforms = uniform! { matrix: box_trans, tex: tex };
-            let img_vertex_buffer = glium::VertexBuffer::new(&display, &image.vertices).unwrap();
-            target.draw(&img_vertex_buffer, &indices, &tex_program, &image_uniforms, &Default::default()).unwrap();
+    // main event loop
+    event_loop.run(move |event, target, control_flow| {
+        match event {
+            Event::WindowEvent { event, window_id } => {
+                let command = match windows.get_mut(&window_id) {
+                    Some(window) => window.handle_event(event, modifiers),
+                    None => WindowCommand::Nothing,
+                };
+                match command {
+                    WindowCommand::Nothing => {},
+                    WindowCommand::Close => {
+                        windows.remove(&window_id);
+                    },
+                    WindowCommand::Open(url) => match BrowserWindow::open(target, &url, &device) {
+                        Ok(window) => {
+                            windows.insert(window.id(), window);
+                        },
+                        Err(err) => println!("couldn't open a window for {} {:#?}", url, err),
+                    },
+                }
+            },
+            Event::DeviceEvent { event: DeviceEvent::ModifiersChanged(state), .. } => modifiers = state,
+            Event::RedrawRequested(window_id) => {
+                if let Some(window) = windows.get_mut(&window_id) {
+                    window.scheduler.damage(Damage::Expose);
+                }
+            },
+            _ => (),
+        }
+        if windows.is_empty() {
+            *control_flow = ControlFlow::Exit;
+            return;
         }
 
-        //draw fonts
-        let scale = Matrix4::from_nonuniform_scale(2.0/w,  2.0/h, 1.0);
-        let translate = Matrix4::from_translation(Vector3{ x: -1.0,  y: -1.0 - yoff/h,  z:0.0 });
-        let transform: [[f32; 4]; 4] = (translate * scale).into();
-        font_cache.brush.draw_queued_with_transform(transform, &display, &mut target);
-        target.finish().unwrap();
+        let now = Instant::now();
+        let mut loading = false;
+        let mut watching = false;
+        for window in windows.values_mut() {
+            match window.browser.check_for_changes() {
+                Ok(true) => window.scheduler.damage(Damage::Navigation),
+                Ok(false) => {},
+                Err(err) => println!("couldn't reload the page {:#?}", err),
+            }
+            watching |= window.browser.is_watching();
+            if window.browser.is_loading() {
+                match window.browser.pump_load(now) {
+                    Ok(true) => {
+                        window.hovered = None;
+                        window.scheduler.damage(Damage::Navigation);
+                    },
+                    Ok(false) => {},
+                    Err(err) => println!("couldn't load the page {:#?}", err),
+                }
+                loading |= window.browser.is_loading();
+            }
+            if window.scheduler.should_draw(now) {
+                window.draw(now);
+            }
+        }
+        *control_flow = next_control_flow(windows.values().map(|window| &window.scheduler), now);
+        // keep checking on pages that are still downloading, or whose files might change
+        if loading || watching {
+            let check = now + if loading { PARTIAL_LAYOUT_INTERVAL / 2 } else { FILE_CHECK_INTERVAL };
+            *control_flow = match *control_flow {
+                ControlFlow::WaitUntil(at) if at < check => ControlFlow::WaitUntil(at),
+                _ => ControlFlow::WaitUntil(check),
+            };
+        }
     })
 }
+
+// sleep until some window's scheduler wants the next frame, or until the next event if none do
+fn next_control_flow<'a>(schedulers:impl Iterator<Item=&'a FrameScheduler>, now:Instant) -> ControlFlow {
+    match schedulers.filter_map(|scheduler| scheduler.next_wake(now)).min() {
+        Some(at) => ControlFlow::WaitUntil(at),
+        None => ControlFlow::Wait,
+    }
+}
 /*
         let right_down = window.get_mouse_down(MouseButton::Right);
         if right_down && !prev_right_down {
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use url::Url;
+
+/// What was loaded from each url, held to a budget of `capacity` bytes. When something
+/// new doesn't fit, the entries used longest ago are dropped to make room.
+pub struct LruCache<V> {
+    capacity: usize,
+    size: usize,
+    clock: u64,
+    // the value, its size in bytes and when it was last used
+    entries: HashMap<Url, (V, usize, u64)>,
+}
+
+impl<V> LruCache<V> {
+    pub fn new(capacity:usize) -> Self {
+        LruCache { capacity, size: 0, clock: 0, entries: HashMap::new() }
+    }
+
+    pub fn get(&mut self, url:&Url) -> Option<&V> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(url).map(|(value, _, used)| {
+            *used = clock;
+            &*value
+        })
+    }
+
+    pub fn contains_key(&self, url:&Url) -> bool {
+        self.entries.contains_key(url)
+    }
+
+    // anything bigger than the whole budget isn't kept at all
+    pub fn insert(&mut self, url:Url, value:V, size:usize) {
+        self.remove(&url);
+        if size > self.capacity {
+            return;
+        }
+        while self.size + size > self.capacity {
+            let oldest = self.entries.iter().min_by_key(|(_, (_, _, used))| *used).map(|(url, _)| url.clone());
+            match oldest {
+                Some(oldest) => self.remove(&oldest),
+                None => break,
+            }
+        }
+        self.clock += 1;
+        self.size += size;
+        self.entries.insert(url, (value, size, self.clock));
+    }
+
+    fn remove(&mut self, url:&Url) {
+        if let Some((_, size, _)) = self.entries.remove(url) {
+            self.size -= size;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.size = 0;
+    }
+}
+
+#[test]
+fn test_lru_cache() {
+    let url = |name:&str| Url::parse("https://example.com/").unwrap().join(name).unwrap();
+    let mut cache = LruCache::new(10);
+    cache.insert(url("a"), "a", 4);
+    cache.insert(url("b"), "b", 4);
+    // using a makes b the oldest, so b goes to make room for c
+    assert_eq!(cache.get(&url("a")), Some(&"a"));
+    cache.insert(url("c"), "c", 4);
+    assert!(cache.contains_key(&url("a")));
+    assert!(!cache.contains_key(&url("b")));
+    assert!(cache.contains_key(&url("c")));
+
+    // replacing an entry counts only its new size
+    cache.insert(url("c"), "c", 6);
+    assert!(cache.contains_key(&url("a")));
+    assert_eq!(cache.size, 10);
+
+    // too big to keep, and nothing else is dropped for it
+    cache.insert(url("d"), "d", 11);
+    assert!(!cache.contains_key(&url("d")));
+    assert_eq!(cache.size, 10);
+
+    cache.clear();
+    assert_eq!(cache.get(&url("a")), None);
+    assert_eq!(cache.size, 0);
+}
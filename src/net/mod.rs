@@ -1,9 +1,9 @@
-use crate::dom::{load_doc_from_buffer, getElementsByTagName, NodeType, Document, load_doc};
-use crate::css::{parse_stylesheet, Stylesheet, parse_stylesheet_from_buffer, RuleType, Value, parse_stylesheet_from_bytestring};
-use crate::style::{dom_tree_to_stylednodes, expand_styles};
+use crate::dom::{load_doc_from_buffer, getElementsByTagName, NodeType, Document, load_doc, load_doc_from_bytestring, IncrementalParser};
+use crate::css::{parse_stylesheet, Stylesheet, parse_stylesheet_from_buffer, RuleType, Value, parse_stylesheet_from_bytestring, MediaRule, MediaQuery};
+use crate::style::{dom_tree_to_stylednodes, expand_styles, apply_supports_rules, MediaEnvironment};
 use crate::image::{load_image_from_buffer, LoadedImage, load_image_from_filepath};
 use image::ImageError;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::env::current_dir;
 use std::io::{Error, Read};
 use url::{Url, ParseError};
@@ -12,6 +12,21 @@ use glium_glyph::glyph_brush::rusttype::{Font};
 use crate::dom::NodeType::Element;
 use glium_glyph::glyph_brush;
 use crate::render::FontCache;
+use std::sync::RwLock;
+use std::time::Duration;
+use std::collections::HashSet;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::mem;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+use reqwest::blocking::{Client, ClientBuilder, Response};
+use reqwest::Proxy;
+use policy::{security_policy, host_matches};
+use cache::LruCache;
+
+pub mod policy;
+mod cache;
 
 #[derive(Debug)]
 pub enum BrowserError {
@@ -19,6 +34,13 @@ pub enum BrowserError {
     DiskError(std::io::Error),
     UrlError(ParseError),
     ImageError(ImageError),
+    Blocked(Url),
+    ParseError(String),
+    NoSuchElement(String),
+    // the server's certificate didn't validate. the url and what was wrong with it.
+    Tls(Url, String),
+    // not downloaded yet, and not worth waiting for while showing a partial page
+    Deferred(Url),
 }
 impl From<std::io::Error> for BrowserError {
     fn from(err: Error) -> Self {
@@ -39,8 +61,8 @@ impl From<ImageError> for BrowserError {
     fn from(err: ImageError) -> Self { BrowserError::ImageError(err) }
 }
 impl From<pom::Error> for BrowserError {
-    fn from(_: pom::Error) -> Self {
-        unimplemented!()
+    fn from(err: pom::Error) -> Self {
+        BrowserError::ParseError(err.to_string())
     }
 }
 impl From<()> for BrowserError {
@@ -51,6 +73,287 @@ impl From<glium_glyph::glyph_brush::rusttype::Error> for BrowserError {
 }
 
 
+pub enum FilterAction {
+    Allow,
+    Block,
+    Rewrite(Url),
+}
+
+type FilterCallback = Box<dyn Fn(&Url) -> FilterAction + Send + Sync>;
+
+// decides which subresource loads (images, stylesheets, fonts) are allowed.
+// patterns are plain substrings of the url, ex: "doubleclick.net" or "/ads/"
+pub struct RequestFilter {
+    pub blocked_patterns: Vec<String>,
+    pub callback: Option<FilterCallback>,
+}
+
+impl Default for RequestFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestFilter {
+    pub fn new() -> Self {
+        RequestFilter {
+            blocked_patterns: vec![],
+            callback: None,
+        }
+    }
+    pub fn check(&self, url:&Url) -> FilterAction {
+        if self.blocked_patterns.iter().any(|pat| url.as_str().contains(pat.as_str())) {
+            return FilterAction::Block;
+        }
+        match &self.callback {
+            Some(cb) => cb(url),
+            None => FilterAction::Allow,
+        }
+    }
+}
+
+lazy_static! {
+    static ref REQUEST_FILTER: RwLock<RequestFilter> = RwLock::new(RequestFilter::new());
+}
+
+pub fn set_request_filter(filter:RequestFilter) {
+    *REQUEST_FILTER.write().unwrap() = filter;
+}
+
+//returns the url to actually load, or a Blocked error. page is the document asking
+//for it, or None when the browser loads its own resources.
+pub fn filter_subresource(page:Option<&Url>, url:&Url) -> Result<Url, BrowserError> {
+    let url = match REQUEST_FILTER.read().unwrap().check(url) {
+        FilterAction::Allow => url.clone(),
+        FilterAction::Block => {
+            println!("blocked subresource {}", url);
+            return Err(BrowserError::Blocked(url.clone()));
+        }
+        FilterAction::Rewrite(new_url) => new_url,
+    };
+    if let Err(reason) = security_policy().check_subresource(page, &url) {
+        println!("blocked subresource {}: {}", url, reason);
+        return Err(BrowserError::Blocked(url));
+    }
+    Ok(url)
+}
+
+/// Whether the security policy lets a page, or the user when `from` is None, open `to`.
+pub fn check_navigation(from:Option<&Url>, to:&Url) -> Result<(), BrowserError> {
+    security_policy().check_navigation(from, to).map_err(|reason| {
+        println!("blocked navigation to {}: {}", to, reason);
+        BrowserError::Blocked(to.clone())
+    })
+}
+
+/// Which proxy each request goes through. Hosts in the no-proxy list are fetched directly,
+/// ex: "localhost", ".internal.example.com", or "*" for everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxyConfig {
+    pub http: Option<Url>,
+    pub https: Option<Url>,
+    pub no_proxy: Vec<String>,
+}
+
+fn env_var(names:&[&str]) -> Option<String> {
+    names.iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+}
+
+fn split_no_proxy(list:&str) -> Vec<String> {
+    list.split(',')
+        .map(|host| host.trim().to_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect()
+}
+
+impl ProxyConfig {
+    /// From HTTP_PROXY, HTTPS_PROXY and NO_PROXY, or their lowercase forms.
+    pub fn from_env() -> ProxyConfig {
+        ProxyConfig {
+            http: env_var(&["HTTP_PROXY", "http_proxy"]).and_then(|u| Url::parse(&u).ok()),
+            https: env_var(&["HTTPS_PROXY", "https_proxy"]).and_then(|u| Url::parse(&u).ok()),
+            no_proxy: env_var(&["NO_PROXY", "no_proxy"]).map(|l| split_no_proxy(&l)).unwrap_or_default(),
+        }
+    }
+    /// Reads `http_proxy`, `https_proxy` and `no_proxy` from a JSON config file. Settings
+    /// missing from the file fall back to the environment.
+    pub fn from_config_file(path:&str) -> Result<ProxyConfig, BrowserError> {
+        let mut file = File::open(path)?;
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
+        let json:serde_json::Value = serde_json::from_str(&text)
+            .map_err(|e| BrowserError::ParseError(e.to_string()))?;
+        let mut config = ProxyConfig::from_env();
+        let proxy_url = |key:&str| -> Result<Option<Url>, BrowserError> {
+            match json.get(key).and_then(|v| v.as_str()) {
+                Some(url) => Ok(Some(Url::parse(url)?)),
+                None => Ok(None),
+            }
+        };
+        if let Some(url) = proxy_url("http_proxy")? {
+            config.http = Some(url);
+        }
+        if let Some(url) = proxy_url("https_proxy")? {
+            config.https = Some(url);
+        }
+        match json.get("no_proxy") {
+            Some(serde_json::Value::String(list)) => config.no_proxy = split_no_proxy(list),
+            Some(serde_json::Value::Array(hosts)) => {
+                config.no_proxy = hosts.iter()
+                    .filter_map(|h| h.as_str())
+                    .flat_map(split_no_proxy)
+                    .collect();
+            }
+            _ => {}
+        }
+        Ok(config)
+    }
+    pub fn bypasses(&self, url:&Url) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host.to_lowercase(),
+            None => return true,
+        };
+        self.no_proxy.iter().any(|entry| entry == "*" || host_matches(&host, entry))
+    }
+    pub fn proxy_for(&self, url:&Url) -> Option<Url> {
+        if self.bypasses(url) {
+            return None;
+        }
+        match url.scheme() {
+            "http" => self.http.clone(),
+            "https" => self.https.clone(),
+            _ => None,
+        }
+    }
+    fn apply(&self, builder:ClientBuilder) -> ClientBuilder {
+        // turn off reqwest's own environment lookup so the no-proxy list is always ours
+        let builder = builder.no_proxy();
+        if self.http.is_none() && self.https.is_none() {
+            return builder;
+        }
+        let config = self.clone();
+        builder.proxy(Proxy::custom(move |url| config.proxy_for(url)))
+    }
+}
+
+/// Settings for the HTTP clients shared by every request. Connections are kept open and
+/// pooled per host, so a page's subresources reuse them instead of handshaking again.
+/// HTTPS connections use HTTP/2 when the server offers it during the TLS handshake.
+pub struct NetworkConfig {
+    pub max_idle_per_host: usize,
+    pub idle_timeout: Duration,
+    pub proxy: ProxyConfig,
+    // sent instead of reqwest's default, ex: a phone's when emulating one
+    pub user_agent: Option<String>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            max_idle_per_host: 8,
+            idle_timeout: Duration::from_secs(90),
+            proxy: ProxyConfig::from_env(),
+            user_agent: None,
+        }
+    }
+}
+
+impl NetworkConfig {
+    fn builder(&self) -> ClientBuilder {
+        // rustls asks the server for h2 or http/1.1 over ALPN, native-tls doesn't ask at all
+        let mut builder = self.proxy.apply(Client::builder().use_rustls_tls());
+        if let Some(ua) = &self.user_agent {
+            builder = builder.user_agent(ua.as_str());
+        }
+        builder
+    }
+    fn client(&self) -> Result<Client, BrowserError> {
+        Ok(self.builder()
+            .pool_max_idle_per_host(self.max_idle_per_host)
+            .pool_idle_timeout(self.idle_timeout)
+            .build()?)
+    }
+}
+
+struct HttpClients {
+    client: Client,
+    // for hosts the user chose to trust despite certificate errors
+    insecure: Client,
+}
+
+impl HttpClients {
+    fn new(config:NetworkConfig) -> Result<HttpClients, BrowserError> {
+        Ok(HttpClients {
+            client: config.client()?,
+            insecure: config.builder().danger_accept_invalid_certs(true).build()?,
+        })
+    }
+}
+
+lazy_static! {
+    static ref HTTP_CLIENTS: RwLock<Option<HttpClients>> = RwLock::new(None);
+    static ref TLS_OVERRIDES: RwLock<HashSet<String>> = RwLock::new(HashSet::new());
+}
+
+/// Proceed anyway: stop checking certificates for this host, for the rest of the session.
+pub fn allow_invalid_certificates(host:&str) {
+    TLS_OVERRIDES.write().unwrap().insert(String::from(host));
+}
+
+pub fn allows_invalid_certificates(host:&str) -> bool {
+    TLS_OVERRIDES.read().unwrap().contains(host)
+}
+
+/// Replace the shared clients. Open connections in the old pools are dropped.
+pub fn set_network_config(config:NetworkConfig) -> Result<(), BrowserError> {
+    *HTTP_CLIENTS.write().unwrap() = Some(HttpClients::new(config)?);
+    Ok(())
+}
+
+fn client_for(url:&Url) -> Result<Client, BrowserError> {
+    if HTTP_CLIENTS.read().unwrap().is_none() {
+        let mut clients = HTTP_CLIENTS.write().unwrap();
+        if clients.is_none() {
+            *clients = Some(HttpClients::new(NetworkConfig::default())?);
+        }
+    }
+    let clients = HTTP_CLIENTS.read().unwrap();
+    let clients = clients.as_ref().unwrap();
+    // clients are reference counted, so the clone shares the connection pool
+    Ok(if url.host_str().is_some_and(allows_invalid_certificates) {
+        clients.insecure.clone()
+    } else {
+        clients.client.clone()
+    })
+}
+
+fn http_get(url:&Url) -> Result<Response, BrowserError> {
+    client_for(url)?.get(url.as_str()).send().map_err(|err| {
+        match certificate_error(&err) {
+            Some(details) => BrowserError::Tls(url.clone(), details),
+            None => BrowserError::NetworkError(err),
+        }
+    })
+}
+
+// The TLS backend's errors only come through as the sources of the request error, so look
+// for one that talks about certificates and report the most specific.
+fn certificate_error(err:&(dyn std::error::Error + 'static)) -> Option<String> {
+    let mut found = None;
+    let mut current = Some(err);
+    while let Some(err) = current {
+        let text = err.to_string();
+        let lower = text.to_lowercase();
+        if lower.contains("certificate") || lower.contains("ssl") || lower.contains("tls") {
+            found = Some(text);
+        }
+        current = err.source();
+    }
+    found
+}
+
 pub fn calculate_url_from_doc(doc:&Document, href:&str) -> Result<Url,BrowserError>{
     Ok(doc.base_url.join(href)?)
 }
@@ -58,12 +361,21 @@ pub fn calculate_url_from_doc(doc:&Document, href:&str) -> Result<Url,BrowserErr
 #[derive(Debug)]
 pub struct StylesheetSet {
     pub stylesheets:Vec<Stylesheet>,
+    // what the @media rules in the stylesheets are checked against
+    pub media:MediaEnvironment,
+    /// how many of the stylesheets, from the start, are the browser's own. the rest are the page's.
+    pub user_agent_count:usize,
+    /// how many of the stylesheets after the browser's are the user's, which go before the page's
+    pub user_count:usize,
 }
 
 impl StylesheetSet {
     pub fn new() -> Self {
         StylesheetSet {
-            stylesheets: vec![]
+            stylesheets: vec![],
+            media: Default::default(),
+            user_agent_count: 0,
+            user_count: 0,
         }
     }
     pub fn append(&mut self, stylesheet:Stylesheet) {
@@ -71,46 +383,116 @@ impl StylesheetSet {
     }
     pub fn append_from_bytestring(&mut self, font_cache:&mut FontCache, css_text:&[u8]) -> Result<(),BrowserError> {
         let ss = parse_stylesheet_from_bytestring(css_text)?;
-        process_stylesheet(self,font_cache,ss)
-    }
-}
-
-fn process_stylesheet(set:&mut StylesheetSet, font_cache:&mut FontCache, stylesheet:Stylesheet) -> Result<(), BrowserError> {
-    //scan for imports
-    for rule in stylesheet.rules.iter() {
-        if let RuleType::AtRule(ar) = rule {
-            if ar.name == "import" {
-                println!("got an import ");
-                if let Some(Value::FunCall(fcv)) = &ar.value {
-                    println!("the url is {:#?}", fcv.arguments);
-                    if let Value::StringLiteral(str) = &fcv.arguments[0] {
-                        let url = Url::parse(str).unwrap();
-                        println!("parsing the imported stylesheet {:#?}", url);
-                        load_stylesheet_2(set, font_cache,&url)?;
-                    }
+        process_stylesheet(self,font_cache,None,ss)
+    }
+}
+
+// how deeply @import rules may nest before the rest are ignored
+const MAX_IMPORT_DEPTH:usize = 8;
+
+// the url and any media queries of each @import in the stylesheet
+fn stylesheet_imports(stylesheet:&Stylesheet) -> Vec<(Url, Option<Vec<MediaQuery>>)> {
+    let import_url = |rule:&RuleType| match rule {
+        RuleType::AtRule(ar) if ar.name == "import" => match &ar.value {
+            Some(Value::FunCall(fcv)) => match fcv.arguments.first() {
+                Some(Value::StringLiteral(href)) => stylesheet.base_url.join(href.trim()).ok(),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    };
+    stylesheet.rules.iter().filter_map(|rule| match rule {
+        RuleType::Media(block) => block.rules.first()
+            .and_then(import_url)
+            .map(|url| (url, Some(block.queries.clone()))),
+        rule => import_url(rule).map(|url| (url, None)),
+    }).collect()
+}
+
+fn process_stylesheet(set:&mut StylesheetSet, font_cache:&mut FontCache, page:Option<&Url>, stylesheet:Stylesheet) -> Result<(), BrowserError> {
+    process_stylesheet_with_imports(set, font_cache, page, stylesheet, &mut vec![])
+}
+
+// imported stylesheets go in first, so the importing sheet's own rules come after them in
+// the cascade. `importers` are the sheets above this one, to stop import cycles.
+fn process_stylesheet_with_imports(set:&mut StylesheetSet, font_cache:&mut FontCache, page:Option<&Url>,
+                                   stylesheet:Stylesheet, importers:&mut Vec<Url>) -> Result<(), BrowserError> {
+    importers.push(stylesheet.base_url.clone());
+    for (url, queries) in stylesheet_imports(&stylesheet) {
+        if importers.contains(&url) || importers.len() > MAX_IMPORT_DEPTH {
+            println!("not importing {} again", url);
+            continue;
+        }
+        let imported = filter_subresource(page, &url).and_then(|url| load_stylesheet_from_net(&url));
+        match imported {
+            Ok(mut imported) => {
+                if let Some(queries) = queries {
+                    let rules = mem::take(&mut imported.rules);
+                    imported.rules = vec![RuleType::Media(MediaRule { queries, rules })];
                 }
+                process_stylesheet_with_imports(set, font_cache, page, imported, importers)?;
             }
+            // like a missing linked stylesheet, this shouldn't stop the page from loading
+            Err(err) => println!("couldn't import the stylesheet {} {:#?}", url, err),
         }
     }
-    //expand the styles
+    importers.pop();
+    //drop or splice in @supports blocks, then expand the styles
     let mut ss = stylesheet;
+    apply_supports_rules(&mut ss);
     expand_styles(&mut ss);
     //scan for font face
-    font_cache.scan_for_fontface_rules(&ss);
+    font_cache.scan_for_fontface_rules(&ss, page);
     set.append(ss);
     Ok(())
 }
-fn load_stylesheet_2(set:&mut StylesheetSet, font_cache:&mut FontCache, url:&Url) -> Result<(), BrowserError> {
-    process_stylesheet(set,font_cache,load_stylesheet_from_net(url)?)
+fn load_stylesheet_2(set:&mut StylesheetSet, font_cache:&mut FontCache, page:Option<&Url>, url:&Url) -> Result<(), BrowserError> {
+    let url = filter_subresource(page, url)?;
+    process_stylesheet(set,font_cache,page,load_stylesheet_from_net(&url)?)
+}
+fn parse_stylesheet_2_from_text(set:&mut StylesheetSet, font_cache:&mut FontCache, page:Option<&Url>, text:&String) -> Result<(),BrowserError> {
+    let mut ss = parse_stylesheet(text)?;
+    // imports in a style element are relative to the page
+    if let Some(page) = page {
+        ss.base_url = page.clone();
+    }
+    process_stylesheet(set,font_cache,page,ss)
 }
-fn parse_stylesheet_2_from_text(set:&mut StylesheetSet, font_cache:&mut FontCache, text:&String) -> Result<(),BrowserError> {
-    process_stylesheet(set,font_cache,parse_stylesheet(text)?)
+
+// the user agent stylesheet, compiled in so pages get sensible defaults wherever the binary runs
+const HTML_CSS:&[u8] = include_bytes!("../../res/html.css");
+
+fn load_user_agent_stylesheet(set:&mut StylesheetSet, font_cache:&mut FontCache) -> Result<(), BrowserError> {
+    let mut ss = parse_stylesheet_from_bytestring(HTML_CSS)?;
+    ss.base_url = Url::parse("resource://ua/html.css")?;
+    process_stylesheet(set, font_cache, None, ss)?;
+    set.user_agent_count = set.stylesheets.len();
+    Ok(())
+}
+
+// the user's own stylesheet, which has no page to be relative to
+fn load_user_stylesheet(set:&mut StylesheetSet, font_cache:&mut FontCache, css:&str) -> Result<(), BrowserError> {
+    let mut ss = parse_stylesheet(css)?;
+    ss.base_url = Url::parse("resource://user/user.css")?;
+    process_stylesheet(set, font_cache, None, ss)?;
+    set.user_count = set.stylesheets.len() - set.user_agent_count;
+    Ok(())
 }
 
 pub fn load_stylesheets_new(doc:&Document, font_cache:&mut FontCache) -> Result<StylesheetSet, BrowserError> {
+    load_stylesheets_with_user(doc, font_cache, None)
+}
+
+/// Loads the document's stylesheets, with the user's stylesheet from user_css between the
+/// browser's and the page's.
+pub fn load_stylesheets_with_user(doc:&Document, font_cache:&mut FontCache, user_css:Option<&str>) -> Result<StylesheetSet, BrowserError> {
     let mut set = StylesheetSet::new();
-    //load the default stylesheet
-    load_stylesheet_2(&mut set, font_cache, &relative_filepath_to_url("tests/default.css")?)?;
+    // first, so everything the page says comes after it in the cascade
+    load_user_agent_stylesheet(&mut set, font_cache)?;
+    if let Some(css) = user_css {
+        load_user_stylesheet(&mut set, font_cache, css)?;
+    }
     //scan for link nodes
     let link_nodes = getElementsByTagName(&doc.root_node, "link");
     for link in link_nodes.iter() {
@@ -121,7 +503,7 @@ pub fn load_stylesheets_new(doc:&Document, font_cache:&mut FontCache) -> Result<
                 let href = href.unwrap();
                 let url = &calculate_url_from_doc(doc, href)?;
                 println!("Loading linked stylesheet {:#?}", url);
-                load_stylesheet_2(&mut set, font_cache, url);
+                load_stylesheet_2(&mut set, font_cache, Some(&doc.base_url), url);
             }
         }
     }
@@ -130,7 +512,7 @@ pub fn load_stylesheets_new(doc:&Document, font_cache:&mut FontCache) -> Result<
     for style in style_nodes.iter() {
         if !style.children.is_empty() {
             if let NodeType::Text(text) = &style.children[0].node_type {
-                parse_stylesheet_2_from_text(&mut set, font_cache, text)?;
+                parse_stylesheet_2_from_text(&mut set, font_cache, Some(&doc.base_url), text)?;
             }
         }
     }
@@ -147,29 +529,309 @@ pub fn load_doc_from_net(url:&Url) -> Result<Document,BrowserError> {
     match url.scheme() {
         "file" => {
             let path = url.to_file_path()?;
-            load_doc(path.as_path())
+            if path.is_dir() {
+                // relative links in the listing need the trailing slash to resolve inside the dir
+                let mut dir_url = url.clone();
+                if !dir_url.path().ends_with('/') {
+                    dir_url.set_path(&format!("{}/", url.path()));
+                }
+                let mut doc = load_doc_from_bytestring(directory_listing(&path)?.as_bytes());
+                doc.base_url = dir_url;
+                return Ok(doc);
+            }
+            match content_type_for_path(&path) {
+                "text/html" => load_doc(path.as_path()),
+                content_type => {
+                    let mut doc = doc_from_body(std::fs::read(&path)?, content_type);
+                    doc.base_url = url.clone();
+                    Ok(doc)
+                }
+            }
         }
         _ => {
-            let mut resp = reqwest::blocking::get(url.as_str())?;
+            let mut resp = http_get(url)?;
             let status = resp.status();
             let len = resp.content_length();
             println!("{:#?}\n content length = {:#?}\n status = {:#?}", resp, len, status);
+            let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(mime_essence)
+                .unwrap_or_else(|| String::from("text/html"));
 
             let mut buf: Vec<u8> = vec![];
             resp.copy_to(&mut buf).ok();
 
-            let mut doc = load_doc_from_buffer(buf);
+            let mut doc = doc_from_body(buf, &content_type);
             doc.base_url = url.clone();
             Ok(doc)
         }
     }
 }
 
-pub fn load_image_from_net(url:&Url) -> Result<LoadedImage, BrowserError> {
-    let mut resp = reqwest::blocking::get(url.as_str())?;
+// "text/plain; charset=utf-8" -> "text/plain"
+fn mime_essence(content_type:&str) -> String {
+    content_type.split(';').next().unwrap_or("").trim().to_lowercase()
+}
+
+// local files have no headers, so go by the extension
+fn content_type_for_path(path:&Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_lowercase()).as_deref() {
+        Some("txt") => "text/plain",
+        Some("md") | Some("markdown") => "text/markdown",
+        _ => "text/html",
+    }
+}
+
+fn doc_from_body(buf:Vec<u8>, content_type:&str) -> Document {
+    match content_type {
+        "text/plain" => load_doc_from_bytestring(plain_text_to_html(&String::from_utf8_lossy(&buf)).as_bytes()),
+        #[cfg(feature = "markdown")]
+        "text/markdown" => load_doc_from_bytestring(markdown_to_html(&String::from_utf8_lossy(&buf)).as_bytes()),
+        // without the markdown feature the source is still readable as plain text
+        #[cfg(not(feature = "markdown"))]
+        "text/markdown" => load_doc_from_bytestring(plain_text_to_html(&String::from_utf8_lossy(&buf)).as_bytes()),
+        _ => load_doc_from_buffer(buf),
+    }
+}
+
+/// Wraps plain text in a `pre` so it keeps its line breaks and shows in monospace.
+pub fn plain_text_to_html(text:&str) -> String {
+    format!("<html><body><pre>{}</pre></body></html>", escape_html(text))
+}
+
+/// Converts Markdown to an HTML page. Raw HTML in the source is shown as text, since
+/// the parser can't recover from the unbalanced tags it often contains.
+#[cfg(feature = "markdown")]
+pub fn markdown_to_html(text:&str) -> String {
+    use pulldown_cmark::{Parser, Event, html};
+    let events = Parser::new(text).map(|event| match event {
+        Event::Html(raw) => Event::Text(raw),
+        event => event,
+    });
+    let mut body = String::new();
+    html::push_html(&mut body, events);
+    format!("<html><body>{}</body></html>", body)
+}
+
+enum LoadChunk {
+    Data(Vec<u8>),
+    Done,
+    // pages that aren't streamed, like local files and plain text, arrive in one piece
+    Whole(Result<Document, BrowserError>),
+}
+
+/// A document downloading on a background thread. Poll it to collect what has arrived,
+/// and ask for the partial document to show the page before it has finished.
+pub struct PageLoad {
+    pub url: Url,
+    receiver: Receiver<LoadChunk>,
+    parser: IncrementalParser,
+}
+
+/// What a poll of a PageLoad found.
+pub enum LoadProgress {
+    Loading,
+    Done(Result<Document, BrowserError>),
+}
+
+impl PageLoad {
+    pub fn start(url:&Url) -> PageLoad {
+        let (sender, receiver) = channel();
+        let thread_url = url.clone();
+        thread::spawn(move || stream_page(&thread_url, &sender));
+        PageLoad {
+            url: url.clone(),
+            receiver,
+            parser: IncrementalParser::new(),
+        }
+    }
+    pub fn poll(&mut self) -> LoadProgress {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(LoadChunk::Data(bytes)) => self.parser.feed(&bytes),
+                Ok(LoadChunk::Whole(result)) => return LoadProgress::Done(result),
+                Ok(LoadChunk::Done) => {
+                    let parser = mem::take(&mut self.parser);
+                    let result = parser.finish().map(|mut doc| {
+                        doc.base_url = self.url.clone();
+                        doc
+                    });
+                    return LoadProgress::Done(result);
+                }
+                Err(TryRecvError::Empty) => return LoadProgress::Loading,
+                Err(TryRecvError::Disconnected) => {
+                    return LoadProgress::Done(Err(BrowserError::ParseError(String::from("page load stopped"))));
+                }
+            }
+        }
+    }
+    // bytes received so far
+    pub fn received(&self) -> usize {
+        self.parser.len()
+    }
+    pub fn partial_document(&self) -> Option<Document> {
+        self.parser.partial_document().map(|mut doc| {
+            doc.base_url = self.url.clone();
+            doc
+        })
+    }
+}
+
+// runs on the load's thread. a closed channel means nobody wants the page any more.
+fn stream_page(url:&Url, sender:&Sender<LoadChunk>) {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        sender.send(LoadChunk::Whole(load_doc_from_net(url))).ok();
+        return;
+    }
+    println!("streaming url {}",url);
+    let mut resp = match http_get(url) {
+        Ok(resp) => resp,
+        Err(err) => {
+            sender.send(LoadChunk::Whole(Err(err))).ok();
+            return;
+        }
+    };
+    let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(mime_essence)
+        .unwrap_or_else(|| String::from("text/html"));
+    if content_type != "text/html" {
+        let mut buf: Vec<u8> = vec![];
+        let result = resp.copy_to(&mut buf).map_err(BrowserError::from).map(|_| {
+            let mut doc = doc_from_body(buf, &content_type);
+            doc.base_url = url.clone();
+            doc
+        });
+        sender.send(LoadChunk::Whole(result)).ok();
+        return;
+    }
+    let mut chunk = [0u8; 8192];
+    loop {
+        match resp.read(&mut chunk) {
+            Ok(0) => {
+                sender.send(LoadChunk::Done).ok();
+                return;
+            }
+            Ok(n) => {
+                if sender.send(LoadChunk::Data(chunk[..n].to_vec())).is_err() {
+                    return;
+                }
+            }
+            Err(err) => {
+                sender.send(LoadChunk::Whole(Err(err.into()))).ok();
+                return;
+            }
+        }
+    }
+}
+
+// for text content only. quotes only matter inside attributes, which don't have their entities expanded.
+pub fn escape_html(text:&str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn format_size(bytes:u64) -> String {
+    let units = ["KB", "MB", "GB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, units[unit])
+}
+
+// seconds since the epoch as a UTC "yyyy-mm-dd hh:mm" (days to civil date from Howard Hinnant)
+fn format_mtime(secs:u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, rem / 3600, rem % 3600 / 60)
+}
+
+/// An index page for a local directory: a parent link, then subdirectories and files by name.
+pub fn directory_listing(path:&Path) -> Result<String, BrowserError> {
+    let mut entries:Vec<(bool, String, u64, Option<u64>)> = vec![];
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        let mtime = meta.modified().ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+        entries.push((meta.is_dir(), entry.file_name().to_string_lossy().to_string(), meta.len(), mtime));
+    }
+    entries.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+    let title = escape_html(&path.display().to_string());
+    let mut html = format!("<html><head><title>Index of {}</title></head><body>\n<h1>Index of {}</h1>\n<table>\n", title, title);
+    html.push_str("<tr><th>Name</th><th>Size</th><th>Modified</th></tr>\n");
+    if path.parent().is_some() {
+        html.push_str("<tr><td><a href=\"../\">Parent directory</a></td><td></td><td></td></tr>\n");
+    }
+    for (is_dir, name, len, mtime) in entries {
+        let mut href:String = url::form_urlencoded::byte_serialize(name.as_bytes()).collect::<String>().replace('+', "%20");
+        let mut label = escape_html(&name);
+        let size = if is_dir {
+            href.push('/');
+            label.push('/');
+            String::from("-")
+        } else {
+            format_size(len)
+        };
+        html.push_str(&format!("<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td></tr>\n",
+            href, label, size, mtime.map(format_mtime).unwrap_or_default()));
+    }
+    html.push_str("</table>\n</body></html>");
+    Ok(html)
+}
+
+// how many bytes of downloaded subresources, and of decoded images, are kept around
+const RESPONSE_CACHE_BYTES:usize = 64 * 1024 * 1024;
+const DECODED_IMAGE_BYTES:usize = 256 * 1024 * 1024;
+
+lazy_static! {
+    static ref RESPONSE_CACHE: RwLock<LruCache<Vec<u8>>> = RwLock::new(LruCache::new(RESPONSE_CACHE_BYTES));
+}
+
+// Images, stylesheets and fonts are downloaded once and shared by every window.
+// Documents always come from the network so following a link shows the latest.
+fn fetch_subresource(url:&Url) -> Result<Vec<u8>, BrowserError> {
+    if let Some(buf) = RESPONSE_CACHE.write().unwrap().get(url) {
+        return Ok(buf.clone());
+    }
+    let mut resp = http_get(url)?;
     let mut buf: Vec<u8> = vec![];
-    resp.copy_to(&mut buf).ok();
-    Ok(load_image_from_buffer(buf)?)
+    resp.copy_to(&mut buf)?;
+    if resp.status().is_success() {
+        RESPONSE_CACHE.write().unwrap().insert(url.clone(), buf.clone(), buf.len());
+    }
+    Ok(buf)
+}
+
+pub fn clear_network_cache() {
+    RESPONSE_CACHE.write().unwrap().clear();
+    forget_decoded_images();
+}
+
+/// Drops the decoded images, so local image files are read again. Downloaded ones are
+/// decoded again from the response cache.
+pub fn forget_decoded_images() {
+    DECODED_IMAGES.with(|images| images.borrow_mut().clear());
+}
+
+pub fn load_image_from_net(url:&Url) -> Result<LoadedImage, BrowserError> {
+    Ok(load_image_from_buffer(fetch_subresource(url)?)?)
 }
 
 pub fn load_stylesheet_from_net(url:&Url) -> Result<Stylesheet, BrowserError>{
@@ -185,32 +847,26 @@ pub fn load_stylesheet_from_net(url:&Url) -> Result<Stylesheet, BrowserError>{
             Ok(ss)
         }
         _ => {
-            let mut resp = reqwest::blocking::get(url.as_str())?;
-            let mut buf: Vec<u8> = vec![];
-            resp.copy_to(&mut buf)?;
-            let mut ss = parse_stylesheet_from_buffer(buf)?;
+            let mut ss = parse_stylesheet_from_buffer(fetch_subresource(url)?)?;
             ss.base_url = url.clone();
             Ok(ss)
         }
     }
 }
 
-pub fn load_font_from_net(url:Url) -> Result<Font<'static>, BrowserError> {
-    match url.scheme() {
+pub fn load_font_from_net(page:Option<&Url>, url:Url) -> Result<Font<'static>, BrowserError> {
+    let url = filter_subresource(page, &url)?;
+    let content = match url.scheme() {
         "file" => {
             let path = url.to_file_path()?;
             let mut file = File::open(path)?;
             let mut content:Vec<u8>= Vec::new();
             file.read_to_end(&mut content).ok();
-            Ok(Font::from_bytes(content).unwrap())
-        }
-        _ => {
-            let mut resp = reqwest::blocking::get(url.as_str())?;
-            let mut buf: Vec<u8> = vec![];
-            resp.copy_to(&mut buf)?;
-            Ok(Font::from_bytes(buf).unwrap())
+            content
         }
-    }
+        _ => fetch_subresource(&url)?,
+    };
+    Font::from_bytes(content).map_err(|err| BrowserError::ParseError(format!("bad font {} {:?}", url, err)))
 }
 
 #[test]
@@ -241,16 +897,264 @@ fn test_request() -> Result<(), BrowserError> {
 }
 
 
-pub fn load_image(doc:&Document, href:&str) -> Result<LoadedImage, BrowserError>{
-    let url = doc.base_url.join(href)?;
-    match url.scheme() {
-        "file" => {
-            Ok(load_image_from_filepath(url.path().to_string())?)
-        },
-        _ => {
-            Ok(load_image_from_net(&url.clone())?)
-        },
+thread_local! {
+    static CACHED_IMAGES_ONLY: Cell<bool> = const { Cell::new(false) };
+    // images are measured and then laid out, often more than once, but decoded just once
+    static DECODED_IMAGES: RefCell<LruCache<Rc<LoadedImage>>> = RefCell::new(LruCache::new(DECODED_IMAGE_BYTES));
+}
+
+/// Runs `f`, usually a layout of a partly loaded page, without waiting on image downloads.
+/// Images that aren't cached yet fail with Deferred and show as placeholders.
+pub fn with_cached_images_only<T>(f:impl FnOnce() -> T) -> T {
+    let old = CACHED_IMAGES_ONLY.with(|flag| flag.replace(true));
+    let result = f();
+    CACHED_IMAGES_ONLY.with(|flag| flag.set(old));
+    result
+}
+
+pub fn load_image(doc:&Document, href:&str) -> Result<Rc<LoadedImage>, BrowserError>{
+    let url = filter_subresource(Some(&doc.base_url), &doc.base_url.join(href)?)?;
+    if let Some(image) = DECODED_IMAGES.with(|images| images.borrow_mut().get(&url).cloned()) {
+        return Ok(image);
     }
+    if url.scheme() != "file" && CACHED_IMAGES_ONLY.with(|flag| flag.get())
+        && !RESPONSE_CACHE.read().unwrap().contains_key(&url) {
+        return Err(BrowserError::Deferred(url));
+    }
+    let image = Rc::new(match url.scheme() {
+        "file" => load_image_from_filepath(url.path().to_string())?,
+        _ => load_image_from_net(&url)?,
+    });
+    let size = image.image2d.as_raw().len();
+    DECODED_IMAGES.with(|images| images.borrow_mut().insert(url, Rc::clone(&image), size));
+    Ok(image)
 }
 
 
+
+#[test]
+fn test_request_filter() {
+    let mut filter = RequestFilter::new();
+    filter.blocked_patterns.push(String::from("ads.example.com"));
+    filter.callback = Some(Box::new(|url:&Url| {
+        if url.scheme() == "http" {
+            let mut secure = url.clone();
+            secure.set_scheme("https").unwrap();
+            FilterAction::Rewrite(secure)
+        } else {
+            FilterAction::Allow
+        }
+    }));
+    let blocked = Url::parse("https://ads.example.com/banner.png").unwrap();
+    assert!(matches!(filter.check(&blocked), FilterAction::Block));
+    let allowed = Url::parse("https://www.example.com/cat.png").unwrap();
+    assert!(matches!(filter.check(&allowed), FilterAction::Allow));
+    let insecure = Url::parse("http://www.example.com/cat.png").unwrap();
+    match filter.check(&insecure) {
+        FilterAction::Rewrite(url) => assert_eq!(url.as_str(), "https://www.example.com/cat.png"),
+        _ => panic!("http url should have been rewritten"),
+    }
+}
+
+#[test]
+fn test_network_config() {
+    let config = NetworkConfig {
+        max_idle_per_host: 2,
+        ..Default::default()
+    };
+    assert!(set_network_config(config).is_ok());
+    assert!(client_for(&Url::parse("https://www.example.com/").unwrap()).is_ok());
+    set_network_config(NetworkConfig::default()).unwrap();
+}
+
+#[test]
+fn test_certificate_error_details() {
+    #[derive(Debug)]
+    struct Wrapped(std::io::Error);
+    impl std::fmt::Display for Wrapped {
+        fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result { write!(f, "error trying to connect") }
+    }
+    impl std::error::Error for Wrapped {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { Some(&self.0) }
+    }
+    let bad_cert = Wrapped(std::io::Error::other("certificate verify failed: self signed certificate"));
+    assert_eq!(certificate_error(&bad_cert), Some(String::from("certificate verify failed: self signed certificate")));
+    let refused = Wrapped(std::io::Error::other("connection refused"));
+    assert_eq!(certificate_error(&refused), None);
+
+    assert!(!allows_invalid_certificates("self-signed.example.com"));
+    allow_invalid_certificates("self-signed.example.com");
+    assert!(allows_invalid_certificates("self-signed.example.com"));
+}
+
+#[test]
+fn test_proxy_config() {
+    let config = ProxyConfig {
+        http: Some(Url::parse("http://proxy.local:3128").unwrap()),
+        https: Some(Url::parse("http://secure-proxy.local:3129").unwrap()),
+        no_proxy: split_no_proxy("localhost, .internal.example.com,127.0.0.1"),
+    };
+    let proxy = |url:&str| config.proxy_for(&Url::parse(url).unwrap()).map(|u| u.to_string());
+    assert_eq!(proxy("http://www.example.com/"), Some(String::from("http://proxy.local:3128/")));
+    assert_eq!(proxy("https://www.example.com/"), Some(String::from("http://secure-proxy.local:3129/")));
+    assert_eq!(proxy("http://localhost:8000/page.html"), None);
+    assert_eq!(proxy("http://127.0.0.1/"), None);
+    assert_eq!(proxy("https://wiki.internal.example.com/"), None);
+    assert_eq!(proxy("https://internal.example.com/"), None);
+    assert_eq!(proxy("https://notinternal.example.com/"), Some(String::from("http://secure-proxy.local:3129/")));
+    assert_eq!(proxy("file:///tmp/page.html"), None);
+
+    let everything = ProxyConfig { no_proxy: vec![String::from("*")], ..config.clone() };
+    assert_eq!(everything.proxy_for(&Url::parse("http://www.example.com/").unwrap()), None);
+
+    let path = std::env::temp_dir().join("minibrowser-proxy-test.json");
+    std::fs::write(&path, r#"{"http_proxy":"http://file-proxy:8080","https_proxy":"http://file-proxy:8443","no_proxy":["localhost","example.org"]}"#).unwrap();
+    let from_file = ProxyConfig::from_config_file(path.to_str().unwrap()).unwrap();
+    assert_eq!(from_file.http, Some(Url::parse("http://file-proxy:8080").unwrap()));
+    assert_eq!(from_file.https, Some(Url::parse("http://file-proxy:8443").unwrap()));
+    assert_eq!(from_file.no_proxy, vec!["localhost", "example.org"]);
+    assert!(from_file.bypasses(&Url::parse("https://www.example.org/").unwrap()));
+}
+
+#[test]
+fn test_directory_listing() {
+    assert_eq!(format_size(512), "512 B");
+    assert_eq!(format_size(1536), "1.5 KB");
+    assert_eq!(format_size(3 * 1024 * 1024), "3.0 MB");
+    assert_eq!(format_mtime(0), "1970-01-01 00:00");
+    assert_eq!(format_mtime(951_782_400 + 3600 * 13 + 60 * 5), "2000-02-29 13:05");
+
+    let dir = std::env::temp_dir().join("minibrowser-listing-test");
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(dir.join("sub dir")).unwrap();
+    std::fs::write(dir.join("b.html"), "<p>hi</p>").unwrap();
+    std::fs::write(dir.join("a&b.txt"), "1234").unwrap();
+
+    let url = Url::from_file_path(&dir).unwrap();
+    let mut doc = load_doc_from_net(&url).unwrap();
+    crate::dom::expand_entities(&mut doc);
+    assert!(doc.base_url.as_str().ends_with("minibrowser-listing-test/"));
+    fn hrefs(node:&crate::dom::Node, found:&mut Vec<String>) {
+        if let Element(ed) = &node.node_type {
+            if let Some(href) = ed.attributes.get("href") {
+                found.push(href.clone());
+            }
+        }
+        node.children.iter().for_each(|ch| hrefs(ch, found));
+    }
+    let mut links = vec![];
+    hrefs(&doc.root_node, &mut links);
+    assert_eq!(links, vec!["../", "sub%20dir/", "a%26b.txt", "b.html"]);
+    assert_eq!(calculate_url_from_doc(&doc, &links[3]).unwrap(), Url::from_file_path(dir.join("b.html")).unwrap());
+    let text = doc.root_node.text_content();
+    assert!(text.contains("a&b.txt"));
+    assert!(text.contains("4 B"));
+}
+
+#[test]
+fn test_plain_text_response() {
+    assert_eq!(mime_essence("text/plain; charset=UTF-8"), "text/plain");
+    assert_eq!(content_type_for_path(Path::new("tests/README.md")), "text/markdown");
+    assert_eq!(content_type_for_path(Path::new("notes.TXT")), "text/plain");
+    assert_eq!(content_type_for_path(Path::new("tests/page1.html")), "text/html");
+
+    let text = "fn main() {\n    println!(\"<b>&</b>\");\n}\n";
+    let mut doc = doc_from_body(text.as_bytes().to_vec(), "text/plain");
+    crate::dom::expand_entities(&mut doc);
+    let pre = getElementsByTagName(&doc.root_node, "pre");
+    assert_eq!(pre.len(), 1);
+    assert_eq!(pre[0].text_content(), text);
+}
+
+#[cfg(feature = "markdown")]
+#[test]
+fn test_markdown_response() {
+    let doc = doc_from_body(b"# Title\n\nsome *text* with <span>html\n\n---\n".to_vec(), "text/markdown");
+    let h1 = getElementsByTagName(&doc.root_node, "h1");
+    assert_eq!(h1[0].text_content(), "Title");
+    assert_eq!(getElementsByTagName(&doc.root_node, "em")[0].text_content(), "text");
+    assert!(getElementsByTagName(&doc.root_node, "span").is_empty());
+    assert_eq!(getElementsByTagName(&doc.root_node, "hr").len(), 1);
+}
+
+#[test]
+fn test_shared_response_cache() {
+    use std::io::Write;
+    // a server that answers exactly once, so a second fetch can only come from the cache
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("http://{}/style.css", listener.local_addr().unwrap())).unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0u8; 1024];
+        assert!(stream.read(&mut request).unwrap() > 0);
+        let body = "p { color: red; }";
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body).unwrap();
+    });
+    assert_eq!(fetch_subresource(&url).unwrap(), b"p { color: red; }".to_vec());
+    server.join().unwrap();
+    assert_eq!(fetch_subresource(&url).unwrap(), b"p { color: red; }".to_vec());
+    assert_eq!(load_stylesheet_from_net(&url).unwrap().rules.len(), 1);
+    clear_network_cache();
+    assert!(fetch_subresource(&url).is_err());
+}
+
+#[test]
+fn test_decoded_image_cache() {
+    let doc = load_doc_from_net(&relative_filepath_to_url("tests/image-sizing.html").unwrap()).unwrap();
+    // measuring and laying out the image share one decode
+    let first = load_image(&doc, "images/cat.jpg").unwrap();
+    assert!(Rc::ptr_eq(&first, &load_image(&doc, "images/cat.jpg").unwrap()));
+    assert_eq!((first.width, first.height), (100, 67));
+    forget_decoded_images();
+    assert!(!Rc::ptr_eq(&first, &load_image(&doc, "images/cat.jpg").unwrap()));
+}
+
+#[test]
+fn test_user_agent_stylesheet() -> Result<(), BrowserError> {
+    let glyph_brush:glyph_brush::GlyphBrush<Font> = glyph_brush::GlyphBrushBuilder::without_fonts().build();
+    let mut font_cache = FontCache {
+        brush: crate::layout::Brush::Style2(glyph_brush),
+        families: Default::default(),
+        fonts: Default::default()
+    };
+    let mut set = StylesheetSet::new();
+    load_user_agent_stylesheet(&mut set, &mut font_cache)?;
+    let doc = crate::dom::load_doc_from_bytestring(br#"<div><h1>a</h1><strong>b</strong><pre>c</pre><a href="x">d</a><td>e</td><head>f</head></div>"#);
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &set);
+    let root = stree.root.borrow();
+    let children = root.children.borrow();
+    let keyword = |kw:&str| Some(Value::Keyword(String::from(kw)));
+    // ems of the h1's own 36px font
+    assert_eq!(children[0].value("margin-top"), Some(Value::Length(0.67 * 36.0, crate::css::Unit::Px)));
+    assert_eq!(children[1].value("display"), keyword("inline"));
+    assert_eq!(children[1].value("font-weight"), Some(Value::Number(700.0)));
+    assert_eq!(children[2].value("font-family"), keyword("monospace"));
+    assert_eq!(children[3].value("color"), keyword("blue"));
+    assert_eq!(children[3].value("text-decoration-line"), keyword("underline"));
+    assert_eq!(children[4].value("display"), keyword("table-cell"));
+    assert_eq!(children[5].value("display"), keyword("none"));
+    Ok(())
+}
+
+#[test]
+fn test_import_rules() -> Result<(), BrowserError> {
+    let glyph_brush:glyph_brush::GlyphBrush<Font> = glyph_brush::GlyphBrushBuilder::without_fonts().build();
+    let mut font_cache = FontCache {
+        brush: crate::layout::Brush::Style2(glyph_brush),
+        families: Default::default(),
+        fonts: Default::default()
+    };
+    let doc = load_doc_from_net(&relative_filepath_to_url("tests/import.html")?)?;
+    let set = load_stylesheets_new(&doc, &mut font_cache)?;
+    let names:Vec<&str> = set.stylesheets.iter()
+        .map(|ss| ss.base_url.path_segments().and_then(|mut segs| segs.next_back()).unwrap_or(""))
+        .collect();
+    // the cycle back to import-a.css is dropped, and imports come before their importers
+    assert_eq!(names, vec!["html.css", "import-b.css", "import-a.css", "import-c.css", "import.html"]);
+    // an import with media queries only applies when they match
+    match &set.stylesheets[3].rules[0] {
+        RuleType::Media(block) => assert_eq!(block.queries[0].media_type, Some(String::from("print"))),
+        rule => panic!("expected the import to be wrapped in a media block, got {:#?}", rule),
+    }
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use url::Url;
+use std::sync::RwLock;
+
+/// Rules about what a page may load, checked before every request. The defaults match
+/// what browsers do; the host allowlist is for embedders that should only ever show their
+/// own content, like a kiosk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecurityPolicy {
+    // no http subresources on https pages
+    pub block_mixed_content: bool,
+    // network pages can't load or link to local files
+    pub block_file_from_network: bool,
+    // when set, http(s) requests may only go to these hosts or their subdomains
+    pub allowed_hosts: Option<Vec<String>>,
+}
+
+impl Default for SecurityPolicy {
+    fn default() -> Self {
+        SecurityPolicy {
+            block_mixed_content: true,
+            block_file_from_network: true,
+            allowed_hosts: None,
+        }
+    }
+}
+
+fn is_network(url:&Url) -> bool {
+    url.scheme() == "http" || url.scheme() == "https"
+}
+
+// "example.com" and ".example.com" both match example.com and anything under it
+pub(crate) fn host_matches(host:&str, entry:&str) -> bool {
+    let domain = entry.trim_start_matches('.');
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+impl SecurityPolicy {
+    /// Why a page may not go to `to`, if it may not. `from` is None for loads the user
+    /// asked for directly.
+    pub fn check_navigation(&self, from:Option<&Url>, to:&Url) -> Result<(), String> {
+        if self.block_file_from_network && to.scheme() == "file" && from.is_some_and(is_network) {
+            return Err(String::from("network pages can't open local files"));
+        }
+        if let Some(allowed) = &self.allowed_hosts {
+            if is_network(to) {
+                let host = to.host_str().unwrap_or("").to_lowercase();
+                if !allowed.iter().any(|entry| host_matches(&host, &entry.to_lowercase())) {
+                    return Err(format!("{} is not an allowed host", host));
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Like check_navigation, plus mixed content. `page` is None for the browser's own
+    /// resources, like the default stylesheet.
+    pub fn check_subresource(&self, page:Option<&Url>, url:&Url) -> Result<(), String> {
+        if self.block_mixed_content && url.scheme() == "http" && page.is_some_and(|p| p.scheme() == "https") {
+            return Err(String::from("insecure content on a secure page"));
+        }
+        self.check_navigation(page, url)
+    }
+}
+
+lazy_static! {
+    static ref SECURITY_POLICY: RwLock<SecurityPolicy> = RwLock::new(SecurityPolicy::default());
+}
+
+pub fn set_security_policy(policy:SecurityPolicy) {
+    *SECURITY_POLICY.write().unwrap() = policy;
+}
+
+pub fn security_policy() -> SecurityPolicy {
+    SECURITY_POLICY.read().unwrap().clone()
+}
+
+#[test]
+fn test_security_policy() {
+    let https_page = Url::parse("https://www.example.com/index.html").unwrap();
+    let http_page = Url::parse("http://www.example.com/index.html").unwrap();
+    let file_page = Url::parse("file:///home/user/page.html").unwrap();
+    let http_image = Url::parse("http://cdn.example.com/cat.png").unwrap();
+    let https_image = Url::parse("https://cdn.example.com/cat.png").unwrap();
+    let local_file = Url::parse("file:///etc/passwd").unwrap();
+
+    let policy = SecurityPolicy::default();
+    assert!(policy.check_subresource(Some(&https_page), &http_image).is_err());
+    assert!(policy.check_subresource(Some(&https_page), &https_image).is_ok());
+    assert!(policy.check_subresource(Some(&http_page), &http_image).is_ok());
+    // links from secure pages to insecure ones are still fine
+    assert!(policy.check_navigation(Some(&https_page), &http_page).is_ok());
+    assert!(policy.check_subresource(Some(&http_page), &local_file).is_err());
+    assert!(policy.check_navigation(Some(&https_page), &local_file).is_err());
+    assert!(policy.check_subresource(Some(&file_page), &local_file).is_ok());
+    assert!(policy.check_subresource(None, &local_file).is_ok());
+
+    let kiosk = SecurityPolicy { allowed_hosts: Some(vec![String::from("example.com")]), ..Default::default() };
+    assert!(kiosk.check_navigation(None, &https_page).is_ok());
+    assert!(kiosk.check_subresource(Some(&https_page), &https_image).is_ok());
+    assert!(kiosk.check_navigation(Some(&https_page), &Url::parse("https://www.rust-lang.org/").unwrap()).is_err());
+    assert!(kiosk.check_navigation(None, &Url::parse("https://notexample.com/").unwrap()).is_err());
+    assert!(kiosk.check_navigation(None, &file_page).is_ok());
+}
@@ -1,13 +1,16 @@
-use crate::css::{Color, Value, Stylesheet, RuleType};
+use crate::css::{Color, Value, Stylesheet, RuleType, Rule};
 use std::collections::HashMap;
-use std::path::Path;
 use std::fs::File;
 use url::Url;
-use crate::net::{relative_filepath_to_url, load_font_from_net};
+use crate::net::{relative_filepath_to_url, load_font_from_net, BrowserError};
 use glium_glyph::GlyphBrush;
 use glium_glyph::glyph_brush::rusttype::{Font,Error};
+use rusttype::FontCollection;
+use font_kit::source::SystemSource;
+use font_kit::handle::Handle;
 use glium_glyph::glyph_brush::FontId;
 use crate::layout::Brush;
+use std::sync::RwLock;
 
 
 #[allow(dead_code)]
@@ -126,6 +129,51 @@ pub fn draw_render_box<R:Resources,F:Factory<R>>(root:&RenderBox, dt:&mut DrawTa
 }
 */
 
+/// How glyphs are placed and antialiased when drawing text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextRendering {
+    // draw glyphs at their fractional positions rather than snapped to whole pixels
+    pub subpixel_positioning: bool,
+    // antialias each color channel on its own, for the red, green and blue stripes of an LCD pixel
+    pub lcd_antialiasing: bool,
+}
+
+impl TextRendering {
+    /// macOS stopped using LCD antialiasing, everywhere else still expects it.
+    pub fn platform_default() -> TextRendering {
+        TextRendering {
+            subpixel_positioning: true,
+            lcd_antialiasing: !cfg!(target_os = "macos"),
+        }
+    }
+    /// How far apart, in pixels, two positions of a glyph can be and still share a cached image.
+    /// The color channels are a third of a pixel apart, so LCD antialiasing needs finer than that.
+    pub fn position_tolerance(&self) -> f32 {
+        if self.subpixel_positioning || self.lcd_antialiasing { 0.1 } else { 1.0 }
+    }
+    /// How far over each of the red, green and blue channels' coverage is taken, in pixels.
+    /// Without LCD antialiasing there is just the one, shared by all three.
+    pub fn channel_offsets(&self) -> Vec<f32> {
+        if self.lcd_antialiasing { vec![-1.0/3.0, 0.0, 1.0/3.0] } else { vec![0.0] }
+    }
+    /// Where to actually draw something laid out at `pos`.
+    pub fn snap(&self, pos:f32) -> f32 {
+        if self.subpixel_positioning { pos } else { pos.round() }
+    }
+}
+
+lazy_static! {
+    static ref TEXT_RENDERING: RwLock<TextRendering> = RwLock::new(TextRendering::platform_default());
+}
+
+/// Change how text is rendered. Each window rebuilds its glyph brush for it before its next frame.
+pub fn set_text_rendering(rendering:TextRendering) {
+    *TEXT_RENDERING.write().unwrap() = rendering;
+}
+
+pub fn text_rendering() -> TextRendering {
+    *TEXT_RENDERING.read().unwrap()
+}
 
 
 pub struct FontCache {
@@ -141,10 +189,7 @@ impl FontCache {
         return format!("{}-{}-{}",family,weight,style);
     }
     pub fn install_font(&mut self, font:Font<'static>, family:&str, weight:i32, style:&str) {
-        let fid = match &mut self.brush {
-            Brush::Style1(b) => b.add_font(font),
-            Brush::Style2(b) => b.add_font(font),
-        };
+        let fid = self.brush.add_font(font);
         let key = self.make_key(family,weight,style);
         // println!("installing font {}",key);
         self.fonts.insert(key,fid);
@@ -160,36 +205,72 @@ impl FontCache {
     }
 }
 
-fn find_truetype_url(value:&Value, url:&Url) -> Option<Url> {
+/// One entry of an @font-face `src` list.
+#[derive(Debug, PartialEq)]
+enum FontSource {
+    // the url, with its format() hint if it had one
+    Url(Url, Option<String>),
+    // an installed font, by its full name or postscript name
+    Local(String),
+}
+
+impl FontSource {
+    // rusttype can only read truetype and opentype, not woff, eot or svg fonts
+    fn is_supported(&self) -> bool {
+        match self {
+            FontSource::Url(_, Some(format)) => ["truetype", "opentype", "truetype-variations", "opentype-variations"]
+                .contains(&format.to_lowercase().as_str()),
+            FontSource::Url(url, None) => {
+                let path = url.path().to_lowercase();
+                path.ends_with(".ttf") || path.ends_with(".otf")
+            }
+            FontSource::Local(_) => true,
+        }
+    }
+}
+
+fn string_argument(value:Option<&Value>) -> Option<String> {
     match value {
-        Value::FunCall(fcv) => {
-            match &fcv.arguments[0] {
-                Value::StringLiteral(str) => {
-                    if !str.to_lowercase().ends_with(".ttf") {
-                        return None
-                    }
-                    if let Ok(url) = url.join(str.as_str()) {
-                        Some(url)
-                    } else {
-                        println!("parsing error on url {:#?}", url);
-                        None
-                    }
-                },
+        Some(Value::StringLiteral(str)) | Some(Value::Keyword(str)) => Some(str.clone()),
+        _ => None,
+    }
+}
+
+// a quoted name, or an unquoted one that may be several words
+fn font_family_name(value:&Value) -> Option<String> {
+    match value {
+        Value::ArrayValue(words) => words.iter()
+            .map(|word| string_argument(Some(word)))
+            .collect::<Option<Vec<String>>>()
+            .map(|words| words.join(" ")),
+        value => string_argument(Some(value)),
+    }
+}
+
+fn font_sources(value:&Value, base:&Url) -> Vec<FontSource> {
+    match value {
+        Value::FunCall(fcv) if fcv.name == "url" => {
+            let format = match fcv.arguments.get(1) {
+                Some(Value::FunCall(format)) => string_argument(format.arguments.first()),
                 _ => None,
-            }
-        },
-        Value::ArrayValue(vals) => {
-            for val in vals.iter() {
-                let res  = find_truetype_url(val,url);
-                if res.is_some() {
-                    return res
+            };
+            match string_argument(fcv.arguments.first()).map(|href| base.join(&href)) {
+                Some(Ok(url)) => vec![FontSource::Url(url, format)],
+                _ => {
+                    println!("parsing error on font url {:#?}", fcv.arguments);
+                    vec![]
                 }
             }
-            None
         }
-        _ => None,
+        Value::FunCall(fcv) if fcv.name == "local" => {
+            let name:Vec<String> = fcv.arguments.iter().filter_map(|arg| string_argument(Some(arg))).collect();
+            vec![FontSource::Local(name.join(" "))]
+        }
+        Value::ArrayValue(vals) => vals.iter().flat_map(|val| font_sources(val, base)).collect(),
+        _ => vec![],
     }
 }
+
 fn extract_font_weight(value:&Value) -> Option<i32> {
     match value {
         Value::Keyword(str) => {
@@ -204,49 +285,171 @@ fn extract_font_weight(value:&Value) -> Option<i32> {
     }
 }
 
+// the weights a face covers. a range like `100 900` is a variable font, which is installed
+// for every hundred inside it.
+fn font_face_weights(value:&Value) -> Option<Vec<i32>> {
+    match value {
+        Value::ArrayValue(vals) if vals.len() == 2 => {
+            let (min, max) = (extract_font_weight(&vals[0])?, extract_font_weight(&vals[1])?);
+            let weights:Vec<i32> = (1..=9).map(|n| n * 100).filter(|w| *w >= min && *w <= max).collect();
+            Some(if weights.is_empty() { vec![min] } else { weights })
+        }
+        value => extract_font_weight(value).map(|weight| vec![weight]),
+    }
+}
+
+// oblique faces are used for italic text, since that is all the font-style property asks for
+fn font_face_style(value:&Value) -> Option<String> {
+    let keyword = match value {
+        Value::Keyword(str) => str,
+        Value::ArrayValue(vals) => match vals.first() {
+            Some(Value::Keyword(str)) => str,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    match keyword.as_str() {
+        "normal" => Some(String::from("normal")),
+        "italic" | "oblique" => Some(String::from("italic")),
+        _ => None,
+    }
+}
+
+// an installed font by its postscript name, like OpenSans-Bold, or its full name, like
+// Open Sans Bold. the system's font index is asked, so nothing is scanned here. a full name
+// starts with its family's, so only the faces of those families are opened to check.
+fn find_local_font(name:&str) -> Option<Font<'static>> {
+    let source = SystemSource::new();
+    let handle = source.select_by_postscript_name(name).ok().or_else(|| {
+        let full_name = name.to_lowercase();
+        source.all_families().ok()?.iter()
+            .filter(|family| full_name.starts_with(&family.to_lowercase()))
+            .filter_map(|family| source.select_family_by_name(family).ok())
+            .flat_map(|family| family.fonts().to_vec())
+            .find(|handle| handle.load().is_ok_and(|font| font.full_name().to_lowercase() == full_name))
+    })?;
+    let (bytes, index) = match handle {
+        Handle::Path { path, font_index } => (std::fs::read(path).ok()?, font_index),
+        Handle::Memory { bytes, font_index } => (bytes.to_vec(), font_index),
+    };
+    // the file may be a collection of several fonts
+    FontCollection::from_bytes(bytes).and_then(|fonts| fonts.font_at(index as usize)).ok()
+}
+
+fn load_font_source(source:&FontSource, page:Option<&Url>) -> Result<Font<'static>, BrowserError> {
+    match source {
+        FontSource::Url(url, _) => load_font_from_net(page, url.clone()),
+        FontSource::Local(name) => find_local_font(name)
+            .ok_or_else(|| BrowserError::ParseError(format!("no local font named {}", name))),
+    }
+}
+
 impl FontCache {
-    pub fn scan_for_fontface_rules(&mut self, stylesheet: &Stylesheet) {
+    pub fn scan_for_fontface_rules(&mut self, stylesheet: &Stylesheet, page: Option<&Url>) {
         for rule in stylesheet.rules.iter() {
             if let RuleType::AtRule(at_rule) = rule {
                 if at_rule.name == "font-face" {
-                    println!("found a font face rule");
-                    // println!("we have an at rule {:#?}",at_rule);
                     for rule in at_rule.rules.iter() {
                         if let RuleType::Rule(rule) = &rule {
-                            // println!("Processing real rules {:#?}",rule);
-                            let mut src: Option<Url> = Option::None;
-                            let mut font_family: Option<String> = Option::None;
-                            let mut font_weight: Option<i32> = Option::None;
-                            for dec in rule.declarations.iter() {
-                                if dec.name == "src" {
-                                    src = find_truetype_url(&dec.value, &stylesheet.base_url);
-                                }
-                                if dec.name == "font-weight" {
-                                    font_weight = extract_font_weight(&dec.value);
-                                }
-                                if dec.name == "font-style" {
-
-                                }
-                                if dec.name == "font-family" {
-                                    match &dec.value {
-                                        Value::StringLiteral(str) => font_family = Some(str.clone()),
-                                        _ => font_family = None,
-                                    }
-                                }
-                            }
-                            println!("got it {:#?} {:#?} {:#?}",font_family, src, font_weight);
-                            if font_family.is_some() && src.is_some() && font_weight.is_some() {
-                                let url = src.unwrap();
-                                let font = load_font_from_net(url);
-                                let font = font.unwrap();
-                                self.install_font(font, &*font_family.unwrap(),
-                                                  font_weight.unwrap(),
-                                                  "normal")
-                            }
+                            self.install_font_face(rule, &stylesheet.base_url, page);
                         }
                     }
                 }
             }
         }
     }
+    // installs the first source in the list that is a supported format and loads
+    fn install_font_face(&mut self, rule:&Rule, base_url:&Url, page:Option<&Url>) {
+        let mut sources:Vec<FontSource> = vec![];
+        let mut font_family: Option<String> = None;
+        let mut weights = vec![400];
+        let mut style = String::from("normal");
+        for dec in rule.declarations.iter() {
+            match dec.name.as_str() {
+                // a later src replaces an earlier one, which is how fallbacks for old browsers work
+                "src" => sources = font_sources(&dec.value, base_url),
+                "font-family" => font_family = font_family_name(&dec.value),
+                "font-weight" => weights = font_face_weights(&dec.value).unwrap_or(weights),
+                "font-style" => style = font_face_style(&dec.value).unwrap_or(style),
+                _ => {}
+            }
+        }
+        let font_family = match font_family {
+            Some(family) => family,
+            None => return,
+        };
+        for source in sources.iter().filter(|source| source.is_supported()) {
+            match load_font_source(source, page) {
+                Ok(font) => {
+                    for weight in weights.iter() {
+                        self.install_font(font.clone(), &font_family, *weight, &style);
+                    }
+                    return;
+                }
+                Err(err) => println!("error loading font {:#?}", err),
+            }
+        }
+        println!("no usable source for the font {}", font_family);
+    }
+}
+
+#[test]
+fn test_font_face_rules() {
+    let css = br#"
+        @font-face {
+            font-family: "Test Sans";
+            src: url("missing.woff2") format("woff2"), local("No Such Font Anywhere"),
+                 url("fonts/Open_Sans/OpenSans-Italic.ttf") format("truetype"),
+                 url("fonts/Open_Sans/OpenSans-Bold.ttf");
+            font-weight: 300 500;
+            font-style: oblique;
+        }
+        @font-face {
+            font-family: Test Mono;
+            src: url(fonts/Source_Code_Pro/SourceCodePro-Regular.ttf);
+        }
+    "#;
+    let mut stylesheet = crate::css::parse_stylesheet_from_bytestring(css).unwrap();
+    stylesheet.base_url = relative_filepath_to_url("tests/fonts.css").unwrap();
+    let mut font_cache = FontCache {
+        brush: Brush::Style2(glium_glyph::glyph_brush::GlyphBrushBuilder::without_fonts().build()),
+        families: Default::default(),
+        fonts: Default::default()
+    };
+    font_cache.scan_for_fontface_rules(&stylesheet, None);
+    for weight in [300, 400, 500].iter() {
+        assert!(font_cache.fonts.contains_key(&font_cache.make_key("Test Sans", *weight, "italic")));
+    }
+    assert!(!font_cache.fonts.contains_key(&font_cache.make_key("Test Sans", 600, "italic")));
+    assert!(!font_cache.fonts.contains_key(&font_cache.make_key("Test Sans", 400, "normal")));
+    // only the first usable source is installed
+    assert_eq!(font_cache.fonts.len(), 3 + 1);
+    assert!(font_cache.fonts.contains_key(&font_cache.make_key("Test Mono", 400, "normal")));
+
+    // whatever is installed can be found by either of its names
+    let installed = SystemSource::new().all_fonts().unwrap_or_default().into_iter()
+        .find_map(|handle| handle.load().ok());
+    if let Some(font) = installed {
+        assert!(find_local_font(&font.full_name()).is_some());
+        if let Some(postscript_name) = font.postscript_name() {
+            assert!(find_local_font(&postscript_name).is_some());
+        }
+    }
+    assert!(find_local_font("No Such Font Anywhere").is_none());
+}
+
+#[test]
+fn test_text_rendering_snap() {
+    let subpixel = TextRendering { subpixel_positioning: true, lcd_antialiasing: false };
+    assert_eq!(subpixel.snap(10.3), 10.3);
+    assert!(subpixel.position_tolerance() < 1.0);
+    let whole = TextRendering { subpixel_positioning: false, ..subpixel };
+    assert_eq!(whole.snap(10.3), 10.0);
+    assert_eq!(whole.snap(10.6), 11.0);
+    assert_eq!(whole.channel_offsets(), vec![0.0]);
+
+    // each channel is rasterized at its own stripe, even when the text itself is snapped
+    let lcd = TextRendering { lcd_antialiasing: true, ..whole };
+    assert_eq!(lcd.channel_offsets().len(), 3);
+    assert!(lcd.position_tolerance() < 1.0 / 3.0);
 }
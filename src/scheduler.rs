@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+/// Why the window needs painting again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Damage {
+    Input,
+    Resize,
+    Navigation,
+    Expose,
+    AnimationTick,
+    ResourceLoaded,
+}
+
+/// Decides when to paint. Nothing is drawn until something damages the window, and then
+/// at most once per display refresh, so an idle page costs no CPU at all.
+pub struct FrameScheduler {
+    frame_interval: Duration,
+    pending: Vec<Damage>,
+    last_frame: Option<Instant>,
+    animating: bool,
+}
+
+impl FrameScheduler {
+    pub fn new(refresh_rate: u32) -> FrameScheduler {
+        FrameScheduler {
+            frame_interval: Duration::from_secs(1) / refresh_rate.max(1),
+            pending: vec![],
+            last_frame: None,
+            animating: false,
+        }
+    }
+    pub fn damage(&mut self, damage: Damage) {
+        if !self.pending.contains(&damage) {
+            self.pending.push(damage);
+        }
+    }
+    /// What has damaged the window since the last frame.
+    pub fn pending(&self) -> &[Damage] {
+        &self.pending
+    }
+    /// While animating, every frame counts as damaged by an animation tick.
+    pub fn set_animating(&mut self, animating: bool) {
+        self.animating = animating;
+    }
+    fn wants_frame(&self) -> bool {
+        self.animating || !self.pending.is_empty()
+    }
+    // the earliest a frame can be drawn, keeping frames a refresh apart
+    fn next_frame_time(&self, now: Instant) -> Instant {
+        match self.last_frame {
+            Some(last) => (last + self.frame_interval).max(now),
+            None => now,
+        }
+    }
+    pub fn should_draw(&self, now: Instant) -> bool {
+        self.wants_frame() && self.next_frame_time(now) <= now
+    }
+    pub fn frame_drawn(&mut self, now: Instant) {
+        self.pending.clear();
+        self.last_frame = Some(now);
+    }
+    /// When the event loop should wake up to draw, or None to sleep until the next event.
+    pub fn next_wake(&self, now: Instant) -> Option<Instant> {
+        if self.wants_frame() {
+            Some(self.next_frame_time(now))
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn test_frame_scheduler() {
+    let start = Instant::now();
+    let mut scheduler = FrameScheduler::new(50);
+    // idle until something happens
+    assert!(!scheduler.should_draw(start));
+    assert_eq!(scheduler.next_wake(start), None);
+    scheduler.damage(Damage::Input);
+    scheduler.damage(Damage::Input);
+    scheduler.damage(Damage::Resize);
+    assert_eq!(scheduler.pending(), &[Damage::Input, Damage::Resize]);
+    assert!(scheduler.should_draw(start));
+    scheduler.frame_drawn(start);
+    assert!(scheduler.pending().is_empty());
+    assert_eq!(scheduler.next_wake(start), None);
+    // more damage right away waits for the next refresh
+    let soon = start + Duration::from_millis(5);
+    scheduler.damage(Damage::Input);
+    assert!(!scheduler.should_draw(soon));
+    assert_eq!(scheduler.next_wake(soon), Some(start + Duration::from_millis(20)));
+    assert!(scheduler.should_draw(start + Duration::from_millis(20)));
+    // animations keep asking for frames
+    scheduler.frame_drawn(start + Duration::from_millis(20));
+    scheduler.set_animating(true);
+    assert_eq!(scheduler.next_wake(start + Duration::from_millis(21)), Some(start + Duration::from_millis(40)));
+}
@@ -0,0 +1,490 @@
+use crate::css::{Value, Color, RuleType, KeyframesRule, Declaration};
+use crate::css::Value::{Keyword, Length, ColorValue, HexColor};
+use crate::net::StylesheetSet;
+use super::{StyledNode, find_color_lazy_static, initial_value};
+
+// The animation model: what the animation-* properties and @keyframes rules say an element
+// should look like at a given time. Nothing drives it from a clock yet; the renderer can ask
+// for the value of a property at some elapsed time once it does.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepPosition {
+    Start,
+    End,
+    None,
+    Both,
+}
+
+/// How the progress through a keyframe segment maps to the progress of the value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimingFunction {
+    Linear,
+    CubicBezier(f32, f32, f32, f32),
+    Steps(u32, StepPosition),
+}
+
+pub const EASE:TimingFunction = TimingFunction::CubicBezier(0.25, 0.1, 0.25, 1.0);
+
+impl TimingFunction {
+    /// None for a value that isn't a timing function, like `cubic-bezier(2, 0, 0, 1)`.
+    pub fn from_value(value:&Value) -> Option<TimingFunction> {
+        match value {
+            Keyword(kw) => match kw.to_lowercase().as_str() {
+                "linear" => Some(TimingFunction::Linear),
+                "ease" => Some(EASE),
+                "ease-in" => Some(TimingFunction::CubicBezier(0.42, 0.0, 1.0, 1.0)),
+                "ease-out" => Some(TimingFunction::CubicBezier(0.0, 0.0, 0.58, 1.0)),
+                "ease-in-out" => Some(TimingFunction::CubicBezier(0.42, 0.0, 0.58, 1.0)),
+                "step-start" => Some(TimingFunction::Steps(1, StepPosition::Start)),
+                "step-end" => Some(TimingFunction::Steps(1, StepPosition::End)),
+                _ => None,
+            },
+            Value::FunCall(call) => match (call.name.as_str(), call.arguments.as_slice()) {
+                ("cubic-bezier", [Value::Number(x1), Value::Number(y1), Value::Number(x2), Value::Number(y2)]) => {
+                    // the curve has to be a function of x
+                    if (0.0..=1.0).contains(x1) && (0.0..=1.0).contains(x2) {
+                        Some(TimingFunction::CubicBezier(*x1, *y1, *x2, *y2))
+                    } else {
+                        None
+                    }
+                }
+                ("steps", [Value::Number(n)]) => steps(*n, StepPosition::End),
+                ("steps", [Value::Number(n), Keyword(pos)]) => match pos.to_lowercase().as_str() {
+                    "start" | "jump-start" => steps(*n, StepPosition::Start),
+                    "end" | "jump-end" => steps(*n, StepPosition::End),
+                    "jump-none" if *n >= 2.0 => steps(*n, StepPosition::None),
+                    "jump-both" => steps(*n, StepPosition::Both),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    /// The eased progress for a progress `t` between 0 and 1.
+    pub fn ease(&self, t:f32) -> f32 {
+        match self {
+            TimingFunction::Linear => t,
+            TimingFunction::CubicBezier(x1, y1, x2, y2) => {
+                let s = solve_bezier_x(*x1, *x2, t);
+                bezier(*y1, *y2, s)
+            }
+            TimingFunction::Steps(n, position) => {
+                let n = *n as f32;
+                let mut step = (t * n).floor();
+                if matches!(position, StepPosition::Start | StepPosition::Both) {
+                    step += 1.0;
+                }
+                let jumps = match position {
+                    StepPosition::None => n - 1.0,
+                    StepPosition::Both => n + 1.0,
+                    _ => n,
+                };
+                (step / jumps).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+fn steps(n:f32, position:StepPosition) -> Option<TimingFunction> {
+    if n >= 1.0 && n.fract() == 0.0 {
+        Some(TimingFunction::Steps(n as u32, position))
+    } else {
+        None
+    }
+}
+
+// one coordinate of a bezier from (0,0) to (1,1) with the control points p1 and p2
+fn bezier(p1:f32, p2:f32, s:f32) -> f32 {
+    let inv = 1.0 - s;
+    3.0 * inv * inv * s * p1 + 3.0 * inv * s * s * p2 + s * s * s
+}
+
+// the curve parameter where the x coordinate is x. newton's method usually gets there in a
+// few steps, with bisection for the flat parts of the curve where it doesn't
+fn solve_bezier_x(x1:f32, x2:f32, x:f32) -> f32 {
+    let mut s = x;
+    for _ in 0..8 {
+        let err = bezier(x1, x2, s) - x;
+        if err.abs() < 1e-6 {
+            return s;
+        }
+        let slope = 3.0 * (1.0 - s) * (1.0 - s) * x1 + 6.0 * (1.0 - s) * s * (x2 - x1) + 3.0 * s * s * (1.0 - x2);
+        if slope.abs() < 1e-6 {
+            break;
+        }
+        s -= err / slope;
+    }
+    let (mut lo, mut hi) = (0.0, 1.0);
+    s = x;
+    while hi - lo > 1e-6 {
+        if bezier(x1, x2, s) < x {
+            lo = s;
+        } else {
+            hi = s;
+        }
+        s = (lo + hi) / 2.0;
+    }
+    s
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IterationCount {
+    Count(f32),
+    Infinite,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnimationDirection {
+    Normal,
+    Reverse,
+    Alternate,
+    AlternateReverse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FillMode {
+    None,
+    Forwards,
+    Backwards,
+    Both,
+}
+
+/// One animation of an element, from its animation-* properties. Times are in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    pub name: String,
+    pub duration: f32,
+    pub timing_function: TimingFunction,
+    pub delay: f32,
+    pub iteration_count: IterationCount,
+    pub direction: AnimationDirection,
+    pub fill_mode: FillMode,
+}
+
+impl Animation {
+    /// How far through the keyframes the animation is after `elapsed` seconds, between 0 and 1
+    /// and with the direction applied. None when the animation has no effect at that time.
+    pub fn progress(&self, elapsed:f32) -> Option<f32> {
+        let active = elapsed - self.delay;
+        if active < 0.0 {
+            return match self.fill_mode {
+                FillMode::Backwards | FillMode::Both => Some(self.directed(0, 0.0)),
+                _ => None,
+            };
+        }
+        // with no duration it's over as soon as it starts, however many times it repeats
+        let total = match self.iteration_count {
+            _ if self.duration <= 0.0 => 0.0,
+            IterationCount::Count(count) => self.duration * count,
+            IterationCount::Infinite => f32::INFINITY,
+        };
+        if active >= total {
+            if !matches!(self.fill_mode, FillMode::Forwards | FillMode::Both) {
+                return None;
+            }
+            let count = match self.iteration_count {
+                IterationCount::Count(count) => count,
+                IterationCount::Infinite => return Some(self.directed(0, 1.0)),
+            };
+            // a whole number of iterations ends at the end of the last one
+            return Some(match count.fract() {
+                part if part == 0.0 && count > 0.0 => self.directed(count as u32 - 1, 1.0),
+                part => self.directed(count as u32, part),
+            });
+        }
+        let iterations = active / self.duration;
+        Some(self.directed(iterations.floor() as u32, iterations.fract()))
+    }
+    fn directed(&self, iteration:u32, progress:f32) -> f32 {
+        let reversed = match self.direction {
+            AnimationDirection::Normal => false,
+            AnimationDirection::Reverse => true,
+            AnimationDirection::Alternate => !iteration.is_multiple_of(2),
+            AnimationDirection::AlternateReverse => iteration.is_multiple_of(2),
+        };
+        if reversed { 1.0 - progress } else { progress }
+    }
+}
+
+fn seconds(value:&Value) -> f32 {
+    match value {
+        Value::Time(secs) if *secs >= 0.0 => *secs,
+        _ => 0.0,
+    }
+}
+
+// the value for the i-th animation. a list shorter than the list of names repeats
+fn layer(value:&Value, i:usize) -> &Value {
+    match value {
+        Value::ArrayValue(list) if !list.is_empty() => &list[i % list.len()],
+        value => value,
+    }
+}
+
+fn keyword_of(value:&Value) -> String {
+    match value {
+        Keyword(kw) => kw.to_lowercase(),
+        _ => String::new(),
+    }
+}
+
+impl StyledNode {
+    /// The animations the element's animation-* properties ask for, skipping any named `none`.
+    pub fn animations(&self) -> Vec<Animation> {
+        let longhand = |name:&str| self.value(name).or_else(|| initial_value(name)).unwrap_or(Value::Number(0.0));
+        let names = longhand("animation-name");
+        let count = match &names {
+            Value::ArrayValue(list) => list.len(),
+            _ => 1,
+        };
+        let (durations, timings, delays) = (longhand("animation-duration"), longhand("animation-timing-function"), longhand("animation-delay"));
+        let (counts, directions, fills) = (longhand("animation-iteration-count"), longhand("animation-direction"), longhand("animation-fill-mode"));
+        (0..count).filter_map(|i| {
+            let name = match layer(&names, i) {
+                Keyword(name) if name.to_lowercase() != "none" => name.clone(),
+                Value::StringLiteral(name) => name.clone(),
+                _ => return None,
+            };
+            Some(Animation {
+                name,
+                duration: seconds(layer(&durations, i)),
+                timing_function: TimingFunction::from_value(layer(&timings, i)).unwrap_or(EASE),
+                delay: match layer(&delays, i) {
+                    // a negative delay starts the animation part way through
+                    Value::Time(secs) => *secs,
+                    _ => 0.0,
+                },
+                iteration_count: match layer(&counts, i) {
+                    Value::Number(n) if *n >= 0.0 => IterationCount::Count(*n),
+                    Keyword(kw) if kw == "infinite" => IterationCount::Infinite,
+                    _ => IterationCount::Count(1.0),
+                },
+                direction: match keyword_of(layer(&directions, i)).as_str() {
+                    "reverse" => AnimationDirection::Reverse,
+                    "alternate" => AnimationDirection::Alternate,
+                    "alternate-reverse" => AnimationDirection::AlternateReverse,
+                    _ => AnimationDirection::Normal,
+                },
+                fill_mode: match keyword_of(layer(&fills, i)).as_str() {
+                    "forwards" => FillMode::Forwards,
+                    "backwards" => FillMode::Backwards,
+                    "both" => FillMode::Both,
+                    _ => FillMode::None,
+                },
+            })
+        }).collect()
+    }
+}
+
+/// The @keyframes rule with this name that is in effect. When there are several the last one wins.
+pub fn find_keyframes<'a>(styles:&'a StylesheetSet, name:&str) -> Option<&'a KeyframesRule> {
+    fn search<'a>(rules:&'a [RuleType], styles:&StylesheetSet, name:&str, found:&mut Option<&'a KeyframesRule>) {
+        for rule in rules {
+            match rule {
+                RuleType::Keyframes(kf) if kf.name == name => *found = Some(kf),
+                RuleType::Media(block) if styles.media.matches_any(&block.queries) => search(&block.rules, styles, name, found),
+                _ => {}
+            }
+        }
+    }
+    let mut found = None;
+    for sheet in styles.stylesheets.iter() {
+        search(&sheet.rules, styles, name, &mut found);
+    }
+    found
+}
+
+fn value_color(value:&Value) -> Option<Color> {
+    match value {
+        ColorValue(color) => Some(color.clone()),
+        HexColor(hex) => Some(Color::from_hex(hex)),
+        Keyword(kw) => find_color_lazy_static(kw),
+        _ => None,
+    }
+}
+
+fn mix(a:f32, b:f32, t:f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// The value part way from `from` to `to`. Lengths in the same unit, numbers and colors change
+/// smoothly, and anything else flips over halfway through.
+pub fn interpolate(from:&Value, to:&Value, t:f32) -> Value {
+    match (from, to) {
+        (Length(a, unit_a), Length(b, unit_b)) if unit_a == unit_b => Length(mix(*a, *b, t), unit_a.clone()),
+        (Value::Number(a), Value::Number(b)) => Value::Number(mix(*a, *b, t)),
+        (Value::Time(a), Value::Time(b)) => Value::Time(mix(*a, *b, t)),
+        (Value::ArrayValue(a), Value::ArrayValue(b)) if a.len() == b.len() => {
+            Value::ArrayValue(a.iter().zip(b.iter()).map(|(a, b)| interpolate(a, b, t)).collect())
+        }
+        _ => match (value_color(from), value_color(to)) {
+            (Some(a), Some(b)) => {
+                let channel = |a:u8, b:u8| mix(a as f32, b as f32, t).round().clamp(0.0, 255.0) as u8;
+                ColorValue(Color { r: channel(a.r, b.r), g: channel(a.g, b.g), b: channel(a.b, b.b), a: channel(a.a, b.a) })
+            }
+            _ if t < 0.5 => from.clone(),
+            _ => to.clone(),
+        },
+    }
+}
+
+/// The value of the property at `progress` through the keyframes. A keyframe can change the
+/// timing function for the segment that starts at it. Where there is no `from` or `to` keyframe
+/// for the property, the element's own value stands in for it.
+pub fn animated_value(rule:&KeyframesRule, name:&str, progress:f32, timing:&TimingFunction, underlying:Option<&Value>) -> Option<Value> {
+    let mut frames:Vec<(f32, Value, Option<TimingFunction>)> = vec![];
+    for keyframe in rule.keyframes.iter() {
+        let find = |dec_name:&str| keyframe.declarations.iter().rev().find(|dec:&&Declaration| dec.name == dec_name);
+        if let Some(dec) = find(name) {
+            let easing = find("animation-timing-function").and_then(|dec| TimingFunction::from_value(&dec.value));
+            for offset in keyframe.offsets.iter() {
+                frames.push((*offset, dec.value.clone(), easing.clone()));
+            }
+        }
+    }
+    // sorting is stable, so of two keyframes at the same offset the later one comes last
+    frames.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    if let Some(value) = underlying {
+        if frames.first().is_none_or(|frame| frame.0 > 0.0) {
+            frames.insert(0, (0.0, value.clone(), None));
+        }
+        if frames.last().is_none_or(|frame| frame.0 < 1.0) {
+            frames.push((1.0, value.clone(), None));
+        }
+    }
+    let start = frames.iter().rposition(|frame| frame.0 <= progress)
+        .map(|i| if i + 1 == frames.len() && i > 0 && frames[i].0 == progress { i - 1 } else { i })?;
+    let (from, to) = match (frames.get(start), frames.get(start + 1)) {
+        (Some(from), Some(to)) => (from, to),
+        (Some(from), None) => return Some(from.1.clone()),
+        _ => return None,
+    };
+    let span = to.0 - from.0;
+    let local = if span > 0.0 { ((progress - from.0) / span).clamp(0.0, 1.0) } else { 1.0 };
+    let eased = from.2.as_ref().unwrap_or(timing).ease(local);
+    Some(interpolate(&from.1, &to.1, eased))
+}
+
+#[test]
+fn test_timing_functions() {
+    let close = |a:f32, b:f32| (a - b).abs() < 0.001;
+    assert_eq!(TimingFunction::Linear.ease(0.3), 0.3);
+    assert!(close(EASE.ease(0.0), 0.0));
+    assert!(close(EASE.ease(1.0), 1.0));
+    // ease starts faster than linear
+    assert!(EASE.ease(0.25) > 0.25);
+    let ease_in_out = TimingFunction::from_value(&Keyword(String::from("ease-in-out"))).unwrap();
+    assert!(close(ease_in_out.ease(0.5), 0.5));
+    assert!(ease_in_out.ease(0.2) < 0.2);
+    let parse = |text:&str| match &crate::css::parse_stylesheet(&format!("a {{ animation-timing-function: {}; }}", text)).unwrap().rules[0] {
+        RuleType::Rule(rule) => TimingFunction::from_value(&rule.declarations[0].value),
+        _ => None,
+    };
+    let steps = |text:&str| parse(text).unwrap();
+    let four = steps("steps(4, end)");
+    assert_eq!((four.ease(0.0), four.ease(0.3), four.ease(0.99), four.ease(1.0)), (0.0, 0.25, 0.75, 1.0));
+    let start = steps("steps(4, jump-start)");
+    assert_eq!((start.ease(0.0), start.ease(0.3)), (0.25, 0.5));
+    assert_eq!(steps("steps(3, jump-none)").ease(0.5), 0.5);
+    assert_eq!(steps("steps(1, jump-both)").ease(0.5), 0.5);
+    assert_eq!(steps("cubic-bezier(0, 0, 1, 1)"), TimingFunction::CubicBezier(0.0, 0.0, 1.0, 1.0));
+    assert!(parse("cubic-bezier(1.5, 0, 0, 1)").is_none());
+}
+
+#[test]
+fn test_animation_progress() {
+    let mut anim = Animation {
+        name: String::from("spin"),
+        duration: 2.0,
+        timing_function: TimingFunction::Linear,
+        delay: 1.0,
+        iteration_count: IterationCount::Count(2.0),
+        direction: AnimationDirection::Normal,
+        fill_mode: FillMode::None,
+    };
+    assert_eq!(anim.progress(0.5), None);
+    assert_eq!(anim.progress(2.0), Some(0.5));
+    assert_eq!(anim.progress(4.0), Some(0.5));
+    assert_eq!(anim.progress(5.0), None);
+    anim.direction = AnimationDirection::Alternate;
+    assert_eq!(anim.progress(1.5), Some(0.25));
+    assert_eq!(anim.progress(3.5), Some(0.75));
+    anim.fill_mode = FillMode::Both;
+    anim.direction = AnimationDirection::Reverse;
+    assert_eq!(anim.progress(0.0), Some(1.0));
+    assert_eq!(anim.progress(9.0), Some(0.0));
+    anim.iteration_count = IterationCount::Count(1.5);
+    anim.direction = AnimationDirection::Normal;
+    assert_eq!(anim.progress(9.0), Some(0.5));
+    anim.iteration_count = IterationCount::Infinite;
+    assert_eq!(anim.progress(101.5), Some(0.25));
+    // no duration goes straight to the end, even repeating forever
+    anim.duration = 0.0;
+    assert_eq!(anim.progress(1.0), Some(1.0));
+    assert_eq!(anim.progress(5.0), Some(1.0));
+    anim.iteration_count = IterationCount::Count(3.0);
+    assert_eq!(anim.progress(5.0), Some(1.0));
+    anim.fill_mode = FillMode::None;
+    assert_eq!(anim.progress(5.0), None);
+}
+
+#[test]
+fn test_interpolate() {
+    use crate::css::Unit;
+    assert_eq!(interpolate(&Length(10.0, Unit::Px), &Length(20.0, Unit::Px), 0.25), Length(12.5, Unit::Px));
+    assert_eq!(interpolate(&Value::Number(0.0), &Value::Number(1.0), 0.5), Value::Number(0.5));
+    assert_eq!(interpolate(&Keyword(String::from("black")), &HexColor(String::from("#ffffff")), 0.5),
+               ColorValue(Color { r: 128, g: 128, b: 128, a: 255 }));
+    assert_eq!(interpolate(&ColorValue(Color { r: 0, g: 0, b: 255, a: 0 }), &ColorValue(Color { r: 255, g: 0, b: 0, a: 255 }), 0.2),
+               ColorValue(Color { r: 51, g: 0, b: 204, a: 51 }));
+    assert_eq!(interpolate(&Value::ArrayValue(vec![Length(0.0, Unit::Px), Length(4.0, Unit::Em)]),
+                           &Value::ArrayValue(vec![Length(10.0, Unit::Px), Length(2.0, Unit::Em)]), 0.5),
+               Value::ArrayValue(vec![Length(5.0, Unit::Px), Length(3.0, Unit::Em)]));
+    // different units and keywords can't be mixed, so they switch halfway
+    assert_eq!(interpolate(&Length(10.0, Unit::Px), &Length(2.0, Unit::Em), 0.4), Length(10.0, Unit::Px));
+    assert_eq!(interpolate(&Keyword(String::from("block")), &Keyword(String::from("none")), 0.6), Keyword(String::from("none")));
+}
+
+#[test]
+fn test_keyframe_animation() {
+    use crate::css::Unit;
+    let mut sheet = crate::css::parse_stylesheet(r#"
+        @keyframes grow {
+            from { width: 0px; animation-timing-function: linear; }
+            50% { width: 100px; color: red; }
+            to { width: 50px; }
+        }
+        @media print { @keyframes grow { to { width: 1px; } } }
+        div { animation: grow 2s 1s both; }
+    "#).unwrap();
+    super::expand_styles(&mut sheet);
+    let mut styles = StylesheetSet::new();
+    styles.append(sheet);
+    let doc = crate::dom::load_doc_from_bytestring(b"<div>hi</div>");
+    let stree = super::dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let root = stree.root.borrow();
+    let anims = root.animations();
+    assert_eq!(anims.len(), 1);
+    assert_eq!((anims[0].duration, anims[0].delay, anims[0].fill_mode), (2.0, 1.0, FillMode::Both));
+    assert_eq!(anims[0].timing_function, EASE);
+    let rule = find_keyframes(&styles, "grow").unwrap();
+    assert_eq!(rule.keyframes.len(), 3);
+    let at = |elapsed:f32, name:&str, underlying:Option<&Value>| {
+        animated_value(rule, name, anims[0].progress(elapsed).unwrap(), &anims[0].timing_function, underlying)
+    };
+    // the first segment is linear, the second eases
+    assert_eq!(at(1.5, "width", None), Some(Length(50.0, Unit::Px)));
+    assert_eq!(at(2.0, "width", None), Some(Length(100.0, Unit::Px)));
+    match at(2.5, "width", None) {
+        Some(Length(w, Unit::Px)) => assert!(w < 75.0 && w > 50.0),
+        value => panic!("unexpected {:?}", value),
+    }
+    assert_eq!(at(0.0, "width", None), Some(Length(0.0, Unit::Px)));
+    assert_eq!(at(10.0, "width", None), Some(Length(50.0, Unit::Px)));
+    // color only has a keyframe at 50%, so the element's own color is used at the ends
+    let blue = ColorValue(Color { r: 0, g: 0, b: 255, a: 255 });
+    assert_eq!(at(0.0, "color", Some(&blue)), Some(blue.clone()));
+    assert_eq!(at(2.0, "color", Some(&blue)), Some(ColorValue(Color { r: 255, g: 0, b: 0, a: 255 })));
+    assert_eq!(at(2.0, "height", None), None);
+}
@@ -0,0 +1,269 @@
+use crate::css::{Value, Unit};
+use crate::css::Value::{Keyword, Length};
+use super::{StyledNode, initial_value};
+use super::transform::is_length;
+use crate::layout::LengthContext;
+
+// Typed forms of the background-repeat, background-position and background-size longhands,
+// for painting a background image tiled and scaled. Only a single layer is understood.
+
+/// How an image repeats along one axis of the background area.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RepeatStyle {
+    Repeat,
+    /// as many whole copies as fit, spread out to fill the area
+    Space,
+    /// as many whole copies as fit, scaled to fill the area
+    Round,
+    NoRepeat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BackgroundRepeat {
+    pub x: RepeatStyle,
+    pub y: RepeatStyle,
+}
+
+/// Where the image goes along one axis. The offset is from the left or top edge, or from
+/// the right or bottom one when `from_end` is set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionComponent {
+    /// a length or percentage
+    pub offset: Value,
+    pub from_end: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundPosition {
+    pub x: PositionComponent,
+    pub y: PositionComponent,
+}
+
+/// How big the image is drawn. The two values of `Explicit` are each a length, a
+/// percentage of the background area, or `auto`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackgroundSize {
+    Cover,
+    Contain,
+    Explicit(Value, Value),
+}
+
+fn repeat_style(kw:&str) -> Option<RepeatStyle> {
+    match kw {
+        "repeat" => Some(RepeatStyle::Repeat),
+        "space" => Some(RepeatStyle::Space),
+        "round" => Some(RepeatStyle::Round),
+        "no-repeat" => Some(RepeatStyle::NoRepeat),
+        _ => None,
+    }
+}
+
+impl BackgroundRepeat {
+    pub fn from_value(value:&Value) -> Option<BackgroundRepeat> {
+        let keyword = |value:&Value| match value {
+            Keyword(kw) => Some(kw.to_lowercase()),
+            _ => None,
+        };
+        match value {
+            Value::ArrayValue(values) if values.len() == 2 => Some(BackgroundRepeat {
+                x: repeat_style(&keyword(&values[0])?)?,
+                y: repeat_style(&keyword(&values[1])?)?,
+            }),
+            value => match keyword(value)?.as_str() {
+                "repeat-x" => Some(BackgroundRepeat { x: RepeatStyle::Repeat, y: RepeatStyle::NoRepeat }),
+                "repeat-y" => Some(BackgroundRepeat { x: RepeatStyle::NoRepeat, y: RepeatStyle::Repeat }),
+                kw => repeat_style(kw).map(|style| BackgroundRepeat { x: style, y: style }),
+            },
+        }
+    }
+}
+
+fn position_keyword(value:&Value) -> Option<&str> {
+    match value {
+        Keyword(kw) if ["left", "right", "top", "bottom", "center"].contains(&kw.as_str()) => Some(kw.as_str()),
+        _ => None,
+    }
+}
+
+fn is_vertical(value:&Value) -> bool {
+    matches!(position_keyword(value), Some("top") | Some("bottom"))
+}
+
+fn is_horizontal(value:&Value) -> bool {
+    matches!(position_keyword(value), Some("left") | Some("right"))
+}
+
+impl PositionComponent {
+    fn center() -> PositionComponent {
+        PositionComponent { offset: Length(50.0, Unit::Per), from_end: false }
+    }
+    // a keyword on its own, or a length from the start edge
+    fn from_value(value:&Value) -> Option<PositionComponent> {
+        match position_keyword(value) {
+            Some("center") => Some(PositionComponent::center()),
+            Some(kw) => Some(PositionComponent { offset: Length(0.0, Unit::Per), from_end: kw == "right" || kw == "bottom" }),
+            None if is_length(value) => Some(PositionComponent { offset: value.clone(), from_end: false }),
+            None => None,
+        }
+    }
+
+    /// Where the image's start edge goes, from the start of the area. Percentages line up
+    /// that point of the image with the same point of the area.
+    pub fn resolve(&self, area:f32, image:f32, context:&LengthContext) -> f32 {
+        let offset = context.to_px(&self.offset, Some(area - image)).unwrap_or(0.0);
+        if self.from_end { area - image - offset } else { offset }
+    }
+}
+
+impl BackgroundPosition {
+    pub fn from_value(value:&Value) -> Option<BackgroundPosition> {
+        let values = match value {
+            Value::ArrayValue(values) => values.clone(),
+            value => vec![value.clone()],
+        };
+        match values.as_slice() {
+            [one] if is_vertical(one) => Some(BackgroundPosition { x: PositionComponent::center(), y: PositionComponent::from_value(one)? }),
+            [one] => Some(BackgroundPosition { x: PositionComponent::from_value(one)?, y: PositionComponent::center() }),
+            [a, b] => {
+                // only two keywords can be given the other way round
+                let swap = is_vertical(a) || is_horizontal(b);
+                let (x, y) = if swap { (b, a) } else { (a, b) };
+                if is_vertical(x) || is_horizontal(y) || (swap && (is_length(a) || is_length(b))) {
+                    return None;
+                }
+                Some(BackgroundPosition { x: PositionComponent::from_value(x)?, y: PositionComponent::from_value(y)? })
+            },
+            _ if values.len() <= 4 => edge_offsets(&values),
+            _ => None,
+        }
+    }
+}
+
+// the three and four value forms, like `right 10px bottom` or `left 5% top 20px`, where an
+// offset is from the edge the keyword before it names
+fn edge_offsets(values:&[Value]) -> Option<BackgroundPosition> {
+    let mut parts:Vec<(&Value, Option<&Value>)> = vec![];
+    let mut i = 0;
+    while i < values.len() {
+        position_keyword(&values[i])?;
+        let offset = values.get(i + 1).filter(|value| is_length(value));
+        parts.push((&values[i], offset));
+        i += if offset.is_some() { 2 } else { 1 };
+    }
+    if parts.len() != 2 {
+        return None;
+    }
+    let component = |(edge, offset):(&Value, Option<&Value>)| match offset {
+        // center can't have an offset
+        Some(_) if position_keyword(edge) == Some("center") => None,
+        Some(offset) => Some(PositionComponent { offset: offset.clone(),
+            from_end: matches!(position_keyword(edge), Some("right") | Some("bottom")) }),
+        None => PositionComponent::from_value(edge),
+    };
+    let (x, y) = if is_vertical(parts[0].0) || is_horizontal(parts[1].0) { (parts[1], parts[0]) } else { (parts[0], parts[1]) };
+    if is_vertical(x.0) || is_horizontal(y.0) {
+        return None;
+    }
+    Some(BackgroundPosition { x: component(x)?, y: component(y)? })
+}
+
+impl BackgroundSize {
+    pub fn from_value(value:&Value) -> Option<BackgroundSize> {
+        let is_size = |value:&Value| is_length(value) || matches!(value, Keyword(kw) if kw == "auto");
+        match value {
+            Keyword(kw) if kw == "cover" => Some(BackgroundSize::Cover),
+            Keyword(kw) if kw == "contain" => Some(BackgroundSize::Contain),
+            Value::ArrayValue(values) if values.len() == 2 && values.iter().all(is_size) =>
+                Some(BackgroundSize::Explicit(values[0].clone(), values[1].clone())),
+            // a single value is the width, and the height keeps the image's proportions
+            value if is_size(value) => Some(BackgroundSize::Explicit(value.clone(), Keyword(String::from("auto")))),
+            _ => None,
+        }
+    }
+
+    /// The size to draw an image of the given size at, in a background area of the given size.
+    pub fn resolve(&self, area:(f32, f32), image:(f32, f32), context:&LengthContext) -> (f32, f32) {
+        let (area_width, area_height) = area;
+        let (image_width, image_height) = image;
+        if image_width <= 0.0 || image_height <= 0.0 {
+            return area;
+        }
+        match self {
+            BackgroundSize::Cover | BackgroundSize::Contain => {
+                let (sx, sy) = (area_width / image_width, area_height / image_height);
+                let scale = if *self == BackgroundSize::Cover { sx.max(sy) } else { sx.min(sy) };
+                (image_width * scale, image_height * scale)
+            },
+            BackgroundSize::Explicit(width, height) => {
+                let width = context.to_px(width, Some(area_width)).map(|w| w.max(0.0));
+                let height = context.to_px(height, Some(area_height)).map(|h| h.max(0.0));
+                match (width, height) {
+                    (Some(w), Some(h)) => (w, h),
+                    (Some(w), None) => (w, w * image_height / image_width),
+                    (None, Some(h)) => (h * image_width / image_height, h),
+                    (None, None) => image,
+                }
+            },
+        }
+    }
+}
+
+impl StyledNode {
+    // the specified value, or the initial one if it's missing or doesn't make sense
+    fn background_longhand<T>(&self, name:&str, parse:fn(&Value) -> Option<T>) -> T {
+        self.value(name).and_then(|value| parse(&value))
+            .or_else(|| initial_value(name).and_then(|value| parse(&value)))
+            .expect("the initial value always parses")
+    }
+    pub fn background_repeat(&self) -> BackgroundRepeat {
+        self.background_longhand("background-repeat", BackgroundRepeat::from_value)
+    }
+    pub fn background_position(&self) -> BackgroundPosition {
+        self.background_longhand("background-position", BackgroundPosition::from_value)
+    }
+    pub fn background_size(&self) -> BackgroundSize {
+        self.background_longhand("background-size", BackgroundSize::from_value)
+    }
+}
+
+#[test]
+fn test_background_longhands() {
+    let mut sheet = crate::css::parse_stylesheet(r#"
+        #a { background: url(bg.png) repeat-x right 10px bottom / 50% auto; }
+        #b { background-repeat: space round; background-position: top; background-size: cover; }
+        #c { background-repeat: sideways; background-position: 10px left; background-size: 5px 6px 7px; }
+        #d { background-position: center 25%; background-size: 40px; }
+    "#).unwrap();
+    super::expand_styles(&mut sheet);
+    let mut styles = crate::net::StylesheetSet::new();
+    styles.append(sheet);
+    let doc = crate::dom::load_doc_from_bytestring(br#"<div><p id="a">a</p><p id="b">b</p><p id="c">c</p><p id="d">d</p></div>"#);
+    let stree = super::dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let root = stree.root.borrow();
+    let ps = root.children.borrow().clone();
+    let context = &LengthContext::new(&ps[0], 0.0, None);
+
+    assert_eq!(ps[0].background_repeat(), BackgroundRepeat { x: RepeatStyle::Repeat, y: RepeatStyle::NoRepeat });
+    let position = ps[0].background_position();
+    assert_eq!(position.x, PositionComponent { offset: Length(10.0, Unit::Px), from_end: true });
+    // an 80 x 20 image in a 200 x 100 area
+    assert_eq!((position.x.resolve(200.0, 80.0, context), position.y.resolve(100.0, 20.0, context)), (110.0, 80.0));
+    let size = ps[0].background_size();
+    assert_eq!(size, BackgroundSize::Explicit(Length(50.0, Unit::Per), Keyword(String::from("auto"))));
+    assert_eq!(size.resolve((200.0, 100.0), (80.0, 20.0), context), (100.0, 25.0));
+
+    assert_eq!(ps[1].background_repeat(), BackgroundRepeat { x: RepeatStyle::Space, y: RepeatStyle::Round });
+    let position = ps[1].background_position();
+    assert_eq!((position.x.resolve(200.0, 80.0, context), position.y.resolve(100.0, 20.0, context)), (60.0, 0.0));
+    assert_eq!(ps[1].background_size().resolve((200.0, 100.0), (80.0, 20.0), context), (400.0, 100.0));
+
+    // values that don't make sense fall back to the initial ones
+    assert_eq!(ps[2].background_repeat(), BackgroundRepeat { x: RepeatStyle::Repeat, y: RepeatStyle::Repeat });
+    assert_eq!(ps[2].background_position().x.resolve(200.0, 80.0, context), 0.0);
+    assert_eq!(ps[2].background_size(), BackgroundSize::Explicit(Keyword(String::from("auto")), Keyword(String::from("auto"))));
+    assert_eq!(ps[2].background_size().resolve((200.0, 100.0), (80.0, 20.0), context), (80.0, 20.0));
+
+    assert_eq!(ps[3].background_position().y.resolve(100.0, 20.0, context), 20.0);
+    assert_eq!(BackgroundSize::Contain.resolve((200.0, 100.0), (80.0, 20.0), context), (200.0, 50.0));
+    assert_eq!(ps[3].background_size().resolve((200.0, 100.0), (80.0, 20.0), context), (40.0, 10.0));
+}
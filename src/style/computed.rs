@@ -0,0 +1,246 @@
+use crate::css::{Value, Unit, CalcExpr};
+use crate::css::Value::{Keyword, Length};
+use crate::dom::Node;
+use super::{PropertyMap, is_current_color};
+
+// Computed values: what the cascade picked for an element, made absolute. Font sizes and
+// lengths in em, rem and the absolute units become px, keywords with a number behind them
+// become the number, and currentColor becomes the color. The ancestors are already computed,
+// so an inherited value never needs resolving again.
+
+const DEFAULT_FONT_SIZE:f32 = 16.0;
+
+/// Turns the element's cascaded values into computed values, in place.
+pub fn compute_values(values:&mut PropertyMap, ancestors:&[(&Node, &PropertyMap)]) {
+    let parent = ancestors.first().map(|(_, parent)| *parent);
+    let parent_font_size = parent.and_then(|parent| px(parent.get("font-size"))).unwrap_or(DEFAULT_FONT_SIZE);
+    // rems are of the root element's font size, and the root's own font size in rems is of the default
+    let root_font_size = ancestors.last().and_then(|(_, root)| px(root.get("font-size"))).unwrap_or(DEFAULT_FONT_SIZE);
+    let font_size = compute_font_size(values.get("font-size"), parent_font_size, root_font_size);
+    if let Some(size) = font_size {
+        values.insert(String::from("font-size"), Length(size, Unit::Px));
+    }
+    let font_size = font_size.unwrap_or(parent_font_size);
+    let root_font_size = if ancestors.is_empty() { font_size } else { root_font_size };
+
+    let parent_weight = match parent.and_then(|parent| parent.get("font-weight")) {
+        Some(Value::Number(weight)) => *weight,
+        _ => 400.0,
+    };
+    if let Some(weight) = values.get("font-weight").and_then(|weight| compute_font_weight(weight, parent_weight)) {
+        values.insert(String::from("font-weight"), Value::Number(weight));
+    }
+
+    for (name, value) in values.iter_mut() {
+        if name == "font-size" {
+            continue;
+        }
+        *value = compute_lengths(value, font_size, root_font_size);
+        if name == "line-height" {
+            if let Length(v, Unit::Per) = value {
+                *value = Length(*v / 100.0 * font_size, Unit::Px);
+            }
+        }
+    }
+    compute_border_widths(values);
+    resolve_current_color(values, ancestors);
+}
+
+fn px(value:Option<&Value>) -> Option<f32> {
+    match value {
+        Some(Length(v, Unit::Px)) => Some(*v),
+        _ => None,
+    }
+}
+
+// the absolute size keywords, from xx-small to xxx-large, around a medium of 16px
+const FONT_SIZE_KEYWORDS:[(&str, f32);8] = [
+    ("xx-small", 9.0), ("x-small", 10.0), ("small", 13.0), ("medium", 16.0),
+    ("large", 18.0), ("x-large", 24.0), ("xx-large", 32.0), ("xxx-large", 48.0),
+];
+
+// None leaves the value as it is, like a calc() that needs a box to resolve against
+fn compute_font_size(value:Option<&Value>, parent:f32, root:f32) -> Option<f32> {
+    match value? {
+        Length(v, Unit::Em) => Some(v * parent),
+        Length(v, Unit::Per) => Some(v / 100.0 * parent),
+        Length(v, Unit::Ch) | Length(v, Unit::Ex) => Some(v / 2.0 * parent),
+        Length(v, Unit::Rem) => Some(v * root),
+        Length(v, unit) => unit.px_per_unit().map(|per| v * per),
+        Keyword(kw) => match kw.to_lowercase().as_str() {
+            "larger" => Some(parent * 1.2),
+            "smaller" => Some(parent / 1.2),
+            kw => FONT_SIZE_KEYWORDS.iter().find(|(name, _)| *name == kw).map(|(_, size)| *size),
+        },
+        _ => None,
+    }
+}
+
+// bolder and lighter step from the parent's weight along the usual font weights
+fn compute_font_weight(value:&Value, parent:f32) -> Option<f32> {
+    match value {
+        Value::Number(weight) => Some(*weight),
+        Keyword(kw) => match kw.to_lowercase().as_str() {
+            "normal" => Some(400.0),
+            "bold" => Some(700.0),
+            "bolder" if parent < 350.0 => Some(400.0),
+            "bolder" if parent < 550.0 => Some(700.0),
+            "bolder" => Some(parent.max(900.0)),
+            "lighter" if parent < 100.0 => Some(parent),
+            "lighter" if parent < 550.0 => Some(100.0),
+            "lighter" if parent < 750.0 => Some(400.0),
+            "lighter" => Some(700.0),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// every length in px, except percentages, which need the size of a box, and ch and ex,
+// which need the font's own metrics
+fn compute_lengths(value:&Value, font_size:f32, root_font_size:f32) -> Value {
+    match value {
+        Length(v, unit) => match unit {
+            Unit::Px | Unit::Per | Unit::Ch | Unit::Ex | Unit::Fr => value.clone(),
+            Unit::Em => Length(v * font_size, Unit::Px),
+            Unit::Rem => Length(v * root_font_size, Unit::Px),
+            unit => Length(v * unit.px_per_unit().unwrap_or(0.0), Unit::Px),
+        },
+        Value::ArrayValue(values) => Value::ArrayValue(values.iter()
+            .map(|value| compute_lengths(value, font_size, root_font_size)).collect()),
+        Value::Calc(expr) => Value::Calc(compute_calc_lengths(expr, font_size, root_font_size)),
+        _ => value.clone(),
+    }
+}
+
+// the same inside a calc(), which keeps its percentages for layout to resolve
+fn compute_calc_lengths(expr:&CalcExpr, font_size:f32, root_font_size:f32) -> CalcExpr {
+    let both = |a:&CalcExpr, b:&CalcExpr| (Box::new(compute_calc_lengths(a, font_size, root_font_size)),
+                                            Box::new(compute_calc_lengths(b, font_size, root_font_size)));
+    match expr {
+        CalcExpr::Length(v, unit) => match compute_lengths(&Length(*v, unit.clone()), font_size, root_font_size) {
+            Length(v, unit) => CalcExpr::Length(v, unit),
+            _ => expr.clone(),
+        },
+        CalcExpr::Number(_) => expr.clone(),
+        CalcExpr::Sum(a, b) => { let (a, b) = both(a, b); CalcExpr::Sum(a, b) },
+        CalcExpr::Difference(a, b) => { let (a, b) = both(a, b); CalcExpr::Difference(a, b) },
+        CalcExpr::Product(a, b) => { let (a, b) = both(a, b); CalcExpr::Product(a, b) },
+        CalcExpr::Quotient(a, b) => { let (a, b) = both(a, b); CalcExpr::Quotient(a, b) },
+    }
+}
+
+fn border_width_keyword(value:&Value) -> Option<f32> {
+    match value {
+        Keyword(kw) => match kw.to_lowercase().as_str() {
+            "thin" => Some(1.0),
+            "medium" => Some(3.0),
+            "thick" => Some(5.0),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// a side without a border style has none, however wide it says it is
+fn compute_border_widths(values:&mut PropertyMap) {
+    for side in ["top", "right", "bottom", "left"].iter() {
+        let name = format!("border-width-{}", side);
+        let style = values.get(&format!("border-style-{}", side)).or_else(|| values.get("border-style"));
+        let no_border = matches!(style, Some(Keyword(kw)) if kw == "none" || kw == "hidden");
+        let width = match values.get(&name) {
+            Some(_) if no_border => Some(0.0),
+            Some(value) => border_width_keyword(value),
+            None => None,
+        };
+        if let Some(width) = width {
+            values.insert(name, Length(width, Unit::Px));
+        }
+    }
+    if let Some(width) = values.get("outline-width").and_then(border_width_keyword) {
+        values.insert(String::from("outline-width"), Length(width, Unit::Px));
+    }
+}
+
+// currentColor is the element's own color, or the inherited one when the element doesn't set
+// it. `color: currentColor` is the same as `color: inherit`.
+fn resolve_current_color(values:&mut PropertyMap, ancestors:&[(&Node, &PropertyMap)]) {
+    let color = match values.get("color") {
+        Some(color) if !is_current_color(color) => Some(color.clone()),
+        _ => ancestors.iter()
+            .find_map(|(_, props)| props.get("color"))
+            .filter(|color| !is_current_color(color))
+            .cloned(),
+    };
+    if let Some(color) = color {
+        for value in values.values_mut().filter(|value| is_current_color(value)) {
+            *value = color.clone();
+        }
+    }
+}
+
+#[test]
+fn test_computed_values() {
+    let mut sheet = crate::css::parse_stylesheet(r#"
+        div { font-size: 20px; font-weight: bold; color: red; border-width: thick; border-style: solid; }
+        p { font-size: 1.5em; margin-left: 2em; padding-top: 1rem; line-height: 150%; font-weight: bolder;
+            border-color: currentColor; border-style: none; border-width: 4px; outline-width: thin; }
+        span { font-size: larger; font-weight: lighter; width: 1in; letter-spacing: 0.5em; }
+    "#).unwrap();
+    super::expand_styles(&mut sheet);
+    let mut styles = crate::net::StylesheetSet::new();
+    styles.append(sheet);
+    let doc = crate::dom::load_doc_from_bytestring(b"<div><p><span>x</span></p></div>");
+    let stree = super::dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    let p = std::rc::Rc::clone(&div.children.borrow()[0]);
+    let span = std::rc::Rc::clone(&p.children.borrow()[0]);
+    let px = |v:f32| Some(Length(v, Unit::Px));
+
+    assert_eq!(div.value("font-weight"), Some(Value::Number(700.0)));
+    assert_eq!(div.value("border-width-top"), px(5.0));
+    assert_eq!(p.value("font-size"), px(30.0));
+    // ems are of the element's own font size, rems of the root's
+    assert_eq!(p.value("margin-left"), px(60.0));
+    assert_eq!(p.value("padding-top"), px(20.0));
+    assert_eq!(p.value("line-height"), px(45.0));
+    assert_eq!(p.value("font-weight"), Some(Value::Number(900.0)));
+    assert_eq!(p.value("border-width-left"), px(0.0));
+    assert_eq!(p.value("border-color"), Some(Keyword(String::from("red"))));
+    assert_eq!(p.value("outline-width"), px(1.0));
+    assert_eq!(span.value("font-size"), px(36.0));
+    assert_eq!(span.value("font-weight"), Some(Value::Number(700.0)));
+    assert_eq!(span.value("width"), px(96.0));
+    assert_eq!(span.value("letter-spacing"), px(18.0));
+}
+
+#[test]
+fn test_root_font_size() {
+    let mut sheet = crate::css::parse_stylesheet(r#"
+        div { font-size: 62.5%; }
+        p { font-size: 2em; padding-left: 1.5rem; width: calc(10rem + 50%); }
+        span { font-size: 1.2rem; margin-left: 1em; }
+    "#).unwrap();
+    super::expand_styles(&mut sheet);
+    let mut styles = crate::net::StylesheetSet::new();
+    styles.append(sheet);
+    let doc = crate::dom::load_doc_from_bytestring(b"<div><p><span>x</span></p></div>");
+    let stree = super::dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    let p = std::rc::Rc::clone(&div.children.borrow()[0]);
+    let span = std::rc::Rc::clone(&p.children.borrow()[0]);
+    let px = |v:f32| Some(Length(v, Unit::Px));
+
+    // the root's percentage is of the default size, and every rem below it is of that
+    assert_eq!(div.value("font-size"), px(10.0));
+    assert_eq!(p.value("font-size"), px(20.0));
+    assert_eq!(p.value("padding-left"), px(15.0));
+    assert_eq!(p.value("width"), Some(Value::Calc(CalcExpr::Sum(
+        Box::new(CalcExpr::Length(100.0, Unit::Px)), Box::new(CalcExpr::Length(50.0, Unit::Per))))));
+    assert_eq!(span.value("font-size"), px(12.0));
+    assert_eq!(span.value("margin-left"), px(12.0));
+    assert_eq!(span.lookup_root_font_size(), 10.0);
+    let context = crate::layout::LengthContext::new(&span, 200.0, None);
+    assert_eq!(context.to_px(&Length(3.0, Unit::Rem), None), Some(30.0));
+    assert_eq!(context.to_px(&Length(3.0, Unit::Em), None), Some(36.0));
+}
@@ -0,0 +1,190 @@
+use crate::css::{Value, FunCallValue};
+use crate::css::Value::{Keyword, Number, StringLiteral};
+use super::PropertyMap;
+
+// CSS counters. Styling walks the tree in document order, so the counters are kept as it goes:
+// counter-reset on an element starts a counter that the element, its descendants and its
+// following siblings see, and counter-increment bumps the nearest one with that name.
+
+/// The counters in scope at the current point of the walk, outermost first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Counters {
+    // name, value, and the depth of the element that started it
+    scopes: Vec<(String, i32, usize)>,
+    // the depth of the display:none element being walked, whose subtree counts nothing
+    hidden: Option<usize>,
+}
+
+// `a 2 b` is a set to 2 and b set to the default. `none` is no counters at all.
+fn counter_list(value:&Value, default:i32) -> Vec<(String, i32)> {
+    let parts = match value {
+        Value::ArrayValue(parts) => parts.as_slice(),
+        value => std::slice::from_ref(value),
+    };
+    let mut list:Vec<(String, i32)> = vec![];
+    for part in parts {
+        match part {
+            Keyword(kw) if kw == "none" => {},
+            Keyword(name) => list.push((name.clone(), default)),
+            Number(v) => if let Some(last) = list.last_mut() {
+                last.1 = *v as i32;
+            },
+            _ => {},
+        }
+    }
+    list
+}
+
+impl Counters {
+    /// Enters an element at `depth`, applying its counter-reset and then its counter-increment.
+    pub fn enter(&mut self, values:&PropertyMap, depth:usize) {
+        if self.hidden.is_none() && matches!(values.get("display"), Some(Keyword(kw)) if kw == "none") {
+            self.hidden = Some(depth);
+        }
+        if self.hidden.is_some() {
+            return;
+        }
+        for (name, start) in values.get("counter-reset").map(|value| counter_list(value, 0)).unwrap_or_default() {
+            // a sibling's counter of the same name ends where this one begins
+            match self.scopes.last_mut() {
+                Some(last) if last.0 == name && last.2 == depth => last.1 = start,
+                _ => self.scopes.push((name, start, depth)),
+            }
+        }
+        for (name, step) in values.get("counter-increment").map(|value| counter_list(value, 1)).unwrap_or_default() {
+            match self.scopes.iter_mut().rev().find(|(n, _, _)| *n == name) {
+                // counters stop at the ends of the i32 range rather than overflowing
+                Some(counter) => counter.1 = counter.1.saturating_add(step),
+                // incrementing a counter that doesn't exist starts one on the element
+                None => self.scopes.push((name, step, depth)),
+            }
+        }
+    }
+    /// Leaves the element at `depth`. The counters its children started go out of scope,
+    /// and its own stay for its following siblings.
+    pub fn leave(&mut self, depth:usize) {
+        self.scopes.retain(|(_, _, d)| *d <= depth);
+        if self.hidden == Some(depth) {
+            self.hidden = None;
+        }
+    }
+    /// The value of the nearest counter called name, 0 when there is none.
+    pub fn value(&self, name:&str) -> i32 {
+        self.scopes.iter().rev().find(|(n, _, _)| n == name).map(|(_, v, _)| *v).unwrap_or(0)
+    }
+    /// The values of every counter called name, outermost first, for counters().
+    pub fn values(&self, name:&str) -> Vec<i32> {
+        let values:Vec<i32> = self.scopes.iter().filter(|(n, _, _)| n == name).map(|(_, v, _)| *v).collect();
+        if values.is_empty() { vec![0] } else { values }
+    }
+}
+
+fn alphabetic(n:i32, first:char) -> String {
+    if n < 1 {
+        return n.to_string();
+    }
+    let mut n = n as u32;
+    let mut text = vec![];
+    while n > 0 {
+        n -= 1;
+        text.push(std::char::from_u32(first as u32 + n % 26).unwrap_or(first));
+        n /= 26;
+    }
+    text.iter().rev().collect()
+}
+
+fn roman(n:i32) -> String {
+    const NUMERALS:[(i32, &str);13] = [
+        (1000, "m"), (900, "cm"), (500, "d"), (400, "cd"), (100, "c"), (90, "xc"),
+        (50, "l"), (40, "xl"), (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i"),
+    ];
+    if !(1..4000).contains(&n) {
+        return n.to_string();
+    }
+    let mut n = n;
+    let mut text = String::new();
+    for (value, numeral) in NUMERALS.iter() {
+        while n >= *value {
+            text.push_str(numeral);
+            n -= value;
+        }
+    }
+    text
+}
+
+/// A counter value in one of the list-style-type styles. Styles it doesn't know are decimal.
+pub fn format_counter(n:i32, style:&str) -> String {
+    match style {
+        "none" => String::new(),
+        "disc" => String::from("•"),
+        "circle" => String::from("◦"),
+        "square" => String::from("▪"),
+        "decimal-leading-zero" if (0..10).contains(&n) => format!("0{}", n),
+        "lower-alpha" | "lower-latin" => alphabetic(n, 'a'),
+        "upper-alpha" | "upper-latin" => alphabetic(n, 'A'),
+        "lower-roman" => roman(n),
+        "upper-roman" => roman(n).to_uppercase(),
+        _ => n.to_string(),
+    }
+}
+
+/// The text of counter(name, style) or counters(name, separator, style) in a content property.
+pub fn counter_text(call:&FunCallValue, counters:&Counters) -> Option<String> {
+    let name = match call.arguments.first() {
+        Some(Keyword(name)) => name,
+        _ => return None,
+    };
+    let style = |arg:Option<&Value>| match arg {
+        Some(Keyword(style)) => style.to_lowercase(),
+        _ => String::from("decimal"),
+    };
+    match call.name.as_str() {
+        "counter" => Some(format_counter(counters.value(name), &style(call.arguments.get(1)))),
+        "counters" => {
+            let separator = match call.arguments.get(1) {
+                Some(StringLiteral(separator)) => separator,
+                _ => return None,
+            };
+            let style = style(call.arguments.get(2));
+            Some(counters.values(name).iter().map(|n| format_counter(*n, &style)).collect::<Vec<_>>().join(separator))
+        },
+        _ => None,
+    }
+}
+
+#[test]
+fn test_counters() {
+    let values = |css:&str| -> PropertyMap {
+        let mut sheet = crate::css::parse_stylesheet(&format!("a {{ {} }}", css)).unwrap();
+        super::expand_styles(&mut sheet);
+        match &sheet.rules[0] {
+            crate::css::RuleType::Rule(rule) => rule.declarations.iter().map(|dec| (dec.name.clone(), dec.value.clone())).collect(),
+            _ => panic!("expected a rule"),
+        }
+    };
+    let mut counters = Counters::default();
+    counters.enter(&values("counter-reset: chapter section 4;"), 0);
+    assert_eq!((counters.value("chapter"), counters.value("section")), (0, 4));
+    counters.enter(&values("counter-increment: chapter;"), 1);
+    counters.leave(1);
+    counters.enter(&values("counter-reset: section; counter-increment: section 2;"), 1);
+    assert_eq!(counters.values("section"), vec![4, 2]);
+    counters.enter(&values("counter-increment: section; display: none;"), 2);
+    counters.leave(2);
+    counters.leave(1);
+    // the sibling's reset is still in scope
+    assert_eq!((counters.value("chapter"), counters.value("section")), (1, 2));
+    counters.leave(0);
+    assert_eq!(counters.value("chapter"), 1);
+    assert_eq!(counters.value("missing"), 0);
+
+    let mut counters = Counters::default();
+    counters.enter(&values("counter-reset: big 2147483647; counter-increment: big;"), 0);
+    assert_eq!(counters.value("big"), i32::MAX);
+    counters.enter(&values("counter-reset: small -2147483648; counter-increment: small -1;"), 0);
+    assert_eq!(counters.value("small"), i32::MIN);
+
+    assert_eq!(format_counter(1994, "upper-roman"), "MCMXCIV");
+    assert_eq!(format_counter(28, "lower-alpha"), "ab");
+    assert_eq!(format_counter(7, "decimal-leading-zero"), "07");
+}
@@ -0,0 +1,207 @@
+use crate::css::{Declaration, Value, Unit};
+use crate::css::Value::{Keyword, Length, HexColor};
+use crate::dom::{Node, ElementData};
+use crate::dom::NodeType::Element;
+use super::{PropertyMap, find_color_lazy_static, expand_declarations};
+
+// Presentational hints: what legacy html attributes like bgcolor and cellpadding say about an
+// element's style. They cascade after the browser's stylesheet and before every page rule,
+// so any css on the page overrides them.
+
+/// The attributes presentational hints come from. Changing one can change the style of the
+/// element and, for a table's cellpadding and border, its cells.
+pub const HINT_ATTRIBUTES:[&str;12] = [
+    "width", "height", "bgcolor", "align", "valign", "text", "color", "face", "size", "border", "cellpadding",
+    "cellspacing",
+];
+
+// `100` is pixels and `50%` a percentage. anything after the number is ignored, like browsers do.
+fn dimension(text:&str) -> Option<Value> {
+    let text = text.trim();
+    let digits:String = text.chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+    let number = digits.parse::<f32>().ok()?;
+    if text[digits.len()..].trim_start().starts_with('%') {
+        Some(Length(number, Unit::Per))
+    } else {
+        Some(Length(number, Unit::Px))
+    }
+}
+
+fn pixels(text:&str) -> Option<Value> {
+    match dimension(text)? {
+        Length(_, Unit::Per) => None,
+        length => Some(length),
+    }
+}
+
+// #rgb, #rrggbb, rrggbb without the hash, or a color name
+fn color(text:&str) -> Option<Value> {
+    let text = text.trim();
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if (hex.len() == 3 || hex.len() == 6) && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Some(HexColor(format!("#{}", hex.to_lowercase())));
+    }
+    find_color_lazy_static(text).map(|_| Keyword(text.to_lowercase()))
+}
+
+fn keyword_from(text:&str, allowed:&[(&str, &str)]) -> Option<Value> {
+    let text = text.trim().to_lowercase();
+    allowed.iter().find(|(name, _)| *name == text).map(|(_, kw)| Keyword(String::from(*kw)))
+}
+
+// the seven sizes of <font size>, with 3 as the normal one. +1 and -1 are relative to it.
+fn font_size(text:&str) -> Option<Value> {
+    const SIZES:[f32;7] = [10.0, 13.0, 16.0, 18.0, 24.0, 32.0, 48.0];
+    let text = text.trim();
+    let size = match text.chars().next()? {
+        '+' => 3i32.saturating_add(text[1..].parse::<i32>().ok()?),
+        '-' => 3i32.saturating_sub(text[1..].parse::<i32>().ok()?),
+        _ => text.parse::<i32>().ok()?,
+    };
+    Some(Length(SIZES[size.clamp(1, 7) as usize - 1], Unit::Px))
+}
+
+// a table's border attribute, where an empty or odd value still means a border
+fn table_border(elem:&ElementData) -> Option<f32> {
+    elem.attributes.get("border").map(|text| match pixels(text) {
+        Some(Length(px, _)) => px,
+        _ => 1.0,
+    })
+}
+
+fn nearest_table<'a>(ancestors:&[(&'a Node, &PropertyMap)]) -> Option<&'a ElementData> {
+    ancestors.iter().find_map(|(node, _)| match &node.node_type {
+        Element(data) if data.tag_name == "table" => Some(data),
+        _ => None,
+    })
+}
+
+/// The declarations the element's presentational attributes come to, expanded into longhands.
+pub fn presentational_hints(elem:&ElementData, ancestors:&[(&Node, &PropertyMap)]) -> Vec<Declaration> {
+    let mut hints:Vec<Declaration> = vec![];
+    let mut hint = |name:&str, value:Option<Value>| {
+        if let Some(value) = value {
+            hints.push(Declaration { name: String::from(name), value, important: false });
+        }
+    };
+    let attr = |name:&str| elem.attributes.get(name).map(|value| value.as_str());
+    let tag = elem.tag_name.as_str();
+    let is_cell = tag == "td" || tag == "th";
+    let align = [("left", "left"), ("right", "right"), ("center", "center"), ("middle", "center")];
+
+    if ["img", "table", "td", "th", "col", "hr", "iframe"].contains(&tag) {
+        hint("width", attr("width").and_then(dimension));
+    }
+    if ["img", "table", "td", "th", "tr", "iframe"].contains(&tag) {
+        hint("height", attr("height").and_then(dimension));
+    }
+    if ["body", "table", "tr", "td", "th"].contains(&tag) {
+        hint("background-color", attr("bgcolor").and_then(color));
+    }
+    if is_cell || ["tr", "div", "p", "h1", "h2", "h3", "h4", "h5", "h6", "caption"].contains(&tag) {
+        hint("text-align", attr("align").and_then(|text| keyword_from(text, &align)));
+    }
+    if is_cell || tag == "tr" {
+        hint("vertical-align", attr("valign").and_then(|text|
+            keyword_from(text, &[("top", "top"), ("middle", "middle"), ("bottom", "bottom"), ("baseline", "baseline")])));
+    }
+    match tag {
+        "body" => hint("color", attr("text").and_then(color)),
+        "font" => {
+            hint("color", attr("color").and_then(color));
+            hint("font-family", attr("face").map(|face| Keyword(face.split(',').next().unwrap_or("").trim().to_string())));
+            hint("font-size", attr("size").and_then(font_size));
+        },
+        "table" => {
+            if attr("align").map(|text| text.trim().eq_ignore_ascii_case("center")) == Some(true) {
+                hint("margin-left", Some(Keyword(String::from("auto"))));
+                hint("margin-right", Some(Keyword(String::from("auto"))));
+            }
+            if let Some(px) = table_border(elem) {
+                hint("border-width", Some(Length(px, Unit::Px)));
+                hint("border-style", Some(Keyword(String::from(if px > 0.0 { "solid" } else { "none" }))));
+                hint("border-color", Some(Keyword(String::from("gray"))));
+            }
+            hint("border-spacing", attr("cellspacing").and_then(pixels));
+        },
+        _ => {},
+    }
+    if is_cell {
+        if let Some(table) = nearest_table(ancestors) {
+            hint("padding", table.attributes.get("cellpadding").and_then(|text| pixels(text)));
+            if table_border(table).is_some_and(|px| px > 0.0) {
+                hint("border-width", Some(Length(1.0, Unit::Px)));
+                hint("border-style", Some(Keyword(String::from("solid"))));
+                hint("border-color", Some(Keyword(String::from("gray"))));
+            }
+        }
+    }
+    expand_declarations(&mut hints);
+    hints
+}
+
+#[test]
+fn test_presentational_hints() {
+    let mut user_agent = crate::css::parse_stylesheet(r#"
+        td { display: table-cell; vertical-align: bottom; }
+    "#).unwrap();
+    super::expand_styles(&mut user_agent);
+    let mut sheet = crate::css::parse_stylesheet(r#"
+        .styled { background-color: red; }
+    "#).unwrap();
+    super::expand_styles(&mut sheet);
+    let mut styles = crate::net::StylesheetSet::new();
+    styles.append(user_agent);
+    styles.user_agent_count = 1;
+    styles.append(sheet);
+    let doc = crate::dom::load_doc_from_bytestring(br##"<div><table border="2" cellpadding="4" cellspacing="3" width="80%" bgcolor="ffcc00" align="center"><tr><td align="middle" valign="top" width="100">a</td><td bgcolor="blue" class="styled">b</td></tr></table><font color="#0F0" size="+2" face="Georgia, serif">c</font><img width="40px" height="x"></div>"##);
+    let stree = super::dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let root = stree.root.borrow();
+    let children = root.children.borrow();
+    let keyword = |kw:&str| Some(Keyword(String::from(kw)));
+    let px = |v:f32| Some(Length(v, Unit::Px));
+
+    let table = &children[0];
+    assert_eq!(table.value("width"), Some(Length(80.0, Unit::Per)));
+    assert_eq!(table.value("background-color"), Some(HexColor(String::from("#ffcc00"))));
+    assert_eq!(table.value("margin-left"), keyword("auto"));
+    assert_eq!(table.value("border-width-top"), px(2.0));
+    assert_eq!(table.value("border-spacing"), px(3.0));
+    let cells = |table:&super::StyledNode| -> Vec<std::rc::Rc<super::StyledNode>> {
+        let mut found = vec![];
+        fn collect(node:&std::rc::Rc<super::StyledNode>, found:&mut Vec<std::rc::Rc<super::StyledNode>>) {
+            if let Element(data) = &node.node.node_type {
+                if data.tag_name == "td" {
+                    found.push(std::rc::Rc::clone(node));
+                }
+            }
+            node.children.borrow().iter().for_each(|child| collect(child, found));
+        }
+        table.children.borrow().iter().for_each(|child| collect(child, &mut found));
+        found
+    };
+    let tds = cells(table);
+    assert_eq!(tds[0].value("text-align"), keyword("center"));
+    // the attribute beats the browser's own stylesheet
+    assert_eq!(tds[0].value("vertical-align"), keyword("top"));
+    assert_eq!(tds[1].value("vertical-align"), keyword("bottom"));
+    assert_eq!(tds[0].value("width"), px(100.0));
+    // cells take their padding and border from the table
+    assert_eq!(tds[0].value("padding-left"), px(4.0));
+    assert_eq!(tds[0].value("border-width-bottom"), px(1.0));
+    // and a page rule beats the attribute, however low its specificity
+    assert_eq!(tds[1].value("background-color"), keyword("red"));
+
+    let font = &children[1];
+    assert_eq!(font.value("color"), Some(HexColor(String::from("#0f0"))));
+    assert_eq!(font.value("font-size"), px(24.0));
+    assert_eq!(font.value("font-family"), keyword("Georgia"));
+    // sizes past either end are the biggest or smallest, however far past
+    assert_eq!(font_size("+2147483647"), px(48.0));
+    assert_eq!(font_size("-2147483647"), px(10.0));
+    assert_eq!(font_size("9"), px(48.0));
+
+    let img = &children[2];
+    assert_eq!(img.value("width"), px(40.0));
+    assert_eq!(img.value("height"), None);
+}
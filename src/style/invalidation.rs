@@ -0,0 +1,126 @@
+use std::cell::RefCell;
+use crate::dom::{Node, Mutation};
+use crate::dom::NodeType::Element;
+use crate::net::StylesheetSet;
+use super::{StyledTree, RuleIndex, styled_chain, replace_styled, restyle_at};
+use super::hints::HINT_ATTRIBUTES;
+
+// Invalidation after the document changes: which part of the styled tree is stale. A new text
+// only changes its own node, an id or class can change every style under the element, and a
+// sibling selector or an added child reaches the element's siblings, which is everything
+// under the parent. Layout is then done again from the styled tree.
+
+/// The part of the styled tree a mutation makes stale.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Invalidation {
+    /// only the node at the path, which keeps its styles
+    Node(Vec<usize>),
+    /// the node at the path and everything under it, styled again
+    Subtree(Vec<usize>),
+}
+
+fn parent_of(path:&[usize]) -> Vec<usize> {
+    path.split_last().map(|(_, parent)| parent.to_vec()).unwrap_or_default()
+}
+
+/// What the mutation makes stale in the styled tree.
+pub fn invalidation(tree:&StyledTree, mutation:&Mutation, index:&RuleIndex) -> Invalidation {
+    match mutation {
+        Mutation::Text(path) => Invalidation::Node(path.clone()),
+        // the new children need styles, and the ones after them have moved
+        Mutation::ChildList(path) => Invalidation::Subtree(path.clone()),
+        Mutation::Attribute(path, name) => {
+            let selected = name == "id" || name == "class";
+            if selected && index.siblings {
+                return Invalidation::Subtree(parent_of(path));
+            }
+            // ::before and ::after can show any attribute with attr()
+            let generated = styled_chain(tree, path)
+                .and_then(|chain| chain.last().cloned())
+                .map(|styled| styled.children.borrow().iter()
+                    .any(|ch| matches!(&ch.node.node_type, Element(ed) if ed.tag_name.starts_with("::"))))
+                .unwrap_or(false);
+            if selected || generated || name == "style" || HINT_ATTRIBUTES.contains(&name.as_str()) {
+                Invalidation::Subtree(path.clone())
+            } else {
+                Invalidation::Node(path.clone())
+            }
+        },
+    }
+}
+
+/// Brings the styled tree up to date after root, the document, was changed by mutation.
+/// Returns the invalidation it acted on, or None when the styled tree doesn't have the
+/// changed node, and has to be built again from the start.
+pub fn restyle_after_mutation(tree:&StyledTree, root:&Node, mutation:&Mutation, styles:&StylesheetSet) -> Option<Invalidation> {
+    let index = RuleIndex::new(styles);
+    let invalidation = invalidation(tree, mutation, &index);
+    let done = match &invalidation {
+        Invalidation::Node(path) => {
+            let styled = styled_chain(tree, path)?;
+            let old = styled.last()?;
+            let node = tree.make_with(root.node_at_path(path).clone(), old.specified_values.clone(),
+                                      old.pseudo_values.clone(), RefCell::new(old.children.borrow().clone()), path.clone());
+            replace_styled(tree, &styled, path, node);
+            true
+        },
+        Invalidation::Subtree(path) => restyle_at(tree, root, path, &index),
+    };
+    if done { Some(invalidation) } else { None }
+}
+
+#[test]
+fn test_mutation_invalidation() {
+    let mut styles = StylesheetSet::new();
+    styles.append(crate::css::parse_stylesheet(r#"
+        .note { color: red; }
+        .note p { font-weight: bold; }
+        a::after { content: attr(href); }
+    "#).unwrap());
+    let mut doc = crate::dom::load_doc_from_bytestring(br#"<div><p>one</p><span><p>two</p></span><a href="x">link</a></div>"#);
+    let tree = super::dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let styled = |path:&[usize]| styled_chain(&tree, path).unwrap().last().cloned().unwrap();
+    let untouched = styled(&[0]);
+
+    // a class reaches the element's descendants, but not its siblings
+    let mutation = doc.root_node.set_attribute(&[1], "class", Some("note")).unwrap();
+    assert_eq!(restyle_after_mutation(&tree, &doc.root_node, &mutation, &styles), Some(Invalidation::Subtree(vec![1])));
+    assert_eq!(styled(&[1]).value("color"), Some(crate::css::Value::Keyword(String::from("red"))));
+    assert_eq!(styled(&[1, 0]).value("font-weight"), Some(crate::css::Value::Number(700.0)));
+    assert!(std::rc::Rc::ptr_eq(&untouched, &styled(&[0])));
+
+    // new text only changes the text node
+    let span = styled(&[1]);
+    let mutation = doc.root_node.set_text(&[0, 0], "uno").unwrap();
+    assert_eq!(restyle_after_mutation(&tree, &doc.root_node, &mutation, &styles), Some(Invalidation::Node(vec![0, 0])));
+    assert_eq!(styled(&[0, 0]).node.text_content(), "uno");
+    assert!(std::rc::Rc::ptr_eq(&span, &styled(&[1])));
+
+    // an attribute no style uses keeps the element's styles, unless generated content shows it
+    assert_eq!(doc.root_node.set_attribute(&[0], "title", Some("t")).map(|m| invalidation(&tree, &m, &RuleIndex::new(&styles))),
+               Some(Invalidation::Node(vec![0])));
+    let mutation = doc.root_node.set_attribute(&[2], "href", Some("y")).unwrap();
+    assert_eq!(restyle_after_mutation(&tree, &doc.root_node, &mutation, &styles), Some(Invalidation::Subtree(vec![2])));
+    assert_eq!(styled(&[2]).children.borrow()[1].node.text_content(), "y");
+    assert_eq!(doc.root_node.set_attribute(&[2], "href", Some("y")), None);
+
+    // added children are styled, and the ones after them move along
+    let new_child = crate::dom::load_doc_from_bytestring(b"<p>zero</p>").root_node;
+    let mutation = doc.root_node.insert_child(&[], 0, new_child).unwrap();
+    assert_eq!(restyle_after_mutation(&tree, &doc.root_node, &mutation, &styles), Some(Invalidation::Subtree(vec![])));
+    assert_eq!(styled(&[2]).value("color"), Some(crate::css::Value::Keyword(String::from("red"))));
+    let (_, mutation) = doc.root_node.remove_child(&[], 0).unwrap();
+    restyle_after_mutation(&tree, &doc.root_node, &mutation, &styles);
+    assert_eq!(styled(&[1]).value("color"), Some(crate::css::Value::Keyword(String::from("red"))));
+
+    // paths past the tree change nothing
+    assert_eq!(doc.root_node.set_attribute(&[9], "class", Some("note")), None);
+    assert_eq!(doc.root_node.set_text(&[0, 0, 4], "x"), None);
+    assert_eq!(doc.root_node.insert_child(&[5, 1], 0, crate::dom::load_doc_from_bytestring(b"<p>x</p>").root_node), None);
+    assert!(doc.root_node.remove_child(&[3], 0).is_none());
+
+    // with a sibling selector, a class restyles the siblings too
+    styles.append(crate::css::parse_stylesheet(".note + a { color: blue; }").unwrap());
+    let mutation = doc.root_node.set_attribute(&[1], "class", None).unwrap();
+    assert_eq!(invalidation(&tree, &mutation, &RuleIndex::new(&styles)), Invalidation::Subtree(vec![]));
+}
@@ -1,34 +1,49 @@
-use crate::dom::{Node, ElementData, NodeType, load_doc_from_bytestring, strip_empty_nodes};
-use crate::css::{Selector, SimpleSelector, Rule, Stylesheet, Specificity, Value, Color, parse_stylesheet_from_bytestring, Unit, RuleType, Declaration};
+use crate::dom::{Node, ElementData, ElementState, NodeType, load_doc_from_bytestring, strip_empty_nodes};
+use crate::css::{Selector, SimpleSelector, Rule, Stylesheet, Specificity, Value, Color, parse_stylesheet_from_bytestring, parse_declarations, Unit, RuleType, Declaration, SupportsCondition, MediaQuery, MediaFeature, FONT_LONGHANDS, BACKGROUND_LONGHANDS, ANIMATION_LONGHANDS, BORDER_RADIUS_LONGHANDS, GRID_COLUMN_LONGHANDS, GRID_ROW_LONGHANDS};
 use std::collections::HashMap;
-use crate::css::Selector::{Simple, Ancestor};
+use crate::css::Selector::{Simple, Ancestor, Sibling};
 use crate::dom::NodeType::{Element, Text, Meta};
 use crate::css::Value::{Keyword, ColorValue, Length, HexColor,};
 use crate::net::{load_stylesheet_from_net, relative_filepath_to_url, load_doc_from_net, StylesheetSet, load_stylesheets_new};
-use std::fs::File;
-use std::io::BufReader;
 use crate::render::FontCache;
+use hints::presentational_hints;
+use computed::compute_values;
+use counters::{Counters, counter_text};
+use rule_index::{RuleIndex, AncestorFilter};
 use std::cell::RefCell;
 use std::rc::{Rc, Weak};
-use crate::layout::{Brush, standard_test_run, standard_test_run_no_default};
+use std::borrow::Cow;
+use std::mem;
+use std::sync::RwLock;
+use crate::layout::{Brush, standard_test_run, standard_test_run_no_default, Dimensions, EdgeSizes, Rect};
+
+pub mod animation;
+pub mod background;
+pub mod computed;
+pub mod counters;
+pub mod hints;
+pub mod invalidation;
+pub mod rule_index;
+pub mod transform;
 
 type PropertyMap = HashMap<String, Value>;
 
 
+// all 148 css named colors. built in, so they don't depend on the working directory.
+const CSS_COLOR_NAMES:&str = include_str!("../../res/css-color-names.json");
+
 fn load_css_json() -> HashMap<String, Color>{
-    println!("loading css-color-names.json");
-    let file = File::open("res/css-color-names.json").unwrap();
-    let reader = BufReader::new(file);
-    let json:serde_json::Value = serde_json::from_reader(reader).unwrap();
+    let json:serde_json::Value = serde_json::from_str(CSS_COLOR_NAMES).unwrap();
 
     let mut map:HashMap<String,Color> = HashMap::new();
     if let serde_json::Value::Object(obj) = json {
         for (key, value) in obj.iter() {
             if let serde_json::Value::String(val) = value {
-                map.insert(key.to_string(),Color::from_hex(&*val));
+                map.insert(key.to_lowercase(),Color::from_hex(val));
             }
         }
     }
+    map.insert(String::from("transparent"), Color { r: 0, g: 0, b: 0, a: 0 });
     map
 }
 
@@ -39,6 +54,116 @@ pub fn find_color_lazy_static(name: &str) -> Option<Color> {
     COLORS_MAP.get(&name.to_lowercase()).cloned()
 }
 
+/// The user agent theme that CSS system colors like `Canvas` and `LinkText` resolve to.
+#[derive(Debug, Clone)]
+pub struct ColorScheme {
+    /// whether pages see `prefers-color-scheme: dark`
+    pub dark: bool,
+    pub canvas: Color,
+    pub canvas_text: Color,
+    pub link_text: Color,
+    pub visited_text: Color,
+    pub active_text: Color,
+    pub button_face: Color,
+    pub button_text: Color,
+    pub field: Color,
+    pub field_text: Color,
+    pub highlight: Color,
+    pub highlight_text: Color,
+    pub gray_text: Color,
+}
+
+impl ColorScheme {
+    pub fn light() -> Self {
+        ColorScheme {
+            dark: false,
+            canvas: Color::from_hex("#ffffff"),
+            canvas_text: Color::from_hex("#000000"),
+            link_text: Color::from_hex("#0000ee"),
+            visited_text: Color::from_hex("#551a8b"),
+            active_text: Color::from_hex("#ff0000"),
+            button_face: Color::from_hex("#efefef"),
+            button_text: Color::from_hex("#000000"),
+            field: Color::from_hex("#ffffff"),
+            field_text: Color::from_hex("#000000"),
+            highlight: Color::from_hex("#3390ff"),
+            highlight_text: Color::from_hex("#ffffff"),
+            gray_text: Color::from_hex("#808080"),
+        }
+    }
+    pub fn dark() -> Self {
+        ColorScheme {
+            dark: true,
+            canvas: Color::from_hex("#121212"),
+            canvas_text: Color::from_hex("#ffffff"),
+            link_text: Color::from_hex("#9e9eff"),
+            visited_text: Color::from_hex("#d0adf0"),
+            active_text: Color::from_hex("#ff9e9e"),
+            button_face: Color::from_hex("#6b6b6b"),
+            button_text: Color::from_hex("#ffffff"),
+            field: Color::from_hex("#3b3b3b"),
+            field_text: Color::from_hex("#ffffff"),
+            highlight: Color::from_hex("#3390ff"),
+            highlight_text: Color::from_hex("#ffffff"),
+            gray_text: Color::from_hex("#a8a8a8"),
+        }
+    }
+    /// The scheme the desktop asks for. GTK_THEME=Adwaita:dark means dark, anything else light.
+    pub fn from_environment() -> Self {
+        match std::env::var("GTK_THEME") {
+            Ok(theme) if theme.to_lowercase().ends_with(":dark") => ColorScheme::dark(),
+            _ => ColorScheme::light(),
+        }
+    }
+    pub fn system_color(&self, name:&str) -> Option<Color> {
+        let color = match name.to_lowercase().as_str() {
+            "canvas" => &self.canvas,
+            "canvastext" => &self.canvas_text,
+            "linktext" => &self.link_text,
+            "visitedtext" => &self.visited_text,
+            "activetext" => &self.active_text,
+            "buttonface" => &self.button_face,
+            "buttontext" => &self.button_text,
+            "field" => &self.field,
+            "fieldtext" => &self.field_text,
+            "highlight" => &self.highlight,
+            "highlighttext" => &self.highlight_text,
+            "graytext" => &self.gray_text,
+            _ => return None,
+        };
+        Some(color.clone())
+    }
+}
+
+lazy_static! {
+    static ref COLOR_SCHEME: RwLock<ColorScheme> = RwLock::new(ColorScheme::light());
+}
+
+/// Switch the theme used for system colors. Takes effect the next time a page is styled.
+pub fn set_color_scheme(scheme:ColorScheme) {
+    *COLOR_SCHEME.write().unwrap() = scheme;
+}
+
+pub fn prefers_dark_color_scheme() -> bool {
+    COLOR_SCHEME.read().unwrap().dark
+}
+
+pub fn find_system_color(name:&str) -> Option<Color> {
+    COLOR_SCHEME.read().unwrap().system_color(name)
+}
+
+// system color keywords become concrete colors while styling, so they follow the current scheme
+fn resolve_system_color(name:&str, value:Value) -> Value {
+    if let Keyword(kw) = &value {
+        if name.ends_with("color") {
+            if let Some(color) = find_system_color(kw) {
+                return ColorValue(color);
+            }
+        }
+    }
+    value
+}
+
 #[derive(Debug)]
 pub enum Display {
     Block,
@@ -49,15 +174,61 @@ pub enum Display {
     TableRow,
     TableCell,
     ListItem,
+    Flex,
+    Grid,
     None,
 }
 
+/// Where an inline box sits on its line. Lengths and percentages are resolved to
+/// `Raise`, the distance in px to lift the box's baseline above the parent's.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerticalAlign {
+    Baseline,
+    Sub,
+    Super,
+    TextTop,
+    TextBottom,
+    Middle,
+    Top,
+    Bottom,
+    Raise(f32),
+}
+
+/// How text handles spaces, newlines and wrapping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WhiteSpace {
+    Normal,
+    NoWrap,
+    Pre,
+    PreWrap,
+    PreLine,
+}
+
+impl WhiteSpace {
+    /// Runs of spaces, tabs and newlines become a single space.
+    pub fn collapses_spaces(&self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::NoWrap | WhiteSpace::PreLine)
+    }
+    /// Newlines in the text start new lines.
+    pub fn keeps_newlines(&self) -> bool {
+        matches!(self, WhiteSpace::Pre | WhiteSpace::PreWrap | WhiteSpace::PreLine)
+    }
+    /// Lines break between words when they run out of room.
+    pub fn wraps(&self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::PreWrap | WhiteSpace::PreLine)
+    }
+}
+
 #[derive(Debug)]
 pub struct StyledNode {
     pub node: Node,
     pub children: RefCell<Vec<Rc<StyledNode>>>,
     parent: RefCell<Weak<StyledNode>>,
     pub specified_values: PropertyMap,
+    /// values for pseudo-elements of this node, such as `::selection`, keyed by name
+    pub pseudo_values: HashMap<String, PropertyMap>,
+    /// the child indexes leading to the node in the document
+    pub path: Vec<usize>,
 }
 
 #[derive(Debug)]
@@ -71,7 +242,9 @@ impl StyledTree {
                 node: Node { node_type: NodeType::Comment(String::from("comment")), children: vec![] },
                 children: RefCell::new(vec![]),
                 parent: RefCell::new(Default::default()),
-                specified_values: Default::default()
+                specified_values: Default::default(),
+                pseudo_values: Default::default(),
+                path: vec![],
             }))
         }
     }
@@ -83,15 +256,19 @@ impl StyledTree {
             },
             children: RefCell::new(vec![]),
             parent: RefCell::new(Weak::new()),
-            specified_values: Default::default()
+            specified_values: Default::default(),
+            pseudo_values: Default::default(),
+            path: vec![],
         })
     }
-    pub fn make_with(&self, node:Node, specified_values:PropertyMap, children:RefCell<Vec<Rc<StyledNode>>>) -> Rc<StyledNode> {
+    pub fn make_with(&self, node:Node, specified_values:PropertyMap, pseudo_values:HashMap<String,PropertyMap>, children:RefCell<Vec<Rc<StyledNode>>>, path:Vec<usize>) -> Rc<StyledNode> {
         let rc =  Rc::new(StyledNode {
             node,
             children,
             parent: RefCell::new(Default::default()),
             specified_values,
+            pseudo_values,
+            path,
         });
         for ch in rc.children.borrow().iter() {
             *ch.parent.borrow_mut() = Rc::downgrade(&rc);
@@ -136,6 +313,63 @@ impl StyledNode {
             _ => default.clone(),
         }
     }
+    pub fn lookup_vertical_align(&self) -> VerticalAlign {
+        match self.value("vertical-align") {
+            Some(Keyword(kw)) => match kw.as_str() {
+                "sub" => VerticalAlign::Sub,
+                "super" => VerticalAlign::Super,
+                "text-top" => VerticalAlign::TextTop,
+                "text-bottom" => VerticalAlign::TextBottom,
+                "middle" => VerticalAlign::Middle,
+                "top" => VerticalAlign::Top,
+                "bottom" => VerticalAlign::Bottom,
+                _ => VerticalAlign::Baseline,
+            },
+            // percentages are of the line height, which is the font size for now
+            Some(Length(v, Unit::Per)) => VerticalAlign::Raise(v / 100.0 * self.lookup_font_size()),
+            Some(Length(_, _)) => VerticalAlign::Raise(self.lookup_length_as_px("vertical-align", 0.0)),
+            _ => VerticalAlign::Baseline,
+        }
+    }
+    /// The line-height in px, or None for `normal`. A bare number is a multiple of the font size.
+    pub fn lookup_line_height(&self) -> Option<f32> {
+        match self.value("line-height") {
+            Some(Value::Number(n)) => Some(n * self.lookup_font_size()),
+            Some(Length(v, Unit::Per)) => Some(v / 100.0 * self.lookup_font_size()),
+            Some(Length(_, _)) | Some(Value::Calc(_)) => Some(self.lookup_length_as_px("line-height", 0.0)),
+            _ => None,
+        }
+    }
+    /// letter-spacing or word-spacing in px, 0 for `normal`.
+    pub fn lookup_spacing(&self, name:&str) -> f32 {
+        match self.value(name) {
+            Some(Length(_, _)) | Some(Value::Calc(_)) => self.lookup_length_as_px(name, 0.0),
+            _ => 0.0,
+        }
+    }
+    /// The element's own opacity, between 0 and 1. It isn't inherited, but it fades the
+    /// whole subtree when painting.
+    pub fn lookup_opacity(&self) -> f32 {
+        match self.value("opacity") {
+            Some(Value::Number(n)) => n.clamp(0.0, 1.0),
+            Some(Length(v, Unit::Per)) => (v / 100.0).clamp(0.0, 1.0),
+            _ => 1.0,
+        }
+    }
+    /// False for `visibility: hidden` or `collapse`. The box still takes up its space, but isn't
+    /// painted, and its descendants can be made visible again.
+    pub fn is_visible(&self) -> bool {
+        !matches!(self.lookup_string("visibility", "visible").as_str(), "hidden" | "collapse")
+    }
+    pub fn lookup_white_space(&self) -> WhiteSpace {
+        match self.lookup_string("white-space", "normal").as_str() {
+            "nowrap" => WhiteSpace::NoWrap,
+            "pre" => WhiteSpace::Pre,
+            "pre-wrap" => WhiteSpace::PreWrap,
+            "pre-line" => WhiteSpace::PreLine,
+            _ => WhiteSpace::Normal,
+        }
+    }
     pub fn lookup_text_decoration_line(&self) -> String {
         let val = self.lookup_keyword("text-decoration-line", &Value::Keyword(String::from("none")));
         if let Keyword(str) = val { str } else { "none".to_string() }
@@ -199,7 +433,10 @@ impl StyledNode {
                     Unit::Px  => v,
                     Unit::Per => v/100.0 * self.parent.borrow().upgrade().unwrap().lookup_font_size(),
                     Unit::Em  => v * self.parent.borrow().upgrade().unwrap().lookup_font_size(),
-                    Unit::Rem => v*18.0, //TODO: use the real document font-size for REMs
+                    Unit::Rem => v * self.lookup_root_font_size(),
+                    // half of the parent's font size, without its metrics
+                    Unit::Ch | Unit::Ex => v / 2.0 * self.parent.borrow().upgrade().unwrap().lookup_font_size(),
+                    unit => v * unit.px_per_unit().unwrap_or(0.0),
                 }
             }
             _ => {
@@ -209,18 +446,27 @@ impl StyledNode {
         }
 
     }
+    /// The font size of the root element, which rems are relative to.
+    pub fn lookup_root_font_size(&self) -> f32 {
+        match self.parent.borrow().upgrade() {
+            Some(parent) => parent.lookup_root_font_size(),
+            None => self.lookup_font_size(),
+        }
+    }
 
     pub fn lookup_length_as_px(&self, name:&str, default:f32) -> f32{
         if let Some(value) = self.value(name) {
             match value {
                 Length(v, Unit::Px) =>  v,
                 Length(v, Unit::Em) =>  v*self.lookup_font_size(),
-                Length(v, Unit::Rem) => v*self.lookup_font_size(),
-                // TODO: use real document font size
+                Length(v, Unit::Rem) => v*self.lookup_root_font_size(),
                 Length(_v, Unit::Per) => {
                     println!("WARNING: percentage in length_to_px. should have be converted to pixels already");
                     default
                 }
+                Length(v, Unit::Ch) | Length(v, Unit::Ex) => v * self.lookup_font_size() / 2.0,
+                Length(v, unit) => v * unit.px_per_unit().unwrap_or(0.0),
+                Value::Calc(expr) => expr.to_px(self.lookup_font_size(), 0.0),
                 _ => default,
             }
         } else {
@@ -238,10 +484,14 @@ impl StyledNode {
                 "none" => Display::None,
                 "inline-block" => Display::InlineBlock,
                 "table" => Display::Table,
-                "table-row-group" => Display::TableRowGroup,
+                "table-row-group" | "table-header-group" | "table-footer-group" => Display::TableRowGroup,
+                // a caption is a block in the table, which puts it first or last
+                "table-caption" => Display::Block,
                 "table-row" => Display::TableRow,
                 "table-cell" => Display::TableCell,
                 "list-item" => Display::ListItem,
+                "flex" => Display::Flex,
+                "grid" => Display::Grid,
                 _ => {
                     println!("WARNING: unsupported display keyword {}",s);
                     Display::Inline
@@ -251,6 +501,38 @@ impl StyledNode {
         }
     }
 
+    /// 1 for the first list item under its parent, 2 for the next, and so on. The list's
+    /// `start` and `reversed` and an item's own `value` change where the counting goes.
+    pub fn list_item_number(&self) -> i32 {
+        let parent = match self.parent.borrow().upgrade() {
+            Some(parent) => parent,
+            None => return 1,
+        };
+        let attribute = |node:&Node, name:&str| match &node.node_type {
+            Element(ed) => ed.attributes.get(name).cloned(),
+            _ => None,
+        };
+        let siblings = parent.children.borrow();
+        let is_item = |sib:&&Rc<StyledNode>| matches!(sib.display(), Display::ListItem);
+        let reversed = attribute(&parent.node, "reversed").is_some();
+        let step = if reversed { -1 } else { 1 };
+        let start = attribute(&parent.node, "start")
+            .and_then(|start| start.trim().parse::<i32>().ok())
+            .unwrap_or(if reversed { siblings.iter().filter(is_item).count() as i32 } else { 1 });
+        let mut number = start - step;
+        for sib in siblings.iter() {
+            if is_item(&sib) {
+                number = attribute(&sib.node, "value")
+                    .and_then(|value| value.trim().parse::<i32>().ok())
+                    .unwrap_or(number + step);
+            }
+            if std::ptr::eq(sib.as_ref(), self) {
+                break;
+            }
+        }
+        number
+    }
+
     pub fn color(&self, name: &str) -> Option<Color> {
         match self.value(name) {
             Some(ColorValue(c)) => Some(c),
@@ -261,6 +543,23 @@ impl StyledNode {
             _ => None,
         }
     }
+    /// color set on a pseudo-element of this node, e.g. `pseudo_color("selection","background-color")`
+    pub fn pseudo_color(&self, pseudo: &str, name: &str) -> Option<Color> {
+        match self.pseudo_values.get(pseudo).and_then(|values| values.get(name)) {
+            Some(ColorValue(c)) => Some(c.clone()),
+            Some(HexColor(str)) => Some(Color::from_hex(str)),
+            Some(Keyword(name)) => find_color_lazy_static(name),
+            _ => None,
+        }
+    }
+    /// colors for selected text, falling back to the given defaults when there is no `::selection` rule
+    pub fn selection_colors(&self, color: &Color, background: &Color) -> (Color, Color) {
+        let bg = self.pseudo_color("selection", "background-color")
+            .or_else(|| self.pseudo_color("selection", "background"))
+            .unwrap_or_else(|| background.clone());
+        let fg = self.pseudo_color("selection", "color").unwrap_or_else(|| color.clone());
+        (fg, bg)
+    }
     pub fn insets(&self, name: &str) -> f32 {
         match self.value(name) {
             Some(Length(v,_unit)) => v,
@@ -270,26 +569,108 @@ impl StyledNode {
 }
 
 fn matches(elem: &ElementData, selector: &Selector, ancestors:&mut Vec::<(&Node,&PropertyMap)>) -> bool {
+    matches_pseudo(elem, selector, ancestors, None)
+}
+
+// like matches, but the selector must target the given pseudo-element (or none)
+fn matches_pseudo(elem: &ElementData, selector: &Selector, ancestors:&mut Vec::<(&Node,&PropertyMap)>, pseudo:Option<&str>) -> bool {
     match *selector {
-        Simple(ref simple_selector) => matches_simple_selector(elem, simple_selector),
-        Ancestor(ref sel) => {
-            println!("ANCESTOR NOT SUPPORTED YET");
-
-            let child_match = matches(elem, &*sel.child, ancestors);
-            let mut parent_match = false;
-            if !ancestors.is_empty() {
-                let (parent_node,_) = &ancestors[0];
-                if let NodeType::Element(ed) = &parent_node.node_type {
-                    parent_match = matches(ed, &*sel.ancestor, ancestors);
-                }
-            }
-            child_match && parent_match
+        Simple(ref simple_selector) => matches_simple_selector(elem, simple_selector, pseudo),
+        Ancestor(_) | Sibling(_) => {
+            let mut parts = vec![];
+            let target = selector_parts(selector, &mut parts);
+            matches_simple_selector(elem, target, pseudo) && matches_parts(&parts, elem, ancestors)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+    Adjacent,
+    Sibling,
+}
+
+// splits a b > c + d into the simple selectors a, b and c, each with how it relates to the
+// one after it, and returns d
+fn selector_parts<'a>(selector:&'a Selector, parts:&mut Vec<(&'a SimpleSelector, Combinator)>) -> &'a SimpleSelector {
+    match selector {
+        Simple(simple) => simple,
+        Ancestor(anc) => {
+            let ancestor = selector_parts(&anc.ancestor, parts);
+            parts.push((ancestor, if anc.immediate { Combinator::Child } else { Combinator::Descendant }));
+            selector_parts(&anc.child, parts)
+        }
+        Sibling(sib) => {
+            let previous = selector_parts(&sib.previous, parts);
+            parts.push((previous, if sib.adjacent { Combinator::Adjacent } else { Combinator::Sibling }));
+            selector_parts(&sib.next, parts)
+        }
+    }
+}
+
+fn element_data(node:&Node) -> Option<&ElementData> {
+    match &node.node_type {
+        NodeType::Element(ed) => Some(ed),
+        _ => None,
+    }
+}
+
+// the elements before elem under the same parent, nearest first
+fn preceding_siblings<'a>(elem:&ElementData, parent:&'a Node) -> Vec<&'a ElementData> {
+    let mut siblings:Vec<&ElementData> = parent.children.iter()
+        .filter_map(element_data)
+        .take_while(|ed| !std::ptr::eq(*ed, elem))
+        .collect();
+    siblings.reverse();
+    siblings
+}
+
+// checks the parts left of elem's own selector. ancestors are elem's, nearest first.
+// descendant and general sibling combinators can match more than one element, so try each.
+fn matches_parts(parts:&[(&SimpleSelector, Combinator)], elem:&ElementData, ancestors:&[(&Node, &PropertyMap)]) -> bool {
+    let ((selector, combinator), rest) = match parts.split_last() {
+        Some(last) => last,
+        None => return true,
+    };
+    match combinator {
+        Combinator::Child | Combinator::Descendant => {
+            let candidates = if *combinator == Combinator::Child { ancestors.len().min(1) } else { ancestors.len() };
+            (0..candidates).any(|i| match element_data(ancestors[i].0) {
+                Some(ed) => matches_simple_selector(ed, selector, None)
+                    && matches_parts(rest, ed, &ancestors[i+1..]),
+                None => false,
+            })
+        }
+        Combinator::Adjacent | Combinator::Sibling => {
+            let siblings = match ancestors.first() {
+                Some((parent, _)) => preceding_siblings(elem, parent),
+                None => return false,
+            };
+            let candidates = if *combinator == Combinator::Adjacent { siblings.len().min(1) } else { siblings.len() };
+            siblings[..candidates].iter().any(|ed| matches_simple_selector(ed, selector, None)
+                && matches_parts(rest, ed, ancestors))
         }
     }
 }
 
-fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
+// the pseudo-classes that depend on what the user is doing. the rest aren't checked yet.
+fn matches_dynamic_pseudo_class(elem: &ElementData, pseudo_class:&str) -> bool {
+    match ElementState::from_pseudo_class(pseudo_class) {
+        Some(state) => elem.state.contains(state),
+        None => true,
+    }
+}
+
+fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector, pseudo:Option<&str>) -> bool {
     //return false for mis-matches
+    if selector.pseudo_element.as_deref() != pseudo {
+        return false;
+    }
+    if !selector.pseudo_class.iter().all(|pc| matches_dynamic_pseudo_class(elem, pc)) {
+        return false;
+    }
     if selector.tag_name.iter().any(|name|  "*" != *name)
         && selector.tag_name.iter().any(|name| elem.tag_name != *name) {
             return false;
@@ -305,249 +686,1348 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
     true
 }
 
-type MatchedRule<'a> = (Specificity, &'a Rule);
+/// Where declarations in the cascade come from, from the lowest precedence to the highest.
+/// !important declarations come after all the normal ones, in the reverse order, so the user
+/// has the last word over the page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Origin {
+    /// the browser's own stylesheet
+    UserAgent,
+    /// the stylesheet the user gave the browser
+    User,
+    /// legacy html attributes like bgcolor
+    Presentational,
+    /// the page's stylesheets
+    Author,
+    /// the element's style attribute
+    Inline,
+    AuthorImportant,
+    InlineImportant,
+    UserImportant,
+    UserAgentImportant,
+}
+
+impl Origin {
+    // where the !important declarations of a rule from this origin go
+    fn important(self) -> Origin {
+        match self {
+            Origin::UserAgent | Origin::UserAgentImportant => Origin::UserAgentImportant,
+            Origin::User | Origin::UserImportant => Origin::UserImportant,
+            Origin::Inline | Origin::InlineImportant => Origin::InlineImportant,
+            Origin::Presentational | Origin::Author | Origin::AuthorImportant => Origin::AuthorImportant,
+        }
+    }
+}
+
+/// One set of declarations that applies to an element, and why it applies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchedRule<'a> {
+    pub origin: Origin,
+    /// None for presentational attributes and the style attribute
+    pub rule: Option<&'a Rule>,
+    /// the rule's selector that matches with the highest specificity
+    pub selector: Option<&'a Selector>,
+    pub specificity: Specificity,
+    /// the rule's number in source order, across all the stylesheets
+    pub order: usize,
+    pub declarations: Cow<'a, [Declaration]>,
+}
 
-// return rule that matches, if any.
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule, ancestors:&mut Vec::<(&Node,&PropertyMap)>) -> Option<MatchedRule<'a>> {
+// the selector of the rule that matches with the highest specificity, if any do
+fn match_rule<'a>(elem: &ElementData, rule: &'a Rule, ancestors:&mut Vec::<(&Node,&PropertyMap)>, pseudo:Option<&str>) -> Option<&'a Selector> {
     rule.selectors.iter()
-        .find(|selector| matches(elem, selector, ancestors))
-        .map(|selector| (selector.specificity(), rule))
+        .filter(|selector| matches_pseudo(elem, selector, ancestors, pseudo))
+        .max_by_key(|selector| selector.specificity())
 }
 
-fn only_real_rules(rtype:&RuleType) -> Option<&Rule> {
-    match rtype {
-        RuleType::Rule(rule) => Some(&rule),
-        _ => None,
+//find all matching rules for an element, numbered in source order across all the stylesheets.
+//only the index's candidates are tried, which rules out the ones that can't match up front
+fn matching_style_rules<'a>(elem: &ElementData, index: &RuleIndex<'a>, filter:&AncestorFilter, ancestors:&mut Vec::<(&Node, &PropertyMap)>, pseudo:Option<&str>) -> Vec<MatchedRule<'a>> {
+    index.candidates(elem, filter).into_iter()
+        .filter_map(|order| {
+            let rule = index.rules[order];
+            match_rule(elem, rule, ancestors, pseudo).map(|selector| MatchedRule {
+                origin: if order < index.user_agent_rules {
+                    Origin::UserAgent
+                } else if order < index.user_rules {
+                    Origin::User
+                } else {
+                    Origin::Author
+                },
+                rule: Some(rule),
+                selector: Some(selector),
+                specificity: selector.specificity(),
+                order,
+                declarations: Cow::Borrowed(&rule.declarations),
+            })
+        })
+        .collect()
+}
+
+// everything that applies to the element, in the order the cascade applies it: by origin, then
+// specificity, then source order, so later declarations win ties. presentational attributes go
+// between the browser's rules and the page's, and the style attribute after every rule. the
+// !important declarations of a rule are split off into their own origin.
+fn cascade<'a>(elem: &ElementData, index: &RuleIndex<'a>, filter:&AncestorFilter, ancestors:&mut Vec::<(&Node, &PropertyMap)>, pseudo:Option<&str>) -> Vec<MatchedRule<'a>> {
+    let mut rules = matching_style_rules(elem, index, filter, ancestors, pseudo);
+    if pseudo.is_none() {
+        let unruled = |origin, declarations:Vec<Declaration>| MatchedRule {
+            origin, rule: None, selector: None, specificity: Default::default(), order: 0, declarations: Cow::Owned(declarations),
+        };
+        let hints = presentational_hints(elem, ancestors);
+        if !hints.is_empty() {
+            rules.push(unruled(Origin::Presentational, hints));
+        }
+        let inline = inline_declarations(elem);
+        if !inline.is_empty() {
+            rules.push(unruled(Origin::Inline, inline));
+        }
     }
+    let mut important = vec![];
+    for rule in rules.iter_mut().filter(|rule| rule.declarations.iter().any(|dec| dec.important)) {
+        let (marked, normal):(Vec<Declaration>, Vec<Declaration>) = rule.declarations.iter().cloned().partition(|dec| dec.important);
+        important.push(MatchedRule { origin: rule.origin.important(), declarations: Cow::Owned(marked), ..rule.clone() });
+        rule.declarations = Cow::Owned(normal);
+    }
+    rules.extend(important);
+    rules.sort_by_key(|rule| (rule.origin, rule.specificity, rule.order));
+    rules
 }
-//find all matching rules for an element
-fn matching_rules<'a>(elem: &ElementData, styles: &'a StylesheetSet, ancestors:&mut Vec::<(&Node, &PropertyMap)>) -> Vec<MatchedRule<'a>> {
-    let mut rules2:Vec<MatchedRule> = vec![];
-    for sheet in styles.stylesheets.iter() {
-        let mut rules:Vec<MatchedRule> = sheet.rules.iter()
-            .filter_map(only_real_rules)
-            .filter_map(|rule|match_rule(elem, &rule, ancestors)).collect();
-        rules2.append(&mut rules);
+
+/// The rules and other declarations that apply to the element at `path` under `root`, in the
+/// order the cascade applies them, so the last one to set a property is where its value came from.
+pub fn matching_rules<'a>(root:&Node, path:&[usize], styles:&'a StylesheetSet) -> Vec<MatchedRule<'a>> {
+    let empty = PropertyMap::new();
+    let mut ancestors:Vec<(&Node, &PropertyMap)> = vec![];
+    let mut filter = AncestorFilter::default();
+    let mut node = root;
+    for i in path {
+        if let Element(ed) = &node.node_type {
+            filter = filter.with(ed);
+        }
+        ancestors.insert(0, (node, &empty));
+        node = match node.children.get(*i) {
+            Some(child) => child,
+            None => return vec![],
+        };
+    }
+    match &node.node_type {
+        Element(ed) => cascade(ed, &RuleIndex::new(styles), &filter, &mut ancestors, None),
+        _ => vec![],
     }
-    rules2
 }
 
 // get all values set by all rules
-fn specified_values(elem: &ElementData, styles: &StylesheetSet, ancestors:&mut Vec::<(&Node, &PropertyMap)>) -> PropertyMap {
+fn specified_values(elem: &ElementData, index: &RuleIndex, filter:&AncestorFilter, ancestors:&mut Vec::<(&Node, &PropertyMap)>) -> PropertyMap {
+    let mut values = specified_pseudo_values(elem, index, filter, ancestors, None);
+    compute_values(&mut values, ancestors);
+    inherit_unset_properties(&mut values, ancestors);
+    values
+}
+
+// pseudo-elements which get their own set of values on the styled node
+const PSEUDO_ELEMENTS:[&str;1] = ["selection"];
+
+fn pseudo_element_values(elem: &ElementData, index: &RuleIndex, filter:&AncestorFilter, ancestors:&mut Vec::<(&Node, &PropertyMap)>) -> HashMap<String, PropertyMap> {
+    let mut pseudo_values = HashMap::new();
+    for name in PSEUDO_ELEMENTS.iter() {
+        let mut values = specified_pseudo_values(elem, index, filter, ancestors, Some(name));
+        compute_values(&mut values, ancestors);
+        if !values.is_empty() {
+            pseudo_values.insert(String::from(*name), values);
+        }
+    }
+    pseudo_values
+}
+
+fn specified_pseudo_values(elem: &ElementData, index: &RuleIndex, filter:&AncestorFilter, ancestors:&mut Vec::<(&Node, &PropertyMap)>, pseudo:Option<&str>) -> PropertyMap {
     // println!("styling with ancestors {:#?}", ancestors.len());
     // for an in ancestors.iter() {
     //     println!("   ancestor {:#?} {:#?}", an.0.node_type, an.1);
     // }
     let mut values:HashMap<String,Value> = HashMap::new();
-    let mut rules = matching_rules(elem, styles, ancestors);
-
-    //sort rules by specificity
-    rules.sort_by(|&(a,_),&(b,_)| a.cmp(&b));
-    for (_,rule) in rules {
-        for declaration in &rule.declarations {
+    let rules = cascade(elem, index, filter, ancestors, pseudo);
+    // what the browser's own rules came to, and then the user's, kept for `revert` in the
+    // rules after them. the user's rules revert to the browser's, and the page's to the user's.
+    let mut user_agent_values:Option<PropertyMap> = None;
+    let mut user_values:Option<PropertyMap> = None;
+    let reverts = rules.iter()
+        .filter(|rule| rule.origin != Origin::UserAgent)
+        .flat_map(|rule| rule.declarations.iter())
+        .any(|dec| dec.name == "all" || matches!(&dec.value, Keyword(kw) if kw.eq_ignore_ascii_case("revert")));
+    for rule in rules.iter() {
+        if reverts && rule.origin > Origin::UserAgent && user_agent_values.is_none() {
+            user_agent_values = Some(values.clone());
+        }
+        if reverts && rule.origin > Origin::User && user_values.is_none() {
+            user_values = Some(values.clone());
+        }
+        let reverted = match rule.origin {
+            Origin::UserAgent | Origin::UserAgentImportant => None,
+            Origin::User | Origin::UserImportant => user_agent_values.as_ref(),
+            _ => user_values.as_ref(),
+        };
+        for declaration in rule.declarations.iter() {
             // println!("checking {} {:#?}", declaration.name, declaration.value);
-            let vv = calculate_inherited_property_value(declaration, ancestors);
-            values.insert(declaration.name.clone(), vv);
+            if declaration.name == "all" {
+                cascade_all(&mut values, declaration, ancestors, reverted);
+                continue;
+            }
+            match cascaded_value(declaration, ancestors, reverted) {
+                Some(vv) => values.insert(declaration.name.clone(), resolve_system_color(&declaration.name, vv)),
+                None => values.remove(&declaration.name),
+            };
         }
     }
     values
 }
 
-//returns inherited value if inherit is set and prop name is found, or just returns the original value
-fn calculate_inherited_property_value(dec:&Declaration, ancestors:&mut Vec::<(&Node, &PropertyMap)>) -> Value {
-    if dec.value == Keyword(String::from("inherit")) {
-        for (_node, props) in ancestors.iter() {
-            if props.contains_key(&*dec.name) {
-                let newval = props.get(&*dec.name).unwrap();
-                if newval == &Keyword(String::from("inherit")) {
-                    continue;
-                }
-                return newval.clone();
+// the declarations of the element's style attribute, expanded like a stylesheet's
+fn inline_declarations(elem:&ElementData) -> Vec<Declaration> {
+    let mut declarations = match elem.attributes.get("style").map(|text| parse_declarations(text)) {
+        Some(Ok(declarations)) => declarations,
+        _ => return vec![],
+    };
+    expand_declarations(&mut declarations);
+    declarations
+}
+
+fn is_current_color(value:&Value) -> bool {
+    matches!(value, Keyword(kw) if kw.eq_ignore_ascii_case("currentcolor"))
+}
+
+// properties an element takes from its parent when nothing sets them
+const INHERITED_PROPERTIES:[&str;22] = [
+    "color", "font-family", "font-size", "font-style", "font-weight", "font-variant",
+    "letter-spacing", "word-spacing", "line-height", "text-align", "text-indent", "text-transform",
+    "white-space", "visibility", "list-style-type", "list-style-position", "cursor",
+    "pointer-events", "border-collapse", "border-spacing", "caption-side", "quotes",
+];
+
+pub fn is_inherited_property(name:&str) -> bool {
+    INHERITED_PROPERTIES.contains(&name)
+}
+
+// inherited properties the cascade left unset take the parent's value, which is already
+// complete, so they reach every descendant without layout walking up the tree for them
+fn inherit_unset_properties(values:&mut PropertyMap, ancestors:&[(&Node, &PropertyMap)]) {
+    let parent = match ancestors.first() {
+        Some((_, parent)) => parent,
+        None => return,
+    };
+    for name in INHERITED_PROPERTIES.iter() {
+        if !values.contains_key(*name) {
+            if let Some(value) = parent.get(*name) {
+                values.insert(String::from(*name), value.clone());
             }
         }
     }
-    dec.value.clone()
+}
+
+/// The value of a property before any stylesheet sets it. None when there is no useful
+/// value to put in the style, like the user agent's choice of font family.
+pub fn initial_value(name:&str) -> Option<Value> {
+    let keyword = |kw:&str| Some(Keyword(String::from(kw)));
+    match name {
+        "color" => keyword("black"),
+        "background-color" => keyword("transparent"),
+        "display" => keyword("inline"),
+        "font-size" => Some(Length(16.0, Unit::Px)),
+        "font-style" | "font-variant" | "font-weight" | "letter-spacing" | "word-spacing"
+            | "line-height" | "white-space" => keyword("normal"),
+        "background-image" | "list-style-image" | "text-decoration-line" | "text-transform"
+            | "float" | "clear" => keyword("none"),
+        "text-align" => keyword("left"),
+        "vertical-align" => keyword("baseline"),
+        "visibility" | "overflow" => keyword("visible"),
+        "list-style-type" => keyword("disc"),
+        "list-style-position" => keyword("outside"),
+        "position" => keyword("static"),
+        "flex-direction" => keyword("row"),
+        "flex-wrap" => keyword("nowrap"),
+        "justify-content" => keyword("flex-start"),
+        "align-items" => keyword("stretch"),
+        "align-self" | "flex-basis" => keyword("auto"),
+        "flex-grow" => Some(Value::Number(0.0)),
+        "flex-shrink" => Some(Value::Number(1.0)),
+        "grid-template-columns" | "grid-template-rows" => keyword("none"),
+        "grid-column-start" | "grid-column-end" | "grid-row-start" | "grid-row-end" => keyword("auto"),
+        "background-repeat" => keyword("repeat"),
+        "background-position" => Some(Value::ArrayValue(vec![Length(0.0, Unit::Per), Length(0.0, Unit::Per)])),
+        "background-size" => keyword("auto"),
+        "animation-name" | "animation-fill-mode" => keyword("none"),
+        "animation-duration" | "animation-delay" => Some(Value::Time(0.0)),
+        "animation-timing-function" => keyword("ease"),
+        "animation-iteration-count" => Some(Value::Number(1.0)),
+        "animation-direction" => keyword("normal"),
+        "border-collapse" => keyword("separate"),
+        "border-spacing" => Some(Length(0.0, Unit::Px)),
+        "caption-side" => keyword("top"),
+        "outline-style" => keyword("none"),
+        "outline-width" => keyword("medium"),
+        "outline-color" => keyword("currentColor"),
+        "transform" => keyword("none"),
+        "transform-origin" => Some(Value::ArrayValue(vec![Length(50.0, Unit::Per), Length(50.0, Unit::Per)])),
+        "width" | "height" | "cursor" | "pointer-events" | "top" | "right" | "bottom" | "left" => keyword("auto"),
+        "min-width" | "min-height" | "text-indent" => Some(Length(0.0, Unit::Px)),
+        name if name.ends_with("-radius") => Some(Length(0.0, Unit::Px)),
+        "max-width" | "max-height" => keyword("none"),
+        "opacity" => Some(Value::Number(1.0)),
+        name if name.starts_with("margin-") || name.starts_with("padding-") => Some(Length(0.0, Unit::Px)),
+        name if name.starts_with("border-style-") => keyword("none"),
+        name if name.starts_with("border-width-") => keyword("medium"),
+        name if name.starts_with("border-color-") => keyword("currentColor"),
+        _ => None,
+    }
+}
+
+// a declaration's value after the css-wide keywords. `inherit` copies the parent's value, which
+// is already resolved, `initial` resets the property and `unset` does whichever of the two the
+// property does by default. `revert` goes back to what the origins before this one said, which
+// is reverted, or acts like unset in the browser's stylesheet itself. None means the property
+// ends up not set.
+fn cascaded_value(dec:&Declaration, ancestors:&[(&Node, &PropertyMap)], reverted:Option<&PropertyMap>) -> Option<Value> {
+    let keyword = match &dec.value {
+        Keyword(kw) => kw.to_lowercase(),
+        _ => return Some(dec.value.clone()),
+    };
+    // the parent's value. an inherited property it doesn't set comes from further up,
+    // any other has its initial value there.
+    let inherit = || {
+        let from = if is_inherited_property(&dec.name) { ancestors } else { ancestors.get(..1).unwrap_or_default() };
+        from.iter().find_map(|(_, values)| values.get(&dec.name).cloned())
+            .or_else(|| initial_value(&dec.name))
+    };
+    let unset = || if is_inherited_property(&dec.name) { inherit() } else { initial_value(&dec.name) };
+    match keyword.as_str() {
+        "inherit" => inherit(),
+        "initial" => initial_value(&dec.name),
+        "unset" => unset(),
+        "revert" => reverted.and_then(|values| values.get(&dec.name).cloned()).or_else(unset),
+        _ => Some(dec.value.clone()),
+    }
+}
+
+// the keywords every property takes
+const CSS_WIDE_KEYWORDS:[&str;4] = ["inherit", "initial", "unset", "revert"];
+
+// the properties `all` leaves alone
+const NOT_IN_ALL:[&str;2] = ["direction", "unicode-bidi"];
+
+// `all` takes every other property back to a css-wide keyword. properties that aren't set
+// have their initial value, or the parent's once the cascade is done if they inherit.
+fn cascade_all(values:&mut PropertyMap, dec:&Declaration, ancestors:&[(&Node, &PropertyMap)], reverted:Option<&PropertyMap>) {
+    let keyword = match &dec.value {
+        Keyword(kw) => kw.to_lowercase(),
+        _ => return,
+    };
+    if !CSS_WIDE_KEYWORDS.contains(&keyword.as_str()) {
+        return;
+    }
+    values.retain(|name, _| NOT_IN_ALL.contains(&name.as_str()));
+    let mut set_from = |from:&PropertyMap| for (name, value) in from.iter().filter(|(name, _)| !NOT_IN_ALL.contains(&name.as_str())) {
+        values.insert(name.clone(), value.clone());
+    };
+    match keyword.as_str() {
+        "initial" => set_from(&INHERITED_PROPERTIES.iter()
+            .filter_map(|name| initial_value(name).map(|value| (String::from(*name), value)))
+            .collect()),
+        "inherit" => if let Some((_, parent)) = ancestors.first() {
+            set_from(parent)
+        },
+        "revert" => if let Some(reverted) = reverted {
+            set_from(reverted)
+        },
+        _ => {},
+    }
 }
 
 pub fn dom_tree_to_stylednodes<'a>(root: &'a Node, styles: &'a StylesheetSet) -> StyledTree {
     let tree = StyledTree::new();
     let mut ansc:Vec<(&Node, &PropertyMap)> = vec![];
-    tree.set_root(real_style_tree(&tree, root, styles, &mut ansc));
+    let index = RuleIndex::new(styles);
+    let mut counters = Counters::default();
+    tree.set_root(real_style_tree(&tree, root, &index, &AncestorFilter::default(), &mut ansc, &mut vec![], &mut counters));
     return tree;
 }
 
-fn real_style_tree<'a>(tree:&StyledTree, root: &'a Node, styles: &'a StylesheetSet, ancestors:&mut Vec::<(&Node, &PropertyMap)>) -> Rc<StyledNode> {
+/// Drops the text that's only whitespace from the elements whose computed white-space
+/// collapses it, so a `pre` or anything styled to keep spaces keeps it. True if anything
+/// was dropped, in which case the tree needs styling again.
+pub fn strip_collapsible_whitespace(node:&mut Node, styled:&StyledNode) -> bool {
+    let mut stripped = false;
+    // the children first, while their paths still point at them
+    for child in styled.children.borrow().iter() {
+        // generated content carries its element's path
+        if child.path.len() != styled.path.len() + 1 {
+            continue;
+        }
+        if let Some(dom) = child.path.last().and_then(|i| node.children.get_mut(*i)) {
+            stripped |= strip_collapsible_whitespace(dom, child);
+        }
+    }
+    if styled.lookup_white_space().collapses_spaces() {
+        let before = node.children.len();
+        node.children.retain(|ch| !matches!(&ch.node_type, NodeType::Text(text) if text.trim().is_empty()));
+        stripped |= node.children.len() != before;
+    }
+    stripped
+}
+
+/// Styles the element at `path` and everything under it again, after the state of elements
+/// in that subtree changed, and swaps the result into the tree. The rest of the tree keeps
+/// its styles. Returns false without touching the tree when no rule depends on the state.
+pub fn restyle_subtree(tree:&StyledTree, root:&Node, path:&[usize], state:ElementState, styles:&StylesheetSet) -> bool {
+    let index = RuleIndex::new(styles);
+    if !index.dynamic_states.contains(state) {
+        return false;
+    }
+    // a selector like a:hover + p restyles the element's siblings too
+    let path = match path.split_last() {
+        Some((_, parent)) if index.dynamic_siblings => parent,
+        _ => path,
+    };
+    restyle_at(tree, root, path, &index)
+}
+
+// the styled nodes from the root down to the one at path, which isn't generated content
+fn styled_chain(tree:&StyledTree, path:&[usize]) -> Option<Vec<Rc<StyledNode>>> {
+    let mut styled:Vec<Rc<StyledNode>> = vec![Rc::clone(&tree.root.borrow())];
+    for depth in 0..path.len() {
+        let child = styled[depth].children.borrow().iter().find(|ch| ch.path == path[..=depth]).cloned()?;
+        styled.push(child);
+    }
+    Some(styled)
+}
+
+// puts node in the tree where the styled node at path is
+fn replace_styled(tree:&StyledTree, styled:&[Rc<StyledNode>], path:&[usize], node:Rc<StyledNode>) {
+    match styled.len().checked_sub(2).map(|i| &styled[i]) {
+        Some(parent) => {
+            *node.parent.borrow_mut() = Rc::downgrade(parent);
+            let mut children = parent.children.borrow_mut();
+            if let Some(pos) = children.iter().position(|ch| ch.path == path) {
+                children[pos] = node;
+            }
+        },
+        None => tree.set_root(node),
+    }
+}
+
+// styles the node at path and everything under it again, from the styles of its ancestors
+fn restyle_at(tree:&StyledTree, root:&Node, path:&[usize], index:&RuleIndex) -> bool {
+    let styled = match styled_chain(tree, path) {
+        Some(styled) => styled,
+        None => return false,
+    };
+    let mut nodes:Vec<&Node> = vec![root];
+    for (depth, i) in path.iter().enumerate() {
+        match nodes[depth].children.get(*i) {
+            Some(node) => nodes.push(node),
+            None => return false,
+        }
+    }
+    let depth = path.len();
+    let mut ancestors:Vec<(&Node, &PropertyMap)> = nodes[..depth].iter().zip(styled.iter())
+        .rev()
+        .map(|(node, styled)| (*node, &styled.specified_values))
+        .collect();
+    let filter = nodes[..depth].iter().fold(AncestorFilter::default(), |filter, node| match &node.node_type {
+        Element(ed) => filter.with(ed),
+        _ => filter,
+    });
+    let mut counters = Counters::default();
+    counters_before(&tree.root.borrow(), path, 0, &mut counters);
+    let restyled = real_style_tree(tree, nodes[depth], index, &filter, &mut ancestors, &mut path.to_vec(), &mut counters);
+    replace_styled(tree, &styled, path, restyled);
+    true
+}
+
+// the counters as they stood when styling reached the element at path, replayed from the
+// styled tree in document order. true once it's reached.
+fn counters_before(node:&StyledNode, path:&[usize], depth:usize, counters:&mut Counters) -> bool {
+    let generated = matches!(&node.node.node_type, Element(ed) if ed.tag_name.starts_with("::"));
+    if node.path == path && !generated {
+        return true;
+    }
+    if let Element(_) = node.node.node_type {
+        counters.enter(&node.specified_values, depth);
+    }
+    if node.children.borrow().iter().any(|child| counters_before(child, path, depth + 1, counters)) {
+        return true;
+    }
+    counters.leave(depth);
+    false
+}
+
+// filter holds the ancestors of root, for ruling out descendant selectors quickly. counters
+// are the ones in scope so far, in document order.
+fn real_style_tree(tree:&StyledTree, root: &Node, index: &RuleIndex, filter:&AncestorFilter, ancestors:&mut Vec::<(&Node, &PropertyMap)>, path:&mut Vec<usize>, counters:&mut Counters) -> Rc<StyledNode> {
     let specified = match root.node_type {
-        Element(ref elem) => specified_values(elem, styles, ancestors),
+        Element(ref elem) => specified_values(elem, index, filter, ancestors),
         Text(_) => HashMap::new(),
         Meta(_) => HashMap::new(),
         _ => HashMap::new(),
     };
-    let mut a2:Vec<(&Node, &PropertyMap)> = vec![];
-    a2.push((root, &specified));
-    let ch2:Vec<Rc<StyledNode>> = root.children.iter()
-        .map(|child| {
-            real_style_tree(tree, child, styles, &mut a2)
-        }).collect();
-    return tree.make_with((*root).clone(),specified,RefCell::new(ch2));
+    let pseudo = match root.node_type {
+        Element(ref elem) => pseudo_element_values(elem, index, filter, ancestors),
+        _ => HashMap::new(),
+    };
+    let child_filter = match root.node_type {
+        Element(ref elem) => filter.with(elem),
+        _ => *filter,
+    };
+    // the whole chain of ancestors, nearest first, for descendant selectors
+    let mut a2:Vec<(&Node, &PropertyMap)> = vec![(root, &specified)];
+    a2.extend(ancestors.iter().cloned());
+    let depth = ancestors.len();
+    if let Element(_) = root.node_type {
+        counters.enter(&specified, depth);
+    }
+    // ::before comes first in document order, so it counts before the children do
+    let before = match root.node_type {
+        Element(ref elem) => generated_content(elem, "before", index, &child_filter, &mut a2, counters),
+        _ => None,
+    };
+    let mut ch2:Vec<Rc<StyledNode>> = vec![];
+    ch2.extend(before.map(|(values, text)| generated_node(tree, "before", values, text, path)));
+    ch2.extend(root.children.iter().enumerate()
+        .map(|(i, child)| {
+            path.push(i);
+            let styled = real_style_tree(tree, child, index, &child_filter, &mut a2, path, counters);
+            path.pop();
+            styled
+        }));
+    if let Element(ref elem) = root.node_type {
+        if let Some((values, text)) = generated_content(elem, "after", index, &child_filter, &mut a2, counters) {
+            ch2.push(generated_node(tree, "after", values, text, path));
+        }
+    }
+    counters.leave(depth);
+    return tree.make_with((*root).clone(),specified,pseudo,RefCell::new(ch2),path.clone());
+}
+
+// the text of a content property: strings, attr() of the element and counters, joined together
+fn content_text(elem:&ElementData, value:&Value, counters:&Counters) -> Option<String> {
+    match value {
+        Value::StringLiteral(txt) => Some(txt.clone()),
+        Value::FunCall(call) if call.name == "attr" => match call.arguments.first() {
+            Some(Keyword(name)) => Some(elem.attributes.get(name).cloned().unwrap_or_default()),
+            _ => None,
+        },
+        Value::FunCall(call) => counter_text(call, counters),
+        Value::ArrayValue(parts) => parts.iter().map(|part| content_text(elem, part, counters)).collect(),
+        _ => None,
+    }
+}
+
+// the values and text of the ::before or ::after of an element, if its content makes one.
+// the pseudo-element is a child of the element, so it can reset and increment counters too.
+fn generated_content(elem:&ElementData, name:&str, index:&RuleIndex, filter:&AncestorFilter,
+                     ancestors:&mut Vec::<(&Node, &PropertyMap)>, counters:&mut Counters) -> Option<(PropertyMap, String)> {
+    let mut values = specified_pseudo_values(elem, index, filter, ancestors, Some(name));
+    compute_values(&mut values, ancestors);
+    inherit_unset_properties(&mut values, ancestors);
+    let content = values.get("content")?;
+    if !matches!(content, Value::StringLiteral(_) | Value::FunCall(_) | Value::ArrayValue(_)) {
+        return None;
+    }
+    let depth = ancestors.len();
+    counters.enter(&values, depth);
+    let text = content_text(elem, content, counters);
+    counters.leave(depth);
+    Some((values, text?))
+}
+
+// the anonymous element for generated content. it has the element's path, so hovering the
+// generated text hovers the element.
+fn generated_node(tree:&StyledTree, name:&str, values:PropertyMap, text:String, path:&[usize]) -> Rc<StyledNode> {
+    let text_nodes = if text.is_empty() { vec![] } else { vec![Node { node_type: Text(text), children: vec![] }] };
+    let children = text_nodes.iter()
+        .map(|node| tree.make_with(node.clone(), HashMap::new(), HashMap::new(), RefCell::new(vec![]), path.to_vec()))
+        .collect();
+    let node = Node {
+        node_type: Element(ElementData {
+            tag_name: format!("::{}", name),
+            attributes: Default::default(),
+            state: Default::default(),
+        }),
+        children: text_nodes,
+    };
+    tree.make_with(node, values, HashMap::new(), RefCell::new(children), path.to_vec())
+}
+
+// find the first element matching any of the selectors. returns the child indexes leading to it
+pub fn query_selector(root:&Node, selectors:&[Selector]) -> Option<Vec<usize>> {
+    let empty = PropertyMap::new();
+    let mut ancestors:Vec<(&Node, &PropertyMap)> = vec![];
+    let mut path:Vec<usize> = vec![];
+    query_selector_helper(root, selectors, &empty, &mut ancestors, &mut path)
+}
+
+fn query_selector_helper<'a>(node:&'a Node, selectors:&[Selector], empty:&'a PropertyMap,
+                             ancestors:&mut Vec::<(&'a Node, &'a PropertyMap)>, path:&mut Vec<usize>) -> Option<Vec<usize>> {
+    if let Element(ed) = &node.node_type {
+        if selectors.iter().any(|selector| matches(ed, selector, ancestors)) {
+            return Some(path.clone());
+        }
+    }
+    ancestors.insert(0, (node, empty));
+    for (i, child) in node.children.iter().enumerate() {
+        path.push(i);
+        let found = query_selector_helper(child, selectors, empty, ancestors, path);
+        path.pop();
+        if found.is_some() {
+            ancestors.remove(0);
+            return found;
+        }
+    }
+    ancestors.remove(0);
+    None
+}
+
+const SIDES:[&str;4] = ["top", "right", "bottom", "left"];
+
+// which of the 1 to 4 values of a box shorthand goes to each of top, right, bottom and left
+fn side_indexes(count:usize) -> Option<[usize;4]> {
+    match count {
+        1 => Some([0, 0, 0, 0]),
+        2 => Some([0, 1, 0, 1]),
+        3 => Some([0, 1, 2, 1]),
+        4 => Some([0, 1, 2, 3]),
+        _ => None,
+    }
 }
 
+// margin: 1px 2px becomes margin-top: 1px, margin-right: 2px, and so on
 fn expand_array_decl(new_decs:&mut Vec::<Declaration>, dec:&Declaration) {
-    match &dec.value {
-        Value::ArrayValue(arr) => {
-            if arr.len() == 2 {
-                new_decs.push(Declaration {
-                    name: format!("{}-top",dec.name),
-                    value: arr[0].clone()
-                });
-                new_decs.push(Declaration {
-                    name: format!("{}-right",dec.name),
-                    value: arr[1].clone()
-                });
-                new_decs.push(Declaration {
-                    name: format!("{}-bottom",dec.name),
-                    value: arr[0].clone()
-                });
-                new_decs.push(Declaration {
-                    name: format!("{}-left",dec.name),
-                    value: arr[1].clone()
-                });
-            }
-            if arr.len() == 4 {
-                new_decs.push(Declaration {
-                    name: format!("{}-top",dec.name),
-                    value: arr[0].clone()
-                });
-                new_decs.push(Declaration {
-                    name: format!("{}-right",dec.name),
-                    value: arr[1].clone()
-                });
-                new_decs.push(Declaration {
-                    name: format!("{}-bottom",dec.name),
-                    value: arr[2].clone()
-                });
+    let values = match &dec.value {
+        Value::ArrayValue(arr) => arr.clone(),
+        value => vec![value.clone()],
+    };
+    match side_indexes(values.len()) {
+        Some(indexes) => {
+            for (side, index) in SIDES.iter().zip(indexes.iter()) {
                 new_decs.push(Declaration {
-                    name: format!("{}-left",dec.name),
-                    value: arr[3].clone()
+                    name: format!("{}-{}", dec.name, side),
+                    value: values[*index].clone(),
+                    important: dec.important,
                 });
             }
         }
-        Value::Length(_, _) => {
-            new_decs.push(Declaration {
-                name: format!("{}-top",dec.name),
-                value: dec.value.clone()
-            });
-            new_decs.push(Declaration {
-                name: format!("{}-right",dec.name),
-                value: dec.value.clone()
-            });
-            new_decs.push(Declaration {
-                name: format!("{}-bottom",dec.name),
-                value: dec.value.clone()
-            });
-            new_decs.push(Declaration {
-                name: format!("{}-left",dec.name),
-                value: dec.value.clone()
-            });
-        }
-        _ => {
-            new_decs.push(dec.clone());
-        }
+        None => new_decs.push(dec.clone()),
     }
 }
 
-pub fn expand_styles(ss:&mut Stylesheet) {
-    for rule in ss.rules.iter_mut() {
-        if let RuleType::Rule(rule) = rule {
-            let mut new_decs = vec![];
-            for dec in rule.declarations.iter_mut() {
-                // println!("decl = {:#?}",dec);
-                match dec.name.as_str() {
-                    "margin" => expand_array_decl(&mut new_decs, dec),
-                    "padding" => expand_array_decl(&mut new_decs, dec),
-                    "border-width" => expand_array_decl(&mut new_decs, dec),
-                    "border" => expand_border_shorthand(&mut new_decs, dec),
-                    _ => new_decs.push(dec.clone()),
-                }
-            }
-            rule.declarations = new_decs;
+// list-style: square inside, in any order. `none` sets whichever of the type and
+// image is not otherwise set, or both.
+fn expand_list_style_shorthand(new_decs:&mut Vec::<Declaration>, dec:&Declaration) {
+    let parts = match &dec.value {
+        Value::ArrayValue(vec) => vec.clone(),
+        value => vec![value.clone()],
+    };
+    let (mut list_type, mut position, mut image) = (None, None, None);
+    let mut nones = 0;
+    for part in parts {
+        match &part {
+            Keyword(kw) if CSS_WIDE_KEYWORDS.contains(&kw.to_lowercase().as_str()) => {
+                list_type = Some(part.clone());
+                position = Some(part.clone());
+                image = Some(part);
+            },
+            Keyword(kw) if kw == "inside" || kw == "outside" => position = Some(part),
+            Keyword(kw) if kw == "none" => nones += 1,
+            Value::FunCall(_) => image = Some(part),
+            _ => list_type = Some(part),
         }
     }
+    let none = || Keyword(String::from("none"));
+    if nones > 0 && list_type.is_none() {
+        list_type = Some(none());
+    }
+    if nones > 0 && image.is_none() {
+        image = Some(none());
+    }
+    let longhands = [
+        ("list-style-type", list_type.unwrap_or_else(|| Keyword(String::from("disc")))),
+        ("list-style-position", position.unwrap_or_else(|| Keyword(String::from("outside")))),
+        ("list-style-image", image.unwrap_or_else(none)),
+    ];
+    for (name, value) in longhands {
+        new_decs.push(Declaration { name: String::from(name), value, important: dec.important });
+    }
 }
 
-fn expand_border_shorthand(new_decs:&mut Vec::<Declaration>, dec:&Declaration) {
-    // println!("expanding border shorthand: {:#?}",dec);
-    match &dec.value {
-        Value::ArrayValue(vec) => {
-            if vec.len() != 3 {
-                panic!("border shorthand must have three values");
-            }
-            new_decs.push(Declaration{
-                name: String::from("border-width-top"),
-                value: vec[0].clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-width-left"),
-                value: vec[0].clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-width-right"),
-                value: vec[0].clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-width-bottom"),
-                value: vec[0].clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-style"),
-                value: vec[1].clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-color"),
-                value: vec[2].clone()
-            });
+// `flex: 2` is 2 1 0, `auto` is 1 1 auto and `none` is 0 0 auto. otherwise it's a grow
+// factor, maybe a shrink factor, and a basis, which is 0 when left out.
+fn expand_flex_shorthand(new_decs:&mut Vec::<Declaration>, dec:&Declaration) {
+    let parts = match &dec.value {
+        Value::ArrayValue(vec) => vec.clone(),
+        value => vec![value.clone()],
+    };
+    let number = |n:f32| Value::Number(n);
+    let auto = || Keyword(String::from("auto"));
+    let (grow, shrink, basis) = match parts.as_slice() {
+        [Keyword(kw)] if CSS_WIDE_KEYWORDS.contains(&kw.to_lowercase().as_str()) => (parts[0].clone(), parts[0].clone(), parts[0].clone()),
+        [Keyword(kw)] if kw == "none" => (number(0.0), number(0.0), auto()),
+        [Keyword(kw)] if kw == "auto" => (number(1.0), number(1.0), auto()),
+        _ => {
+            let numbers:Vec<&Value> = parts.iter().filter(|part| matches!(part, Value::Number(_))).collect();
+            let basis = parts.iter().find(|part| !matches!(part, Value::Number(_))).cloned();
+            // a third number can only be a basis of 0
+            let basis = basis.or_else(|| numbers.get(2).map(|_| Length(0.0, Unit::Px)));
+            (numbers.first().map_or(number(1.0), |n| (*n).clone()),
+             numbers.get(1).map_or(number(1.0), |n| (*n).clone()),
+             basis.unwrap_or(Length(0.0, Unit::Px)))
+        },
+    };
+    for (name, value) in [("flex-grow", grow), ("flex-shrink", shrink), ("flex-basis", basis)] {
+        new_decs.push(Declaration { name: String::from(name), value, important: dec.important });
+    }
+}
+
+// a direction and a wrap, in either order
+fn expand_flex_flow_shorthand(new_decs:&mut Vec::<Declaration>, dec:&Declaration) {
+    let parts = match &dec.value {
+        Value::ArrayValue(vec) => vec.clone(),
+        value => vec![value.clone()],
+    };
+    let (mut direction, mut wrap) = (Keyword(String::from("row")), Keyword(String::from("nowrap")));
+    for part in parts {
+        match &part {
+            Keyword(kw) if CSS_WIDE_KEYWORDS.contains(&kw.to_lowercase().as_str()) => {
+                direction = part.clone();
+                wrap = part;
+            },
+            Keyword(kw) if kw.contains("wrap") => wrap = part,
+            _ => direction = part,
         }
-        Value::Number(v) => {
-            new_decs.push(Declaration{
-                name: String::from("border-width-top"),
-                value: dec.value.clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-width-left"),
-                value: dec.value.clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-width-right"),
-                value: dec.value.clone()
+    }
+    new_decs.push(Declaration { name: String::from("flex-direction"), value: direction, important: dec.important });
+    new_decs.push(Declaration { name: String::from("flex-wrap"), value: wrap, important: dec.important });
+}
+
+// `gap: 10px` is the same between rows and columns, `gap: 10px 20px` is a row gap and then a column gap
+fn expand_gap_shorthand(new_decs:&mut Vec::<Declaration>, dec:&Declaration) {
+    let (row, column) = match &dec.value {
+        Value::ArrayValue(vec) if vec.len() == 2 => (vec[0].clone(), vec[1].clone()),
+        Value::ArrayValue(_) => return,
+        value => (value.clone(), value.clone()),
+    };
+    new_decs.push(Declaration { name: String::from("row-gap"), value: row, important: dec.important });
+    new_decs.push(Declaration { name: String::from("column-gap"), value: column, important: dec.important });
+}
+
+// the parser gives `font`, `background` and `animation` one value per longhand, already
+// in longhand order. a css-wide keyword applies to each.
+fn expand_longhands(new_decs:&mut Vec::<Declaration>, dec:&Declaration, longhands:&[&str]) {
+    let values = match &dec.value {
+        Value::ArrayValue(values) if values.len() == longhands.len() => values.clone(),
+        Keyword(kw) if CSS_WIDE_KEYWORDS.contains(&kw.to_lowercase().as_str()) => vec![dec.value.clone(); longhands.len()],
+        _ => return new_decs.push(dec.clone()),
+    };
+    for (name, value) in longhands.iter().zip(values) {
+        new_decs.push(Declaration { name: String::from(*name), value, important: dec.important });
+    }
+}
+
+const LENGTH_PROPERTIES:[&str;38] = [
+    "width", "height", "min-width", "max-width", "min-height", "max-height", "font-size", "column-width", "column-gap", "row-gap", "flex-basis",
+    "top", "right", "bottom", "left",
+    "margin", "margin-top", "margin-right", "margin-bottom", "margin-left",
+    "padding", "padding-top", "padding-right", "padding-bottom", "padding-left",
+    "border-width", "border-width-top", "border-width-right", "border-width-bottom", "border-width-left",
+    "outline-width", "border-spacing", "text-indent",
+    "border-radius", "border-top-left-radius", "border-top-right-radius", "border-bottom-right-radius", "border-bottom-left-radius",
+];
+const COLOR_PROPERTIES:[&str;8] = ["color", "background-color", "border-color",
+    "border-color-top", "border-color-right", "border-color-bottom", "border-color-left", "outline-color"];
+const BORDER_STYLES:[&str;6] = ["none", "hidden", "solid", "dashed", "dotted", "double"];
+const CURSORS:[&str;36] = ["auto", "default", "none", "context-menu", "help", "pointer", "progress", "wait",
+    "cell", "crosshair", "text", "vertical-text", "alias", "copy", "move", "no-drop", "not-allowed",
+    "grab", "grabbing", "all-scroll", "col-resize", "row-resize", "n-resize", "e-resize", "s-resize",
+    "w-resize", "ne-resize", "nw-resize", "se-resize", "sw-resize", "ew-resize", "ns-resize",
+    "nesw-resize", "nwse-resize", "zoom-in", "zoom-out"];
+
+// grid properties, whose values are track lists and line numbers the parser has already checked
+const GRID_PROPERTIES:[&str;6] = ["grid-template-columns", "grid-template-rows",
+    "grid-column-start", "grid-column-end", "grid-row-start", "grid-row-end"];
+
+// the keyword values the engine implements for each keyword property
+fn supported_keywords(name:&str) -> Option<&'static [&'static str]> {
+    match name {
+        "display" => Some(&["block", "inline", "inline-block", "none", "list-item",
+            "table", "table-row-group", "table-header-group", "table-footer-group", "table-row", "table-cell",
+            "table-caption", "flex", "grid"]),
+        "flex-direction" => Some(&["row", "row-reverse", "column", "column-reverse"]),
+        "flex-wrap" => Some(&["nowrap", "wrap"]),
+        "justify-content" => Some(&["normal", "start", "end", "flex-start", "flex-end", "center",
+            "space-between", "space-around", "space-evenly"]),
+        "align-items" => Some(&["normal", "stretch", "start", "end", "flex-start", "flex-end", "center"]),
+        "align-self" => Some(&["auto", "normal", "stretch", "start", "end", "flex-start", "flex-end", "center"]),
+        "white-space" => Some(&["normal", "nowrap", "pre", "pre-wrap", "pre-line"]),
+        "overflow" => Some(&["visible", "hidden", "scroll", "auto"]),
+        "text-align" => Some(&["left", "right", "center", "justify"]),
+        "vertical-align" => Some(&["baseline", "top", "middle", "bottom", "sub", "super", "text-top", "text-bottom"]),
+        "text-decoration-line" => Some(&["none", "underline", "line-through"]),
+        "font-style" => Some(&["normal", "italic"]),
+        "font-weight" => Some(&["normal", "bold"]),
+        "list-style-type" => Some(&["none", "disc", "circle", "square", "decimal", "decimal-leading-zero",
+            "lower-alpha", "upper-alpha", "lower-latin", "upper-latin", "lower-roman", "upper-roman"]),
+        "list-style-position" => Some(&["inside", "outside"]),
+        "float" => Some(&["none", "left", "right"]),
+        "clear" => Some(&["none", "left", "right", "both"]),
+        "position" => Some(&["static", "relative", "absolute", "fixed"]),
+        "pointer-events" => Some(&["auto", "none"]),
+        "visibility" => Some(&["visible", "hidden", "collapse"]),
+        "cursor" => Some(&CURSORS),
+        "border-collapse" => Some(&["separate", "collapse"]),
+        "caption-side" => Some(&["top", "bottom"]),
+        "box-decoration-break" => Some(&["slice", "clone"]),
+        "image-rendering" => Some(&["auto", "smooth", "high-quality", "crisp-edges", "pixelated"]),
+        "border-style" | "border-style-top" | "border-style-right" | "border-style-bottom"
+            | "border-style-left" => Some(&BORDER_STYLES),
+        "outline-style" => Some(&["none", "auto", "solid", "dashed", "dotted", "double"]),
+        _ => None,
+    }
+}
+
+fn is_supported_property(name:&str) -> bool {
+    LENGTH_PROPERTIES.contains(&name) || COLOR_PROPERTIES.contains(&name)
+        || supported_keywords(name).is_some() || name == "font-family" || name == "column-count" || name == "content"
+        || name == "line-height" || name == "letter-spacing" || name == "word-spacing" || name == "opacity"
+        || name == "all" || name == "flex-grow" || name == "flex-shrink" || GRID_PROPERTIES.contains(&name)
+}
+
+// is this a declaration that layout or rendering will actually do something with
+pub fn is_supported_declaration(dec:&Declaration) -> bool {
+    let name = dec.name.as_str();
+    if !is_supported_property(name) {
+        return false;
+    }
+    if let Keyword(kw) = &dec.value {
+        if CSS_WIDE_KEYWORDS.contains(&kw.to_lowercase().as_str()) {
+            return true;
+        }
+    }
+    // all takes nothing else
+    if name == "all" {
+        return false;
+    }
+    if name == "line-height" || name == "letter-spacing" || name == "word-spacing" {
+        return match &dec.value {
+            Length(_, _) | Value::Calc(_) => true,
+            Value::Number(n) => name == "line-height" || *n == 0.0,
+            Keyword(kw) => kw == "normal",
+            _ => false,
+        };
+    }
+    if name == "opacity" {
+        return matches!(dec.value, Value::Number(_) | Length(_, Unit::Per));
+    }
+    if GRID_PROPERTIES.contains(&name) {
+        return !matches!(dec.value, Value::Unparsed(_));
+    }
+    if LENGTH_PROPERTIES.contains(&name) {
+        return match &dec.value {
+            Length(_, _) | Value::Number(_) | Value::ArrayValue(_) => true,
+            Keyword(kw) if name == "outline-width" => ["thin", "medium", "thick"].contains(&kw.as_str()),
+            Keyword(kw) if name.ends_with("width") && !name.starts_with("border") && !name.starts_with("column")
+                && ["min-content", "max-content", "fit-content"].contains(&kw.as_str()) => true,
+            Keyword(kw) if name.starts_with("max-") => kw == "none",
+            Keyword(kw) => kw == "auto",
+            _ => false,
+        };
+    }
+    if COLOR_PROPERTIES.contains(&name) {
+        return match &dec.value {
+            ColorValue(_) | HexColor(_) => true,
+            Keyword(kw) => find_color_lazy_static(kw).is_some() || find_system_color(kw).is_some() || is_current_color(&dec.value),
+            _ => false,
+        };
+    }
+    match (&dec.value, supported_keywords(name)) {
+        (Keyword(kw), Some(keywords)) => keywords.contains(&kw.as_str()),
+        (Value::Number(_), _) => name == "font-weight" || name == "column-count" || name == "flex-grow" || name == "flex-shrink",
+        (Length(_, _), _) => name == "vertical-align",
+        (_, None) => true,
+        _ => false,
+    }
+}
+
+pub fn evaluate_supports_condition(cond:&SupportsCondition) -> bool {
+    match cond {
+        SupportsCondition::Declaration(dec) => is_supported_declaration(dec),
+        SupportsCondition::Not(cond) => !evaluate_supports_condition(cond),
+        SupportsCondition::And(a, b) => evaluate_supports_condition(a) && evaluate_supports_condition(b),
+        SupportsCondition::Or(a, b) => evaluate_supports_condition(a) || evaluate_supports_condition(b),
+    }
+}
+
+// splice in the rules of @supports blocks whose condition holds, and drop the rest
+pub fn apply_supports_rules(ss:&mut Stylesheet) {
+    apply_supports_to_rules(&mut ss.rules);
+}
+
+fn apply_supports_to_rules(rules:&mut Vec<RuleType>) {
+    for rule in mem::take(rules) {
+        match rule {
+            RuleType::Supports(supports) => {
+                if evaluate_supports_condition(&supports.condition) {
+                    rules.extend(supports.rules);
+                }
+            }
+            RuleType::Media(mut block) => {
+                apply_supports_to_rules(&mut block.rules);
+                rules.push(RuleType::Media(block));
+            }
+            _ => rules.push(rule),
+        }
+    }
+}
+
+/// What @media queries are checked against: the kind of output, and the size of the
+/// viewport in px.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaEnvironment {
+    pub media_type: String,
+    pub width: f32,
+    pub height: f32,
+    /// matches `prefers-color-scheme: dark` instead of light
+    pub prefers_dark: bool,
+}
+
+impl Default for MediaEnvironment {
+    fn default() -> Self {
+        MediaEnvironment::screen(800.0, 600.0)
+    }
+}
+
+/// Whether each @media block in the stylesheets matches their environment, in source order.
+/// The styles only change with the environment when this does.
+pub fn media_query_results(styles:&StylesheetSet) -> Vec<bool> {
+    fn collect(rules:&[RuleType], media:&MediaEnvironment, results:&mut Vec<bool>) {
+        for rule in rules {
+            if let RuleType::Media(block) = rule {
+                results.push(media.matches_any(&block.queries));
+                collect(&block.rules, media, results);
+            }
+        }
+    }
+    let mut results = vec![];
+    for sheet in styles.stylesheets.iter() {
+        collect(&sheet.rules, &styles.media, &mut results);
+    }
+    results
+}
+
+impl MediaEnvironment {
+    pub fn screen(width:f32, height:f32) -> Self {
+        MediaEnvironment { media_type: String::from("screen"), width, height, prefers_dark: prefers_dark_color_scheme() }
+    }
+    pub fn matches_any(&self, queries:&[MediaQuery]) -> bool {
+        queries.iter().any(|query| self.matches(query))
+    }
+    pub fn matches(&self, query:&MediaQuery) -> bool {
+        let type_matches = match &query.media_type {
+            None => true,
+            Some(media_type) => media_type == "all" || *media_type == self.media_type,
+        };
+        let matches = type_matches && query.features.iter().all(|feature| self.matches_feature(feature));
+        matches != query.negated
+    }
+    // features we don't know about never match
+    fn matches_feature(&self, feature:&MediaFeature) -> bool {
+        let px = feature.value.as_ref().and_then(media_length_px);
+        match (feature.name.as_str(), px) {
+            ("width", Some(px)) => self.width == px,
+            ("min-width", Some(px)) => self.width >= px,
+            ("max-width", Some(px)) => self.width <= px,
+            ("height", Some(px)) => self.height == px,
+            ("min-height", Some(px)) => self.height >= px,
+            ("max-height", Some(px)) => self.height <= px,
+            ("orientation", None) => match &feature.value {
+                Some(Keyword(kw)) if kw == "portrait" => self.height >= self.width,
+                Some(Keyword(kw)) if kw == "landscape" => self.width > self.height,
+                _ => false,
+            },
+            ("prefers-color-scheme", None) => match &feature.value {
+                Some(Keyword(kw)) if kw == "light" => !self.prefers_dark,
+                Some(Keyword(kw)) if kw == "dark" => self.prefers_dark,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+// em in media queries is always the initial font size
+fn media_length_px(value:&Value) -> Option<f32> {
+    match value {
+        Length(v, Unit::Px) => Some(*v),
+        Length(v, Unit::Em) | Length(v, Unit::Rem) => Some(v * 16.0),
+        Length(v, unit) => unit.px_per_unit().map(|px| v * px),
+        Value::Number(v) if *v == 0.0 => Some(0.0),
+        _ => None,
+    }
+}
+
+const VENDOR_PREFIXES:[&str;4] = ["-webkit-", "-moz-", "-ms-", "-o-"];
+
+// -webkit-foo becomes foo, but only if we support foo. anything else keeps its prefix.
+fn unprefixed_name(name:&str) -> Option<&str> {
+    VENDOR_PREFIXES.iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .filter(|name| is_supported_property(name))
+}
+
+pub fn expand_styles(ss:&mut Stylesheet) {
+    expand_rules(&mut ss.rules);
+}
+
+fn expand_rules(rules:&mut [RuleType]) {
+    for rule in rules.iter_mut() {
+        match rule {
+            RuleType::Rule(rule) => expand_declarations(&mut rule.declarations),
+            RuleType::Page(page) => expand_declarations(&mut page.declarations),
+            RuleType::Media(block) => expand_rules(&mut block.rules),
+            RuleType::Keyframes(kf) => kf.keyframes.iter_mut().for_each(|keyframe| expand_declarations(&mut keyframe.declarations)),
+            _ => {}
+        }
+    }
+}
+
+fn expand_declarations(declarations:&mut Vec<Declaration>) {
+    let mut new_decs = vec![];
+    for dec in declarations.iter_mut() {
+        // println!("decl = {:#?}",dec);
+        if let Some(name) = unprefixed_name(&dec.name) {
+            dec.name = String::from(name);
+        }
+        // a bad part makes the whole shorthand invalid
+        if !is_valid_declaration(dec) {
+            continue;
+        }
+        match dec.name.as_str() {
+            "margin" | "padding" | "border-width" => expand_array_decl(&mut new_decs, dec),
+            // a single color or style stays as the shorthand, which layout falls back to
+            "border-color" | "border-style" if matches!(dec.value, Value::ArrayValue(_)) => expand_array_decl(&mut new_decs, dec),
+            "border" => expand_border_shorthand(&mut new_decs, dec),
+            "border-top" | "border-right" | "border-bottom" | "border-left" => expand_border_side_shorthand(&mut new_decs, dec),
+            "outline" => expand_outline_shorthand(&mut new_decs, dec),
+            "font" => expand_longhands(&mut new_decs, dec, &FONT_LONGHANDS),
+            "list-style" => expand_list_style_shorthand(&mut new_decs, dec),
+            "flex" => expand_flex_shorthand(&mut new_decs, dec),
+            "flex-flow" => expand_flex_flow_shorthand(&mut new_decs, dec),
+            "gap" | "grid-gap" => expand_gap_shorthand(&mut new_decs, dec),
+            "grid-column" => expand_longhands(&mut new_decs, dec, &GRID_COLUMN_LONGHANDS),
+            "grid-row" => expand_longhands(&mut new_decs, dec, &GRID_ROW_LONGHANDS),
+            "background" => expand_longhands(&mut new_decs, dec, &BACKGROUND_LONGHANDS),
+            "animation" => expand_longhands(&mut new_decs, dec, &ANIMATION_LONGHANDS),
+            "border-radius" => expand_longhands(&mut new_decs, dec, &BORDER_RADIUS_LONGHANDS),
+            name => match border_longhand_name(name) {
+                Some(internal) => new_decs.push(Declaration { name: internal, value: dec.value.clone(), important: dec.important }),
+                None => new_decs.push(dec.clone()),
+            },
+        }
+    }
+    new_decs.retain(is_valid_declaration);
+    *declarations = new_decs;
+}
+
+// properties whose lengths and numbers can't be negative
+const NON_NEGATIVE_PROPERTIES:[&str;34] = [
+    "width", "height", "min-width", "min-height", "max-width", "max-height",
+    "flex-grow", "flex-shrink", "flex-basis",
+    "padding", "padding-top", "padding-right", "padding-bottom", "padding-left",
+    "border-width", "border-width-top", "border-width-right", "border-width-bottom", "border-width-left",
+    "outline-width", "border-spacing", "font-size", "line-height", "column-width", "column-gap", "row-gap", "column-count",
+    "animation-duration", "animation-iteration-count",
+    "border-radius", "border-top-left-radius", "border-top-right-radius", "border-bottom-right-radius", "border-bottom-left-radius",
+];
+// properties whose lengths can't be percentages
+const NO_PERCENTAGE_PROPERTIES:[&str;8] = ["border-width", "border-width-top", "border-width-right",
+    "border-width-bottom", "border-width-left", "outline-width", "column-width", "border-spacing"];
+
+/// Whether the value is one the property can take. An invalid declaration is dropped, like
+/// one that doesn't parse, so an earlier valid value stays in effect. Values from calc() are
+/// only known during layout, which clamps them instead.
+pub fn is_valid_declaration(dec:&Declaration) -> bool {
+    let name = dec.name.as_str();
+    let non_negative = NON_NEGATIVE_PROPERTIES.contains(&name);
+    // a border-radius corner can be an array inside the array
+    let parts:Vec<&Value> = match &dec.value {
+        Value::ArrayValue(parts) => parts.iter().flat_map(|part| match part {
+            Value::ArrayValue(inner) => inner.iter().collect(),
+            part => vec![part],
+        }).collect(),
+        value => vec![value],
+    };
+    parts.iter().all(|part| match part {
+        Length(v, unit) => v.is_finite() && !(non_negative && *v < 0.0)
+            && !(*unit == Unit::Per && NO_PERCENTAGE_PROPERTIES.contains(&name)),
+        Value::Number(v) => v.is_finite() && !(non_negative && *v < 0.0) && !(name == "column-count" && *v < 1.0),
+        Value::Time(v) => v.is_finite() && !(non_negative && *v < 0.0),
+        _ => true,
+    })
+}
+
+// US letter at 96px per inch, with half inch margins
+const DEFAULT_PAGE_SIZE:(f32,f32) = (816.0, 1056.0);
+const DEFAULT_PAGE_MARGIN:f32 = 48.0;
+
+fn page_size_keyword(name:&str) -> Option<(f32,f32)> {
+    match name.to_lowercase().as_str() {
+        "letter" => Some((816.0, 1056.0)),
+        "legal" => Some((816.0, 1344.0)),
+        "a5" => Some((559.0, 794.0)),
+        "a4" => Some((794.0, 1123.0)),
+        "a3" => Some((1123.0, 1587.0)),
+        _ => None,
+    }
+}
+
+fn page_length_px(value:&Value, relative_to:f32) -> Option<f32> {
+    match value {
+        Length(v, Unit::Px) => Some(*v),
+        Length(v, Unit::Em) | Length(v, Unit::Rem) => Some(v * 16.0),
+        Length(v, Unit::Per) => Some(v / 100.0 * relative_to),
+        Length(v, unit) => unit.px_per_unit().map(|px| v * px),
+        Value::Number(v) if *v == 0.0 => Some(0.0),
+        _ => None,
+    }
+}
+
+fn apply_page_size(value:&Value, size:&mut (f32,f32)) {
+    let parts = match value {
+        Value::ArrayValue(arr) => arr.clone(),
+        _ => vec![value.clone()],
+    };
+    let lengths:Vec<f32> = parts.iter().filter_map(|v| page_length_px(v, 0.0)).collect();
+    match lengths.len() {
+        1 => *size = (lengths[0], lengths[0]),
+        2 => *size = (lengths[0], lengths[1]),
+        _ => {}
+    }
+    for part in parts.iter() {
+        if let Keyword(kw) = part {
+            if let Some(named) = page_size_keyword(kw) {
+                *size = named;
+            }
+        }
+    }
+    for part in parts.iter() {
+        match part {
+            Keyword(kw) if kw == "landscape" => *size = (size.0.max(size.1), size.0.min(size.1)),
+            Keyword(kw) if kw == "portrait" => *size = (size.0.min(size.1), size.0.max(size.1)),
+            _ => {}
+        }
+    }
+}
+
+/// The page box used when paginating for print, from the stylesheets' `@page` rules.
+/// The margin holds the page margins and the content rect is the printable area inside them.
+pub fn page_dimensions(styles:&StylesheetSet) -> Dimensions {
+    let pages:Vec<&Declaration> = styles.stylesheets.iter()
+        .flat_map(|ss| ss.rules.iter())
+        .filter_map(|rule| match rule {
+            RuleType::Page(page) if page.pseudo_class.is_none() => Some(page.declarations.iter()),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    let mut size = DEFAULT_PAGE_SIZE;
+    for dec in pages.iter().filter(|dec| dec.name == "size") {
+        apply_page_size(&dec.value, &mut size);
+    }
+    let mut margin = EdgeSizes {
+        left: DEFAULT_PAGE_MARGIN,
+        right: DEFAULT_PAGE_MARGIN,
+        top: DEFAULT_PAGE_MARGIN,
+        bottom: DEFAULT_PAGE_MARGIN,
+    };
+    for dec in pages.iter() {
+        let (edge, relative_to) = match dec.name.as_str() {
+            "margin-left" => (&mut margin.left, size.0),
+            "margin-right" => (&mut margin.right, size.0),
+            "margin-top" => (&mut margin.top, size.1),
+            "margin-bottom" => (&mut margin.bottom, size.1),
+            _ => continue,
+        };
+        if let Some(px) = page_length_px(&dec.value, relative_to) {
+            *edge = px;
+        }
+    }
+    Dimensions {
+        content: Rect {
+            x: margin.left,
+            y: margin.top,
+            width: size.0 - margin.left - margin.right,
+            height: size.1 - margin.top - margin.bottom,
+        },
+        padding: Default::default(),
+        border: Default::default(),
+        margin,
+    }
+}
+
+// the standard border-top-color style names, as the border-color-top style names used internally
+fn border_longhand_name(name:&str) -> Option<String> {
+    let parts:Vec<&str> = name.split('-').collect();
+    match parts.as_slice() {
+        ["border", side @ ("top" | "right" | "bottom" | "left"), prop @ ("width" | "color" | "style")] =>
+            Some(format!("border-{}-{}", prop, side)),
+        _ => None,
+    }
+}
+
+// the width, style and color parts of a border shorthand, in any order
+fn border_parts(value:&Value) -> Vec<(&'static str, Value)> {
+    let parts = match value {
+        Value::ArrayValue(vec) => vec.clone(),
+        value => vec![value.clone()],
+    };
+    parts.into_iter().map(|part| {
+        let prop = match &part {
+            Length(_, _) | Value::Number(_) => "width",
+            Keyword(kw) if BORDER_STYLES.contains(&kw.as_str()) => "style",
+            _ => "color",
+        };
+        (prop, part)
+    }).collect()
+}
+
+// border-top: 1px solid red and friends
+fn expand_border_side_shorthand(new_decs:&mut Vec::<Declaration>, dec:&Declaration) {
+    let side = dec.name.trim_start_matches("border-");
+    for (prop, part) in border_parts(&dec.value) {
+        new_decs.push(Declaration {
+            name: format!("border-{}-{}", prop, side),
+            value: part,
+            important: dec.important,
+        });
+    }
+}
+
+// border: 1px solid red sets every side. the style and color are also kept as the
+// all-sides border-style and border-color, which the per-side values fall back to.
+fn expand_border_shorthand(new_decs:&mut Vec::<Declaration>, dec:&Declaration) {
+    for (prop, part) in border_parts(&dec.value) {
+        for side in SIDES.iter() {
+            new_decs.push(Declaration {
+                name: format!("border-{}-{}", prop, side),
+                value: part.clone(),
+                important: dec.important,
             });
-            new_decs.push(Declaration{
-                name: String::from("border-width-bottom"),
-                value: dec.value.clone()
+        }
+        if prop != "width" {
+            new_decs.push(Declaration {
+                name: format!("border-{}", prop),
+                value: part,
+                important: dec.important,
             });
         }
-        _ => {
-            panic!("border shorthand must be an array value {:#?}",dec);
+    }
+}
+
+// outline: 2px dotted blue. `auto` is a style here rather than a color, and the parts left
+// out go back to their initial values.
+fn expand_outline_shorthand(new_decs:&mut Vec::<Declaration>, dec:&Declaration) {
+    let props = ["width", "style", "color"];
+    if let Keyword(kw) = &dec.value {
+        if CSS_WIDE_KEYWORDS.contains(&kw.to_lowercase().as_str()) {
+            for prop in props {
+                new_decs.push(Declaration { name: format!("outline-{}", prop), value: dec.value.clone(), important: dec.important });
+            }
+            return;
+        }
+    }
+    let parts:Vec<(&str, Value)> = border_parts(&dec.value).into_iter().map(|(prop, part)| match &part {
+        Keyword(kw) if kw == "auto" => ("style", part),
+        Keyword(kw) if ["thin", "medium", "thick"].contains(&kw.as_str()) => ("width", part),
+        _ => (prop, part),
+    }).collect();
+    for prop in props {
+        let name = format!("outline-{}", prop);
+        let value = parts.iter().find(|(p, _)| *p == prop).map(|(_, part)| part.clone()).or_else(|| initial_value(&name));
+        if let Some(value) = value {
+            new_decs.push(Declaration { name, value, important: dec.important });
         }
     }
 }
 
+/// Styles the page with css as its one stylesheet, like standard_test_run without the layout.
+#[cfg(test)]
+pub(crate) fn style_tree(html:&[u8], css:&[u8]) -> StyledTree {
+    let doc = load_doc_from_bytestring(html);
+    let mut stylesheet = parse_stylesheet_from_bytestring(css).unwrap();
+    expand_styles(&mut stylesheet);
+    let mut styles = StylesheetSet::new();
+    styles.append(stylesheet);
+    dom_tree_to_stylednodes(&doc.root_node, &styles)
+}
+
 #[test]
 fn test_multifile_cascade() {
     let stylesheet_parent = load_stylesheet_from_net(&relative_filepath_to_url("tests/default.css").unwrap()).unwrap();
     let mut stylesheet = load_stylesheet_from_net(&relative_filepath_to_url("tests/child.css").unwrap()).unwrap();
     let elem = ElementData {
         tag_name: String::from("div"),
-        attributes: Default::default()
+        attributes: Default::default(),
+        state: Default::default(),
     };
     let mut a2:Vec<(&Node, &PropertyMap)> = vec![];
     let mut styles = StylesheetSet::new();
     styles.append(stylesheet_parent);
     styles.append(stylesheet);
-    let values = specified_values(&elem, &styles, &mut a2);
+    let values = specified_values(&elem, &RuleIndex::new(&styles), &AncestorFilter::default(), &mut a2);
     println!("got the values {:#?}", values);
     assert_eq!(values.get("background-color").unwrap(),&Value::Keyword(String::from("blue")));
 }
 
+#[test]
+fn test_matching_rules() {
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(b"p { color: gray; }").unwrap());
+    styles.user_agent_count = 1;
+    styles.append(parse_stylesheet_from_bytestring(b"#y { color: blue; } div p, .x { color: red; } ul p { color: pink; }").unwrap());
+    let doc = load_doc_from_bytestring(br#"<div><p id="y" class="x" align="center" style="color: green">a</p></div>"#);
+    let rules = matching_rules(&doc.root_node, &[0], &styles);
+    let origins:Vec<Origin> = rules.iter().map(|rule| rule.origin).collect();
+    assert_eq!(origins, vec![Origin::UserAgent, Origin::Presentational, Origin::Author, Origin::Author, Origin::Inline]);
+    // the more specific of the two selectors that match, and the id rule after it despite coming first
+    assert_eq!(rules[2].selector.map(|selector| selector.specificity()), Some((0, 1, 0)));
+    assert_eq!(rules[2].order, 2);
+    assert_eq!(rules[3].specificity, (1, 0, 0));
+    assert_eq!(rules[1].declarations[0].name, "text-align");
+    assert_eq!(rules[4].rule, None);
+    assert_eq!(rules[4].declarations[0].value, Keyword(String::from("green")));
+    assert_eq!(matching_rules(&doc.root_node, &[3], &styles), vec![]);
+}
+
+#[test]
+fn test_important_cascade() {
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(b"p { color: gray !important; }").unwrap());
+    styles.append(parse_stylesheet_from_bytestring(b"p { color: green !important; font-weight: bold; }").unwrap());
+    styles.append(parse_stylesheet_from_bytestring(b"#y { color: red !important; font-weight: normal; } p { margin: 0 !important; }").unwrap());
+    styles.user_agent_count = 1;
+    styles.user_count = 1;
+    let doc = load_doc_from_bytestring(br#"<div><p id="y" style="color: blue; margin: 1px !important">a</p></div>"#);
+    let origins:Vec<Origin> = matching_rules(&doc.root_node, &[0], &styles).iter().map(|rule| rule.origin).collect();
+    assert_eq!(origins, vec![Origin::UserAgent, Origin::User, Origin::Author, Origin::Author, Origin::Inline,
+        Origin::AuthorImportant, Origin::AuthorImportant, Origin::InlineImportant, Origin::UserImportant, Origin::UserAgentImportant]);
+    let values = specified_values(match &doc.root_node.children[0].node_type {
+        Element(ed) => ed,
+        _ => panic!("the p should be an element"),
+    }, &RuleIndex::new(&styles), &AncestorFilter::default(), &mut vec![]);
+    // the browser's !important beats everything, and the page's normal rules beat the user's
+    assert_eq!(values.get("color"), Some(&Keyword(String::from("gray"))));
+    assert_eq!(values.get("font-weight"), Some(&Value::Number(400.0)));
+    // the style attribute's !important beats the stylesheet's
+    assert_eq!(values.get("margin-top"), Some(&Length(1.0, Unit::Px)));
+}
+
 #[test]
 fn test_inherited_match() {
     let doc_text = br#"
@@ -583,12 +2063,40 @@ fn test_inherited_match() {
     assert_eq!(snode.children.borrow()[0].specified_values.get("color").unwrap(),
                &Keyword(String::from("black")));
     assert_eq!(snode.children.borrow()[0].specified_values.get("font-weight").unwrap(),
-               &Keyword(String::from("bold")));
+               &Value::Number(700.0));
     // check html b a element
     assert_eq!(snode.children.borrow()[0].children.borrow()[1].specified_values.get("color").unwrap(),
                &Keyword(String::from("blue")));
     assert_eq!(snode.children.borrow()[0].children.borrow()[1].specified_values.get("font-weight").unwrap(),
-               &Keyword(String::from("bold")));
+               &Value::Number(700.0));
+}
+
+#[test]
+fn test_inherited_properties() {
+    let mut sheet = crate::css::parse_stylesheet(r#"
+        div { color: red; text-align: center; font-size: 20px; line-height: 1.5; cursor: pointer; background-color: blue; }
+        span { font-size: 2em; }
+        em { text-align: right; }
+    "#).unwrap();
+    expand_styles(&mut sheet);
+    let mut styles = StylesheetSet::new();
+    styles.append(sheet);
+    let doc = crate::dom::load_doc_from_bytestring(b"<div><p><span><em>deep</em></span></p></div>");
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let root = stree.root.borrow();
+    let p = Rc::clone(&root.children.borrow()[0]);
+    let span = Rc::clone(&p.children.borrow()[0]);
+    let em = Rc::clone(&span.children.borrow()[0]);
+    // nothing but the div sets them, and they reach all the way down
+    assert_eq!(em.value("color"), Some(Keyword(String::from("red"))));
+    assert_eq!(em.value("line-height"), Some(Value::Number(1.5)));
+    assert_eq!(em.value("cursor"), Some(Keyword(String::from("pointer"))));
+    assert_eq!(em.value("text-align"), Some(Keyword(String::from("right"))));
+    assert_eq!(p.value("text-align"), Some(Keyword(String::from("center"))));
+    // the em size is of the div's, and the em inherits the result rather than doubling it again
+    assert_eq!(span.value("font-size"), Some(Length(40.0, Unit::Px)));
+    assert_eq!(em.value("font-size"), Some(Length(40.0, Unit::Px)));
+    assert_eq!(p.value("background-color"), None);
 }
 
 #[test]
@@ -612,7 +2120,7 @@ fn test_em_to_px() {
     println!("doc={:#?}  snode={:#?}",doc,snode);
 
     //check html element
-    assert_eq!(snode.specified_values.get("margin-left").unwrap(), &Length(1.0,Unit::Em));
+    assert_eq!(snode.specified_values.get("margin-left").unwrap(), &Length(18.0,Unit::Px));
 }
 
 #[test]
@@ -665,6 +2173,46 @@ fn test_multi_selector_match() {
 
 }
 
+#[test]
+fn test_compound_selector_match() {
+    let doc = load_doc_from_bytestring(br#"<div><p class="card featured">a</p><p class="card">b</p><a id="cta" class="button">c</a><a class="button">d</a></div>"#);
+    let mut stylesheet = parse_stylesheet_from_bytestring(br#"
+        p.card.featured { color: red; }
+        a.button#cta { color: blue; }
+    "#).unwrap();
+    expand_styles(&mut stylesheet);
+    let mut styles = StylesheetSet::new();
+    styles.append(stylesheet);
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let root = stree.root.borrow();
+    let colors:Vec<Option<Value>> = root.children.borrow().iter().map(|ch| ch.value("color")).collect();
+    // every class and the id have to be there
+    assert_eq!(colors, vec![Some(Keyword(String::from("red"))), None, Some(Keyword(String::from("blue"))), None]);
+}
+
+#[test]
+fn test_inline_style() {
+    let doc = load_doc_from_bytestring(br#"<div><p id="a" style="color: blue; margin: 1px 2px">a</p><p style="font-weight:bold">b</p><p style="color: ; width">c</p></div>"#);
+    let mut stylesheet = parse_stylesheet_from_bytestring(br#"
+        #a { color: red; margin-left: 5px; }
+        p { font-weight: normal; color: green; }
+    "#).unwrap();
+    expand_styles(&mut stylesheet);
+    let mut styles = StylesheetSet::new();
+    styles.append(stylesheet);
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let root = stree.root.borrow();
+    let ps = root.children.borrow();
+    // the attribute beats even an id selector, and its shorthands are expanded
+    assert_eq!(ps[0].value("color"), Some(Keyword(String::from("blue"))));
+    assert_eq!(ps[0].value("margin-left"), Some(Length(2.0, Unit::Px)));
+    assert_eq!(ps[0].value("margin-top"), Some(Length(1.0, Unit::Px)));
+    // no trailing semicolon is needed
+    assert_eq!(ps[1].value("font-weight"), Some(Value::Number(700.0)));
+    // and one that doesn't parse is ignored
+    assert_eq!(ps[2].value("color"), Some(Keyword(String::from("green"))));
+}
+
 #[test]
 fn test_ancestor_match() {
     let doc_text = br#"
@@ -695,6 +2243,201 @@ fn test_ancestor_match() {
 
 }
 
+#[test]
+fn test_descendant_match() {
+    let doc_text = br#"<div><article><section><p>deep</p></section></article><p>outside</p></div>"#;
+    let css_text = br#"
+        p { color: black; }
+        article p { color: red; }
+        div article section p { background-color: blue; }
+        section article p { border-color: green; }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(css_text).unwrap());
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    let article = div.children.borrow()[0].clone();
+    let section = article.children.borrow()[0].clone();
+    let deep = section.children.borrow()[0].clone();
+    assert_eq!(deep.value("color"), Some(Keyword(String::from("red"))));
+    assert_eq!(deep.value("background-color"), Some(Keyword(String::from("blue"))));
+    // the ancestors have to be in the right order
+    assert_eq!(deep.value("border-color"), None);
+    let outside = div.children.borrow()[1].clone();
+    assert_eq!(outside.value("color"), Some(Keyword(String::from("black"))));
+}
+
+#[test]
+fn test_child_match() {
+    let doc_text = br#"<div><ul><li>item<ol><li>nested</li></ol></li></ul></div>"#;
+    let css_text = br#"
+        li { color: black; }
+        ul > li { color: red; }
+        div>ul>li { background-color: blue; }
+        div > li { border-color: green; }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(css_text).unwrap());
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    let ul = div.children.borrow()[0].clone();
+    let item = ul.children.borrow()[0].clone();
+    assert_eq!(item.value("color"), Some(Keyword(String::from("red"))));
+    assert_eq!(item.value("background-color"), Some(Keyword(String::from("blue"))));
+    assert_eq!(item.value("border-color"), None);
+    // a descendant of the ul, but not its child
+    let ol = item.children.borrow()[1].clone();
+    let nested = ol.children.borrow()[0].clone();
+    assert_eq!(nested.value("color"), Some(Keyword(String::from("black"))));
+    assert_eq!(nested.value("background-color"), None);
+}
+
+#[test]
+fn test_sibling_match() {
+    let doc_text = br#"<div><h1>title</h1><p>first</p><p>second</p><section><h1>inner</h1></section><p>third</p></div>"#;
+    let css_text = br#"
+        h1 + p { color: red; }
+        h1 ~ p { background-color: blue; }
+        section + p { border-color: green; }
+        div h1 ~ section h1 { color: purple; }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(css_text).unwrap());
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    let children = div.children.borrow();
+    let (first, second, section, third) = (&children[1], &children[2], &children[3], &children[4]);
+    assert_eq!(first.value("color"), Some(Keyword(String::from("red"))));
+    assert_eq!(first.value("background-color"), Some(Keyword(String::from("blue"))));
+    // not right after the h1, but still after it
+    assert_eq!(second.value("color"), None);
+    assert_eq!(second.value("background-color"), Some(Keyword(String::from("blue"))));
+    assert_eq!(third.value("border-color"), Some(Keyword(String::from("green"))));
+    assert_eq!(third.value("color"), None);
+    let inner = section.children.borrow()[0].clone();
+    assert_eq!(inner.value("color"), Some(Keyword(String::from("purple"))));
+}
+
+#[test]
+fn test_dynamic_pseudo_classes() {
+    let doc_text = br#"<div><a>link</a><input></div>"#;
+    let css_text = br#"
+        a { color: black; }
+        a:hover { color: red; }
+        div:hover a { background-color: blue; }
+        input:focus { border-color: green; }
+        a:hover:active { color: purple; }
+    "#;
+    let mut doc = load_doc_from_bytestring(doc_text);
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(css_text).unwrap());
+    let style = |doc:&crate::dom::Document, i:usize, name:&str| {
+        let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+        let div = stree.root.borrow();
+        let child = div.children.borrow()[i].clone();
+        child.value(name)
+    };
+    assert_eq!(style(&doc, 0, "color"), Some(Keyword(String::from("black"))));
+    assert_eq!(style(&doc, 0, "background-color"), None);
+    assert!(doc.root_node.set_element_state(Some(&[0]), ElementState::HOVER));
+    assert_eq!(style(&doc, 0, "color"), Some(Keyword(String::from("red"))));
+    assert_eq!(style(&doc, 0, "background-color"), Some(Keyword(String::from("blue"))));
+    assert!(doc.root_node.set_element_state(Some(&[0]), ElementState::ACTIVE));
+    assert_eq!(style(&doc, 0, "color"), Some(Keyword(String::from("purple"))));
+    assert_eq!(style(&doc, 1, "border-color"), None);
+    assert!(doc.root_node.set_element_state(Some(&[1]), ElementState::FOCUS));
+    assert_eq!(style(&doc, 1, "border-color"), Some(Keyword(String::from("green"))));
+    assert!(!doc.root_node.set_element_state(Some(&[1]), ElementState::FOCUS));
+}
+
+#[test]
+fn test_restyle_subtree() {
+    let doc_text = br#"<div><p><a>a</a></p><p><a>b</a></p></div>"#;
+    let css_text = br#"
+        a { color: black; }
+        p:hover a { color: red; }
+    "#;
+    let mut doc = load_doc_from_bytestring(doc_text);
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(css_text).unwrap());
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let color = |stree:&StyledTree, i:usize| {
+        let p = stree.root.borrow().children.borrow()[i].clone();
+        let a = p.children.borrow()[0].clone();
+        a.value("color")
+    };
+    // the div is hovered too, so the whole tree is restyled
+    let changed = doc.root_node.update_element_state(Some(&[1, 0]), ElementState::HOVER).unwrap();
+    assert_eq!(changed, Vec::<usize>::new());
+    assert!(restyle_subtree(&stree, &doc.root_node, &changed, ElementState::HOVER, &styles));
+    assert_eq!(color(&stree, 1), Some(Keyword(String::from("red"))));
+    assert_eq!(color(&stree, 0), Some(Keyword(String::from("black"))));
+    assert_eq!(doc.root_node.update_element_state(Some(&[1, 0]), ElementState::HOVER), None);
+    // restyling one paragraph leaves the other's styled nodes alone
+    let first = stree.root.borrow().children.borrow()[0].clone();
+    let second = stree.root.borrow().children.borrow()[1].clone();
+    assert!(restyle_subtree(&stree, &doc.root_node, &[0], ElementState::HOVER, &styles));
+    assert!(!Rc::ptr_eq(&first, &stree.root.borrow().children.borrow()[0]));
+    assert!(Rc::ptr_eq(&second, &stree.root.borrow().children.borrow()[1]));
+    // nothing depends on focus
+    assert!(!restyle_subtree(&stree, &doc.root_node, &[0], ElementState::FOCUS, &styles));
+}
+
+#[test]
+fn test_cascade_order() {
+    let doc_text = br#"<div><p class="note" id="main">a</p><p class="note">b</p></div>"#;
+    let css_text = br#"
+        p.note { color: red; }
+        p { color: black; }
+        #main { background-color: blue; }
+        p.note { background-color: green; }
+        p { border-color: white; }
+        p { border-color: gray; }
+        div p, p.note { font-style: italic; }
+        .note { font-style: normal; }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(css_text).unwrap());
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    let children = div.children.borrow();
+    let (main, other) = (&children[0], &children[1]);
+    assert_eq!(main.value("color"), Some(Keyword(String::from("red"))));
+    assert_eq!(main.value("background-color"), Some(Keyword(String::from("blue"))));
+    assert_eq!(other.value("background-color"), Some(Keyword(String::from("green"))));
+    // equal specificity, so the later rule wins
+    assert_eq!(main.value("border-color"), Some(Keyword(String::from("gray"))));
+    // a rule counts with its most specific matching selector
+    assert_eq!(main.value("font-style"), Some(Keyword(String::from("italic"))));
+}
+
+#[test]
+fn test_selector_list_match() {
+    let doc_text = br#"<div><h1>a</h1><h2 class="big">b</h2><h3>c</h3></div>"#;
+    let css_text = br#"
+        h1, h2.big, h4 { color: red; }
+        h1 { color: blue; }
+        h2 { color: blue; }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(css_text).unwrap());
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    let children = div.children.borrow();
+    // each selector in the list keeps its own specificity
+    assert_eq!(children[0].value("color"), Some(Keyword(String::from("blue"))));
+    assert_eq!(children[1].value("color"), Some(Keyword(String::from("red"))));
+    assert_eq!(children[2].value("color"), None);
+    let selectors = crate::css::parse_selector_list("h4, h3, .big").unwrap();
+    // the first in document order, not the first selector
+    assert_eq!(query_selector(&doc.root_node, &selectors), Some(vec![1]));
+}
+
 #[test]
 fn test_property_expansion_1() {
     let doc_text = br#"<div></div>"#;
@@ -754,6 +2497,189 @@ fn test_property_expansion_4() {
     assert_eq!(snode.lookup_length_px("margin-left",5.0),4.0);
 }
 
+#[test]
+fn test_property_expansion_single_values() {
+    let doc_text = br#"<div></div>"#;
+    let css_text = br#"
+        div {
+            margin: auto;
+            padding: 1px 2px 3px;
+        }
+    "#;
+
+    let (_doc, _stylesheet,stree,_lbox, _rbox) = standard_test_run(doc_text, css_text).unwrap();
+    let snode = stree.root.borrow();
+    for side in ["top", "right", "bottom", "left"].iter() {
+        assert_eq!(snode.value(&format!("margin-{}", side)), Some(Keyword(String::from("auto"))));
+    }
+    assert_eq!(snode.value("margin"), None);
+    assert_eq!(snode.lookup_length_px("padding-top",5.0),1.0);
+    assert_eq!(snode.lookup_length_px("padding-right",5.0),2.0);
+    assert_eq!(snode.lookup_length_px("padding-bottom",5.0),3.0);
+    assert_eq!(snode.lookup_length_px("padding-left",5.0),2.0);
+    assert_eq!(snode.value("padding"), None);
+}
+
+#[test]
+fn test_list_style() {
+    let doc_text = br#"<div><ul><li>a</li><p>b</p><li>c</li></ul><ol><li>d</li></ol></div>"#;
+    let css_text = br#"
+        ul { list-style: none; }
+        ol { list-style: decimal inside; }
+        li { display: list-item; list-style-type: inherit; list-style-position: inherit; }
+        p { display: block; }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut stylesheet = parse_stylesheet_from_bytestring(css_text).unwrap();
+    expand_styles(&mut stylesheet);
+    let mut styles = StylesheetSet::new();
+    styles.append(stylesheet);
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    let ul = Rc::clone(&div.children.borrow()[0]);
+    let ol = Rc::clone(&div.children.borrow()[1]);
+    assert_eq!(ul.value("list-style-type"), Some(Keyword(String::from("none"))));
+    assert_eq!(ul.value("list-style-image"), Some(Keyword(String::from("none"))));
+    assert_eq!(ul.value("list-style-position"), Some(Keyword(String::from("outside"))));
+    let items = ul.children.borrow();
+    assert_eq!(items[0].value("list-style-type"), Some(Keyword(String::from("none"))));
+    // only list items count
+    assert_eq!(items[0].list_item_number(), 1);
+    assert_eq!(items[2].list_item_number(), 2);
+    let item = &ol.children.borrow()[0];
+    assert_eq!(item.value("list-style-type"), Some(Keyword(String::from("decimal"))));
+    assert_eq!(item.value("list-style-position"), Some(Keyword(String::from("inside"))));
+}
+
+#[test]
+fn test_background_shorthand_expansion() {
+    let doc_text = br#"<div><p>a</p><b>b</b></div>"#;
+    let css_text = br#"
+        div { background: #fff url(bg.png) no-repeat center / cover; }
+        p { background-image: url(other.png); background: red; }
+        b { background: inherit; }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut stylesheet = parse_stylesheet_from_bytestring(css_text).unwrap();
+    expand_styles(&mut stylesheet);
+    let mut styles = StylesheetSet::new();
+    styles.append(stylesheet);
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    assert_eq!(div.color("background-color"), Some(Color::from_hex("#ffffff")));
+    assert!(matches!(div.value("background-image"), Some(Value::FunCall(call)) if call.name == "url"));
+    assert_eq!(div.value("background-repeat"), Some(Keyword(String::from("no-repeat"))));
+    assert_eq!(div.value("background-position"), Some(Keyword(String::from("center"))));
+    assert_eq!(div.value("background-size"), Some(Keyword(String::from("cover"))));
+    assert_eq!(div.value("background"), None);
+    // the shorthand resets what it leaves out
+    let p = &div.children.borrow()[0];
+    assert_eq!(p.color("background-color"), find_color_lazy_static("red"));
+    assert_eq!(p.value("background-image"), Some(Keyword(String::from("none"))));
+    let b = &div.children.borrow()[1];
+    assert_eq!(b.value("background-repeat"), Some(Keyword(String::from("no-repeat"))));
+    assert_eq!(b.color("background-color"), Some(Color::from_hex("#ffffff")));
+}
+
+#[test]
+fn test_outline_shorthand_expansion() {
+    let doc_text = br#"<div><p>a</p></div>"#;
+    let css_text = br#"
+        div { color: green; outline: 2px dashed blue; }
+        p { outline-width: 4px; outline: auto; }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut stylesheet = parse_stylesheet_from_bytestring(css_text).unwrap();
+    expand_styles(&mut stylesheet);
+    let mut styles = StylesheetSet::new();
+    styles.append(stylesheet);
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    assert_eq!(div.value("outline-width"), Some(Length(2.0, Unit::Px)));
+    assert_eq!(div.value("outline-style"), Some(Keyword(String::from("dashed"))));
+    assert_eq!(div.color("outline-color"), find_color_lazy_static("blue"));
+    // the shorthand resets what it leaves out, and the color follows the text
+    let p = &div.children.borrow()[0];
+    assert_eq!(p.value("outline-style"), Some(Keyword(String::from("auto"))));
+    assert_eq!(p.value("outline-width"), Some(Length(3.0, Unit::Px)));
+    assert_eq!(p.color("outline-color"), find_color_lazy_static("green"));
+    assert!(is_supported_declaration(&Declaration { name: String::from("outline-width"), value: Keyword(String::from("thin")), important: false }));
+}
+
+#[test]
+fn test_invalid_values_dropped() {
+    let doc_text = br#"<div>a</div>"#;
+    let css_text = br#"
+        div {
+            padding: 5px;
+            padding: -50px;
+            padding-left: -1em;
+            border-width: 2px;
+            border-width: 10%;
+            width: -20px;
+            font-size: -3px;
+            line-height: 1.5;
+            line-height: -2;
+            margin-left: -10px;
+            letter-spacing: -1px;
+            column-count: 0;
+            border: -4px solid red;
+            padding: 1px -2px;
+        }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut stylesheet = parse_stylesheet_from_bytestring(css_text).unwrap();
+    expand_styles(&mut stylesheet);
+    let mut styles = StylesheetSet::new();
+    styles.append(stylesheet);
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    // the earlier valid values stay
+    assert_eq!(div.value("padding-left"), Some(Length(5.0, Unit::Px)));
+    assert_eq!(div.value("padding-top"), Some(Length(5.0, Unit::Px)));
+    assert_eq!(div.value("border-width-top"), Some(Length(2.0, Unit::Px)));
+    assert_eq!(div.value("line-height"), Some(Value::Number(1.5)));
+    assert_eq!(div.value("width"), None);
+    assert_eq!(div.value("font-size"), None);
+    assert_eq!(div.value("column-count"), None);
+    // the rest of the border shorthand still applies
+    assert_eq!(div.value("border-style-top"), Some(Keyword(String::from("solid"))));
+    // negative margins and spacing are fine
+    assert_eq!(div.value("margin-left"), Some(Length(-10.0, Unit::Px)));
+    assert_eq!(div.value("letter-spacing"), Some(Length(-1.0, Unit::Px)));
+}
+
+#[test]
+fn test_font_shorthand_expansion() {
+    let doc_text = br#"<div><p>a</p><b>b</b></div>"#;
+    let css_text = br#"
+        div { font: italic 700 16px/1.5 "Open Sans", sans-serif; }
+        p { font-weight: 300; font: 12px serif; }
+        b { font: inherit; }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut stylesheet = parse_stylesheet_from_bytestring(css_text).unwrap();
+    expand_styles(&mut stylesheet);
+    let mut styles = StylesheetSet::new();
+    styles.append(stylesheet);
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    assert_eq!(div.value("font-style"), Some(Keyword(String::from("italic"))));
+    assert_eq!(div.lookup_font_weight(400), 700);
+    assert_eq!(div.value("font-size"), Some(Length(16.0, Unit::Px)));
+    assert_eq!(div.value("line-height"), Some(Value::Number(1.5)));
+    assert_eq!(div.value("font-family"), Some(Value::ArrayValue(vec![
+        Value::StringLiteral(String::from("Open Sans")), Keyword(String::from("sans-serif"))])));
+    assert_eq!(div.value("font"), None);
+    // the shorthand resets what it leaves out
+    let p = &div.children.borrow()[0];
+    assert_eq!(p.value("font-weight"), Some(Value::Number(400.0)));
+    assert_eq!(p.value("font-style"), Some(Keyword(String::from("normal"))));
+    let b = &div.children.borrow()[1];
+    assert_eq!(b.value("font-size"), Some(Length(16.0, Unit::Px)));
+    assert_eq!(b.value("font-style"), Some(Keyword(String::from("italic"))));
+}
+
 #[test]
 fn test_border_shorthand() {
     let doc_text = br#"<div></div>"#;
@@ -773,6 +2699,28 @@ fn test_border_shorthand() {
     assert_eq!(snode.lookup_keyword("border-color", &Keyword(String::from("white"))), Keyword(String::from("black")));
 }
 
+#[test]
+fn test_border_shorthand_forms() {
+    let doc_text = br#"<div><p></p><span></span></div>"#;
+    let css_text = br#"
+        div { border: red 2px dashed; }
+        p { border: none; }
+        span { border: 3px; border-left-style: dotted; }
+    "#;
+
+    let (_doc, _stylesheet,stree,_lbox, _rbox) = standard_test_run(doc_text, css_text).unwrap();
+    let div = stree.root.borrow();
+    for side in ["top", "right", "bottom", "left"].iter() {
+        assert_eq!(div.lookup_length_px(&format!("border-width-{}", side),5.0),2.0);
+        assert_eq!(div.value(&format!("border-style-{}", side)), Some(Keyword(String::from("dashed"))));
+        assert_eq!(div.value(&format!("border-color-{}", side)), Some(Keyword(String::from("red"))));
+    }
+    let children = div.children.borrow();
+    assert_eq!(children[0].value("border-style-top"), Some(Keyword(String::from("none"))));
+    assert_eq!(children[1].lookup_length_px("border-width-right",5.0),3.0);
+    assert_eq!(children[1].value("border-style-left"), Some(Keyword(String::from("dotted"))));
+}
+
 #[test]
 fn test_relative_font_sizes() {
     let doc_text = br#"<body><p>stuff</p></body>"#;
@@ -798,3 +2746,343 @@ fn test_default_styles() {
         html_style.lookup_keyword("display",&Value::Keyword(String::from("foo"))),
                                     Value::Keyword("block".to_string()));
 }
+
+#[test]
+fn test_supports_rules() {
+    let doc_text = br#"<div></div>"#;
+    let css_text = br#"
+        div { color: black; }
+        @supports (display: run-in) {
+            div { color: red; }
+        }
+        @supports (display: block) and (not (display: run-in)) {
+            div { background-color: blue; }
+        }
+    "#;
+    let (_doc, _stylesheet, stree, _lbox, _rbox) = standard_test_run(doc_text, css_text).unwrap();
+    let snode = stree.root.borrow();
+    assert_eq!(snode.specified_values.get("color").unwrap(), &Keyword(String::from("black")));
+    assert_eq!(snode.specified_values.get("background-color").unwrap(), &Keyword(String::from("blue")));
+}
+
+#[test]
+fn test_selection_pseudo_element() {
+    let doc_text = br#"<div><p>some text</p></div>"#;
+    let css_text = br#"
+        div { color: black; }
+        p::selection { color: white; background-color: #ff0000; }
+    "#;
+    let stree = style_tree(doc_text, css_text);
+    let div = stree.root.borrow();
+    let p = &div.children.borrow()[0];
+    // the p inherits the div's color, but not its ::selection
+    assert_eq!(p.specified_values.get("color"), Some(&Keyword(String::from("black"))));
+    assert_eq!(p.selection_colors(&Color::from_hex("#000000"), &Color::from_hex("#0000ff")),
+               (Color::from_hex("#ffffff"), Color::from_hex("#ff0000")));
+    assert_eq!(div.selection_colors(&Color::from_hex("#000000"), &Color::from_hex("#0000ff")),
+               (Color::from_hex("#000000"), Color::from_hex("#0000ff")));
+}
+
+#[test]
+fn test_generated_content() {
+    let doc_text = br#"<div><q>quoted</q><a href="/docs">docs</a><p class="clearfix">x</p></div>"#;
+    let css_text = br#"
+        q::before { content: "<<"; color: red; }
+        q:after { content: ">>"; }
+        a::after { content: " (" attr(href) ")"; }
+        .clearfix::after { content: ""; display: block; }
+        div::before { content: none; }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(css_text).unwrap());
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    let children = div.children.borrow();
+    assert_eq!(children.len(), 3);
+    let text = |node:&Rc<StyledNode>| node.children.borrow().iter().map(|ch| ch.node.text_content()).collect::<String>();
+    assert_eq!(text(&children[0]), "<<quoted>>");
+    let q_children = children[0].children.borrow();
+    assert_eq!(q_children[0].value("color"), Some(Keyword(String::from("red"))));
+    assert_eq!(q_children[0].path, vec![0]);
+    assert_eq!(text(&children[1]), "docs (/docs)");
+    let clearfix = children[2].children.borrow();
+    assert_eq!(clearfix.len(), 2);
+    assert_eq!(clearfix[1].value("display"), Some(Keyword(String::from("block"))));
+    assert!(clearfix[1].children.borrow().is_empty());
+}
+
+#[test]
+fn test_counter_content() {
+    let doc_text = br#"<div><h2>a</h2><ol><li>x</li><li>y<ol><li>z</li></ol></li></ol><h2 class="hidden">b</h2><h2 class="roman">c</h2></div>"#;
+    let css_text = br#"
+        div { counter-reset: section; }
+        h2::before { counter-increment: section; content: counter(section) ". "; }
+        .hidden { display: none; }
+        .roman::before { content: counter(section, upper-roman) " "; }
+        ol { counter-reset: item; }
+        li { counter-increment: item; }
+        li::before { content: counters(item, ".") " "; }
+        li:hover { color: red; }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(css_text).unwrap());
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let text = |node:&Rc<StyledNode>| node.children.borrow().iter()
+        .map(|ch| ch.children.borrow().iter().map(|text| text.node.text_content()).collect::<String>())
+        .collect::<Vec<String>>();
+    let children = stree.root.borrow().children.borrow().clone();
+    assert_eq!(text(&children[0])[0], "1. ");
+    // the hidden heading doesn't count
+    assert_eq!(text(&children[3])[0], "II ");
+    let items = children[1].children.borrow().clone();
+    assert_eq!(text(&items[0])[0], "1 ");
+    assert_eq!(text(&items[1])[0], "2 ");
+    let nested = items[1].children.borrow()[2].children.borrow()[0].clone();
+    assert_eq!(text(&nested)[0], "2.1 ");
+
+    // restyling the hovered items picks the counters up where they were
+    let mut doc = doc;
+    doc.root_node.set_element_state(Some(&[1, 1]), ElementState::HOVER);
+    let changed = doc.root_node.update_element_state(Some(&[1, 1, 1, 0]), ElementState::HOVER).unwrap();
+    assert_eq!(changed, vec![1, 1, 1]);
+    assert!(restyle_subtree(&stree, &doc.root_node, &changed, ElementState::HOVER, &styles));
+    let items = stree.root.borrow().children.borrow()[1].children.borrow().clone();
+    let nested = items[1].children.borrow()[2].children.borrow()[0].clone();
+    assert_eq!(nested.value("color"), Some(Keyword(String::from("red"))));
+    assert_eq!(text(&nested)[0], "2.1 ");
+}
+
+#[test]
+fn test_media_rules() {
+    let doc_text = br#"<div><p>text</p></div>"#;
+    let css_text = br#"
+        p { color: black; margin: 0px; }
+        @media (min-width: 600px) { p { color: red; margin: 1px 2px; } }
+        @media screen and (max-width: 30em), print { p { background-color: blue; } }
+        @media not screen { p { border-color: green; } }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut styles = StylesheetSet::new();
+    let mut ss = parse_stylesheet_from_bytestring(css_text).unwrap();
+    expand_styles(&mut ss);
+    styles.append(ss);
+    let p_value = |styles:&StylesheetSet, name:&str| {
+        let stree = dom_tree_to_stylednodes(&doc.root_node, styles);
+        let div = stree.root.borrow();
+        let p = div.children.borrow()[0].clone();
+        p.value(name)
+    };
+    styles.media = MediaEnvironment::screen(800.0, 600.0);
+    assert_eq!(p_value(&styles, "color"), Some(Keyword(String::from("red"))));
+    assert_eq!(p_value(&styles, "margin-right"), Some(Length(2.0, Unit::Px)));
+    assert_eq!(p_value(&styles, "background-color"), None);
+    styles.media = MediaEnvironment::screen(400.0, 600.0);
+    assert_eq!(p_value(&styles, "color"), Some(Keyword(String::from("black"))));
+    assert_eq!(p_value(&styles, "background-color"), Some(Keyword(String::from("blue"))));
+    assert_eq!(p_value(&styles, "border-color"), None);
+    styles.media = MediaEnvironment { media_type: String::from("print"), width: 800.0, height: 1000.0, prefers_dark: false };
+    assert_eq!(p_value(&styles, "background-color"), Some(Keyword(String::from("blue"))));
+    assert_eq!(p_value(&styles, "border-color"), Some(Keyword(String::from("green"))));
+}
+
+#[test]
+fn test_prefers_color_scheme() {
+    let doc = load_doc_from_bytestring(br#"<div><p>text</p></div>"#);
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(br#"
+        p { color: black; }
+        @media (prefers-color-scheme: dark) { p { color: white; } }
+        @media (prefers-color-scheme: light) { p { background-color: white; } }
+        @media (prefers-color-scheme: sepia) { p { border-color: brown; } }
+    "#).unwrap());
+    let p_value = |styles:&StylesheetSet, name:&str| {
+        let stree = dom_tree_to_stylednodes(&doc.root_node, styles);
+        let p = stree.root.borrow().children.borrow()[0].clone();
+        p.value(name)
+    };
+    styles.media = MediaEnvironment { prefers_dark: false, ..MediaEnvironment::screen(800.0, 600.0) };
+    assert_eq!(p_value(&styles, "color"), Some(Keyword(String::from("black"))));
+    assert_eq!(p_value(&styles, "background-color"), Some(Keyword(String::from("white"))));
+    let before = media_query_results(&styles);
+    styles.media.prefers_dark = true;
+    assert_ne!(media_query_results(&styles), before);
+    assert_eq!(p_value(&styles, "color"), Some(Keyword(String::from("white"))));
+    assert_eq!(p_value(&styles, "background-color"), None);
+    assert_eq!(p_value(&styles, "border-color"), None);
+    assert!(ColorScheme::dark().dark && !ColorScheme::light().dark);
+}
+
+#[test]
+fn test_vendor_prefix_aliases() {
+    let mut ss = parse_stylesheet_from_bytestring(br#"
+        div {
+            -webkit-text-align: center;
+            -moz-box-sizing: border-box;
+            -webkit-transition: opacity 0.3s, transform 0.2s;
+        }
+    "#).unwrap();
+    expand_styles(&mut ss);
+    if let RuleType::Rule(rule) = &ss.rules[0] {
+        let names:Vec<&str> = rule.declarations.iter().map(|dec| dec.name.as_str()).collect();
+        assert_eq!(names, vec!["text-align", "-moz-box-sizing", "-webkit-transition"]);
+        assert_eq!(rule.declarations[2].value, Value::Unparsed(String::from("opacity 0.3s, transform 0.2s")));
+    } else {
+        panic!("expected a rule");
+    }
+}
+
+#[test]
+fn test_page_dimensions() {
+    let mut styles = StylesheetSet::new();
+    assert_eq!(page_dimensions(&styles).content.width, 816.0 - 96.0);
+    let mut ss = parse_stylesheet_from_bytestring(br#"
+        @page { size: A4 landscape; margin: 10px 20px; }
+        @page :first { margin-top: 100px; }
+    "#).unwrap();
+    expand_styles(&mut ss);
+    styles.append(ss);
+    let page = page_dimensions(&styles);
+    assert_eq!(page.margin.top, 10.0);
+    assert_eq!(page.margin.left, 20.0);
+    assert_eq!(page.content.x, 20.0);
+    assert_eq!(page.content.width, 1123.0 - 40.0);
+    assert_eq!(page.content.height, 794.0 - 20.0);
+}
+
+#[test]
+fn test_system_colors() {
+    let doc_text = br#"<div><a>link</a></div>"#;
+    let css_text = br#"
+        div { background-color: Canvas; color: CanvasText; }
+        a { color: LinkText; border-color: #0000ff80; }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(css_text).unwrap());
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    assert_eq!(div.color("background-color"), Some(Color::from_hex("#ffffff")));
+    assert_eq!(div.color("color"), Some(Color::from_hex("#000000")));
+    let a = &div.children.borrow()[0];
+    assert_eq!(a.color("color"), Some(Color::from_hex("#0000ee")));
+    assert_eq!(a.color("border-color"), Some(Color { r: 0, g: 0, b: 255, a: 128 }));
+    let dark = ColorScheme::dark();
+    assert_eq!(dark.system_color("canvas"), Some(Color::from_hex("#121212")));
+    assert_eq!(dark.system_color("red"), None);
+}
+
+#[test]
+fn test_named_and_current_colors() {
+    let doc_text = br#"<div><p>para <em>em</em></p><span>span</span></div>"#;
+    let css_text = br#"
+        div { color: RebeccaPurple; background-color: transparent; }
+        p { color: lightgoldenrodyellow; border-color: currentColor; }
+        em { color: currentColor; background-color: CURRENTCOLOR; }
+        span { border-color: currentcolor; }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(css_text).unwrap());
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    assert_eq!(div.color("color"), Some(Color::from_hex("#663399")));
+    assert_eq!(div.color("background-color"), Some(Color { r: 0, g: 0, b: 0, a: 0 }));
+    let p = &div.children.borrow()[0];
+    let yellow = Some(Color::from_hex("#fafad2"));
+    assert_eq!(p.color("border-color"), yellow);
+    // currentColor on color itself inherits
+    let em = &p.children.borrow()[1];
+    assert_eq!(em.color("color"), yellow);
+    assert_eq!(em.color("background-color"), yellow);
+    let span = &div.children.borrow()[1];
+    assert_eq!(span.color("border-color"), Some(Color::from_hex("#663399")));
+    assert_eq!(find_color_lazy_static("Chartreuse"), Some(Color::from_hex("#7fff00")));
+}
+
+#[test]
+fn test_css_wide_keywords() {
+    let doc_text = br#"<div><p><em>em</em><b>b</b></p><ul><li>li</li></ul></div>"#;
+    let css_text = br#"
+        div { color: green; margin-left: 10px; font-weight: bold; list-style-type: none; }
+        p { color: red; margin-left: inherit; font-weight: initial; }
+        em { color: unset; margin-left: unset; font-weight: inherit; }
+        b { color: initial; margin-left: 5px; }
+        b { margin-left: initial; }
+        li { list-style-type: inherit; font-family: initial; margin-left: inherit; }
+    "#;
+    let doc = load_doc_from_bytestring(doc_text);
+    let mut styles = StylesheetSet::new();
+    styles.append(parse_stylesheet_from_bytestring(css_text).unwrap());
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    let p = &div.children.borrow()[0];
+    assert_eq!(p.value("margin-left"), Some(Length(10.0, Unit::Px)));
+    assert_eq!(p.value("font-weight"), Some(Value::Number(400.0)));
+    let em = &p.children.borrow()[0];
+    // color is inherited, margins are not
+    assert_eq!(em.value("color"), Some(Keyword(String::from("red"))));
+    assert_eq!(em.value("margin-left"), Some(Length(0.0, Unit::Px)));
+    assert_eq!(em.value("font-weight"), Some(Value::Number(400.0)));
+    let b = &p.children.borrow()[1];
+    assert_eq!(b.value("color"), Some(Keyword(String::from("black"))));
+    assert_eq!(b.value("margin-left"), Some(Length(0.0, Unit::Px)));
+    // the ul doesn't set it, but inherits it from the div, so the li gets it too
+    let ul = Rc::clone(&div.children.borrow()[1]);
+    let li = &ul.children.borrow()[0];
+    assert_eq!(li.value("list-style-type"), Some(Keyword(String::from("none"))));
+    assert_eq!(li.value("font-family"), None);
+    // a margin the ul doesn't set is its initial one, whatever the div says
+    assert_eq!(li.value("margin-left"), Some(Length(0.0, Unit::Px)));
+    assert!(is_inherited_property("font-size") && !is_inherited_property("margin-left"));
+}
+
+#[test]
+fn test_all_and_revert() {
+    let doc_text = br#"<div><h1>a</h1><h2>b</h2><p class="reset">c</p><p class="initial">d</p><p class="inherit">e</p><h1 class="again">f</h1></div>"#;
+    let mut user_agent = parse_stylesheet_from_bytestring(br#"
+        h1, h2 { display: block; font-weight: bold; margin-top: 20px; }
+        p { display: block; margin-top: 10px; }
+    "#).unwrap();
+    expand_styles(&mut user_agent);
+    let mut sheet = parse_stylesheet_from_bytestring(br#"
+        div { color: green; border-width: 2px; }
+        h1, h2, p { color: red; font-weight: normal; margin-top: 0px; }
+        h1 { font-weight: revert; }
+        h2 { margin: revert; }
+        .reset { all: revert; }
+        .initial { all: initial; margin-top: 5px; }
+        .inherit { all: inherit; }
+        .again { all: unset; color: blue; }
+    "#).unwrap();
+    expand_styles(&mut sheet);
+    let mut styles = StylesheetSet::new();
+    styles.append(user_agent);
+    styles.user_agent_count = 1;
+    styles.append(sheet);
+    let doc = load_doc_from_bytestring(doc_text);
+    let stree = dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let div = stree.root.borrow();
+    let children = div.children.borrow();
+    let keyword = |kw:&str| Some(Keyword(String::from(kw)));
+    let px = |v:f32| Some(Length(v, Unit::Px));
+
+    // revert goes back to the browser's value, not the initial one
+    assert_eq!(children[0].value("font-weight"), Some(Value::Number(700.0)));
+    assert_eq!(children[0].value("margin-top"), px(0.0));
+    assert_eq!(children[1].value("margin-top"), px(20.0));
+    assert_eq!(children[1].value("font-weight"), Some(Value::Number(400.0)));
+    // all: revert undoes every page rule, and the color comes from the parent again
+    assert_eq!(children[2].value("margin-top"), px(10.0));
+    assert_eq!(children[2].value("display"), keyword("block"));
+    assert_eq!(children[2].value("color"), keyword("green"));
+    // all: initial drops the browser's rules too. later declarations still apply.
+    assert_eq!(children[3].value("display"), None);
+    assert_eq!(children[3].value("color"), keyword("black"));
+    assert_eq!(children[3].value("margin-top"), px(5.0));
+    assert_eq!(children[4].value("border-width-top"), px(2.0));
+    assert_eq!(children[5].value("display"), None);
+    assert_eq!(children[5].value("color"), keyword("blue"));
+    assert_eq!(children[5].value("font-weight"), None);
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use crate::css::{Rule, RuleType, Selector, SimpleSelector};
+use crate::dom::{ElementData, ElementState};
+use crate::net::StylesheetSet;
+use super::{selector_parts, Combinator, MediaEnvironment};
+
+// Rule indexes, so styling an element only tries the rules that could match it. Each selector
+// is filed under the id, a class or the tag of the element it targets, and a bloom filter of
+// the ancestors' ids, classes and tags rules out most descendant selectors without walking
+// up the tree.
+
+const FILTER_WORDS:usize = 16;
+const FILTER_BITS:u64 = FILTER_WORDS as u64 * 64;
+
+// the hash of an id, class or tag name, kept apart so `#main` and `.main` don't collide
+fn key_hash(kind:char, name:&str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    kind.hash(&mut hasher);
+    name.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn element_keys(elem:&ElementData) -> Vec<u64> {
+    let mut keys = vec![key_hash('t', &elem.tag_name)];
+    keys.extend(elem.id().map(|id| key_hash('#', id)));
+    keys.extend(elem.classes().iter().filter(|class| !class.is_empty()).map(|class| key_hash('.', class)));
+    keys
+}
+
+fn selector_keys(selector:&SimpleSelector) -> Vec<u64> {
+    let mut keys = vec![];
+    keys.extend(selector.tag_name.iter().filter(|tag| *tag != "*").map(|tag| key_hash('t', tag)));
+    keys.extend(selector.id.iter().map(|id| key_hash('#', id)));
+    keys.extend(selector.class.iter().map(|class| key_hash('.', class)));
+    keys
+}
+
+/// A bloom filter of the ids, classes and tag names of an element's ancestors. It can say
+/// for sure that no ancestor has one, but only that one might.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AncestorFilter {
+    bits:[u64;FILTER_WORDS],
+}
+
+impl Default for AncestorFilter {
+    fn default() -> Self {
+        AncestorFilter { bits: [0;FILTER_WORDS] }
+    }
+}
+
+impl AncestorFilter {
+    // two bits for each key, from the two halves of its hash
+    fn bits(key:u64) -> [u64;2] {
+        [key % FILTER_BITS, (key >> 32) % FILTER_BITS]
+    }
+    fn insert(&mut self, key:u64) {
+        for bit in AncestorFilter::bits(key).iter() {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+    fn might_contain(&self, key:u64) -> bool {
+        AncestorFilter::bits(key).iter().all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+    /// The filter for the children of elem, whose ancestors are the ones in this filter.
+    pub fn with(&self, elem:&ElementData) -> AncestorFilter {
+        let mut filter = *self;
+        for key in element_keys(elem) {
+            filter.insert(key);
+        }
+        filter
+    }
+}
+
+// a selector filed in the index: which rule it is from, and what its ancestors must have
+struct IndexEntry {
+    rule: usize,
+    ancestor_keys: Vec<u64>,
+}
+
+/// The active style rules of a stylesheet set, numbered in source order and indexed by the
+/// id, class or tag name their selectors need.
+pub struct RuleIndex<'a> {
+    pub rules: Vec<&'a Rule>,
+    /// how many of the rules, from the start, are the browser's own
+    pub user_agent_rules: usize,
+    /// how many of the rules, from the start, are the browser's or the user's
+    pub user_rules: usize,
+    by_id: HashMap<&'a str, Vec<IndexEntry>>,
+    by_class: HashMap<&'a str, Vec<IndexEntry>>,
+    by_tag: HashMap<&'a str, Vec<IndexEntry>>,
+    universal: Vec<IndexEntry>,
+    /// the states that some selector's :hover, :active or :focus depends on
+    pub dynamic_states: ElementState,
+    /// whether one of those selectors also has a sibling combinator, so changing an
+    /// element's state can restyle its siblings
+    pub dynamic_siblings: bool,
+    /// whether any selector has a sibling combinator, so changing an element's id or
+    /// class can restyle its siblings
+    pub siblings: bool,
+}
+
+// the style rules in effect, including the ones in @media blocks that match the environment
+fn active_rules<'a>(rules:&'a [RuleType], media:&MediaEnvironment, found:&mut Vec<&'a Rule>) {
+    for rtype in rules {
+        match rtype {
+            RuleType::Rule(rule) => found.push(rule),
+            RuleType::Media(block) if media.matches_any(&block.queries) => active_rules(&block.rules, media, found),
+            _ => {}
+        }
+    }
+}
+
+impl<'a> RuleIndex<'a> {
+    pub fn new(styles:&'a StylesheetSet) -> RuleIndex<'a> {
+        let mut index = RuleIndex {
+            rules: vec![],
+            user_agent_rules: 0,
+            user_rules: 0,
+            by_id: HashMap::new(),
+            by_class: HashMap::new(),
+            by_tag: HashMap::new(),
+            universal: vec![],
+            dynamic_states: ElementState::default(),
+            dynamic_siblings: false,
+            siblings: false,
+        };
+        for (i, sheet) in styles.stylesheets.iter().enumerate() {
+            active_rules(&sheet.rules, &styles.media, &mut index.rules);
+            if i < styles.user_agent_count {
+                index.user_agent_rules = index.rules.len();
+            }
+            if i < styles.user_agent_count + styles.user_count {
+                index.user_rules = index.rules.len();
+            }
+        }
+        for (order, rule) in index.rules.clone().into_iter().enumerate() {
+            for selector in rule.selectors.iter() {
+                index.add(order, selector);
+            }
+        }
+        index
+    }
+
+    // files the selector under the most particular thing its target needs: the id, then the
+    // first class, then the tag
+    fn add(&mut self, rule:usize, selector:&'a Selector) {
+        let mut parts = vec![];
+        let target = selector_parts(selector, &mut parts);
+        // only the parts left of a descendant or child combinator are certainly ancestors
+        let ancestor_keys = parts.iter()
+            .filter(|(_, combinator)| *combinator == Combinator::Descendant || *combinator == Combinator::Child)
+            .flat_map(|(part, _)| selector_keys(part))
+            .collect();
+        let entry = IndexEntry { rule, ancestor_keys };
+        let states = parts.iter().map(|(part, _)| *part).chain(std::iter::once(target))
+            .flat_map(|part| part.pseudo_class.iter())
+            .filter_map(|pseudo_class| ElementState::from_pseudo_class(pseudo_class));
+        let mut dynamic = false;
+        for state in states {
+            self.dynamic_states.set(state, true);
+            dynamic = true;
+        }
+        if parts.iter().any(|(_, combinator)| *combinator == Combinator::Adjacent || *combinator == Combinator::Sibling) {
+            self.siblings = true;
+            self.dynamic_siblings |= dynamic;
+        }
+        if let Some(id) = &target.id {
+            self.by_id.entry(id.as_str()).or_default().push(entry);
+        } else if let Some(class) = target.class.first() {
+            self.by_class.entry(class.as_str()).or_default().push(entry);
+        } else if let Some(tag) = target.tag_name.as_deref().filter(|tag| *tag != "*") {
+            self.by_tag.entry(tag).or_default().push(entry);
+        } else {
+            self.universal.push(entry);
+        }
+    }
+
+    /// The numbers of the rules that might match elem, in source order. Every rule that does
+    /// match is among them, but they still have to be checked.
+    pub fn candidates(&self, elem:&ElementData, ancestors:&AncestorFilter) -> Vec<usize> {
+        let classes = elem.classes();
+        let mut buckets:Vec<&Vec<IndexEntry>> = vec![&self.universal];
+        buckets.extend(elem.id().and_then(|id| self.by_id.get(id.as_str())));
+        buckets.extend(classes.iter().filter_map(|class| self.by_class.get(class)));
+        buckets.extend(self.by_tag.get(elem.tag_name.as_str()));
+        let mut found:Vec<usize> = buckets.iter()
+            .flat_map(|bucket| bucket.iter())
+            .filter(|entry| entry.ancestor_keys.iter().all(|key| ancestors.might_contain(*key)))
+            .map(|entry| entry.rule)
+            .collect();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+}
+
+#[test]
+fn test_rule_index() {
+    let mut styles = StylesheetSet::new();
+    styles.append(crate::css::parse_stylesheet(r#"
+        #a { color: red; }
+        .b { color: red; }
+        p { color: red; }
+        * { color: red; }
+        div .b { color: red; }
+        ul .b { color: red; }
+        .c, p.b { color: red; }
+        ul + p { color: red; }
+    "#).unwrap());
+    let index = RuleIndex::new(&styles);
+    let doc = crate::dom::load_doc_from_bytestring(br#"<div class="outer"><p class="b">x</p></div>"#);
+    let (div, p) = match (&doc.root_node.node_type, &doc.root_node.children[0].node_type) {
+        (crate::dom::NodeType::Element(div), crate::dom::NodeType::Element(p)) => (div, p),
+        _ => panic!("expected a div and a p"),
+    };
+    let filter = AncestorFilter::default().with(div);
+    // the id rule and the one needing a ul ancestor are never tried. the sibling selector
+    // can't be ruled out by ancestors, so it's left to matching.
+    assert_eq!(index.candidates(p, &filter), vec![1, 2, 3, 4, 6, 7]);
+    assert!(filter.might_contain(key_hash('.', "outer")));
+    assert!(!filter.might_contain(key_hash('#', "outer")));
+    assert_eq!(index.candidates(div, &AncestorFilter::default()), vec![3]);
+    assert_eq!(index.dynamic_states, ElementState::default());
+
+    let mut styles = StylesheetSet::new();
+    styles.append(crate::css::parse_stylesheet("a:hover { color: red; } div:focus p { color: red; }").unwrap());
+    let index = RuleIndex::new(&styles);
+    assert!(index.dynamic_states.contains(ElementState::HOVER) && index.dynamic_states.contains(ElementState::FOCUS));
+    assert!(!index.dynamic_states.contains(ElementState::ACTIVE) && !index.dynamic_siblings);
+    assert!(!index.siblings);
+}
@@ -0,0 +1,241 @@
+use crate::css::{Value, Unit};
+use crate::css::Value::{Keyword, Length};
+use super::StyledNode;
+use crate::layout::LengthContext;
+
+// The transform property as a list of functions, and the 2D matrix they come to for a box.
+// Nothing is painted transformed yet; the renderer can map its vertices through the matrix
+// once it is.
+
+/// A 2D affine transform, written `matrix(a, b, c, d, e, f)` in css. A point (x, y) goes
+/// to (a*x + c*y + e, b*x + d*y + f).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+impl Matrix {
+    pub const IDENTITY:Matrix = Matrix { a: 1.0, b: 0.0, c: 0.0, d: 1.0, e: 0.0, f: 0.0 };
+
+    pub fn translate(x:f32, y:f32) -> Matrix {
+        Matrix { e: x, f: y, ..Matrix::IDENTITY }
+    }
+    pub fn scale(x:f32, y:f32) -> Matrix {
+        Matrix { a: x, d: y, ..Matrix::IDENTITY }
+    }
+    /// clockwise on screen, since y goes down
+    pub fn rotate(degrees:f32) -> Matrix {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Matrix { a: cos, b: sin, c: -sin, d: cos, e: 0.0, f: 0.0 }
+    }
+    pub fn skew(x_degrees:f32, y_degrees:f32) -> Matrix {
+        Matrix { b: y_degrees.to_radians().tan(), c: x_degrees.to_radians().tan(), ..Matrix::IDENTITY }
+    }
+    /// `other` applied first, then this one
+    pub fn multiply(&self, other:&Matrix) -> Matrix {
+        Matrix {
+            a: self.a * other.a + self.c * other.b,
+            b: self.b * other.a + self.d * other.b,
+            c: self.a * other.c + self.c * other.d,
+            d: self.b * other.c + self.d * other.d,
+            e: self.a * other.e + self.c * other.f + self.e,
+            f: self.b * other.e + self.d * other.f + self.f,
+        }
+    }
+    pub fn apply(&self, x:f32, y:f32) -> (f32, f32) {
+        (self.a * x + self.c * y + self.e, self.b * x + self.d * y + self.f)
+    }
+}
+
+/// One function of a `transform` list. Translations stay css values until there's a box,
+/// because their percentages are of the box's own size.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransformFunction {
+    Translate(Value, Value),
+    Scale(f32, f32),
+    /// degrees
+    Rotate(f32),
+    /// degrees along x, then along y
+    Skew(f32, f32),
+    Matrix(Matrix),
+}
+
+impl TransformFunction {
+    /// None for anything that isn't a 2D transform function with the right arguments.
+    pub fn from_value(value:&Value) -> Option<TransformFunction> {
+        let call = match value {
+            Value::FunCall(call) => call,
+            _ => return None,
+        };
+        let zero = Length(0.0, Unit::Px);
+        let args = call.arguments.as_slice();
+        let function = match (call.name.to_lowercase().as_str(), args) {
+            ("translate", [x]) if is_length(x) => TransformFunction::Translate(x.clone(), zero),
+            ("translate", [x, y]) if is_length(x) && is_length(y) => TransformFunction::Translate(x.clone(), y.clone()),
+            ("translatex", [x]) if is_length(x) => TransformFunction::Translate(x.clone(), zero),
+            ("translatey", [y]) if is_length(y) => TransformFunction::Translate(zero, y.clone()),
+            ("scale", [Value::Number(s)]) => TransformFunction::Scale(*s, *s),
+            ("scale", [Value::Number(x), Value::Number(y)]) => TransformFunction::Scale(*x, *y),
+            ("scalex", [Value::Number(x)]) => TransformFunction::Scale(*x, 1.0),
+            ("scaley", [Value::Number(y)]) => TransformFunction::Scale(1.0, *y),
+            ("rotate", [angle]) => TransformFunction::Rotate(degrees(angle)?),
+            ("skew", [x]) => TransformFunction::Skew(degrees(x)?, 0.0),
+            ("skew", [x, y]) => TransformFunction::Skew(degrees(x)?, degrees(y)?),
+            ("skewx", [x]) => TransformFunction::Skew(degrees(x)?, 0.0),
+            ("skewy", [y]) => TransformFunction::Skew(0.0, degrees(y)?),
+            ("matrix", [Value::Number(a), Value::Number(b), Value::Number(c), Value::Number(d), Value::Number(e), Value::Number(f)]) =>
+                TransformFunction::Matrix(Matrix { a: *a, b: *b, c: *c, d: *d, e: *e, f: *f }),
+            _ => return None,
+        };
+        Some(function)
+    }
+    fn to_matrix(&self, context:&LengthContext, width:f32, height:f32) -> Matrix {
+        match self {
+            TransformFunction::Translate(x, y) => Matrix::translate(
+                context.to_px(x, Some(width)).unwrap_or(0.0), context.to_px(y, Some(height)).unwrap_or(0.0)),
+            TransformFunction::Scale(x, y) => Matrix::scale(*x, *y),
+            TransformFunction::Rotate(angle) => Matrix::rotate(*angle),
+            TransformFunction::Skew(x, y) => Matrix::skew(*x, *y),
+            TransformFunction::Matrix(matrix) => *matrix,
+        }
+    }
+}
+
+pub(super) fn is_length(value:&Value) -> bool {
+    matches!(value, Length(_, _) | Value::Calc(_)) || *value == Value::Number(0.0)
+}
+
+// a unitless zero is allowed for angles too
+fn degrees(value:&Value) -> Option<f32> {
+    match value {
+        Value::Angle(degrees) => Some(*degrees),
+        Value::Number(n) if *n == 0.0 => Some(0.0),
+        _ => None,
+    }
+}
+
+// one part of transform-origin along an axis, from the left or top of the box
+fn origin_part(value:&Value, context:&LengthContext, size:f32) -> Option<f32> {
+    match value {
+        Keyword(kw) => match kw.to_lowercase().as_str() {
+            "left" | "top" => Some(0.0),
+            "center" => Some(size / 2.0),
+            "right" | "bottom" => Some(size),
+            _ => None,
+        },
+        value => context.to_px(value, Some(size)),
+    }
+}
+
+fn is_vertical_keyword(value:&Value) -> bool {
+    matches!(value, Keyword(kw) if kw == "top" || kw == "bottom")
+}
+
+fn is_horizontal_keyword(value:&Value) -> bool {
+    matches!(value, Keyword(kw) if kw == "left" || kw == "right")
+}
+
+impl StyledNode {
+    /// The functions of the transform property, in the order they're written. Empty for
+    /// `none`, and for a list with anything in it that isn't understood.
+    pub fn transform_functions(&self) -> Vec<TransformFunction> {
+        let values = match self.value("transform") {
+            Some(Value::ArrayValue(values)) => values,
+            Some(value) => vec![value],
+            None => return vec![],
+        };
+        values.iter().map(TransformFunction::from_value).collect::<Option<Vec<_>>>().unwrap_or_default()
+    }
+
+    /// The transform-origin point for a box of this size, from its top left. A single
+    /// keyword is the center along the other axis, and a third value for z is ignored.
+    pub fn transform_origin(&self, width:f32, height:f32) -> (f32, f32) {
+        let center = Keyword(String::from("center"));
+        let (x, y) = match self.value("transform-origin") {
+            Some(Value::ArrayValue(values)) if values.len() >= 2 => {
+                if is_vertical_keyword(&values[0]) || is_horizontal_keyword(&values[1]) {
+                    (values[1].clone(), values[0].clone())
+                } else {
+                    (values[0].clone(), values[1].clone())
+                }
+            },
+            Some(value) if is_vertical_keyword(&value) => (center, value),
+            Some(value) => (value, center),
+            None => (center.clone(), center),
+        };
+        let context = LengthContext::new(self, width, Some(height));
+        (origin_part(&x, &context, width).unwrap_or(width / 2.0),
+         origin_part(&y, &context, height).unwrap_or(height / 2.0))
+    }
+
+    /// The matrix the transform property comes to for a border box at (left, top) of the
+    /// given size, mapping page coordinates to page coordinates. None when there's nothing
+    /// to transform.
+    pub fn transform(&self, left:f32, top:f32, width:f32, height:f32) -> Option<Matrix> {
+        let functions = self.transform_functions();
+        if functions.is_empty() {
+            return None;
+        }
+        let context = LengthContext::new(self, width, Some(height));
+        let (ox, oy) = self.transform_origin(width, height);
+        let (ox, oy) = (left + ox, top + oy);
+        // move the origin to 0,0, apply the list left to right, then move back
+        let matrix = functions.iter()
+            .fold(Matrix::translate(ox, oy), |matrix, function| matrix.multiply(&function.to_matrix(&context, width, height)));
+        Some(matrix.multiply(&Matrix::translate(-ox, -oy)))
+    }
+}
+
+#[test]
+fn test_matrix() {
+    let close = |(x, y):(f32, f32), (ex, ey):(f32, f32)| (x - ex).abs() < 0.001 && (y - ey).abs() < 0.001;
+    assert!(close(Matrix::rotate(90.0).apply(1.0, 0.0), (0.0, 1.0)));
+    // translate then scale, applied right to left
+    let m = Matrix::translate(10.0, 0.0).multiply(&Matrix::scale(2.0, 3.0));
+    assert!(close(m.apply(1.0, 1.0), (12.0, 3.0)));
+    assert!(close(Matrix::skew(45.0, 0.0).apply(0.0, 1.0), (1.0, 1.0)));
+    assert_eq!(Matrix::IDENTITY.multiply(&m), m);
+}
+
+#[test]
+fn test_transform() {
+    let mut sheet = crate::css::parse_stylesheet(r#"
+        #a { transform: translate(10px, 50%) rotate(90deg); }
+        #b { transform: scale(2) skewX(0); transform-origin: left top; }
+        #c { transform: rotate(45deg) wobble(1); }
+        #d { transform: none; transform-origin: bottom; }
+    "#).unwrap();
+    super::expand_styles(&mut sheet);
+    let mut styles = crate::net::StylesheetSet::new();
+    styles.append(sheet);
+    let doc = crate::dom::load_doc_from_bytestring(br#"<div><p id="a">a</p><p id="b">b</p><p id="c">c</p><p id="d">d</p></div>"#);
+    let stree = super::dom_tree_to_stylednodes(&doc.root_node, &styles);
+    let root = stree.root.borrow();
+    let ps = root.children.borrow().clone();
+
+    assert_eq!(ps[0].transform_functions(), vec![
+        TransformFunction::Translate(Length(10.0, Unit::Px), Length(50.0, Unit::Per)),
+        TransformFunction::Rotate(90.0)]);
+    // rotating about the center of a 100 x 40 box at 0,0, then moving over and down
+    let m = ps[0].transform(0.0, 0.0, 100.0, 40.0).unwrap();
+    let (x, y) = m.apply(50.0, 20.0);
+    assert!((x - 60.0).abs() < 0.001 && (y - 40.0).abs() < 0.001);
+    let (x, y) = m.apply(60.0, 20.0);
+    assert!((x - 60.0).abs() < 0.001 && (y - 50.0).abs() < 0.001);
+
+    // scaling from the top left corner keeps it in place
+    let m = ps[1].transform(5.0, 5.0, 100.0, 40.0).unwrap();
+    assert_eq!(m.apply(5.0, 5.0), (5.0, 5.0));
+    assert_eq!(m.apply(10.0, 5.0), (15.0, 5.0));
+
+    // one bad function spoils the whole list
+    assert_eq!(ps[2].transform_functions(), vec![]);
+    assert_eq!(ps[2].transform(0.0, 0.0, 10.0, 10.0), None);
+    assert_eq!(ps[3].transform(0.0, 0.0, 10.0, 10.0), None);
+    assert_eq!(ps[3].transform_origin(100.0, 40.0), (50.0, 40.0));
+}
@@ -0,0 +1,87 @@
+use crate::dom::{Document, Node, NodeType};
+use crate::net::{BrowserError, calculate_url_from_doc};
+use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::time::Duration;
+use url::Url;
+
+impl From<notify::Error> for BrowserError {
+    fn from(err: notify::Error) -> Self {
+        BrowserError::DiskError(std::io::Error::other(err.to_string()))
+    }
+}
+
+/// Watches a local page and its local stylesheets, for reloading when they are edited.
+pub struct FileWatcher {
+    // dropping the watcher stops the events
+    _watcher: RecommendedWatcher,
+    receiver: Receiver<DebouncedEvent>,
+    files: Vec<PathBuf>,
+}
+
+impl FileWatcher {
+    /// None if the page isn't a local file.
+    pub fn for_page(url:&Url, doc:&Document) -> Result<Option<FileWatcher>, BrowserError> {
+        if url.scheme() != "file" {
+            return Ok(None);
+        }
+        let files:Vec<PathBuf> = page_files(url, doc).iter()
+            .filter_map(|path| path.canonicalize().ok())
+            .filter(|path| path.is_file())
+            .collect();
+        let (sender, receiver) = channel();
+        let mut watcher = watcher(sender, Duration::from_millis(100))?;
+        // editors often save by writing a new file and renaming it over the old one, which
+        // would end a watch on the file itself, so watch the folders they are in
+        let mut dirs:Vec<PathBuf> = files.iter().filter_map(|path| path.parent().map(PathBuf::from)).collect();
+        dirs.sort();
+        dirs.dedup();
+        for dir in dirs.iter() {
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
+        Ok(Some(FileWatcher { _watcher: watcher, receiver, files }))
+    }
+    /// Whether any of the files changed since the last check.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        loop {
+            match self.receiver.try_recv() {
+                Ok(DebouncedEvent::Write(path))
+                | Ok(DebouncedEvent::Create(path))
+                | Ok(DebouncedEvent::Remove(path))
+                | Ok(DebouncedEvent::Rename(_, path)) => changed |= self.watches(&path),
+                Ok(DebouncedEvent::Rescan) => changed = true,
+                Ok(_) => {}
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => return changed,
+            }
+        }
+    }
+    fn watches(&self, path:&Path) -> bool {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.files.contains(&path)
+    }
+}
+
+// the page's own file and the files of its linked stylesheets
+fn page_files(url:&Url, doc:&Document) -> Vec<PathBuf> {
+    let mut urls = vec![url.clone()];
+    collect_stylesheet_urls(doc, &doc.root_node, &mut urls);
+    urls.iter()
+        .filter(|url| url.scheme() == "file")
+        .filter_map(|url| url.to_file_path().ok())
+        .collect()
+}
+
+fn collect_stylesheet_urls(doc:&Document, node:&Node, urls:&mut Vec<Url>) {
+    if let NodeType::Element(ed) = &node.node_type {
+        if ed.tag_name == "link" && ed.attributes.get("rel").is_some_and(|rel| rel == "stylesheet") {
+            if let Some(url) = ed.attributes.get("href").and_then(|href| calculate_url_from_doc(doc, href).ok()) {
+                urls.push(url);
+            }
+        }
+    }
+    for ch in node.children.iter() {
+        collect_stylesheet_urls(doc, ch, urls);
+    }
+}